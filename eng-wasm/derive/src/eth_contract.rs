@@ -1,128 +1,317 @@
 use std::fs::File;
 
-use quote::quote;
-
-use ethabi::{Contract, ParamType};
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use serde_json::Value;
 
+mod abi;
 mod errors;
 mod ethereum;
 
+use abi::{signature, Abi, AbiEvent, AbiFunction, AbiParam, AbiType};
 use errors::EngWasmError;
-use ethereum::short_signature;
+use ethereum::{event_signature, short_signature};
 
 trait Write {
     fn write(&self) -> String;
-    fn error(&self) -> String;
 }
 
-impl Write for ParamType {
+impl Write for AbiType {
     /// Returns string which is a formatted representation of param.
+    /// An integer which doesn't fit in `u128`/`i128` is a `U256`, the two's complement of the value if it's signed.
     fn write(&self) -> String {
         match *self {
-            ParamType::Address => "Address".to_owned(),
-            ParamType::Bytes => "Vec<u8>".to_owned(),
-            ParamType::FixedBytes(len) => format!("u8[{}]", len),
-            ParamType::Int(len) => match len {
-                32 | 64 => format!("i{}", len),
-                _ => panic!("{}", self.error()),
+            AbiType::Address => "Address".to_owned(),
+            AbiType::Bytes => "Vec<u8>".to_owned(),
+            AbiType::FixedBytes(len) => format!("[u8; {}]", len),
+            AbiType::Int(bits) => match integer_width(bits) {
+                Some(width) => format!("i{}", width),
+                None => "U256".to_owned(),
             },
-            ParamType::Uint(len) => match len {
-                32 | 64 => format!("u{}", len),
-                256 => "U256".to_owned(),
-                _ => panic!("{}", self.error()),
+            AbiType::Uint(bits) => match integer_width(bits) {
+                Some(width) => format!("u{}", width),
+                None => "U256".to_owned(),
             },
-            ParamType::Bool => "bool".to_owned(),
-            ParamType::String => "String".to_owned(),
-            ParamType::FixedArray(ref param, len) => format!("{}[{}]", param.write(), len),
-            ParamType::Array(ref param) => format!("Vec<{}>", param.write()),
+            AbiType::Bool => "bool".to_owned(),
+            AbiType::String => "String".to_owned(),
+            AbiType::Array(ref item) | AbiType::FixedArray(ref item, _) => format!("Vec<{}>", item.write()),
+            AbiType::Tuple(ref members) => {
+                format!("({})", members.iter().map(|member| format!("{},", member.write())).collect::<String>())
+            }
+        }
+    }
+}
+
+/// The width of the smallest rust integer which holds an integer of `bits` bits, `None` if it needs a `U256`.
+fn integer_width(bits: usize) -> Option<usize> { [8, 16, 32, 64, 128].iter().cloned().find(|&width| bits <= width) }
+
+/// `createPoll` -> `create_poll`, for the names of the generated decoders.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if previous.map_or(false, |p| p.is_lowercase() || p.is_numeric()) {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+        previous = Some(c);
+    }
+    snake
+}
+
+/// The expression of the `eng_wasm::eth_abi::ParamType` of the type.
+fn param_type(kind: &AbiType) -> TokenStream {
+    match kind {
+        AbiType::Address => quote!(eng_wasm::eth_abi::ParamType::Address),
+        AbiType::Bytes => quote!(eng_wasm::eth_abi::ParamType::Bytes),
+        AbiType::Int(bits) => quote!(eng_wasm::eth_abi::ParamType::Int(#bits)),
+        AbiType::Uint(bits) => quote!(eng_wasm::eth_abi::ParamType::Uint(#bits)),
+        AbiType::Bool => quote!(eng_wasm::eth_abi::ParamType::Bool),
+        AbiType::String => quote!(eng_wasm::eth_abi::ParamType::String),
+        AbiType::FixedBytes(len) => quote!(eng_wasm::eth_abi::ParamType::FixedBytes(#len)),
+        AbiType::Array(item) => {
+            let item = param_type(item);
+            quote!(eng_wasm::eth_abi::ParamType::array(#item))
+        }
+        AbiType::FixedArray(item, len) => {
+            let item = param_type(item);
+            quote!(eng_wasm::eth_abi::ParamType::fixed_array(#item, #len))
+        }
+        AbiType::Tuple(members) => {
+            let members = members.iter().map(param_type);
+            quote!(eng_wasm::eth_abi::ParamType::tuple(&[#(#members),*]))
+        }
+    }
+}
+
+/// The expression which converts the rust value in the variable `value` to an `eng_wasm::eth_abi::Token`.
+fn to_token(kind: &AbiType, value: &syn::Ident) -> TokenStream {
+    match kind {
+        AbiType::Address => quote!(eng_wasm::eth_abi::Token::Address(#value)),
+        AbiType::Bytes => quote!(eng_wasm::eth_abi::Token::Bytes(#value)),
+        AbiType::Int(bits) if integer_width(*bits).is_some() => {
+            quote!(eng_wasm::eth_abi::Token::Int(eng_wasm::eth_abi::int_to_u256(#value as i128)))
+        }
+        AbiType::Int(_) => quote!(eng_wasm::eth_abi::Token::Int(#value)),
+        AbiType::Uint(bits) if integer_width(*bits).is_some() => {
+            quote!(eng_wasm::eth_abi::Token::Uint(eng_wasm::eth_abi::uint_to_u256(#value as u128)))
+        }
+        AbiType::Uint(_) => quote!(eng_wasm::eth_abi::Token::Uint(#value)),
+        AbiType::Bool => quote!(eng_wasm::eth_abi::Token::Bool(#value)),
+        AbiType::String => quote!(eng_wasm::eth_abi::Token::String(#value)),
+        AbiType::FixedBytes(_) => quote!(eng_wasm::eth_abi::Token::FixedBytes(eng_wasm::Vec::from(&#value[..]))),
+        AbiType::Array(item) => {
+            let item = to_token(item, &format_ident!("item"));
+            quote!(eng_wasm::eth_abi::Token::Array(#value.into_iter().map(|item| #item).collect()))
+        }
+        AbiType::FixedArray(item, _) => {
+            let item = to_token(item, &format_ident!("item"));
+            quote!(eng_wasm::eth_abi::Token::FixedArray(#value.into_iter().map(|item| #item).collect()))
+        }
+        AbiType::Tuple(members) => {
+            let names: Vec<syn::Ident> = (0..members.len()).map(|i| format_ident!("member{}", i)).collect();
+            let tokens = members.iter().zip(&names).map(|(member, name)| to_token(member, name));
+            quote!({
+                let (#(#names,)*) = #value;
+                let mut members = eng_wasm::Vec::new();
+                #(members.push(#tokens);)*
+                eng_wasm::eth_abi::Token::Tuple(members)
+            })
         }
     }
-    fn error(&self) -> String {
-        format!("The type {} is not supported", self.to_string())
+}
+
+/// The expression which converts the `eng_wasm::eth_abi::Token` of `token` to the rust value.
+/// The token was decoded as `kind`, so it has the right shape and widths, a mismatch is returned with `?`.
+fn from_token(kind: &AbiType, token: TokenStream) -> TokenStream {
+    match kind {
+        AbiType::Address => quote!(#token.into_address()?),
+        AbiType::Bytes => quote!(#token.into_bytes()?),
+        AbiType::Int(bits) => match integer_width(*bits) {
+            Some(width) => {
+                let int = format_ident!("i{}", width);
+                quote!(eng_wasm::eth_abi::low_u128(&#token.into_int()?) as #int)
+            }
+            None => quote!(#token.into_int()?),
+        },
+        AbiType::Uint(bits) => match integer_width(*bits) {
+            Some(width) => {
+                let uint = format_ident!("u{}", width);
+                quote!(eng_wasm::eth_abi::low_u128(&#token.into_uint()?) as #uint)
+            }
+            None => quote!(#token.into_uint()?),
+        },
+        AbiType::Bool => quote!(#token.into_bool()?),
+        AbiType::String => quote!(#token.into_string()?),
+        AbiType::FixedBytes(len) => quote!({
+            let mut bytes = [0u8; #len];
+            bytes.copy_from_slice(&#token.into_fixed_bytes()?);
+            bytes
+        }),
+        AbiType::Array(item) | AbiType::FixedArray(item, _) => {
+            let item = from_token(item, quote!(token));
+            quote!(
+                #token.into_array()?
+                    .into_iter()
+                    .map(|token| -> Result<_, eng_wasm::eth_abi::Error> { Ok(#item) })
+                    .collect::<Result<eng_wasm::Vec<_>, _>>()?
+            )
+        }
+        AbiType::Tuple(members) => {
+            let values = members.iter().map(|member| from_token(member, quote!(members.next().unwrap())));
+            quote!({
+                let mut members = #token.into_tuple()?.into_iter();
+                (#(#values,)*)
+            })
+        }
     }
 }
 
-struct FunctionAst {
-    name: syn::Ident,
-    args_ast_types: Vec<syn::Type>,
-    args_types: Vec<ParamType>,
+fn rust_type(kind: &AbiType) -> Result<syn::Type, EngWasmError> { Ok(syn::parse_str(&kind.write())?) }
+
+/// A single value is returned as is and more than one as a tuple.
+fn tuple_or_single<T: quote::ToTokens>(items: Vec<T>) -> TokenStream {
+    if items.len() == 1 {
+        quote!(#(#items)*)
+    } else {
+        quote!((#(#items),*))
+    }
 }
 
+fn arg_names(params: &[AbiParam]) -> Vec<syn::Ident> { (0..params.len()).map(|i| format_ident!("arg{}", i)).collect() }
+
 fn read_contract_file(file_path: String) -> Result<Box<File>, EngWasmError> {
     let file = File::open(file_path)?;
     let contents = Box::new(file);
     Ok(contents)
 }
 
-fn generate_eth_functions(
-    contract: &Contract,
-) -> Result<Vec<proc_macro2::TokenStream>, EngWasmError> {
-    let mut functions: Vec<FunctionAst> = Vec::new();
-    for function in &contract.functions {
-        let mut args_ast_types = Vec::new();
-        for input in &function.1.inputs {
-            let arg_type: syn::Type = syn::parse_str(&input.kind.clone().write())?;
-            args_ast_types.push(arg_type);
-        }
-        let args_types = function
-            .1
-            .inputs
-            .iter()
-            .map(|input| input.kind.clone())
-            .collect();
-
-        let name = syn::Ident::new(&function.1.name, proc_macro2::Span::call_site());
-        functions.push(FunctionAst {
-            name,
-            args_types,
-            args_ast_types,
-        })
+fn load_abi(file_path: String) -> Result<Abi, EngWasmError> {
+    let contents: Box<File> = read_contract_file(file_path)?;
+    let json: Value = serde_json::from_reader(contents)?;
+    Abi::parse(&json)
+}
+
+/// A method which calls the function on Ethereum through the bridge.
+fn generate_call(function: &AbiFunction) -> Result<TokenStream, EngWasmError> {
+    let function_name = syn::Ident::new(&function.name, Span::call_site());
+    let selector = short_signature(&signature(&function.name, &function.inputs)).to_vec();
+    let args_names = arg_names(&function.inputs);
+    let args_ast_types = function.inputs.iter().map(|input| rust_type(&input.kind)).collect::<Result<Vec<_>, _>>()?;
+    let param_types = function.inputs.iter().map(|input| param_type(&input.kind));
+    let tokens = function.inputs.iter().zip(&args_names).map(|(input, name)| to_token(&input.kind, name));
+    let error = format!("the arguments of {} don't match its types", function.name);
+
+    Ok(quote! {
+        fn #function_name(&self, #(#args_names: #args_ast_types),*) {
+            let args = eng_wasm::eth_abi::encode(&[#(#param_types),*], &[#(#tokens),*]).expect(#error);
+            let mut payload = Vec::with_capacity(4 + args.len());
+            payload.extend_from_slice(&[#(#selector),*]);
+            payload.extend_from_slice(&args);
+            write_ethereum_bridge(&payload, &self.addr);
+        }
+    })
+}
+
+/// An associated function which decodes the return data of the function, `None` if the function has no outputs.
+fn generate_output_decoder(function: &AbiFunction) -> Result<Option<TokenStream>, EngWasmError> {
+    if function.outputs.is_empty() {
+        return Ok(None);
     }
+    let decoder_name = format_ident!("decode_{}_output", snake_case(&function.name));
+    let doc = format!("Decodes the return data of `{}`.", signature(&function.name, &function.inputs));
+    let output_types = function.outputs.iter().map(|output| rust_type(&output.kind)).collect::<Result<Vec<_>, _>>()?;
+    let output_type = tuple_or_single(output_types);
+    let param_types = function.outputs.iter().map(|output| param_type(&output.kind));
+    let values: Vec<TokenStream> =
+        function.outputs.iter().map(|output| from_token(&output.kind, quote!(tokens.next().unwrap()))).collect();
+    let value = tuple_or_single(values);
 
-    let result: Vec<proc_macro2::TokenStream> = functions
-        .iter()
-        .map(|function| {
-            let function_name = &function.name;
-            let args_ast_types = function.args_ast_types.clone();
-            let sig_u32 = short_signature(&function_name.to_string(), &function.args_types);
-            let sig = syn::Lit::Int(syn::LitInt::new(
-                &format!("{}_u32", sig_u32 as u32),
-                proc_macro2::Span::call_site(),
-            ));
-            let args_number = syn::Lit::Int(syn::LitInt::new(
-                &format!("{}_usize", args_ast_types.len() as usize),
-                proc_macro2::Span::call_site(),
-            ));
-            let args_names: Vec<syn::Ident> = function
-                .args_ast_types
-                .iter()
-                .enumerate()
-                .map(|item| {
-                    let mut arg = String::from("arg");
-                    arg.push_str(item.0.to_string().as_str());
-                    syn::Ident::new(&arg, proc_macro2::Span::call_site())
-                })
-                .collect();
-            let args_names_copy = args_names.clone();
-            quote! {
-                fn #function_name(&self, #(#args_names: #args_ast_types),*){
-                    #![allow(unused_mut)]
-                    #![allow(unused_variables)]
-                    let mut payload = Vec::with_capacity(4 + #args_number * 32);
-                    payload.push((#sig >> 24) as u8);
-                    payload.push((#sig >> 16) as u8);
-                    payload.push((#sig >> 8) as u8);
-                    payload.push(#sig as u8);
-
-                    let mut sink = eng_pwasm_abi::eth::Sink::new(#args_number);
-                    #(sink.push(#args_names_copy);)*
-                    sink.drain_to(&mut payload);
-                    write_ethereum_bridge(&payload, &self.addr);
-                }
+    Ok(Some(quote! {
+        #[doc = #doc]
+        fn #decoder_name(data: &[u8]) -> Result<#output_type, eng_wasm::eth_abi::Error> {
+            let mut tokens = eng_wasm::eth_abi::decode(&[#(#param_types),*], data)?.into_iter();
+            Ok(#value)
+        }
+    }))
+}
+
+/// An associated function which decodes a log of the event from its topics and data.
+/// An indexed argument which isn't a value type is the hash of the value, so it's returned as the `H256` of its topic.
+fn generate_event_decoder(event: &AbiEvent) -> Result<TokenStream, EngWasmError> {
+    let decoder_name = format_ident!("decode_{}_event", snake_case(&event.name));
+    let event_signature_str = signature(&event.name, &event.inputs);
+    let doc = format!("Decodes a log of `{}`.", event_signature_str);
+
+    let mut types = Vec::new();
+    let mut values = Vec::new();
+    let mut data_types = Vec::new();
+    let mut topic_index = 0usize;
+    for input in &event.inputs {
+        if !input.indexed {
+            types.push(rust_type(&input.kind)?);
+            values.push(from_token(&input.kind, quote!(tokens.next().unwrap())));
+            data_types.push(param_type(&input.kind));
+        } else if input.kind.is_value_type() {
+            let kind = param_type(&input.kind);
+            types.push(rust_type(&input.kind)?);
+            values.push(from_token(&input.kind, quote!(eng_wasm::eth_abi::decode_topic(&#kind, &topics[#topic_index])?)));
+            topic_index += 1;
+        } else {
+            types.push(syn::parse_str("H256")?);
+            values.push(quote!(topics[#topic_index]));
+            topic_index += 1;
+        }
+    }
+    let output_type = tuple_or_single(types);
+    let value = tuple_or_single(values);
+    let check_signature = if event.anonymous {
+        quote!()
+    } else {
+        let topic = event_signature(&event_signature_str).to_vec();
+        quote! {
+            if topics.first() != Some(&H256::from_slice(&[#(#topic),*])) {
+                return Err(eng_wasm::eth_abi::Error::InvalidTopics);
             }
-        })
-        .collect();
+            let topics = &topics[1..];
+        }
+    };
+    let decode_data = if data_types.is_empty() {
+        quote!()
+    } else {
+        quote!(let mut tokens = eng_wasm::eth_abi::decode(&[#(#data_types),*], data)?.into_iter();)
+    };
 
+    Ok(quote! {
+        #[doc = #doc]
+        #[allow(unused_variables)]
+        fn #decoder_name(topics: &[H256], data: &[u8]) -> Result<#output_type, eng_wasm::eth_abi::Error> {
+            #check_signature
+            if topics.len() != #topic_index {
+                return Err(eng_wasm::eth_abi::Error::InvalidTopics);
+            }
+            #decode_data
+            Ok(#value)
+        }
+    })
+}
+
+fn generate_eth_functions(abi: &Abi) -> Result<Vec<TokenStream>, EngWasmError> {
+    let mut result = Vec::new();
+    for (i, function) in abi.functions.iter().enumerate() {
+        if abi.functions[..i].iter().any(|other| other.name == function.name) {
+            return Err(EngWasmError::AbiError { error: format!("overloaded functions aren't supported: {}", function.name) });
+        }
+        result.push(generate_call(function)?);
+        result.extend(generate_output_decoder(function)?);
+    }
+    for event in &abi.events {
+        result.push(generate_event_decoder(event)?);
+    }
     Ok(result)
 }
 
@@ -133,23 +322,90 @@ pub fn impl_eth_contract(
     let input_tokens = parse_macro_input2!(input as syn::ItemStruct);
     let struct_name = input_tokens.ident;
     let file_path = parse_macro_input2!(args as syn::LitStr);
-    let contents: Box<File> = read_contract_file(file_path.value()).expect("Bad contract file");
-    let contract = Contract::load(contents).unwrap();
-    let it: Vec<proc_macro2::TokenStream> = generate_eth_functions(&contract).unwrap();
+    let it = match load_abi(file_path.value()).and_then(|abi| generate_eth_functions(&abi)) {
+        Ok(it) => it,
+        Err(err) => return syn::Error::new(file_path.span(), err).to_compile_error(),
+    };
 
     quote! {
         struct #struct_name {
             addr: Address,
         }
-        impl EthContract{
+        // the calls and the decoders are generated for the whole ABI, a contract uses only some of them.
+        #[allow(dead_code)]
+        impl #struct_name {
             fn new(addr_str: /*Address*/&str) -> Self {
                 use core::str::FromStr;
 
                 // Ethereum Addresses need to start with `0x` so we remove the first two characters
                 let addr = Address::from_str(&addr_str[2..]).expect("Failed converting the address from hex");
-                EthContract{ addr }
+                #struct_name { addr }
             }
              #(#it)*
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rust_types() {
+        let types = [
+            ("uint24", "u32"),
+            ("int8", "i8"),
+            ("uint128", "u128"),
+            ("int200", "U256"),
+            ("bytes4", "[u8; 4]"),
+            ("address[][2]", "Vec<Vec<Address>>"),
+        ];
+        for (abi_type, rust_type) in &types {
+            assert_eq!(AbiType::parse(abi_type, &[]).unwrap().write(), *rust_type);
+        }
+        let members = [
+            AbiParam { name: "a".to_owned(), kind: AbiType::Bool, indexed: false },
+            AbiParam { name: "b".to_owned(), kind: AbiType::String, indexed: false },
+        ];
+        assert_eq!(AbiType::parse("tuple", &members).unwrap().write(), "(bool,String,)");
+        assert_eq!(AbiType::parse("tuple", &members[..1]).unwrap().write(), "(bool,)");
+    }
+
+    #[test]
+    fn snake_case_names() {
+        assert_eq!(snake_case("createPoll"), "create_poll");
+        assert_eq!(snake_case("Transfer"), "transfer");
+        assert_eq!(snake_case("ERC20Transfer"), "erc20_transfer");
+        assert_eq!(snake_case("polls"), "polls");
+    }
+
+    #[test]
+    fn event_decoder_generation() {
+        let event = AbiEvent {
+            name: "Deposit".to_owned(),
+            inputs: vec![
+                AbiParam { name: "from".to_owned(), kind: AbiType::Address, indexed: true },
+                AbiParam { name: "memo".to_owned(), kind: AbiType::String, indexed: true },
+                AbiParam { name: "amount".to_owned(), kind: AbiType::Uint(64), indexed: false },
+            ],
+            anonymous: true,
+        };
+        let expected: syn::ItemFn = syn::parse_quote! {
+            #[doc = "Decodes a log of `Deposit(address,string,uint64)`."]
+            #[allow(unused_variables)]
+            fn decode_deposit_event(topics: &[H256], data: &[u8]) -> Result<(Address, H256, u64), eng_wasm::eth_abi::Error> {
+                if topics.len() != 2usize {
+                    return Err(eng_wasm::eth_abi::Error::InvalidTopics);
+                }
+                let mut tokens = eng_wasm::eth_abi::decode(&[eng_wasm::eth_abi::ParamType::Uint(64usize)], data)?.into_iter();
+                Ok((
+                    eng_wasm::eth_abi::decode_topic(&eng_wasm::eth_abi::ParamType::Address, &topics[0usize])?.into_address()?,
+                    topics[1usize],
+                    eng_wasm::eth_abi::low_u128(&tokens.next().unwrap().into_uint()?) as u64
+                ))
+            }
+        };
+        let generated: syn::ItemFn = syn::parse2(generate_event_decoder(&event).unwrap()).unwrap();
+        assert_eq!(generated, expected);
+    }
+}
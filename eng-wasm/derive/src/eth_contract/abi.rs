@@ -0,0 +1,214 @@
+//! Parsing of the JSON ABI of an Ethereum contract.
+//!
+//! This covers the parts of the ABI that `eth_contract` generates code for: the functions with their
+//! inputs and outputs, and the events, including tuples with their `components`.
+
+use serde_json::Value;
+
+use super::errors::EngWasmError;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AbiType {
+    Address,
+    Bytes,
+    Int(usize),
+    Uint(usize),
+    Bool,
+    String,
+    Array(Box<AbiType>),
+    FixedBytes(usize),
+    FixedArray(Box<AbiType>, usize),
+    Tuple(Vec<AbiType>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AbiParam {
+    pub name: String,
+    pub kind: AbiType,
+    /// Only the inputs of events can be indexed.
+    pub indexed: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AbiFunction {
+    pub name: String,
+    pub inputs: Vec<AbiParam>,
+    pub outputs: Vec<AbiParam>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AbiEvent {
+    pub name: String,
+    pub inputs: Vec<AbiParam>,
+    pub anonymous: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Abi {
+    pub functions: Vec<AbiFunction>,
+    pub events: Vec<AbiEvent>,
+}
+
+fn abi_error(error: String) -> EngWasmError { EngWasmError::AbiError { error } }
+
+impl AbiType {
+    /// Parses a type of the ABI, e.g. `uint256[][3]`, `components` are the members of a `tuple`.
+    pub fn parse(type_: &str, components: &[AbiParam]) -> Result<AbiType, EngWasmError> {
+        // the last dimension of an array type is the outermost one.
+        if type_.ends_with(']') {
+            let open = type_.rfind('[').ok_or_else(|| abi_error(format!("invalid type {}", type_)))?;
+            let item = Box::new(AbiType::parse(&type_[..open], components)?);
+            return match &type_[open + 1..type_.len() - 1] {
+                "" => Ok(AbiType::Array(item)),
+                len => match len.parse::<usize>() {
+                    Ok(len) => Ok(AbiType::FixedArray(item, len)),
+                    Err(_) => Err(abi_error(format!("invalid array length in {}", type_))),
+                },
+            };
+        }
+        let kind = match type_ {
+            "address" => AbiType::Address,
+            "bool" => AbiType::Bool,
+            "string" => AbiType::String,
+            "bytes" => AbiType::Bytes,
+            "int" => AbiType::Int(256),
+            "uint" => AbiType::Uint(256),
+            "tuple" => AbiType::Tuple(components.iter().map(|component| component.kind.clone()).collect()),
+            _ if type_.starts_with("bytes") => match type_[5..].parse::<usize>() {
+                Ok(len) if len >= 1 && len <= 32 => AbiType::FixedBytes(len),
+                _ => return Err(abi_error(format!("The type {} is not supported", type_))),
+            },
+            _ if type_.starts_with("uint") => AbiType::Uint(Self::parse_bits(type_, &type_[4..])?),
+            _ if type_.starts_with("int") => AbiType::Int(Self::parse_bits(type_, &type_[3..])?),
+            _ => return Err(abi_error(format!("The type {} is not supported", type_))),
+        };
+        Ok(kind)
+    }
+
+    fn parse_bits(type_: &str, bits: &str) -> Result<usize, EngWasmError> {
+        match bits.parse::<usize>() {
+            Ok(bits) if bits >= 8 && bits <= 256 && bits % 8 == 0 => Ok(bits),
+            _ => Err(abi_error(format!("The type {} is not supported", type_))),
+        }
+    }
+
+    /// The canonical name of the type, which is used in the signatures of functions and events.
+    pub fn canonical(&self) -> String {
+        match self {
+            AbiType::Address => "address".to_owned(),
+            AbiType::Bytes => "bytes".to_owned(),
+            AbiType::Int(bits) => format!("int{}", bits),
+            AbiType::Uint(bits) => format!("uint{}", bits),
+            AbiType::Bool => "bool".to_owned(),
+            AbiType::String => "string".to_owned(),
+            AbiType::Array(item) => format!("{}[]", item.canonical()),
+            AbiType::FixedBytes(len) => format!("bytes{}", len),
+            AbiType::FixedArray(item, len) => format!("{}[{}]", item.canonical(), len),
+            AbiType::Tuple(members) => {
+                format!("({})", members.iter().map(AbiType::canonical).collect::<Vec<_>>().join(","))
+            }
+        }
+    }
+
+    /// Whether an indexed argument of this type is stored as is in its topic, and not as its hash.
+    pub fn is_value_type(&self) -> bool {
+        match self {
+            AbiType::Address | AbiType::Int(_) | AbiType::Uint(_) | AbiType::Bool | AbiType::FixedBytes(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The signature of a function or an event, e.g. `transfer(address,uint256)`.
+pub fn signature(name: &str, params: &[AbiParam]) -> String {
+    let types: Vec<String> = params.iter().map(|param| param.kind.canonical()).collect();
+    format!("{}({})", name, types.join(","))
+}
+
+fn parse_params(value: Option<&Value>) -> Result<Vec<AbiParam>, EngWasmError> {
+    let params = match value {
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(Value::Array(params)) => params,
+        Some(other) => return Err(abi_error(format!("expected a list of parameters, got {}", other))),
+    };
+    params.iter().map(parse_param).collect()
+}
+
+fn parse_param(value: &Value) -> Result<AbiParam, EngWasmError> {
+    let type_ = value["type"].as_str().ok_or_else(|| abi_error(format!("the parameter {} has no type", value)))?;
+    let components = parse_params(value.get("components"))?;
+    Ok(AbiParam {
+        name: value["name"].as_str().unwrap_or_default().to_owned(),
+        kind: AbiType::parse(type_, &components)?,
+        indexed: value["indexed"].as_bool().unwrap_or(false),
+    })
+}
+
+impl Abi {
+    pub fn parse(json: &Value) -> Result<Abi, EngWasmError> {
+        let items = json.as_array().ok_or_else(|| abi_error("the ABI should be a list".to_owned()))?;
+        let mut abi = Abi::default();
+        for item in items {
+            let name = || item["name"].as_str().map(str::to_owned).ok_or_else(|| abi_error(format!("{} has no name", item)));
+            // the type of a function can be omitted.
+            match item["type"].as_str().unwrap_or("function") {
+                "function" => abi.functions.push(AbiFunction {
+                    name: name()?,
+                    inputs: parse_params(item.get("inputs"))?,
+                    outputs: parse_params(item.get("outputs"))?,
+                }),
+                "event" => abi.events.push(AbiEvent {
+                    name: name()?,
+                    inputs: parse_params(item.get("inputs"))?,
+                    anonymous: item["anonymous"].as_bool().unwrap_or(false),
+                }),
+                // the constructor and the fallback can't be called by a secret contract.
+                _ => (),
+            }
+        }
+        Ok(abi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_types() {
+        assert_eq!(AbiType::parse("uint24", &[]).unwrap(), AbiType::Uint(24));
+        assert_eq!(AbiType::parse("int", &[]).unwrap(), AbiType::Int(256));
+        assert_eq!(AbiType::parse("bytes7", &[]).unwrap(), AbiType::FixedBytes(7));
+        assert_eq!(
+            AbiType::parse("uint8[][3]", &[]).unwrap(),
+            AbiType::FixedArray(Box::new(AbiType::Array(Box::new(AbiType::Uint(8)))), 3)
+        );
+        assert!(AbiType::parse("uint7", &[]).is_err());
+        assert!(AbiType::parse("bytes33", &[]).is_err());
+        assert!(AbiType::parse("fixed128x18", &[]).is_err());
+    }
+
+    #[test]
+    fn parse_abi() {
+        let json = serde_json::json!([
+            {"type": "constructor", "inputs": []},
+            {"type": "function", "name": "submit", "inputs": [
+                {"name": "proof", "type": "tuple[]", "components": [
+                    {"name": "root", "type": "bytes32"},
+                    {"name": "path", "type": "uint256[2]"}
+                ]}
+            ], "outputs": [{"name": "", "type": "bool"}]},
+            {"type": "event", "name": "Transfer", "anonymous": false, "inputs": [
+                {"name": "from", "type": "address", "indexed": true},
+                {"name": "value", "type": "uint256", "indexed": false}
+            ]}
+        ]);
+        let abi = Abi::parse(&json).unwrap();
+        assert_eq!(abi.functions.len(), 1);
+        assert_eq!(signature(&abi.functions[0].name, &abi.functions[0].inputs), "submit((bytes32,uint256[2])[])");
+        assert_eq!(abi.functions[0].outputs[0].kind, AbiType::Bool);
+        assert_eq!(signature(&abi.events[0].name, &abi.events[0].inputs), "Transfer(address,uint256)");
+        assert!(abi.events[0].inputs[0].indexed);
+        assert!(!abi.events[0].inputs[1].indexed);
+    }
+}
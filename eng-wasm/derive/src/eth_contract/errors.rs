@@ -10,6 +10,8 @@ pub enum EngWasmError {
     JsonError { error: String },
     #[fail(display = "Token parse error: {}", error)]
     TokenParseError { error: String },
+    #[fail(display = "ABI error: {}", error)]
+    AbiError { error: String },
 }
 
 impl From<io::Error> for EngWasmError {
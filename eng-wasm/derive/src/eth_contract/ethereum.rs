@@ -1,22 +1,19 @@
-use ethabi::param_type::{ParamType, Writer};
 use tiny_keccak::Keccak;
 
-pub fn short_signature(name: &str, params: &[ParamType]) -> u32 /*[u8; 4] */ {
+/// The selector of a function, the first 4 bytes of the hash of its signature.
+pub fn short_signature(signature: &str) -> [u8; 4] {
     let mut result = [0u8; 4];
-    fill_signature(name, params, &mut result);
-    u32::from_be_bytes(result)
+    result.copy_from_slice(&keccak256(signature)[..4]);
+    result
 }
 
-fn fill_signature(name: &str, params: &[ParamType], result: &mut [u8]) {
-    let types = params
-        .iter()
-        .map(Writer::write)
-        .collect::<Vec<String>>()
-        .join(",");
-
-    let data: Vec<u8> = From::from(format!("{}({})", name, types).as_str());
+/// The first topic of the logs of an event which isn't anonymous, the hash of its signature.
+pub fn event_signature(signature: &str) -> [u8; 32] { keccak256(signature) }
 
+fn keccak256(data: &str) -> [u8; 32] {
+    let mut result = [0u8; 32];
     let mut sponge = Keccak::new_keccak256();
-    sponge.update(&data);
-    sponge.finalize(result);
+    sponge.update(data.as_bytes());
+    sponge.finalize(&mut result);
+    result
 }
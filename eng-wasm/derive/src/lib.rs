@@ -37,11 +37,20 @@ use pub_interface::impl_pub_interface;
 /// trait will be considered exported by the contract. If it is placed on an impl block,
 /// all methods declared as pub will be exported by the contract. if placed on an impl block,
 /// the implementing struct can have any name you choose.
+///
+/// A method can return `Result<T, E>` where `E: Display`, an `Err` fails the task with the
+/// error message and the user gets it instead of the result.
 #[proc_macro_attribute]
 pub fn pub_interface(attr: TokenStream, item: TokenStream) -> TokenStream {
     impl_pub_interface(attr.into(), item.into()).into()
 }
 
+/// This macro generates the bindings of an Ethereum contract from its JSON ABI.
+///
+/// It is placed on a struct, e.g. `#[eth_contract("Contract.json")] struct EthContract;`,
+/// which gets a method for every function of the ABI that calls it through the Ethereum bridge,
+/// a `decode_<function>_output` for every function with outputs, and a `decode_<event>_event` which
+/// decodes a log of an event from its topics and data, so a contract can verify data that comes from Ethereum.
 #[proc_macro_attribute]
 pub fn eth_contract(attr: TokenStream, item: TokenStream) -> TokenStream {
     impl_eth_contract(attr.into(), item.into()).into()
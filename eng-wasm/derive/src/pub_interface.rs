@@ -1,15 +1,23 @@
 use quote::{quote, quote_spanned, ToTokens};
 use syn::spanned::Spanned;
 
+mod abi_interface;
 mod parse_signatures;
 
 use super::into_ident::IntoIdent;
-use parse_signatures::{ParseError, PubInterfaceItemType, PubInterfaceSignatures};
+use abi_interface::generate_interface_section;
+use parse_signatures::{
+    result_types, return_value_types, ParseError, PubInterfaceItemType, PubInterfaceSignatures,
+};
 
 const DEFAULT_IMPLEMENTOR_NAME: &str = "Contract";
 const CONSTRUCTOR_NAME: &str = "construct";
+const MIGRATE_NAME: &str = "migrate";
+const AUTHORIZE_UPGRADE_NAME: &str = "authorize_upgrade";
 
 const DEPLOY_FUNC_NAME: &str = "deploy";
+const MIGRATE_FUNC_NAME: &str = "migrate";
+const AUTHORIZE_UPGRADE_FUNC_NAME: &str = "authorize_upgrade";
 const DISPATCH_FUNC_NAME: &str = "dispatch";
 const FUNCTION_NAME_FUNC_NAME: &str = "function_name";
 const ARGS_FUNC_NAME: &str = "args";
@@ -31,7 +39,7 @@ pub(crate) fn impl_pub_interface(
                 .map(|()| pub_interface_signatures)
         });
     let pub_interface_signatures = match parse_result {
-        Ok(pub_interface_signatures) => pub_interface_signatures,
+        Ok(parsed) => parsed,
         Err(error) => {
             let compilation_error = error.to_compile_error();
             return quote!(
@@ -41,15 +49,22 @@ pub(crate) fn impl_pub_interface(
         }
     };
 
+    let interface_section = generate_interface_section(&pub_interface_signatures);
     let deploy_func_name = DEPLOY_FUNC_NAME.into_ident();
     let dispatch_func_name = DISPATCH_FUNC_NAME.into_ident();
     let function_name_func_name = FUNCTION_NAME_FUNC_NAME.into_ident();
     let args_func_name = ARGS_FUNC_NAME.into_ident();
     let call_func_name = CALL_FUNC_NAME.into_ident();
+    let migrate_func_name = MIGRATE_FUNC_NAME.into_ident();
+    let authorize_upgrade_func_name = AUTHORIZE_UPGRADE_FUNC_NAME.into_ident();
 
     let aux_functions = generate_eng_wasm_aux_functions(&function_name_func_name, &args_func_name);
     let constructor_function =
         generate_deploy_function(&deploy_func_name, &pub_interface_signatures);
+    let migrate_function =
+        generate_migrate_function(&migrate_func_name, &pub_interface_signatures);
+    let authorize_upgrade_function =
+        generate_authorize_upgrade_function(&authorize_upgrade_func_name, &pub_interface_signatures);
     let dispatch_function =
         generate_dispatch_function(&dispatch_func_name, &pub_interface_signatures);
 
@@ -57,7 +72,10 @@ pub(crate) fn impl_pub_interface(
         #item
         #aux_functions
         #constructor_function
+        #migrate_function
+        #authorize_upgrade_function
         #dispatch_function
+        #interface_section
 
         #[no_mangle]
         pub fn #call_func_name(){
@@ -180,42 +198,72 @@ fn generate_deploy_function(
     deploy_func_name: &syn::Ident,
     signatures: &PubInterfaceSignatures,
 ) -> proc_macro2::TokenStream {
-    if let Some(constructor_signature) = signatures
+    generate_export_function(deploy_func_name, CONSTRUCTOR_NAME, signatures).unwrap_or_else(|| {
+        quote! {
+            #[no_mangle]
+            pub fn #deploy_func_name() {}
+        }
+    })
+}
+
+/// The `migrate` export only exists if the contract has a `migrate` function,
+/// it runs against the existing state when a deployed contract is upgraded to this contract.
+fn generate_migrate_function(
+    migrate_func_name: &syn::Ident,
+    signatures: &PubInterfaceSignatures,
+) -> proc_macro2::TokenStream {
+    generate_export_function(migrate_func_name, MIGRATE_NAME, signatures).unwrap_or_default()
+}
+
+/// The `authorize_upgrade` export only exists if the contract has an `authorize_upgrade` function,
+/// it gets the hash of the new code and the sender's public key, and approves the upgrade unless it fails.
+/// A contract without it can't be upgraded.
+fn generate_authorize_upgrade_function(
+    authorize_upgrade_func_name: &syn::Ident,
+    signatures: &PubInterfaceSignatures,
+) -> proc_macro2::TokenStream {
+    generate_export_function(authorize_upgrade_func_name, AUTHORIZE_UPGRADE_NAME, signatures)
+        .unwrap_or_default()
+}
+
+/// Generate an export which decodes the arguments and calls the `method_name` function of the contract,
+/// or `None` if the contract has no such function.
+fn generate_export_function(
+    export_func_name: &syn::Ident,
+    method_name: &str,
+    signatures: &PubInterfaceSignatures,
+) -> Option<proc_macro2::TokenStream> {
+    signatures
         .signatures
         .iter()
-        .find(|item| item.ident == CONSTRUCTOR_NAME)
-    {
-        let implementor = &signatures.implementor;
-        let constructor_name = &constructor_signature.ident;
-        let input_pats_and_types = get_signature_input_pats_and_types(&constructor_signature);
-        let expectations = get_contract_input_parsing_error_messages(&input_pats_and_types);
-
-        // Manually construct the code that implicitly checks properties of the input types
-        // so that the spans of type errors are correctly propagated
-        let parsed_inputs = input_pats_and_types
-            .iter()
-            .map(|(_pat, type_)| type_)
-            .zip(expectations)
-            .map(|(type_, expectation)|
-                quote_spanned!(type_.span()=> stream.pop::<#type_>().expect(#expectation))
-            );
-        let variables = generate_enumerated_idents("var_", input_pats_and_types.len());
+        .find(|item| item.ident == method_name)
+        .map(|signature| {
+            let implementor = &signatures.implementor;
+            let input_pats_and_types = get_signature_input_pats_and_types(&signature);
+            let expectations = get_contract_input_parsing_error_messages(&input_pats_and_types);
 
-        return quote! {
-            #[no_mangle]
-            pub fn #deploy_func_name() {
-                let args_ = args();
-                let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(&args_);
-                #(let #variables = #parsed_inputs;)*
-                <#implementor>::#constructor_name(#(#variables),*);
+            // Manually construct the code that implicitly checks properties of the input types
+            // so that the spans of type errors are correctly propagated
+            let parsed_inputs = input_pats_and_types
+                .iter()
+                .map(|(_pat, type_)| type_)
+                .zip(expectations)
+                .map(|(type_, expectation)|
+                    quote_spanned!(type_.span()=> stream.pop::<#type_>().unwrap_or_else(|_| eng_wasm::revert(#expectation)))
+                );
+            let variables = generate_enumerated_idents("var_", input_pats_and_types.len());
+            let method_call = generate_method_call(implementor, signature, &variables);
+
+            quote! {
+                #[no_mangle]
+                pub fn #export_func_name() {
+                    let args_ = args();
+                    let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(&args_);
+                    #(let #variables = #parsed_inputs;)*
+                    #method_call;
+                }
             }
-        };
-    } else {
-        return quote! {
-            #[no_mangle]
-            pub fn #deploy_func_name() {}
-        };
-    }
+        })
 }
 
 fn get_signature_input_pats_and_types(signature: &syn::Signature) -> Vec<(&syn::Pat, &syn::Type)> {
@@ -259,7 +307,11 @@ fn generate_dispatch_function(
         .iter()
         .filter_map(|signature| {
             let method_name = &signature.ident;
-            if method_name == CONSTRUCTOR_NAME {
+            // these only run through their own exports, on deployment and on upgrade.
+            if method_name == CONSTRUCTOR_NAME
+                || method_name == MIGRATE_NAME
+                || method_name == AUTHORIZE_UPGRADE_NAME
+            {
                 return None;
             }
 
@@ -275,26 +327,21 @@ fn generate_dispatch_function(
                 .map(|(_pat, type_)| type_)
                 .zip(expectations)
                 .map(|(type_, expectation)|
-                    quote_spanned!(type_.span()=> stream.pop::<#type_>().expect(#expectation))
+                    quote_spanned!(type_.span()=> stream.pop::<#type_>().unwrap_or_else(|_| eng_wasm::revert(#expectation)))
                 );
             let variables = generate_enumerated_idents("var_", input_pats_and_types.len());
 
-            let return_value_count = match output_type {
-                syn::ReturnType::Default => 0,
-                syn::ReturnType::Type(_, type_) => match type_.as_ref() {
-                    // If the return value is a tuple, we count it like multiple return values.
-                    // This is the same thing that pwasm_abi does under
-                    // pwasm_abi/derive/src/item.rs :: fn into_signature
-                    // which flows back to
-                    // pwasm_abi/derive/src/lib.rs :: fn generate_eth_endpoint
-                    // which dictates how return values are serialised into the Sink.
-                    // This can be 0 if the return type is () which is correct.
-                    syn::Type::Tuple(return_tuple) => return_tuple.elems.len(),
-                    // Any other type is a single return value. Arrays such as [u8; 4]
-                    // are not AbiType so Sink will reject them at compile time.
-                    _ => 1,
-                }
-            };
+            // If the return value is a tuple, we count it like multiple return values.
+            // This is the same thing that pwasm_abi does under
+            // pwasm_abi/derive/src/item.rs :: fn into_signature
+            // which flows back to
+            // pwasm_abi/derive/src/lib.rs :: fn generate_eth_endpoint
+            // which dictates how return values are serialised into the Sink.
+            // Any other type is a single return value. Arrays such as [u8; 4]
+            // are not AbiType so Sink will reject them at compile time.
+            // A `Result` returns the values of its `Ok` type.
+            let return_value_count = return_value_types(signature).len();
+            let method_call = generate_method_call(implementor, signature, &variables);
 
             // Make sure we only generate code for initializing the stream of inputs,
             // if we expect inputs at all
@@ -308,7 +355,7 @@ fn generate_dispatch_function(
                     #method_name_as_string => {
                         #stream_initialization_snippet
                         #(let #variables = #parsed_inputs;)*
-                        <#implementor>::#method_name(#(#variables),*);
+                        #method_call;
                     }
                 }),
                 _ => {
@@ -317,15 +364,17 @@ fn generate_dispatch_function(
                     let result_token = quote!(result);
                     // disassociate the return type from the arrow.
                     let output_type = match output_type {
-                        syn::ReturnType::Type(_arrow, type_) => type_,
+                        syn::ReturnType::Type(_arrow, type_) => type_.as_ref(),
                         syn::ReturnType::Default => unreachable!("We know that there is an explicit return type")
                     };
+                    // only the `Ok` value of a `Result` is pushed.
+                    let output_type = result_types(output_type).map_or(output_type, |(ok_type, _err_type)| ok_type);
                     let push_result_to_sink = quote_spanned!(output_type.span()=> sink.push(#result_token));
                     Some(quote! {
                         #method_name_as_string => {
                             #stream_initialization_snippet
                             #(let #variables = #parsed_inputs;)*
-                            let #result_token = <#implementor>::#method_name(#(#variables),*);
+                            let #result_token = #method_call;
                             // 32 is the size of each argument in the serialised form
                             // The Sink.drain_to() method might resize this array if any
                             // dynamically sized elements are returned, but if not, then only one
@@ -346,12 +395,40 @@ fn generate_dispatch_function(
         pub fn #dispatch_func_name(name: &str, args: &[u8]){
             match name {
                 #(#match_arms)*
-                _ => panic!("Unknown method called:\"{}\"", name),
+                _ => eng_wasm::revert(&eng_wasm::fmt::format(format_args!("Unknown method called:\"{}\"", name))),
             }
         }
     }
 }
 
+/// Generate the call to the function of the contract.
+/// If the function returns a `Result`, an `Err` fails the task with the error message, and the call evaluates to the `Ok` value.
+fn generate_method_call(
+    implementor: &syn::Type,
+    signature: &syn::Signature,
+    variables: &[syn::Ident],
+) -> proc_macro2::TokenStream {
+    let method_name = &signature.ident;
+    let call = quote!(<#implementor>::#method_name(#(#variables),*));
+    match &signature.output {
+        syn::ReturnType::Type(_, type_) => match result_types(type_) {
+            Some((_ok_type, err_type)) => {
+                // the error type has to implement `Display`, the span points at it if it doesn't.
+                let error_message =
+                    quote_spanned!(err_type.span()=> eng_wasm::ToString::to_string(&error));
+                quote! {
+                    match #call {
+                        Ok(result) => result,
+                        Err(error) => eng_wasm::revert(&#error_message),
+                    }
+                }
+            }
+            None => call,
+        },
+        syn::ReturnType::Default => call,
+    }
+}
+
 /// Generate `count` identifiers with the specified prefix, and a decimal suffix in ascending order.
 ///
 /// for example, calling `generate_enumerated_idents("var_", 5)` will generate a vector with the
@@ -542,10 +619,10 @@ mod tests {
                 let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(&args_);
                 let var_0 = stream
                     .pop::<H256>()
-                    .expect("could not decode argument `contract_owner` as `H256`");
+                    .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `contract_owner` as `H256`"));
                 let var_1 = stream
                     .pop::<U256>()
-                    .expect("could not decode argument `total_supply` as `U256`");
+                    .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `total_supply` as `U256`"));
                 <Contract>::construct(var_0, var_1);
             }
         );
@@ -560,6 +637,236 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn migrate_generation() -> syn::Result<()> {
+        let input = quote!(
+            pub trait Erc20Interface {
+                fn construct(contract_owner: H256, total_supply: U256);
+                fn migrate(new_owner: H256);
+                fn total_supply() -> U256;
+            }
+        );
+
+        let expected_output = quote!(
+            #[no_mangle]
+            pub fn migrate() {
+                let args_ = args();
+                let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(&args_);
+                let var_0 = stream
+                    .pop::<H256>()
+                    .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `new_owner` as `H256`"));
+                <Contract>::migrate(var_0);
+            }
+        );
+
+        let signatures = syn::parse2::<PubInterfaceSignatures>(input)?;
+        let output = generate_migrate_function(&MIGRATE_FUNC_NAME.into_ident(), &signatures);
+        assert_eq!(
+            syn::parse2::<syn::ItemFn>(output)?,
+            syn::parse2::<syn::ItemFn>(expected_output)?,
+        );
+
+        // `migrate` only runs through its export.
+        let dispatch = generate_dispatch_function(&DISPATCH_FUNC_NAME.into_ident(), &signatures);
+        assert!(!dispatch.to_string().contains("\"migrate\""));
+
+        // without a `migrate` function there's no export at all.
+        let signatures = syn::parse2::<PubInterfaceSignatures>(quote!(pub trait Foo { fn construct(); }))?;
+        assert!(generate_migrate_function(&MIGRATE_FUNC_NAME.into_ident(), &signatures).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn authorize_upgrade_generation() -> syn::Result<()> {
+        let input = quote!(
+            pub trait Erc20Interface {
+                fn construct(contract_owner: H256, total_supply: U256);
+                fn authorize_upgrade(new_code_hash: H256, sender: Vec<u8>) -> Result<(), String>;
+            }
+        );
+
+        let expected_output = quote!(
+            #[no_mangle]
+            pub fn authorize_upgrade() {
+                let args_ = args();
+                let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(&args_);
+                let var_0 = stream
+                    .pop::<H256>()
+                    .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `new_code_hash` as `H256`"));
+                let var_1 = stream
+                    .pop::<Vec<u8>>()
+                    .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `sender` as `Vec < u8 >`"));
+                match <Contract>::authorize_upgrade(var_0, var_1) {
+                    Ok(result) => result,
+                    Err(error) => eng_wasm::revert(&eng_wasm::ToString::to_string(&error)),
+                };
+            }
+        );
+
+        let signatures = syn::parse2::<PubInterfaceSignatures>(input)?;
+        let output = generate_authorize_upgrade_function(&AUTHORIZE_UPGRADE_FUNC_NAME.into_ident(), &signatures);
+        assert_eq!(
+            syn::parse2::<syn::ItemFn>(output)?,
+            syn::parse2::<syn::ItemFn>(expected_output)?,
+        );
+
+        // it only runs through its export, so a task can't call it.
+        let dispatch = generate_dispatch_function(&DISPATCH_FUNC_NAME.into_ident(), &signatures);
+        assert!(!dispatch.to_string().contains("\"authorize_upgrade\""));
+        Ok(())
+    }
+
+    #[test]
+    fn result_dispatch_generation() -> syn::Result<()> {
+        let input = quote!(
+            pub trait Bank {
+                fn withdraw(amount: U256) -> Result<U256, String>;
+                fn close() -> Result<(), String>;
+            }
+        );
+
+        #[rustfmt::skip]
+        let expected_output = quote!(
+            pub fn dispatch(name: &str, args: &[u8]) {
+                match name {
+                    "withdraw" => {
+                        let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(args);
+                        let var_0 = stream
+                            .pop::<U256>()
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `amount` as `U256`"));
+                        let result = match <Contract>::withdraw(var_0) {
+                            Ok(result) => result,
+                            Err(error) => eng_wasm::revert(&eng_wasm::ToString::to_string(&error)),
+                        };
+                        let mut result_bytes = eng_wasm::Vec::with_capacity(1usize * 32);
+                        let mut sink = eng_wasm::eng_pwasm_abi::eth::Sink::new(1usize);
+                        sink.push(result);
+                        sink.drain_to(&mut result_bytes);
+                        unsafe {
+                            eng_wasm::external::ret(
+                                result_bytes.as_ptr(),
+                                result_bytes.len() as u32
+                            )
+                        }
+                    }
+                    "close" => {
+                        match <Contract>::close() {
+                            Ok(result) => result,
+                            Err(error) => eng_wasm::revert(&eng_wasm::ToString::to_string(&error)),
+                        };
+                    }
+                    _ => eng_wasm::revert(&eng_wasm::fmt::format(format_args!(
+                        "Unknown method called:\"{}\"",
+                        name
+                    ))),
+                }
+            }
+        );
+
+        let signatures = syn::parse2::<PubInterfaceSignatures>(input)?;
+        let output = generate_dispatch_function(&DISPATCH_FUNC_NAME.into_ident(), &signatures);
+        assert_eq!(
+            syn::parse2::<syn::ItemFn>(output)?,
+            syn::parse2::<syn::ItemFn>(expected_output)?,
+        );
+
+        let interface = abi_interface::generate_interface(&signatures)?;
+        assert_eq!(interface["functions"][0]["outputs"], serde_json::json!([{"type": "uint256"}]));
+        assert_eq!(interface["functions"][1]["outputs"], serde_json::json!([]));
+        Ok(())
+    }
+
+    #[test]
+    fn interface_generation() -> syn::Result<()> {
+        let input = quote!(
+            pub trait Erc20Interface {
+                fn construct(contract_owner: H256, total_supply: U256);
+                fn mint(owner: H256, addr: H256, tokens: U256, sig: Vec<u8>);
+                fn balance_of(token_owner: H256) -> U256;
+                fn holders() -> (Vec<H256>, Vec<String>);
+            }
+        );
+
+        let expected_interface = serde_json::json!({
+            "constructor": "construct",
+            "functions": [
+                {
+                    "name": "construct",
+                    "inputs": [{"name": "contract_owner", "type": "bytes32"}, {"name": "total_supply", "type": "uint256"}],
+                    "outputs": []
+                },
+                {
+                    "name": "mint",
+                    "inputs": [
+                        {"name": "owner", "type": "bytes32"},
+                        {"name": "addr", "type": "bytes32"},
+                        {"name": "tokens", "type": "uint256"},
+                        {"name": "sig", "type": "bytes"}
+                    ],
+                    "outputs": []
+                },
+                {
+                    "name": "balance_of",
+                    "inputs": [{"name": "token_owner", "type": "bytes32"}],
+                    "outputs": [{"type": "uint256"}]
+                },
+                {
+                    "name": "holders",
+                    "inputs": [],
+                    "outputs": [{"type": "bytes32[]"}, {"type": "string[]"}]
+                }
+            ]
+        });
+
+        let signatures = syn::parse2::<PubInterfaceSignatures>(input)?;
+        assert_eq!(abi_interface::generate_interface(&signatures)?, expected_interface);
+
+        let section = generate_interface_section(&signatures).to_string();
+        assert!(section.contains(abi_interface::INTERFACE_SECTION_NAME));
+        Ok(())
+    }
+
+    #[test]
+    fn interface_without_constructor() -> syn::Result<()> {
+        let signatures = syn::parse2::<PubInterfaceSignatures>(quote!(pub trait Foo { fn foo(x: u64) -> bool; }))?;
+        let interface = abi_interface::generate_interface(&signatures)?;
+        assert_eq!(interface["constructor"], serde_json::Value::Null);
+        assert_eq!(interface["functions"][0]["inputs"][0]["type"], "uint64");
+        assert_eq!(interface["functions"][0]["outputs"][0]["type"], "bool");
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_abi_type_error() -> syn::Result<()> {
+        let signatures = syn::parse2::<PubInterfaceSignatures>(quote!(pub trait Foo { fn foo(x: [u8; 4]); }))?;
+        let error = abi_interface::generate_interface(&signatures).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            ParseError::UnsupportedAbiType("[ u8 ; 4 ]".to_string()).to_string()
+        );
+        // the contract still compiles, only without the section.
+        assert!(generate_interface_section(&signatures).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn type_alias_without_interface() -> syn::Result<()> {
+        let input = quote!(
+            pub trait Erc20Interface {
+                fn balance_of(token_owner: H256) -> Balance;
+            }
+        );
+        let signatures = syn::parse2::<PubInterfaceSignatures>(input.clone())?;
+        assert!(abi_interface::generate_interface(&signatures).is_err());
+        assert!(generate_interface_section(&signatures).is_empty());
+
+        // the alias is decoded like the type it stands for, only the section is missing.
+        let output = impl_pub_interface(quote!(), input).to_string();
+        assert!(!output.contains("compile_error"));
+        assert!(!output.contains(abi_interface::INTERFACE_SECTION_NAME));
+        Ok(())
+    }
+
     #[test]
     fn dispatch_generation() -> syn::Result<()> {
         let input = quote!(
@@ -594,16 +901,16 @@ mod tests {
                         let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(args);
                         let var_0 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `owner` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `owner` as `H256`"));
                         let var_1 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `addr` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `addr` as `H256`"));
                         let var_2 = stream
                             .pop::<U256>()
-                            .expect("could not decode argument `tokens` as `U256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `tokens` as `U256`"));
                         let var_3 = stream
                             .pop::<Vec<u8>>()
-                            .expect("could not decode argument `sig` as `Vec < u8 >`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `sig` as `Vec < u8 >`"));
                         <Contract>::mint(var_0, var_1, var_2, var_3);
                     }
                     "total_supply" => {
@@ -623,7 +930,7 @@ mod tests {
                         let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(args);
                         let var_0 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `token_owner` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `token_owner` as `H256`"));
                         let result = <Contract>::balance_of(var_0);
                         let mut result_bytes = eng_wasm::Vec::with_capacity(1usize * 32);
                         let mut sink = eng_wasm::eng_pwasm_abi::eth::Sink::new(1usize);
@@ -640,10 +947,10 @@ mod tests {
                         let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(args);
                         let var_0 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `owner` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `owner` as `H256`"));
                         let var_1 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `spender` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `spender` as `H256`"));
                         let result = <Contract>::allowance(var_0, var_1);
                         let mut result_bytes = eng_wasm::Vec::with_capacity(1usize * 32);
                         let mut sink = eng_wasm::eng_pwasm_abi::eth::Sink::new(1usize);
@@ -660,54 +967,57 @@ mod tests {
                         let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(args);
                         let var_0 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `from` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `from` as `H256`"));
                         let var_1 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `to` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `to` as `H256`"));
                         let var_2 = stream
                             .pop::<U256>()
-                            .expect("could not decode argument `tokens` as `U256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `tokens` as `U256`"));
                         let var_3 = stream
                             .pop::<Vec<u8>>()
-                            .expect("could not decode argument `sig` as `Vec < u8 >`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `sig` as `Vec < u8 >`"));
                         <Contract>::transfer(var_0, var_1, var_2, var_3);
                     }
                     "approve" => {
                         let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(args);
                         let var_0 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `token_owner` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `token_owner` as `H256`"));
                         let var_1 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `spender` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `spender` as `H256`"));
                         let var_2 = stream
                             .pop::<U256>()
-                            .expect("could not decode argument `tokens` as `U256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `tokens` as `U256`"));
                         let var_3 = stream
                             .pop::<Vec<u8>>()
-                            .expect("could not decode argument `sig` as `Vec < u8 >`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `sig` as `Vec < u8 >`"));
                         <Contract>::approve(var_0, var_1, var_2, var_3);
                     }
                     "transfer_from" => {
                         let mut stream = eng_wasm::eng_pwasm_abi::eth::Stream::new(args);
                         let var_0 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `owner` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `owner` as `H256`"));
                         let var_1 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `spender` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `spender` as `H256`"));
                         let var_2 = stream
                             .pop::<H256>()
-                            .expect("could not decode argument `to` as `H256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `to` as `H256`"));
                         let var_3 = stream
                             .pop::<U256>()
-                            .expect("could not decode argument `tokens` as `U256`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `tokens` as `U256`"));
                         let var_4 = stream
                             .pop::<Vec<u8>>()
-                            .expect("could not decode argument `sig` as `Vec < u8 >`");
+                            .unwrap_or_else(|_| eng_wasm::revert("could not decode argument `sig` as `Vec < u8 >`"));
                         <Contract>::transfer_from(var_0, var_1, var_2, var_3, var_4);
                     }
-                    _ => panic!("Unknown method called:\"{}\"", name),
+                    _ => eng_wasm::revert(&eng_wasm::fmt::format(format_args!(
+                        "Unknown method called:\"{}\"",
+                        name
+                    ))),
                 }
             }
         );
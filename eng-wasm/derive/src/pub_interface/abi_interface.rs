@@ -0,0 +1,135 @@
+//! This module generates the machine readable description of the exported functions.
+//!
+//! The description is JSON, and it's embedded in the contract as the `enigma_interface` custom wasm section,
+//! so clients can check their callables and arguments against the code they're sending a task to.
+//! e.g. for `fn construct(owner: H256)` and `fn balance_of(owner: H256) -> U256`:
+//! ```json
+//! {
+//!     "constructor": "construct",
+//!     "functions": [
+//!         {"name": "construct", "inputs": [{"name": "owner", "type": "bytes32"}], "outputs": []},
+//!         {"name": "balance_of", "inputs": [{"name": "owner", "type": "bytes32"}], "outputs": [{"type": "uint256"}]}
+//!     ]
+//! }
+//! ```
+//! `functions` lists every exported function, including the constructor, `migrate` and `authorize_upgrade`,
+//! which only deployments and upgrades run, so a compute task can't call them.
+//!
+//! The ABI types are known only for the types eng-wasm decodes (e.g. `U256`, `H256`, `Vec<T>`) by their names,
+//! so a contract with any other type in a signature, a type alias included, gets no section at all.
+
+use quote::{quote, ToTokens};
+use serde_json::{json, Value};
+
+use super::parse_signatures::{return_value_types, ParseError, PubInterfaceSignatures};
+use super::{get_signature_input_pats_and_types, CONSTRUCTOR_NAME};
+
+/// The name of the custom wasm section the interface is embedded in.
+pub(crate) const INTERFACE_SECTION_NAME: &str = "enigma_interface";
+
+const INTERFACE_STATIC_NAME: &str = "__ENIGMA_INTERFACE";
+
+/// Generate a static holding the interface JSON in the `enigma_interface` custom section.
+/// The section only exists in wasm builds, and it's left out if a type in the signatures has no known ABI type.
+pub(crate) fn generate_interface_section(signatures: &PubInterfaceSignatures) -> proc_macro2::TokenStream {
+    let interface = match generate_interface(signatures) {
+        Ok(interface) => interface.to_string(),
+        Err(_) => return proc_macro2::TokenStream::new(),
+    };
+    let static_name = quote::format_ident!("{}", INTERFACE_STATIC_NAME);
+    let length = interface.len();
+    let bytes = syn::LitByteStr::new(interface.as_bytes(), proc_macro2::Span::call_site());
+
+    quote! {
+        #[cfg_attr(target_arch = "wasm32", link_section = #INTERFACE_SECTION_NAME)]
+        #[doc(hidden)]
+        pub static #static_name: [u8; #length] = *#bytes;
+    }
+}
+
+/// Describe every exported function with the ABI types of its arguments and return values.
+pub(crate) fn generate_interface(signatures: &PubInterfaceSignatures) -> syn::Result<Value> {
+    let functions = signatures
+        .signatures
+        .iter()
+        .map(describe_function)
+        .collect::<syn::Result<Vec<Value>>>()?;
+    let constructor = signatures
+        .signatures
+        .iter()
+        .find(|signature| signature.ident == CONSTRUCTOR_NAME)
+        .map(|signature| signature.ident.to_string());
+
+    Ok(json!({ "constructor": constructor, "functions": functions }))
+}
+
+fn describe_function(signature: &syn::Signature) -> syn::Result<Value> {
+    let inputs = get_signature_input_pats_and_types(signature)
+        .into_iter()
+        .map(|(pat, type_)| {
+            let name = match pat {
+                syn::Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+                pat => pat.to_token_stream().to_string(),
+            };
+            Ok(json!({ "name": name, "type": abi_type(type_)? }))
+        })
+        .collect::<syn::Result<Vec<Value>>>()?;
+
+    // A tuple is returned as multiple values and a `Result` as its `Ok` value, just like in the dispatch function.
+    let output_types = return_value_types(signature);
+    let outputs = output_types
+        .into_iter()
+        .map(|type_| Ok(json!({ "type": abi_type(type_)? })))
+        .collect::<syn::Result<Vec<Value>>>()?;
+
+    Ok(json!({ "name": signature.ident.to_string(), "inputs": inputs, "outputs": outputs }))
+}
+
+/// The ABI type of a Rust type, as it's written in the function signatures (e.g. `uint256` for `U256`).
+fn abi_type(type_: &syn::Type) -> syn::Result<String> {
+    let unsupported = || {
+        let type_name = type_.to_token_stream().to_string();
+        syn::Error::new_spanned(type_, ParseError::UnsupportedAbiType(type_name))
+    };
+
+    let segment = match type_ {
+        syn::Type::Paren(paren) => return abi_type(&paren.elem),
+        syn::Type::Group(group) => return abi_type(&group.elem),
+        syn::Type::Path(path) if path.qself.is_none() => {
+            path.path.segments.last().ok_or_else(unsupported)?
+        }
+        _ => return Err(unsupported()),
+    };
+
+    match &segment.arguments {
+        syn::PathArguments::None => {
+            let abi_type = match segment.ident.to_string().as_str() {
+                "U256" => "uint256",
+                "H256" => "bytes32",
+                "H160" | "Address" => "address",
+                "u32" => "uint32",
+                "u64" => "uint64",
+                "i32" => "int32",
+                "i64" => "int64",
+                "bool" => "bool",
+                "String" => "string",
+                _ => return Err(unsupported()),
+            };
+            Ok(abi_type.to_string())
+        }
+        syn::PathArguments::AngleBracketed(arguments)
+            if segment.ident == "Vec" && arguments.args.len() == 1 =>
+        {
+            match arguments.args.first() {
+                Some(syn::GenericArgument::Type(syn::Type::Path(inner)))
+                    if inner.path.is_ident("u8") =>
+                {
+                    Ok("bytes".to_string())
+                }
+                Some(syn::GenericArgument::Type(inner)) => Ok(format!("{}[]", abi_type(inner)?)),
+                _ => Err(unsupported()),
+            }
+        }
+        _ => Err(unsupported()),
+    }
+}
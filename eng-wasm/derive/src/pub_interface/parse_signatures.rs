@@ -8,7 +8,7 @@ use parse_display::Display;
 use crate::into_ident::IntoIdent;
 use crate::reduce_mut::ReduceMut;
 
-use crate::pub_interface::CONSTRUCTOR_NAME;
+use crate::pub_interface::{AUTHORIZE_UPGRADE_NAME, CONSTRUCTOR_NAME, MIGRATE_NAME};
 
 /// Failures that can happen while parsing the macro input.
 ///
@@ -27,6 +27,18 @@ pub(crate) enum ParseError {
     #[display("The constructor function of a secret contract should not have a return value")]
     ConstructorWithReturnValue,
 
+    #[display("The migrate function of a secret contract should be `pub`")]
+    PrivateImplMigrate,
+
+    #[display("The migrate function of a secret contract should not have a return value")]
+    MigrateWithReturnValue,
+
+    #[display("The authorize_upgrade function of a secret contract should be `pub`")]
+    PrivateImplAuthorizeUpgrade,
+
+    #[display("The authorize_upgrade function of a secret contract should not have a return value")]
+    AuthorizeUpgradeWithReturnValue,
+
     #[display("pub_interface item can not have methods that receive `self`")]
     MethodWithReceiver,
 
@@ -50,6 +62,9 @@ pub(crate) enum ParseError {
 
     #[display("custom implementors are not supported when pub_interface is applied to `impl`s")]
     CustomImplementorOnImpl,
+
+    #[display("`{0}` has no ABI type, exported functions can use U256, H256, H160, u32, u64, i32, i64, bool, String and Vec of them")]
+    UnsupportedAbiType(String),
 }
 
 /// This enum is used to present the result of the speculative parsing inside
@@ -192,7 +207,7 @@ fn get_trait_item_as_method(trait_item: syn::TraitItem) -> Option<syn::TraitItem
 /// * it has no additional attributes on it (except doc comments)
 /// * it has no `self` receiver
 /// * it has no default implementation
-/// * if it's the constructor function, we make sure it returns nothing.
+/// * if it's the constructor, the migrate or the authorize_upgrade function, we make sure it returns nothing.
 fn check_trait_method(
     trait_method: syn::TraitItemMethod,
 ) -> Result<syn::TraitItemMethod, Vec<syn::Error>> {
@@ -229,6 +244,20 @@ fn check_trait_method(
         ))
     }
 
+    if trait_method.sig.ident == MIGRATE_NAME && signature_has_return_value(&trait_method.sig) {
+        errors.push(syn::Error::new_spanned(
+            trait_method.sig.output.clone(),
+            ParseError::MigrateWithReturnValue,
+        ))
+    }
+
+    if trait_method.sig.ident == AUTHORIZE_UPGRADE_NAME && signature_has_return_value(&trait_method.sig) {
+        errors.push(syn::Error::new_spanned(
+            trait_method.sig.output.clone(),
+            ParseError::AuthorizeUpgradeWithReturnValue,
+        ))
+    }
+
     if trait_method.default.is_some() {
         errors.push(syn::Error::new_spanned(
             trait_method.default.clone(),
@@ -319,8 +348,8 @@ fn is_impl_item_method_private(method: &syn::ImplItemMethod) -> bool {
 /// Check if the private impl-method is defined the way we want it.
 ///
 /// Specifically we check that:
-/// * it is not named like the constructor.
-/// * if it's named like a constructor, check that it looks like a valid constructor.
+/// * it is not named like the constructor, the migrate or the authorize_upgrade function.
+/// * if it's named like one of them, check that it looks like a valid one.
 fn check_impl_priv_method(
     impl_method: syn::ImplItemMethod,
 ) -> Result<syn::ImplItemMethod, Vec<syn::Error>> {
@@ -340,6 +369,34 @@ fn check_impl_priv_method(
         }
     }
 
+    if impl_method.sig.ident == MIGRATE_NAME {
+        errors.push(syn::Error::new_spanned(
+            impl_method.sig.clone(),
+            ParseError::PrivateImplMigrate,
+        ));
+
+        if signature_has_return_value(&impl_method.sig) {
+            errors.push(syn::Error::new_spanned(
+                impl_method.sig.output.clone(),
+                ParseError::MigrateWithReturnValue,
+            ))
+        }
+    }
+
+    if impl_method.sig.ident == AUTHORIZE_UPGRADE_NAME {
+        errors.push(syn::Error::new_spanned(
+            impl_method.sig.clone(),
+            ParseError::PrivateImplAuthorizeUpgrade,
+        ));
+
+        if signature_has_return_value(&impl_method.sig) {
+            errors.push(syn::Error::new_spanned(
+                impl_method.sig.output.clone(),
+                ParseError::AuthorizeUpgradeWithReturnValue,
+            ))
+        }
+    }
+
     if errors.is_empty() {
         Ok(impl_method)
     } else {
@@ -393,6 +450,20 @@ fn check_impl_non_priv_method(
         ))
     }
 
+    if impl_method.sig.ident == MIGRATE_NAME && signature_has_return_value(&impl_method.sig) {
+        errors.push(syn::Error::new_spanned(
+            impl_method.sig.output.clone(),
+            ParseError::MigrateWithReturnValue,
+        ))
+    }
+
+    if impl_method.sig.ident == AUTHORIZE_UPGRADE_NAME && signature_has_return_value(&impl_method.sig) {
+        errors.push(syn::Error::new_spanned(
+            impl_method.sig.output.clone(),
+            ParseError::AuthorizeUpgradeWithReturnValue,
+        ))
+    }
+
     if errors.is_empty() {
         Ok(impl_method)
     } else {
@@ -404,16 +475,44 @@ fn check_impl_non_priv_method(
 ///
 /// This has two syntactic forms, either the function specifies no return type at all,
 /// or explicitly states that it returns an empty tuple: `-> ()`.
+/// A function returning `Result<(), E>` has no return value either, the `Err` fails the task.
 fn signature_has_return_value(signature: &syn::Signature) -> bool {
-    match &signature.output {
-        syn::ReturnType::Default => false, // No return type specified
-        syn::ReturnType::Type(_, type_) => match type_.as_ref() {
-            syn::Type::Tuple(tuple) => match tuple.elems.len() {
-                0 => false, // return type is ()
-                _ => true,  // return type is some other tuple
-            },
-            _ => true, // return type is any other explicit type
-        },
+    !return_value_types(signature).is_empty()
+}
+
+/// The types of the values a function returns to the user.
+///
+/// A tuple is returned as multiple values, and a `Result<T, E>` returns the values of `T`.
+pub(crate) fn return_value_types(signature: &syn::Signature) -> Vec<&syn::Type> {
+    let type_ = match &signature.output {
+        syn::ReturnType::Default => return Vec::new(),
+        syn::ReturnType::Type(_, type_) => type_.as_ref(),
+    };
+    let type_ = result_types(type_).map_or(type_, |(ok_type, _err_type)| ok_type);
+    match type_ {
+        syn::Type::Tuple(tuple) => tuple.elems.iter().collect(),
+        _ => vec![type_],
+    }
+}
+
+/// The `T` and `E` of a `Result<T, E>`, or `None` if the type isn't a `Result`.
+pub(crate) fn result_types(type_: &syn::Type) -> Option<(&syn::Type, &syn::Type)> {
+    let segment = match type_ {
+        syn::Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(arguments) if segment.ident == "Result" => {
+            let mut types = arguments.args.iter().map(|argument| match argument {
+                syn::GenericArgument::Type(type_) => Some(type_),
+                _ => None,
+            });
+            match (types.next(), types.next(), types.next()) {
+                (Some(Some(ok_type)), Some(Some(err_type)), None) => Some((ok_type, err_type)),
+                _ => None,
+            }
+        }
+        _ => None,
     }
 }
 
@@ -563,6 +662,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn constructor_in_trait_with_unit_result_return_type() -> syn::Result<()> {
+        let tokens = quote!(
+            trait Foo {
+                fn construct() -> Result<(), String>;
+            }
+        );
+
+        let signatures = syn::parse2::<PubInterfaceSignatures>(tokens)?;
+
+        assert_eq!(
+            signatures.signatures,
+            vec![signature_of!(fn construct() -> Result<(), String>;)],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn constructor_in_trait_with_result_return_type_error() {
+        let tokens = quote!(
+            trait Foo {
+                fn construct() -> Result<U256, String>;
+            }
+        );
+
+        let parse_errors = syn::parse2::<PubInterfaceSignatures>(tokens)
+            .err()
+            .expect("This macro should not accept constructors with return types");
+
+        assert_eq!(
+            vec![ParseError::ConstructorWithReturnValue.to_string(); 1],
+            parse_errors
+                .into_iter()
+                .map(|parse_error| parse_error.to_string())
+                .collect::<Vec<_>>(),
+        );
+    }
+
     #[test]
     fn constructor_in_trait_with_return_type_error() {
         let tokens = quote!(
@@ -657,6 +794,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn migrate_in_trait_with_return_type_error() {
+        let tokens = quote!(
+            trait Foo {
+                fn migrate(version: u32) -> u32;
+            }
+        );
+
+        let parse_errors = syn::parse2::<PubInterfaceSignatures>(tokens)
+            .err()
+            .expect("This macro should not accept migrate functions with return types");
+
+        assert_eq!(
+            vec![ParseError::MigrateWithReturnValue.to_string(); 1],
+            parse_errors
+                .into_iter()
+                .map(|parse_error| parse_error.to_string())
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn private_migrate_in_impl_error() {
+        let tokens = quote!(
+            impl Foo {
+                fn migrate() {}
+            }
+        );
+
+        let parse_errors = syn::parse2::<PubInterfaceSignatures>(tokens)
+            .err()
+            .expect("This macro should not accept migrate functions without pub");
+
+        assert_eq!(
+            vec![ParseError::PrivateImplMigrate.to_string(); 1],
+            parse_errors
+                .into_iter()
+                .map(|parse_error| parse_error.to_string())
+                .collect::<Vec<_>>(),
+        );
+    }
+
     #[test]
     fn trait_methods_with_attributes_errors() {
         let tokens = quote!(
@@ -0,0 +1,208 @@
+//! A compact binary encoding for the values in the state, in the spirit of Borsh.
+//!
+//! Integers are fixed size little endian, strings and vectors are prefixed with their length as a `u32`,
+//! `H256`/`H160` are their bytes, and a `U256` is the number of its significant bytes followed by them in big endian,
+//! so an amount of tokens takes a few bytes instead of a JSON hex string.
+//! The values are written with `write_binary` and stored as is, the runtime doesn't parse them.
+
+use eng_pwasm_abi::types::{H160, H256, U256};
+use std::mem;
+use std::string::String;
+use std::vec::Vec;
+
+/// A type that can be stored in the state in the binary encoding.
+pub trait BinaryCodec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    /// Decodes a value from the start of `input` and advances it past the value, `None` if the input is malformed.
+    fn decode(input: &mut &[u8]) -> Option<Self>;
+}
+
+/// Encodes a value in the binary encoding.
+pub fn encode<T: BinaryCodec>(value: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    value.encode(&mut out);
+    out
+}
+
+/// Decodes a value from the binary encoding, `None` if the input is malformed or has trailing bytes.
+pub fn decode<T: BinaryCodec>(mut input: &[u8]) -> Option<T> {
+    let value = T::decode(&mut input)?;
+    if input.is_empty() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Some(taken)
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) { (len as u32).encode(out) }
+
+fn decode_len(input: &mut &[u8]) -> Option<usize> { u32::decode(input).map(|len| len as usize) }
+
+macro_rules! impl_binary_codec_for_int {
+    ( $($int: ty),* ) => {
+        $(
+            impl BinaryCodec for $int {
+                fn encode(&self, out: &mut Vec<u8>) { out.extend_from_slice(&self.to_le_bytes()) }
+
+                fn decode(input: &mut &[u8]) -> Option<Self> {
+                    let mut bytes = [0u8; mem::size_of::<$int>()];
+                    bytes.copy_from_slice(take(input, bytes.len())?);
+                    Some(<$int>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_codec_for_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl BinaryCodec for bool {
+    fn encode(&self, out: &mut Vec<u8>) { out.push(*self as u8) }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl BinaryCodec for U256 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let mut bytes = [0u8; 32];
+        self.to_big_endian(&mut bytes);
+        let significant = (self.bits() + 7) / 8;
+        out.push(significant as u8);
+        out.extend_from_slice(&bytes[32 - significant..]);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = u8::decode(input)? as usize;
+        if len > 32 {
+            return None;
+        }
+        Some(U256::from_big_endian(take(input, len)?))
+    }
+}
+
+impl BinaryCodec for H256 {
+    fn encode(&self, out: &mut Vec<u8>) { out.extend_from_slice(&self[..]) }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> { Some(H256::from_slice(take(input, 32)?)) }
+}
+
+impl BinaryCodec for H160 {
+    fn encode(&self, out: &mut Vec<u8>) { out.extend_from_slice(&self[..]) }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> { Some(H160::from_slice(take(input, 20)?)) }
+}
+
+impl BinaryCodec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = decode_len(input)?;
+        String::from_utf8(take(input, len)?.to_vec()).ok()
+    }
+}
+
+impl<T: BinaryCodec> BinaryCodec for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = decode_len(input)?;
+        // every item takes at least a byte, so a malformed length can't allocate more than the input.
+        if len > input.len() {
+            return None;
+        }
+        (0..len).map(|_| T::decode(input)).collect()
+    }
+}
+
+impl<T: BinaryCodec> BinaryCodec for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(None),
+            1 => T::decode(input).map(Some),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_binary_codec_for_tuple {
+    ( $($name: ident),+ ) => {
+        impl<$($name: BinaryCodec),+> BinaryCodec for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode(&self, out: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode(out);)+
+            }
+
+            fn decode(input: &mut &[u8]) -> Option<Self> {
+                Some(($($name::decode(input)?,)+))
+            }
+        }
+    };
+}
+
+impl_binary_codec_for_tuple!(A, B);
+impl_binary_codec_for_tuple!(A, B, C);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_u256_is_compact() {
+        assert_eq!(encode(&U256::zero()), vec![0]);
+        assert_eq!(encode(&U256::from(1000)), vec![2, 0x03, 0xe8]);
+        let max = encode(&U256::max_value());
+        assert_eq!(max.len(), 33);
+        assert_eq!(decode::<U256>(&max), Some(U256::max_value()));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let value = (vec![String::from("enigma"), String::new()], Some(H256::from_slice(&[7u8; 32])), -5i64);
+        assert_eq!(decode(&encode(&value)), Some(value));
+        let bytes = vec![1u8, 2, 3];
+        assert_eq!(encode(&bytes), vec![3, 0, 0, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(decode::<u32>(&[1, 2, 3]), None);
+        assert_eq!(decode::<u8>(&[1, 2]), None);
+        assert_eq!(decode::<bool>(&[2]), None);
+        assert_eq!(decode::<Vec<u8>>(&[0xff, 0xff, 0xff, 0xff, 1]), None);
+        assert_eq!(decode::<U256>(&[33]), None);
+    }
+}
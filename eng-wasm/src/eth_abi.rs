@@ -0,0 +1,560 @@
+//! Encoding and decoding of the Ethereum contract ABI, for the code that `eth_contract` generates.
+//!
+//! The values are `Token`s and the types are `ParamType`s. Decoding is strict: the padding, the widths
+//! of the integers, the lengths and the offsets are all checked, and nothing can follow the values,
+//! so the data passed to a secret contract as args (e.g. the log of an event which is proven on Ethereum)
+//! decodes only if it's exactly what `encode` produces for the same values, like Ethereum does.
+
+use eng_pwasm_abi::types::{Address, H256, U256};
+use std::boxed::Box;
+use std::fmt;
+use std::string::String;
+use std::vec::Vec;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParamType {
+    Address,
+    Bytes,
+    /// A signed integer of the given number of bits.
+    Int(usize),
+    /// An unsigned integer of the given number of bits.
+    Uint(usize),
+    Bool,
+    String,
+    Array(Box<ParamType>),
+    FixedBytes(usize),
+    FixedArray(Box<ParamType>, usize),
+    Tuple(Vec<ParamType>),
+}
+
+impl ParamType {
+    pub fn array(param: ParamType) -> ParamType { ParamType::Array(Box::new(param)) }
+
+    pub fn fixed_array(param: ParamType, len: usize) -> ParamType { ParamType::FixedArray(Box::new(param), len) }
+
+    pub fn tuple(params: &[ParamType]) -> ParamType { ParamType::Tuple(params.to_vec()) }
+
+    /// Whether the value is encoded in the tail and referenced by an offset.
+    pub fn is_dynamic(&self) -> bool {
+        match self {
+            ParamType::Bytes | ParamType::String | ParamType::Array(_) => true,
+            ParamType::FixedArray(param, _) => param.is_dynamic(),
+            ParamType::Tuple(params) => params.iter().any(ParamType::is_dynamic),
+            _ => false,
+        }
+    }
+
+    /// Whether the value is stored as is in a topic when it's an indexed argument of an event,
+    /// the topic of any other type is the hash of the value.
+    pub fn is_value_type(&self) -> bool {
+        match self {
+            ParamType::Address | ParamType::Int(_) | ParamType::Uint(_) | ParamType::Bool | ParamType::FixedBytes(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The size of the value in the head of a sequence.
+    fn head_len(&self) -> usize {
+        match self {
+            _ if self.is_dynamic() => 32,
+            ParamType::FixedArray(param, len) => param.head_len() * len,
+            ParamType::Tuple(params) => params.iter().map(ParamType::head_len).sum(),
+            _ => 32,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Token {
+    Address(Address),
+    FixedBytes(Vec<u8>),
+    Bytes(Vec<u8>),
+    /// The two's complement of the integer.
+    Int(U256),
+    Uint(U256),
+    Bool(bool),
+    String(String),
+    FixedArray(Vec<Token>),
+    Array(Vec<Token>),
+    Tuple(Vec<Token>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Error {
+    /// The data isn't a valid encoding of the types.
+    InvalidData,
+    /// The topics aren't the topics of the event.
+    InvalidTopics,
+    /// The token doesn't match the type it's encoded as.
+    TypeMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidData => write!(f, "the data isn't a valid ABI encoding"),
+            Error::InvalidTopics => write!(f, "the topics don't match the event"),
+            Error::TypeMismatch => write!(f, "the value doesn't match its ABI type"),
+        }
+    }
+}
+
+impl Token {
+    pub fn into_address(self) -> Result<Address, Error> {
+        match self {
+            Token::Address(address) => Ok(address),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    pub fn into_fixed_bytes(self) -> Result<Vec<u8>, Error> {
+        match self {
+            Token::FixedBytes(bytes) => Ok(bytes),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    pub fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        match self {
+            Token::Bytes(bytes) => Ok(bytes),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    pub fn into_int(self) -> Result<U256, Error> {
+        match self {
+            Token::Int(int) => Ok(int),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    pub fn into_uint(self) -> Result<U256, Error> {
+        match self {
+            Token::Uint(uint) => Ok(uint),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    pub fn into_bool(self) -> Result<bool, Error> {
+        match self {
+            Token::Bool(b) => Ok(b),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    pub fn into_string(self) -> Result<String, Error> {
+        match self {
+            Token::String(s) => Ok(s),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    /// The items of an array or of a fixed array.
+    pub fn into_array(self) -> Result<Vec<Token>, Error> {
+        match self {
+            Token::Array(items) | Token::FixedArray(items) => Ok(items),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    pub fn into_tuple(self) -> Result<Vec<Token>, Error> {
+        match self {
+            Token::Tuple(items) => Ok(items),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+
+    /// Whether the token is a value of `param`, including the widths of the integers and the lengths.
+    pub fn type_check(&self, param: &ParamType) -> bool {
+        match (param, self) {
+            (ParamType::Address, Token::Address(_)) | (ParamType::Bool, Token::Bool(_)) => true,
+            (ParamType::Bytes, Token::Bytes(_)) | (ParamType::String, Token::String(_)) => true,
+            (ParamType::Uint(bits), Token::Uint(uint)) => uint.bits() <= *bits,
+            (ParamType::Int(bits), Token::Int(int)) => fits_signed(int, *bits),
+            (ParamType::FixedBytes(len), Token::FixedBytes(bytes)) => bytes.len() == *len,
+            (ParamType::Array(param), Token::Array(items)) => items.iter().all(|item| item.type_check(param)),
+            (ParamType::FixedArray(param, len), Token::FixedArray(items)) => {
+                items.len() == *len && items.iter().all(|item| item.type_check(param))
+            }
+            (ParamType::Tuple(params), Token::Tuple(items)) => {
+                items.len() == params.len() && items.iter().zip(params).all(|(item, param)| item.type_check(param))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether the two's complement `int` is an integer of `bits` bits, i.e. all the bits above its sign bit are equal to it.
+fn fits_signed(int: &U256, bits: usize) -> bool {
+    if bits == 0 || bits > 256 {
+        return false;
+    }
+    let high = *int >> (bits - 1);
+    high.is_zero() || high == U256::max_value() >> (bits - 1)
+}
+
+/// The two's complement of `value`, for `Token::Int`.
+pub fn int_to_u256(value: i128) -> U256 {
+    if value < 0 {
+        !uint_to_u256(!value as u128)
+    } else {
+        uint_to_u256(value as u128)
+    }
+}
+
+pub fn uint_to_u256(value: u128) -> U256 { (U256::from((value >> 64) as u64) << 64) | U256::from(value as u64) }
+
+/// The low 128 bits of `value`, an int of up to 128 bits is its low bits `as i128`.
+pub fn low_u128(value: &U256) -> u128 { ((*value >> 64).low_u64() as u128) << 64 | value.low_u64() as u128 }
+
+/// Encodes the tokens as the arguments of a function, after checking they are values of the types.
+pub fn encode(types: &[ParamType], tokens: &[Token]) -> Result<Vec<u8>, Error> {
+    if types.len() != tokens.len() || !tokens.iter().zip(types).all(|(token, param)| token.type_check(param)) {
+        return Err(Error::TypeMismatch);
+    }
+    let mut out = Vec::new();
+    encode_sequence(&types.iter().zip(tokens).collect::<Vec<_>>(), &mut out);
+    Ok(out)
+}
+
+fn encode_sequence(values: &[(&ParamType, &Token)], out: &mut Vec<u8>) {
+    let head_len: usize = values.iter().map(|(param, _)| param.head_len()).sum();
+    let mut tail = Vec::new();
+    for (param, token) in values {
+        if param.is_dynamic() {
+            push_word(out, U256::from((head_len + tail.len()) as u64));
+            encode_value(param, token, &mut tail);
+        } else {
+            encode_value(param, token, out);
+        }
+    }
+    out.extend_from_slice(&tail);
+}
+
+fn encode_value(param: &ParamType, token: &Token, out: &mut Vec<u8>) {
+    match (param, token) {
+        (ParamType::Address, Token::Address(address)) => {
+            out.extend_from_slice(&[0u8; 12]);
+            out.extend_from_slice(&address[..]);
+        }
+        (ParamType::Uint(_), Token::Uint(value)) | (ParamType::Int(_), Token::Int(value)) => push_word(out, *value),
+        (ParamType::Bool, Token::Bool(b)) => push_word(out, U256::from(*b as u64)),
+        (ParamType::FixedBytes(_), Token::FixedBytes(bytes)) => push_padded(out, bytes),
+        (ParamType::Bytes, Token::Bytes(bytes)) => {
+            push_word(out, U256::from(bytes.len() as u64));
+            push_padded(out, bytes);
+        }
+        (ParamType::String, Token::String(s)) => {
+            push_word(out, U256::from(s.len() as u64));
+            push_padded(out, s.as_bytes());
+        }
+        (ParamType::Array(param), Token::Array(items)) => {
+            push_word(out, U256::from(items.len() as u64));
+            encode_sequence(&items.iter().map(|item| (&**param, item)).collect::<Vec<_>>(), out);
+        }
+        (ParamType::FixedArray(param, _), Token::FixedArray(items)) => {
+            encode_sequence(&items.iter().map(|item| (&**param, item)).collect::<Vec<_>>(), out);
+        }
+        (ParamType::Tuple(params), Token::Tuple(items)) => {
+            encode_sequence(&params.iter().zip(items).collect::<Vec<_>>(), out);
+        }
+        _ => unreachable!("the tokens are type checked before they are encoded"),
+    }
+}
+
+fn push_word(out: &mut Vec<u8>, value: U256) {
+    let mut word = [0u8; 32];
+    value.to_big_endian(&mut word);
+    out.extend_from_slice(&word);
+}
+
+/// Pushes the bytes padded with zeros on the right to a multiple of 32 bytes.
+fn push_padded(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(bytes);
+    let padding = (32 - bytes.len() % 32) % 32;
+    out.extend_from_slice(&[0u8; 32][..padding]);
+}
+
+/// The most tokens that decoding any data can produce, the items of nested arrays included.
+const MAX_DECODED_TOKENS: usize = 1 << 16;
+
+/// Decodes data which was encoded as the arguments of a function, e.g. the return data of a function or the data of an event.
+/// Nothing can follow the encoded values.
+pub fn decode(types: &[ParamType], data: &[u8]) -> Result<Vec<Token>, Error> {
+    let mut tokens_left = MAX_DECODED_TOKENS;
+    let (tokens, end) = decode_sequence(&types.iter().collect::<Vec<_>>(), data, 0, &mut tokens_left)?;
+    if end != data.len() {
+        return Err(Error::InvalidData);
+    }
+    Ok(tokens)
+}
+
+/// Decodes an indexed argument of an event from its topic, the type must be a value type.
+pub fn decode_topic(param: &ParamType, topic: &H256) -> Result<Token, Error> {
+    if !param.is_value_type() {
+        return Err(Error::TypeMismatch);
+    }
+    decode_value(param, &topic[..], 0, &mut 1).map(|(token, _)| token)
+}
+
+/// Decodes the values of the types which are encoded as a sequence starting at `base`, and returns where the sequence ends.
+/// The offsets of the dynamic values are relative to `base`, and every one of them has to point right after
+/// the previous dynamic value, like `encode_sequence` lays them out, so no two values share the same data.
+fn decode_sequence(types: &[&ParamType], data: &[u8], base: usize, tokens_left: &mut usize) -> Result<(Vec<Token>, usize), Error> {
+    let head_len: usize = types.iter().map(|param| param.head_len()).sum();
+    let mut tail = base.checked_add(head_len).ok_or(Error::InvalidData)?;
+    let mut tokens = Vec::with_capacity(types.len());
+    let mut head = base;
+    for param in types {
+        if param.is_dynamic() {
+            if base.checked_add(read_len(data, head)?) != Some(tail) {
+                return Err(Error::InvalidData);
+            }
+            let (token, end) = decode_value(param, data, tail, tokens_left)?;
+            tokens.push(token);
+            tail = end;
+        } else {
+            tokens.push(decode_value(param, data, head, tokens_left)?.0);
+        }
+        head += param.head_len();
+    }
+    Ok((tokens, tail))
+}
+
+/// Decodes the value at `at`, and returns where it ends.
+fn decode_value(param: &ParamType, data: &[u8], at: usize, tokens_left: &mut usize) -> Result<(Token, usize), Error> {
+    *tokens_left = tokens_left.checked_sub(1).ok_or(Error::InvalidData)?;
+    let token = match param {
+        ParamType::Address => {
+            let word = read_word(data, at)?;
+            if word[..12].iter().any(|&b| b != 0) {
+                return Err(Error::InvalidData);
+            }
+            Token::Address(Address::from_slice(&word[12..]))
+        }
+        ParamType::Uint(bits) => {
+            let value = U256::from_big_endian(read_word(data, at)?);
+            if value.bits() > *bits {
+                return Err(Error::InvalidData);
+            }
+            Token::Uint(value)
+        }
+        ParamType::Int(bits) => {
+            let value = U256::from_big_endian(read_word(data, at)?);
+            if !fits_signed(&value, *bits) {
+                return Err(Error::InvalidData);
+            }
+            Token::Int(value)
+        }
+        ParamType::Bool => match U256::from_big_endian(read_word(data, at)?) {
+            value if value.is_zero() => Token::Bool(false),
+            value if value == U256::one() => Token::Bool(true),
+            _ => return Err(Error::InvalidData),
+        },
+        ParamType::FixedBytes(len) => {
+            let word = read_word(data, at)?;
+            if *len > 32 || word[*len..].iter().any(|&b| b != 0) {
+                return Err(Error::InvalidData);
+            }
+            Token::FixedBytes(word[..*len].to_vec())
+        }
+        ParamType::Bytes => return read_bytes(data, at).map(|(bytes, end)| (Token::Bytes(bytes.to_vec()), end)),
+        ParamType::String => {
+            let (bytes, end) = read_bytes(data, at)?;
+            let string = String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidData)?;
+            return Ok((Token::String(string), end));
+        }
+        ParamType::Array(param) => {
+            let len = read_len(data, at)?;
+            // every item takes at least a word, so a malformed length can't allocate more than the data.
+            if len > data.len() / 32 || len > *tokens_left {
+                return Err(Error::InvalidData);
+            }
+            let (items, end) = decode_sequence(&vec![&**param; len], data, at + 32, tokens_left)?;
+            return Ok((Token::Array(items), end));
+        }
+        ParamType::FixedArray(param, len) => {
+            let (items, end) = decode_sequence(&vec![&**param; *len], data, at, tokens_left)?;
+            return Ok((Token::FixedArray(items), end));
+        }
+        ParamType::Tuple(params) => {
+            let (items, end) = decode_sequence(&params.iter().collect::<Vec<_>>(), data, at, tokens_left)?;
+            return Ok((Token::Tuple(items), end));
+        }
+    };
+    // the rest are a single word.
+    Ok((token, at + 32))
+}
+
+fn read_word(data: &[u8], at: usize) -> Result<&[u8], Error> {
+    let end = at.checked_add(32).ok_or(Error::InvalidData)?;
+    data.get(at..end).ok_or(Error::InvalidData)
+}
+
+/// Reads a word which is a length or an offset.
+fn read_len(data: &[u8], at: usize) -> Result<usize, Error> {
+    let value = U256::from_big_endian(read_word(data, at)?);
+    if value.bits() > 32 {
+        return Err(Error::InvalidData);
+    }
+    Ok(value.low_u64() as usize)
+}
+
+/// Reads bytes which are prefixed by their length and padded with zeros, and returns where the padding ends.
+fn read_bytes(data: &[u8], at: usize) -> Result<(&[u8], usize), Error> {
+    let len = read_len(data, at)?;
+    let start = at.checked_add(32).ok_or(Error::InvalidData)?;
+    let padded_len = len.checked_add(31).ok_or(Error::InvalidData)? / 32 * 32;
+    let end = start.checked_add(padded_len).ok_or(Error::InvalidData)?;
+    let padded = data.get(start..end).ok_or(Error::InvalidData)?;
+    if padded[len..].iter().any(|&b| b != 0) {
+        return Err(Error::InvalidData);
+    }
+    Ok((&padded[..len], end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(hex: &[&str]) -> Vec<u8> {
+        hex.iter()
+            .flat_map(|word| (0..64).step_by(2).map(move |i| u8::from_str_radix(&word[i..i + 2], 16).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_static() {
+        // baz(uint32,bool) from the examples of the ABI specification.
+        let types = [ParamType::Uint(32), ParamType::Bool];
+        let tokens = [Token::Uint(69.into()), Token::Bool(true)];
+        let expected = words(&[
+            "0000000000000000000000000000000000000000000000000000000000000045",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+        ]);
+        assert_eq!(encode(&types, &tokens).unwrap(), expected);
+        assert_eq!(decode(&types, &expected).unwrap(), tokens.to_vec());
+    }
+
+    #[test]
+    fn test_encode_dynamic() {
+        // sam(bytes,bool,uint256[]) with "dave", true and [1,2,3] from the examples of the ABI specification.
+        let types = [ParamType::Bytes, ParamType::Bool, ParamType::Array(Box::new(ParamType::Uint(256)))];
+        let tokens = [
+            Token::Bytes(b"dave".to_vec()),
+            Token::Bool(true),
+            Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into()), Token::Uint(3.into())]),
+        ];
+        let expected = words(&[
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "0000000000000000000000000000000000000000000000000000000000000004",
+            "6461766500000000000000000000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+        ]);
+        assert_eq!(encode(&types, &tokens).unwrap(), expected);
+        assert_eq!(decode(&types, &expected).unwrap(), tokens.to_vec());
+    }
+
+    #[test]
+    fn test_nested_roundtrip() {
+        let types = [
+            ParamType::Array(Box::new(ParamType::FixedArray(Box::new(ParamType::String), 2))),
+            ParamType::Tuple(vec![ParamType::Int(24), ParamType::FixedBytes(3), ParamType::Address]),
+        ];
+        let tokens = [
+            Token::Array(vec![
+                Token::FixedArray(vec![Token::String("enigma".into()), Token::String(String::new())]),
+                Token::FixedArray(vec![Token::String("secret".into()), Token::String("contract".into())]),
+            ]),
+            Token::Tuple(vec![Token::Int(int_to_u256(-5)), Token::FixedBytes(vec![1, 2, 3]), Token::Address([9u8; 20].into())]),
+        ];
+        let data = encode(&types, &tokens).unwrap();
+        assert_eq!(decode(&types, &data).unwrap(), tokens.to_vec());
+    }
+
+    #[test]
+    fn test_integer_widths() {
+        assert_eq!(int_to_u256(-1), U256::max_value());
+        assert_eq!(low_u128(&int_to_u256(i128::min_value())) as i128, i128::min_value());
+        assert_eq!(low_u128(&uint_to_u256(u128::max_value())), u128::max_value());
+
+        assert!(Token::Uint(255.into()).type_check(&ParamType::Uint(8)));
+        assert!(!Token::Uint(256.into()).type_check(&ParamType::Uint(8)));
+        assert!(Token::Int(int_to_u256(-128)).type_check(&ParamType::Int(8)));
+        assert!(!Token::Int(int_to_u256(-129)).type_check(&ParamType::Int(8)));
+        assert!(!Token::Int(128.into()).type_check(&ParamType::Int(8)));
+        assert_eq!(encode(&[ParamType::Uint(8)], &[Token::Uint(256.into())]), Err(Error::TypeMismatch));
+
+        let too_wide = words(&["0000000000000000000000000000000000000000000000000000000000000100"]);
+        assert_eq!(decode(&[ParamType::Uint(8)], &too_wide), Err(Error::InvalidData));
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        let types = [ParamType::Bool];
+        assert_eq!(decode(&types, &words(&["0000000000000000000000000000000000000000000000000000000000000002"])), Err(Error::InvalidData));
+        assert_eq!(decode(&types, &[0u8; 31]), Err(Error::InvalidData));
+        // an offset past the end of the data.
+        let bytes = words(&["00000000000000000000000000000000000000000000000000000000000000ff"]);
+        assert_eq!(decode(&[ParamType::Bytes], &bytes), Err(Error::InvalidData));
+        // a huge array length.
+        let array = words(&[
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "00000000000000000000000000000000000000000000000000000000ffffffff",
+        ]);
+        assert_eq!(decode(&[ParamType::Array(Box::new(ParamType::Bool))], &array), Err(Error::InvalidData));
+        // non zero padding of fixed bytes.
+        let fixed = words(&["0102030000000000000000000000000000000000000000000000000000000001"]);
+        assert_eq!(decode(&[ParamType::FixedBytes(3)], &fixed), Err(Error::InvalidData));
+    }
+
+    #[test]
+    fn test_decode_non_canonical() {
+        let types = [ParamType::Bytes, ParamType::Bytes];
+        let data = encode(&types, &[Token::Bytes(b"dave".to_vec()), Token::Bytes(b"enigma".to_vec())]).unwrap();
+        assert!(decode(&types, &data).is_ok());
+        // trailing data after the values.
+        let mut trailing = data.clone();
+        trailing.extend_from_slice(&[0u8; 32]);
+        assert_eq!(decode(&types, &trailing), Err(Error::InvalidData));
+        // both offsets point at the same bytes.
+        let mut shared = data.clone();
+        shared[32..64].copy_from_slice(&data[..32]);
+        assert_eq!(decode(&types, &shared), Err(Error::InvalidData));
+
+        // the items of a nested array which share the same inner array.
+        let array = ParamType::Array(Box::new(ParamType::Array(Box::new(ParamType::Uint(256)))));
+        let nested = words(&[
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+        ]);
+        assert_eq!(decode(&[array], &nested), Err(Error::InvalidData));
+    }
+
+    #[test]
+    fn test_decode_too_many_tokens() {
+        let types = [ParamType::Array(Box::new(ParamType::Tuple(Vec::new())))];
+        let mut data = words(&["0000000000000000000000000000000000000000000000000000000000000020"]);
+        push_word(&mut data, U256::from(MAX_DECODED_TOKENS as u64));
+        // every item is empty, so only the limit stops them and not the length of the data.
+        data.resize(data.len() + 32 * MAX_DECODED_TOKENS, 0);
+        assert_eq!(decode(&types, &data), Err(Error::InvalidData));
+    }
+
+    #[test]
+    fn test_decode_topic() {
+        let mut topic = [0u8; 32];
+        topic[12..].copy_from_slice(&[9u8; 20]);
+        let topic = H256::from_slice(&topic);
+        assert_eq!(decode_topic(&ParamType::Address, &topic), Ok(Token::Address([9u8; 20].into())));
+        assert_eq!(decode_topic(&ParamType::String, &topic), Err(Error::TypeMismatch));
+    }
+}
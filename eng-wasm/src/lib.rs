@@ -12,19 +12,25 @@ extern crate serde_json;
 extern crate serde;
 #[macro_use]
 mod internal_std;
+pub mod codec;
 pub mod crypto_wasm;
+pub mod eth_abi;
 mod rand_wasm;
+pub mod storage;
 pub extern crate eng_pwasm_abi;
 
+pub use codec::BinaryCodec;
 pub use crypto_wasm::*;
 pub use eng_pwasm_abi::types::*;
 pub use internal_std::*;
 pub use rand_wasm::*;
+pub use storage::{StorageMap, StorageValue, StorageVec};
 pub use serde_json::Value;
 
 pub mod external {
     extern "C" {
         pub fn write_state(key: *const u8, key_len: u32, value: *const u8, value_len: u32);
+        pub fn write_state_with_flags(key: *const u8, key_len: u32, value: *const u8, value_len: u32, flags: u32);
         pub fn read_state_len(key: *const u8, key_len: u32) -> i32;
         pub fn read_state(key: *const u8, key_len: u32, value_holder: *const u8);
         pub fn remove_from_state(key: *const u8, key_len: u32);
@@ -39,6 +45,14 @@ pub mod external {
         pub fn rand(payload: *const u8, payload_len: u32);
         pub fn encrypt(message: *const u8, message_len: u32, key: *const u8, payload: *const u8);
         pub fn decrypt(cipheriv: *const u8, cipheriv_len: u32, key: *const u8, payload: *const u8);
+        pub fn call_contract(address: *const u8, name: *const u8, name_len: u32, args: *const u8, args_len: u32) -> i32;
+        pub fn fetch_call_result(result_holder: *const u8);
+        pub fn emit_event(topic: *const u8, topic_len: u32, data: *const u8, data_len: u32);
+        pub fn block_number() -> i64;
+        pub fn epoch_nonce(nonce_holder: *mut u8);
+        pub fn sender_pubkey(pubkey_holder: *mut u8);
+        pub fn self_address(address_holder: *mut u8);
+        pub fn revert(message: *const u8, message_len: u32);
     }
 }
 
@@ -65,12 +79,22 @@ where T: serde::Serialize {
     unsafe { external::write_state(key.as_ptr(), key.len() as u32, value_vec.as_ptr(), value_vec.len() as u32) }
 }
 
-/// Read from state
-pub fn read<T>(key: &str) -> Option<T>
-where for<'de> T: serde::Deserialize<'de> {
+/// The flag of `write_state_with_flags` for a value in the binary encoding of `codec`.
+const BINARY_VALUE_FLAG: u32 = 1;
+/// The byte the runtime stores before a binary value, a JSON value can't start with it.
+const BINARY_VALUE_TAG: u8 = 0;
+
+fn read_raw(key: &str) -> Vec<u8> {
     let val_len = unsafe { external::read_state_len(key.as_ptr(), key.len() as u32) };
     let value_holder: Vec<u8> = iter::repeat(0).take(val_len as usize).collect();
     unsafe { external::read_state(key.as_ptr(), key.len() as u32, value_holder.as_ptr()) };
+    value_holder
+}
+
+/// Read from state
+pub fn read<T>(key: &str) -> Option<T>
+where for<'de> T: serde::Deserialize<'de> {
+    let value_holder = read_raw(key);
     let value: Value = serde_json::from_slice(&value_holder)
         .map_err(|_| print("failed unwrapping from_slice in read_state"))
         .expect("read_state failed");
@@ -84,6 +108,25 @@ where for<'de> T: serde::Deserialize<'de> {
     )
 }
 
+/// Write to state in the binary encoding of `codec`, which is more compact than JSON and isn't parsed by the runtime.
+/// The value can only be read with `read_binary`.
+pub fn write_binary<T: BinaryCodec>(key: &str, value: &T) {
+    let value_vec = codec::encode(value);
+    unsafe {
+        external::write_state_with_flags(key.as_ptr(), key.len() as u32, value_vec.as_ptr(), value_vec.len() as u32, BINARY_VALUE_FLAG)
+    }
+}
+
+/// Read from state a value which was written with `write_binary`
+pub fn read_binary<T: BinaryCodec>(key: &str) -> Option<T> {
+    let value_holder = read_raw(key);
+    match value_holder.split_first() {
+        Some((&BINARY_VALUE_TAG, value)) => Some(codec::decode(value).expect("read_binary failed decoding the value")),
+        _ if value_holder == b"null" => None,
+        _ => panic!("read_binary: the value of `{}` isn't binary encoded", key),
+    }
+}
+
 /// Remove key and value from state
 pub fn remove<T>(key: &str) -> Option<T>
 where for<'de> T: serde::Deserialize<'de> {
@@ -96,6 +139,61 @@ pub fn write_ethereum_bridge(payload: &[u8], address: &Address) {
     unsafe { external::write_eth_bridge(payload.as_ptr(), payload.len() as u32, address.as_ptr()) };
 }
 
+/// Calls a function of another secret contract and returns its output.
+/// The called contract runs under the remaining gas of this execution and its state changes produce its own delta.
+/// The arguments need to be encoded the way the called function expects them.
+pub fn call_contract(address: &H256, function: &str, args: &[u8]) -> Vec<u8> {
+    let result_len = unsafe {
+        external::call_contract(address.as_ptr(), function.as_ptr(), function.len() as u32, args.as_ptr(), args.len() as u32)
+    };
+    let result_holder: Vec<u8> = iter::repeat(0).take(result_len as usize).collect();
+    unsafe { external::fetch_call_result(result_holder.as_ptr()) };
+    result_holder
+}
+
+/// Emit an event, the data is serialized to JSON like the state values.
+/// The events are returned to the user encrypted with their key, in the order they were emitted.
+pub fn emit_event<T>(topic: &str, data: T)
+where T: serde::Serialize {
+    let data_vec = serde_json::to_vec(&json!(data)).unwrap();
+    unsafe { external::emit_event(topic.as_ptr(), topic.len() as u32, data_vec.as_ptr(), data_vec.len() as u32) }
+}
+
+/// The number of the Ethereum block the task was submitted in.
+pub fn block_number() -> u64 {
+    unsafe { external::block_number() as u64 }
+}
+
+/// The nonce of the epoch the task belongs to.
+pub fn epoch_nonce() -> H256 {
+    let mut nonce = H256::zero();
+    unsafe { external::epoch_nonce(nonce.as_mut_ptr()) };
+    nonce
+}
+
+/// The 64 bytes public key of the user who sent the task.
+/// It's the same for the contracts called with `call_contract`, they run in the task of the user.
+pub fn sender_pubkey() -> Vec<u8> {
+    let mut pubkey = vec![0u8; 64];
+    unsafe { external::sender_pubkey(pubkey.as_mut_ptr()) };
+    pubkey
+}
+
+/// The address of the running contract.
+pub fn self_address() -> H256 {
+    let mut address = H256::zero();
+    unsafe { external::self_address(address.as_mut_ptr()) };
+    address
+}
+
+/// Fails the task with an error message, the message is returned to the user encrypted instead of the result
+/// and the gas used until now is charged.
+/// This is what an `Err` returned from a function of `#[pub_interface]` turns into.
+pub fn revert(message: &str) -> ! {
+    unsafe { external::revert(message.as_ptr(), message.len() as u32) };
+    unreachable!("revert traps the execution")
+}
+
 #[macro_export]
 macro_rules! write_state {
      ( $($key: expr => $val: expr),+ ) => {
@@ -121,6 +219,16 @@ macro_rules! remove_from_state {
     }};
 }
 
+#[macro_export]
+macro_rules! emit {
+    ( $topic: expr ) => {{
+        $crate::emit_event($topic, ())
+    }};
+    ( $topic: expr, $data: expr ) => {{
+        $crate::emit_event($topic, $data)
+    }};
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
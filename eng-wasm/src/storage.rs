@@ -0,0 +1,238 @@
+//! Typed accessors to the state of the contract.
+//!
+//! Every entry of a collection is kept under its own key in the state, so changing one entry
+//! doesn't read or write the rest of the collection. The keys are namespaced by the name the collection
+//! is created with: the entry of `key` in a `StorageMap` named `balances` is kept under `balances:<key as JSON>`.
+//! A namespace shouldn't contain a `:`, so the keys of different collections can't collide.
+//!
+//! The entries that were read or written are cached by the accessor, so reading an entry
+//! again in the same call doesn't go to the runtime.
+//! The values are JSON, like the values of `write_state!`, unless the accessor is created with `new_binary`,
+//! which stores them in the compact encoding of `codec`.
+//! ```ignore
+//! let mut balances: StorageMap<H256, U256> = StorageMap::new("balances");
+//! let balance = balances.get(&owner).unwrap_or_default();
+//! balances.insert(owner, balance + tokens);
+//! ```
+
+use crate::{external, read_binary, write_binary, BinaryCodec};
+use serde::{de::DeserializeOwned, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::string::{String, ToString};
+
+fn remove_key(key: &str) {
+    unsafe { external::remove_from_state(key.as_ptr(), key.len() as u32) }
+}
+
+/// How the values of an accessor are encoded in the state.
+pub trait ValueCodec<T> {
+    fn read(key: &str) -> Option<T>;
+    fn write(key: &str, value: &T);
+}
+
+/// The values are JSON.
+pub struct Json;
+
+/// The values are in the binary encoding of `codec`.
+pub struct Binary;
+
+impl<T: Serialize + DeserializeOwned> ValueCodec<T> for Json {
+    fn read(key: &str) -> Option<T> { crate::read(key) }
+
+    fn write(key: &str, value: &T) { crate::write(key, value) }
+}
+
+impl<T: BinaryCodec> ValueCodec<T> for Binary {
+    fn read(key: &str) -> Option<T> { read_binary(key) }
+
+    fn write(key: &str, value: &T) { write_binary(key, value) }
+}
+
+/// A single value under a key of the state.
+pub struct StorageValue<T, C = Json> {
+    key: String,
+    cache: RefCell<Option<Option<T>>>,
+    codec: PhantomData<C>,
+}
+
+impl<T> StorageValue<T, Json> {
+    pub fn new(key: &str) -> Self { Self::with_codec(key) }
+}
+
+impl<T> StorageValue<T, Binary> {
+    pub fn new_binary(key: &str) -> Self { Self::with_codec(key) }
+}
+
+impl<T, C> StorageValue<T, C> {
+    fn with_codec(key: &str) -> Self {
+        StorageValue { key: key.to_string(), cache: RefCell::new(None), codec: PhantomData }
+    }
+}
+
+impl<T: Clone, C: ValueCodec<T>> StorageValue<T, C> {
+
+    /// The value, `None` if it was never set or was removed.
+    pub fn get(&self) -> Option<T> {
+        let mut cache = self.cache.borrow_mut();
+        if cache.is_none() {
+            *cache = Some(C::read(&self.key));
+        }
+        cache.as_ref().and_then(Clone::clone)
+    }
+
+    pub fn set(&mut self, value: T) {
+        C::write(&self.key, &value);
+        *self.cache.get_mut() = Some(Some(value));
+    }
+
+    /// Removes the value from the state and returns it.
+    pub fn remove(&mut self) -> Option<T> {
+        let value = self.get();
+        remove_key(&self.key);
+        *self.cache.get_mut() = Some(None);
+        value
+    }
+}
+
+/// A map whose entries are kept under separate keys of the state.
+/// It can't be iterated, the keys of the state aren't listed.
+pub struct StorageMap<K, V, C = Json> {
+    namespace: String,
+    cache: RefCell<BTreeMap<String, Option<V>>>,
+    key_type: PhantomData<K>,
+    codec: PhantomData<C>,
+}
+
+impl<K, V> StorageMap<K, V, Json> {
+    pub fn new(namespace: &str) -> Self { Self::with_codec(namespace) }
+}
+
+impl<K, V> StorageMap<K, V, Binary> {
+    pub fn new_binary(namespace: &str) -> Self { Self::with_codec(namespace) }
+}
+
+impl<K, V, C> StorageMap<K, V, C> {
+    fn with_codec(namespace: &str) -> Self {
+        StorageMap {
+            namespace: namespace.to_string(),
+            cache: RefCell::new(BTreeMap::new()),
+            key_type: PhantomData,
+            codec: PhantomData,
+        }
+    }
+}
+
+/// The keys are always JSON in the name of the key in the state, only the values are encoded by `C`.
+impl<K: Serialize, V: Clone, C: ValueCodec<V>> StorageMap<K, V, C> {
+
+    /// The key in the state of the entry of `key`.
+    fn state_key(&self, key: &K) -> String {
+        let key = serde_json::to_string(key).expect("the key of a StorageMap should serialize to JSON");
+        eformat!("{}:{}", self.namespace, key)
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let state_key = self.state_key(key);
+        let mut cache = self.cache.borrow_mut();
+        cache.entry(state_key.clone()).or_insert_with(|| C::read(&state_key)).clone()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool { self.get(key).is_some() }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        let state_key = self.state_key(&key);
+        C::write(&state_key, &value);
+        self.cache.get_mut().insert(state_key, Some(value));
+    }
+
+    /// Removes the entry of `key` from the state and returns its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.get(key);
+        let state_key = self.state_key(key);
+        remove_key(&state_key);
+        self.cache.get_mut().insert(state_key, None);
+        value
+    }
+}
+
+/// A vector whose elements are kept under separate keys of the state, its length is kept under `<namespace>:len`.
+pub struct StorageVec<T, C = Json> {
+    namespace: String,
+    len: StorageValue<u64>,
+    cache: RefCell<BTreeMap<u64, Option<T>>>,
+    codec: PhantomData<C>,
+}
+
+impl<T> StorageVec<T, Json> {
+    pub fn new(namespace: &str) -> Self { Self::with_codec(namespace) }
+}
+
+impl<T> StorageVec<T, Binary> {
+    pub fn new_binary(namespace: &str) -> Self { Self::with_codec(namespace) }
+}
+
+impl<T, C> StorageVec<T, C> {
+    fn with_codec(namespace: &str) -> Self {
+        let len = StorageValue::new(&eformat!("{}:len", namespace));
+        StorageVec { namespace: namespace.to_string(), len, cache: RefCell::new(BTreeMap::new()), codec: PhantomData }
+    }
+}
+
+impl<T: Clone, C: ValueCodec<T>> StorageVec<T, C> {
+
+    fn state_key(&self, index: u64) -> String {
+        eformat!("{}:{}", self.namespace, index)
+    }
+
+    pub fn len(&self) -> u64 { self.len.get().unwrap_or_default() }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The element at `index`, `None` if it's out of bounds.
+    pub fn get(&self, index: u64) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut cache = self.cache.borrow_mut();
+        let state_key = self.state_key(index);
+        cache.entry(index).or_insert_with(|| C::read(&state_key)).clone()
+    }
+
+    /// Replaces the element at `index`.
+    ///
+    /// # Panics
+    /// If `index` is out of bounds.
+    pub fn set(&mut self, index: u64, value: T) {
+        let len = self.len();
+        assert!(index < len, "index out of bounds: the len is {} but the index is {}", len, index);
+        C::write(&self.state_key(index), &value);
+        self.cache.get_mut().insert(index, Some(value));
+    }
+
+    pub fn push(&mut self, value: T) {
+        let len = self.len();
+        C::write(&self.state_key(len), &value);
+        self.cache.get_mut().insert(len, Some(value));
+        self.len.set(len + 1);
+    }
+
+    /// Removes the last element from the state and returns it, `None` if the vector is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        let value = self.get(len - 1);
+        remove_key(&self.state_key(len - 1));
+        self.cache.get_mut().remove(&(len - 1));
+        self.len.set(len - 1);
+        value
+    }
+
+    /// Reads the elements one by one as the iterator advances.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+}
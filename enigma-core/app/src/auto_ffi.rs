@@ -27,6 +27,27 @@ extern "C" {
         args_len: usize,
         address: *const ContractAddress,
         user_key: *mut [u8; 64usize],
+        context: *const TaskContext,
+        options: *const TaskOptions,
+        gas_limit: *const u64,
+        db_ptr: *const RawPointer,
+        result: *mut ExecuteResult,
+    ) -> sgx_status_t;
+}
+extern "C" {
+    pub fn ecall_upgrade(
+        eid: sgx_enclave_id_t,
+        retval: *mut EnclaveReturn,
+        bytecode: *const u8,
+        bytecode_len: usize,
+        callable: *const u8,
+        callable_len: usize,
+        args: *const u8,
+        args_len: usize,
+        address: *const ContractAddress,
+        user_key: *mut [u8; 64usize],
+        context: *const TaskContext,
+        options: *const TaskOptions,
         gas_limit: *const u64,
         db_ptr: *const RawPointer,
         result: *mut ExecuteResult,
@@ -44,11 +65,41 @@ extern "C" {
         callable_args_len: usize,
         pubkey: *mut [u8; 64usize],
         address: *const ContractAddress,
+        context: *const TaskContext,
+        options: *const TaskOptions,
         gas_limit: *const u64,
         db_ptr: *const RawPointer,
         result: *mut ExecuteResult,
     ) -> sgx_status_t;
 }
+extern "C" {
+    pub fn ecall_query(
+        eid: sgx_enclave_id_t,
+        retval: *mut EnclaveReturn,
+        bytecode: *const u8,
+        bytecode_len: usize,
+        callable: *const u8,
+        callable_len: usize,
+        callable_args: *const u8,
+        callable_args_len: usize,
+        pubkey: *mut [u8; 64usize],
+        address: *const ContractAddress,
+        context: *const TaskContext,
+        options: *const TaskOptions,
+        gas_limit: *const u64,
+        db_ptr: *const RawPointer,
+        result: *mut ExecuteResult,
+    ) -> sgx_status_t;
+}
+extern "C" {
+    pub fn ecall_set_gas_schedule(
+        eid: sgx_enclave_id_t,
+        retval: *mut EnclaveReturn,
+        schedule: *const u8,
+        schedule_len: usize,
+        schedule_hash: *mut [u8; 32usize],
+    ) -> sgx_status_t;
+}
 extern "C" {
     pub fn ecall_get_signing_address(eid: sgx_enclave_id_t, arr: *mut [u8; 20usize]) -> sgx_status_t;
 }
@@ -5,6 +5,10 @@
 //! it is used by running `let opt: Opt = Opt::from_args();` and then it will fill up the struct from the user inputs.
 //! (and of course fail if needed)
 
+use db::config::{Compression, DbConfig};
+use enigma_types::ContractAddress;
+use failure::Error;
+use hex::FromHexError;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -23,7 +27,88 @@ pub struct Opt {
     /// Specify the number of Attestation call retries when failing
     #[structopt(long = "retries", short = "r", default_value = "10")]
     pub retries: u32,
+    /// Specify the number of workers that handle the read only requests concurrently
+    #[structopt(long = "workers", short = "w", default_value = "4")]
+    pub workers: usize,
+    /// Optional: a JSON file with the gas schedule of the tasks, the missing costs take the default values
+    #[structopt(parse(from_os_str), long = "gas-schedule")]
+    pub gas_schedule: Option<PathBuf>,
+    /// Optional: a JSON file with the DB configuration, the DB flags override it
+    #[structopt(parse(from_os_str), long = "db-config")]
+    pub db_config: Option<PathBuf>,
+    /// Optional: the size of the DB block cache in bytes
+    #[structopt(long = "db-block-cache")]
+    pub db_block_cache: Option<usize>,
+    /// Optional: the DB compression type (none, snappy, zlib, bz2, lz4, lz4hc, zstd)
+    #[structopt(long = "db-compression")]
+    pub db_compression: Option<Compression>,
+    /// Optional: don't sync every DB write to the disk
+    #[structopt(long = "db-no-sync")]
+    pub db_no_sync: bool,
+    /// Optional: the maximum number of files the DB keeps open (-1 for no limit)
+    #[structopt(long = "db-max-open-files")]
+    pub db_max_open_files: Option<i32>,
+    /// Optional: bits per key of the DB bloom filters (0 disables them)
+    #[structopt(long = "db-bloom-bits")]
+    pub db_bloom_bits: Option<i32>,
     /// Optional: change the minimum log level
     #[structopt(short = "l", long = "log-level", default_value = "info")]
     pub log_level: String,
-}
\ No newline at end of file
+    /// Optional: run a command on the DB instead of starting the worker
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+impl Opt {
+    /// Builds the DB configuration from the config file (if given) and the DB flags.
+    pub fn db_config(&self) -> Result<DbConfig, Error> {
+        let mut config = match &self.db_config {
+            Some(path) => DbConfig::from_file(path)?,
+            None => DbConfig::default(),
+        };
+        if let Some(block_cache_size) = self.db_block_cache {
+            config.block_cache_size = block_cache_size;
+        }
+        if let Some(compression) = self.db_compression {
+            config.compression = compression;
+        }
+        if self.db_no_sync {
+            config.sync = false;
+        }
+        if let Some(max_open_files) = self.db_max_open_files {
+            config.max_open_files = max_open_files;
+        }
+        if let Some(bloom_filter_bits) = self.db_bloom_bits {
+            config.bloom_filter_bits = bloom_filter_bits;
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Export the bytecode, deltas and state of a contract into an archive file, the node has to be stopped
+    #[structopt(name = "export")]
+    Export {
+        /// The address of the contract, in hex
+        #[structopt(long = "address", parse(try_from_str = "parse_address"))]
+        address: ContractAddress,
+        /// Specify the archive file, the default is `<address>.archive` in the current directory
+        #[structopt(parse(from_os_str), long = "file")]
+        file: Option<PathBuf>,
+    },
+    /// Import a contract from an archive file
+    #[structopt(name = "import")]
+    Import {
+        /// The archive file
+        #[structopt(parse(from_os_str), long = "file")]
+        file: PathBuf,
+        /// Overwrite the contract if it already exists
+        #[structopt(long = "force")]
+        force: bool,
+    },
+}
+
+fn parse_address(address: &str) -> Result<ContractAddress, FromHexError> {
+    ContractAddress::from_hex(address)
+}
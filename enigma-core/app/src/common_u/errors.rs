@@ -2,6 +2,7 @@
 use sgx_types::*;
 use std::fmt;
 use failure::Error;
+use crate::networking::messages::IpcErrorCode;
 
 // error while requesting to produce a quote (registration)
 #[derive(Fail, Debug)]
@@ -39,6 +40,23 @@ pub struct P2PErr {
     pub msg: String,
 }
 
+// error while decoding a message received from the p2p
+#[derive(Fail, Debug)]
+#[fail(display = "Error while decoding the p2p message, code: {:?}, error: {}", code, msg)]
+pub struct P2PDecodeErr {
+    /// the id of the request if it was possible to recover it from the message.
+    pub id: Option<String>,
+    pub code: IpcErrorCode,
+    pub msg: String,
+}
+
+// error while exporting or importing a contract archive
+#[derive(Fail, Debug)]
+#[fail(display = "Error with the contract archive: {}", msg)]
+pub struct ArchiveErr {
+    pub msg: String,
+}
+
 #[derive(Fail, Debug)]
 #[fail(display = "Error while trying to {}, Because: {}", command, kind)]
 pub struct DBErr {
@@ -62,6 +80,8 @@ pub enum DBErrKind {
     MissingKey(String),
     UpdateError,
     MissingKeys,
+    UncoveredDeltas(u32),
+    InvalidConfig(String),
 }
 
 impl fmt::Display for DBErrKind {
@@ -73,6 +93,8 @@ impl fmt::Display for DBErrKind {
             DBErrKind::MissingKey(k) => format!("The following Key doesn't exist: {}", &k),
             DBErrKind::UpdateError => "Failed to update the key".into(),
             DBErrKind::MissingKeys => "No keys exist the DB".into(),
+            DBErrKind::UncoveredDeltas(i) => format!("No snapshot covers the deltas below index: {}", i),
+            DBErrKind::InvalidConfig(msg) => format!("Invalid DB configuration: {}", msg),
         };
         write!(f, "{}", printable)
     }
@@ -0,0 +1,251 @@
+//! # Contract Archives.
+//!
+//! An archive holds everything the DB stores for a single contract (bytecode, previous bytecodes, deltas, state and snapshots),
+//! so a contract can be moved between data directories. <br>
+//! The file starts with a magic, a format version and a keccak256 checksum of the body,
+//! the body is the MessagePack encoded `ContractArchive`. <br>
+//! The checksum only detects a corrupted file, the deltas themselves are encrypted and signed by the enclave
+//! and are verified by it as usual when the state is built from them.
+
+use common_u::errors::ArchiveErr;
+use db::dal::{CRUDInterface, DB};
+use db::iterator::{P2PCalls, ResultType};
+use db::primitives::{DeltaKey, Stype};
+use enigma_crypto::hash::Keccak256;
+use enigma_types::ContractAddress;
+use failure::Error;
+use hex::ToHex;
+use rmp_serde;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"ENGCNTRC";
+pub const ARCHIVE_VERSION: u32 = 1;
+// magic + version + checksum
+const HEADER_SIZE: usize = 8 + 4 + 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractArchive {
+    pub address: ContractAddress,
+    pub bytecode: Vec<u8>,
+    /// the bytecodes the contract had before its upgrades, by the index of the last delta they made.
+    pub versions: Vec<(u32, Vec<u8>)>,
+    pub deltas: Vec<ArchivedDelta>,
+    pub state: Option<Vec<u8>>,
+    pub snapshots: Vec<(u32, Vec<u8>)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArchivedDelta {
+    pub index: u32,
+    pub data: Vec<u8>,
+}
+
+impl ContractArchive {
+    /// Reads all the data of the contract from the DB.
+    pub fn from_db(db: &DB, address: ContractAddress) -> Result<Self, Error> {
+        let bytecode = db.get_contract(address)?;
+        let from = DeltaKey::new(address, Stype::Delta(0));
+        let to = DeltaKey::new(address, Stype::Delta(u32::max_value()));
+        let deltas = match db.get_deltas(from, to)? {
+            ResultType::None => Vec::new(),
+            ResultType::Full(deltas) | ResultType::Partial(deltas) => deltas
+                .into_iter()
+                .map(|(key, data)| ArchivedDelta { index: key.key_type.unwrap_delta(), data })
+                .collect(),
+        };
+        let mut versions = Vec::new();
+        for index in db.get_bytecode_version_indexes(&address)? {
+            versions.push((index, db.read(&DeltaKey::new(address, Stype::ByteCodeVersion(index)))?));
+        }
+        let state = db.read(&DeltaKey::new(address, Stype::State)).ok();
+        let mut snapshots = Vec::new();
+        for index in db.get_snapshot_indexes(&address)? {
+            snapshots.push((index, db.read(&DeltaKey::new(address, Stype::Snapshot(index)))?));
+        }
+        Ok(ContractArchive { address, bytecode, versions, deltas, state, snapshots })
+    }
+
+    /// Checks that the deltas are consecutive.
+    /// The deltas don't have to start from 0 since old deltas might have been pruned.
+    pub fn verify(&self) -> Result<(), Error> {
+        for pair in self.deltas.windows(2) {
+            if pair[1].index != pair[0].index + 1 {
+                return Err(ArchiveErr { msg: format!("delta {} doesn't follow delta {}", pair[1].index, pair[0].index) }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the whole archive into the DB in one atomic operation.
+    /// If the contract already exists it's refused, unless `force` is set,
+    /// and then all the existing keys of the contract are removed in the same operation,
+    /// so a failed import leaves the existing contract as it was.
+    pub fn into_db(self, db: &mut DB, force: bool) -> Result<(), Error> {
+        self.verify()?;
+        let address = self.address;
+        let exists = db.get_contract(address).is_ok() || db.get_tip::<DeltaKey>(&address).is_ok();
+        if exists && !force {
+            return Err(ArchiveErr { msg: format!("the contract {} already exists, use force to overwrite it", address.to_hex()) }.into());
+        }
+
+        let mut transaction = db.begin_transaction();
+        if exists {
+            transaction.delete_contract(&DeltaKey::new(address, Stype::ByteCode))?;
+        }
+        transaction.force_update(&DeltaKey::new(address, Stype::ByteCode), &self.bytecode)?;
+        for (index, bytecode) in &self.versions {
+            transaction.force_update(&DeltaKey::new(address, Stype::ByteCodeVersion(*index)), bytecode)?;
+        }
+        for delta in &self.deltas {
+            transaction.force_update(&DeltaKey::new(address, Stype::Delta(delta.index)), &delta.data)?;
+        }
+        if let Some(state) = &self.state {
+            transaction.force_update(&DeltaKey::new(address, Stype::State), state)?;
+        }
+        for (index, snapshot) in &self.snapshots {
+            transaction.force_update(&DeltaKey::new(address, Stype::Snapshot(*index)), snapshot)?;
+        }
+        transaction.commit()?;
+        // the state isn't necessarily up to date with the imported deltas.
+        db.update_state_status(false);
+        Ok(())
+    }
+
+    /// Encodes the archive into the file format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let body = rmp_serde::to_vec_named(self)?;
+        let mut archive = Vec::with_capacity(HEADER_SIZE + body.len());
+        archive.extend_from_slice(MAGIC);
+        archive.extend_from_slice(&ARCHIVE_VERSION.to_be_bytes());
+        archive.extend_from_slice(&body.keccak256()[..]);
+        archive.extend_from_slice(&body);
+        Ok(archive)
+    }
+
+    /// Decodes an archive from the file format, checking the version and the checksum.
+    pub fn from_bytes(archive: &[u8]) -> Result<Self, Error> {
+        if archive.len() < HEADER_SIZE || &archive[..8] != MAGIC {
+            return Err(ArchiveErr { msg: "not a contract archive".to_string() }.into());
+        }
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&archive[8..12]);
+        let version = u32::from_be_bytes(version);
+        if version != ARCHIVE_VERSION {
+            return Err(ArchiveErr { msg: format!("unsupported archive version: {}", version) }.into());
+        }
+        let body = &archive[HEADER_SIZE..];
+        if body.keccak256()[..] != archive[12..HEADER_SIZE] {
+            return Err(ArchiveErr { msg: "the checksum doesn't match".to_string() }.into());
+        }
+        Ok(rmp_serde::from_slice(body)?)
+    }
+}
+
+/// Exports the contract into an archive file and returns the path of the file.
+/// If no file is given the archive is written to `<address>.archive` in the current directory.
+pub fn export_to_file(db: &DB, address: ContractAddress, file: Option<PathBuf>) -> Result<PathBuf, Error> {
+    let file = file.unwrap_or_else(|| format!("{}.archive", address.to_hex()).into());
+    let archive = ContractArchive::from_db(db, address)?;
+    fs::write(&file, archive.to_bytes()?)?;
+    info!("Exported the contract {} with {} deltas into {:?}", address.to_hex(), archive.deltas.len(), file);
+    Ok(file)
+}
+
+/// Imports a contract from an archive file and returns its address.
+pub fn import_from_file<P: AsRef<Path>>(db: &mut DB, file: P, force: bool) -> Result<ContractAddress, Error> {
+    let archive = ContractArchive::from_bytes(&fs::read(&file)?)?;
+    let address = archive.address;
+    let deltas = archive.deltas.len();
+    archive.into_db(db, force)?;
+    info!("Imported the contract {} with {} deltas from {:?}", address.to_hex(), deltas, file.as_ref());
+    Ok(address)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use db::tests::create_test_db;
+
+    fn fill_contract(db: &mut DB, address: ContractAddress) {
+        let mut tuples = vec![
+            (DeltaKey::new(address, Stype::ByteCode), b"bytecode".to_vec()),
+            (DeltaKey::new(address, Stype::ByteCodeVersion(1)), b"old bytecode".to_vec()),
+            (DeltaKey::new(address, Stype::State), b"state".to_vec()),
+            (DeltaKey::new(address, Stype::Snapshot(2)), b"snapshot".to_vec()),
+        ];
+        tuples.extend((0..5).map(|i| (DeltaKey::new(address, Stype::Delta(i)), vec![i as u8; 10])));
+        for res in db.insert_tuples(&tuples) {
+            res.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_export_import() {
+        let (mut db, _dir) = create_test_db();
+        let address: ContractAddress = [3u8; 32].into();
+        fill_contract(&mut db, address);
+
+        let archive = ContractArchive::from_db(&db, address).unwrap();
+        assert_eq!(archive.deltas.len(), 5);
+        assert_eq!(archive.versions, vec![(1, b"old bytecode".to_vec())]);
+        let bytes = archive.to_bytes().unwrap();
+
+        let (mut other_db, _other_dir) = create_test_db();
+        let imported = ContractArchive::from_bytes(&bytes).unwrap();
+        assert_eq!(imported, archive);
+        imported.into_db(&mut other_db, false).unwrap();
+        assert_eq!(ContractArchive::from_db(&other_db, address).unwrap(), archive);
+        assert_eq!(other_db.read(&DeltaKey::new(address, Stype::ByteCodeVersion(1))).unwrap(), b"old bytecode".to_vec());
+    }
+
+    #[test]
+    fn test_import_existing() {
+        let (mut db, _dir) = create_test_db();
+        let address: ContractAddress = [4u8; 32].into();
+        fill_contract(&mut db, address);
+        db.create(&DeltaKey::new(address, Stype::Delta(5)), &b"newer"[..]).unwrap();
+
+        let mut archive = ContractArchive::from_db(&db, address).unwrap();
+        archive.deltas.pop();
+        assert!(archive.clone().into_db(&mut db, false).is_err());
+        archive.clone().into_db(&mut db, true).unwrap();
+        // forcing replaces the whole contract, so the newer delta is gone and the versions are kept.
+        assert_eq!(ContractArchive::from_db(&db, address).unwrap(), archive);
+        assert_eq!(db.get_bytecode_version_indexes(&address).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_export_import_file() {
+        let (mut db, dir) = create_test_db();
+        let address: ContractAddress = [6u8; 32].into();
+        fill_contract(&mut db, address);
+        let file = export_to_file(&db, address, Some(dir.path().join("contract.archive"))).unwrap();
+
+        let (mut other_db, _other_dir) = create_test_db();
+        assert_eq!(import_from_file(&mut other_db, &file, false).unwrap(), address);
+        assert_eq!(other_db.get_contract(address).unwrap(), b"bytecode".to_vec());
+    }
+
+    #[test]
+    fn test_corrupted_archive() {
+        let (mut db, _dir) = create_test_db();
+        let address: ContractAddress = [5u8; 32].into();
+        fill_contract(&mut db, address);
+        let archive = ContractArchive::from_db(&db, address).unwrap();
+
+        let mut bytes = archive.to_bytes().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(ContractArchive::from_bytes(&bytes).is_err());
+
+        let mut missing = archive.clone();
+        missing.deltas.remove(3);
+        assert!(missing.verify().is_err());
+
+        // the first deltas might have been pruned.
+        let mut pruned = archive;
+        pruned.deltas.remove(0);
+        assert!(pruned.verify().is_ok());
+    }
+}
@@ -0,0 +1,138 @@
+//! # DB Configuration.
+//!
+//! The RocksDB tuning of the core, it can be loaded from a JSON file (`--db-config`)
+//! and each of the fields can be overridden by the matching CLI flag. <br>
+//! Every field is optional in the file, the missing ones take the default value. e.g.
+//! ```json
+//! { "blockCacheSize": 67108864, "compression": "lz4", "sync": false }
+//! ```
+
+use common_u::errors::{DBErr, DBErrKind};
+use failure::Error;
+use rocksdb::{BlockBasedOptions, DBCompressionType, Options};
+use serde_json;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Snappy,
+    Zlib,
+    Bz2,
+    Lz4,
+    Lz4hc,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "snappy" => Ok(Compression::Snappy),
+            "zlib" => Ok(Compression::Zlib),
+            "bz2" => Ok(Compression::Bz2),
+            "lz4" => Ok(Compression::Lz4),
+            "lz4hc" => Ok(Compression::Lz4hc),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(DBErr { command: "parse_compression".to_string(), kind: DBErrKind::InvalidConfig(format!("unknown compression type: {}", s)) }.into()),
+        }
+    }
+}
+
+impl From<Compression> for DBCompressionType {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::None => DBCompressionType::None,
+            Compression::Snappy => DBCompressionType::Snappy,
+            Compression::Zlib => DBCompressionType::Zlib,
+            Compression::Bz2 => DBCompressionType::Bz2,
+            Compression::Lz4 => DBCompressionType::Lz4,
+            Compression::Lz4hc => DBCompressionType::Lz4hc,
+            Compression::Zstd => DBCompressionType::Zstd,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DbConfig {
+    /// The size of the LRU block cache in bytes.
+    pub block_cache_size: usize,
+    /// Compression other than `None` requires building with the `compression` feature.
+    pub compression: Compression,
+    /// Sync every write to the disk before returning.
+    pub sync: bool,
+    /// The maximum number of files RocksDB keeps open, -1 keeps all of them open.
+    pub max_open_files: i32,
+    /// Bits per key of the bloom filters, 0 disables them.
+    pub bloom_filter_bits: i32,
+}
+
+impl Default for DbConfig {
+    // these are the RocksDB defaults, except for `sync` which the core always used.
+    fn default() -> Self {
+        DbConfig { block_cache_size: 8 * 1024 * 1024, compression: Compression::None, sync: true, max_open_files: -1, bloom_filter_bits: 0 }
+    }
+}
+
+impl DbConfig {
+    /// Loads the configuration from a JSON file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let config = serde_json::from_slice(&fs::read(path)?)?;
+        Ok(config)
+    }
+
+    /// Checks that the configuration can be applied with the features this binary was built with.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !cfg!(feature = "compression") && self.compression != Compression::None {
+            let msg = format!("{:?} compression requires building with the `compression` feature", self.compression);
+            return Err(DBErr { command: "validate_config".to_string(), kind: DBErrKind::InvalidConfig(msg) }.into());
+        }
+        if self.bloom_filter_bits < 0 {
+            let msg = format!("the bloom filter bits can't be negative: {}", self.bloom_filter_bits);
+            return Err(DBErr { command: "validate_config".to_string(), kind: DBErrKind::InvalidConfig(msg) }.into());
+        }
+        Ok(())
+    }
+
+    /// Applies the tuning to the options, used both for the DB and for each of the column families.
+    pub(crate) fn apply(&self, options: &mut Options) {
+        let mut block_options = BlockBasedOptions::default();
+        block_options.set_lru_cache(self.block_cache_size);
+        if self.bloom_filter_bits > 0 {
+            block_options.set_bloom_filter(self.bloom_filter_bits, false);
+        }
+        options.set_block_based_table_factory(&block_options);
+        options.set_compression_type(self.compression.into());
+        options.set_max_open_files(self.max_open_files);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    extern crate tempfile;
+
+    #[test]
+    fn test_config_from_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(br#"{"blockCacheSize": 1024, "sync": false, "bloomFilterBits": 10}"#).unwrap();
+        let config = DbConfig::from_file(file.path()).unwrap();
+        let expected = DbConfig { block_cache_size: 1024, sync: false, bloom_filter_bits: 10, ..Default::default() };
+        assert_eq!(config, expected);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!("lzma".parse::<Compression>().is_err());
+        assert_eq!("LZ4".parse::<Compression>().unwrap(), Compression::Lz4);
+        let config = DbConfig { bloom_filter_bits: -1, ..Default::default() };
+        assert!(config.validate().is_err());
+    }
+}
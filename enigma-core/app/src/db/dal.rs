@@ -1,21 +1,25 @@
 use failure::Error;
 use rocksdb::DB as rocks_db;
-use rocksdb::{Options, SliceTransform, WriteOptions, ColumnFamilyDescriptor};
+use rocksdb::{Options, SliceTransform, WriteBatch, WriteOptions, ColumnFamilyDescriptor, IteratorMode};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use common_u::errors::{DBErr, DBErrKind};
+use db::config::DbConfig;
 use db::primitives::SplitKey;
 
 // These are global variables for Reade/Write/Create Options
-const SYNC: bool = true;
 const PREFIX_SIZE: usize = 1;
 
 pub struct DB {
     pub location: PathBuf,
-    pub database: rocks_db,
+    // shared between all the handles of the same DB (see `shared_handle`)
+    pub database: Arc<rocks_db>,
     // the DB needs to store the options for creating new
     // cf's that would be able to imitate the DB behaviour
-    pub options: Options,
+    pub options: Arc<Options>,
+    // sync every write to the disk, see `DbConfig`
+    sync: bool,
     // keeps track if the state needs to be rebuilt
     state_updated: bool,
 }
@@ -38,12 +42,24 @@ impl DB {
     /// let mut db = DB::new(path, true).unwrap();
     /// ```
     pub fn new<P: AsRef<Path>>(location: P, create_if_missing: bool) -> Result<DB, Error> {
-        // number of bytes to take into consideration when looking for a similar prefix
-        // would be helpful when querying the DB using iterators.
-        let prefix_extractor = SliceTransform::create_fixed_prefix(PREFIX_SIZE);
-        let mut options = Options::default();
+        DB::with_config(location, create_if_missing, &DbConfig::default())
+    }
+
+    /// Constructs a new `DB` tuned according to the `DbConfig`.
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # use enigma_core_app::db::{dal::DB, config::DbConfig};
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// let config = DbConfig { sync: false, bloom_filter_bits: 10, ..Default::default() };
+    /// let mut db = DB::with_config(tempdir.path(), true, &config).unwrap();
+    /// ```
+    pub fn with_config<P: AsRef<Path>>(location: P, create_if_missing: bool, config: &DbConfig) -> Result<DB, Error> {
+        config.validate()?;
+        let mut options = DB::build_options(config);
         options.create_if_missing(create_if_missing);
-        options.set_prefix_extractor(prefix_extractor);
         // cf_list gets a list of all column families (addresses) from the location where the DB
         // is stored and when opening it, it adds the list as an argument to the DB. this is done
         // in case the DB existed, otherwise, an empty list will be added and the call will
@@ -53,20 +69,63 @@ impl DB {
             Err(_) => Vec::new(),
         };
         // converts the Strings to descriptors (adds to each cf an options object)
-        let cf_descriptors = cf_list.into_iter().map(|name| {
-            let prefix_extractor = SliceTransform::create_fixed_prefix(PREFIX_SIZE);
-            let mut cf_opts = Options::default();
-            cf_opts.set_prefix_extractor(prefix_extractor);
-            ColumnFamilyDescriptor::new(name, cf_opts)
-        });
-        let database = rocks_db::open_cf_descriptors(&options, &location, cf_descriptors)?;
+        let cf_descriptors = cf_list.into_iter().map(|name| ColumnFamilyDescriptor::new(name, DB::build_options(config)));
+        let database = Arc::new(rocks_db::open_cf_descriptors(&options, &location, cf_descriptors)?);
+        let options = Arc::new(options);
         let location = location.as_ref().to_path_buf();
         // the state_updated is initialized to true since it won't be necessary to build
         // the state when the DB is empty.
-        let db_par = DB { location, database, options, state_updated: true};
+        let db_par = DB { location, database, options, sync: config.sync, state_updated: true};
         Ok(db_par)
     }
 
+    fn build_options(config: &DbConfig) -> Options {
+        // number of bytes to take into consideration when looking for a similar prefix
+        // would be helpful when querying the DB using iterators.
+        let prefix_extractor = SliceTransform::create_fixed_prefix(PREFIX_SIZE);
+        let mut options = Options::default();
+        options.set_prefix_extractor(prefix_extractor);
+        config.apply(&mut options);
+        options
+    }
+
+    pub(crate) fn write_options(&self) -> WriteOptions {
+        let mut write_options = WriteOptions::default();
+        write_options.set_sync(self.sync);
+        write_options
+    }
+
+    /// Returns another handle to the same underlying DB, which can be moved to another thread.
+    ///
+    /// RocksDB is safe to use concurrently, so this is used to serve the read only requests
+    /// in parallel with the requests that write to the DB.
+    /// The state status is tracked separately by each handle,
+    /// so it's only meaningful for the handle which writes the deltas and builds the state.
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # use enigma_core_app::db::dal::{DB, CRUDInterface};
+    /// # use enigma_core_app::db::primitives::{DeltaKey, Stype};
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// let mut db = DB::new(tempdir.path(), true).unwrap();
+    /// let reader = db.shared_handle();
+    /// let key = DeltaKey::new([3u8; 32].into(), Stype::State);
+    /// db.create(&key, b"state").unwrap();
+    /// let value = std::thread::spawn(move || reader.read(&key).unwrap()).join().unwrap();
+    /// assert_eq!(value, b"state".to_vec());
+    /// ```
+    pub fn shared_handle(&self) -> DB {
+        DB {
+            location: self.location.clone(),
+            database: Arc::clone(&self.database),
+            options: Arc::clone(&self.options),
+            sync: self.sync,
+            state_updated: self.state_updated,
+        }
+    }
+
     /// updates the state_updated field according to the status of the state.
     /// every time the state is built (=true) and
     /// on the other hand when new deltas enter the DB (=false).
@@ -78,6 +137,129 @@ impl DB {
     pub fn get_state_status(& mut self) -> bool {
         self.state_updated
     }
+
+    /// Starts a new transaction over the DB.
+    ///
+    /// All the operations staged in the returned `DBTransaction` are kept in a `WriteBatch`
+    /// and are written to the DB together in one atomic operation when calling `commit`.
+    /// Dropping the transaction without committing discards all the staged operations.
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # use enigma_core_app::db::dal::{DB, CRUDInterface};
+    /// # use enigma_core_app::db::primitives::{DeltaKey, Stype};
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// # let mut db = DB::new(tempdir.path(), true).unwrap();
+    /// let delta_key = DeltaKey::new([2u8; 32].into(), Stype::Delta(0));
+    /// let state_key = DeltaKey::new([2u8; 32].into(), Stype::State);
+    /// let mut transaction = db.begin_transaction();
+    /// transaction.create(&delta_key, b"delta").unwrap();
+    /// transaction.force_update(&state_key, b"state").unwrap();
+    /// transaction.commit().unwrap();
+    /// assert_eq!(db.read(&state_key).unwrap(), b"state".to_vec());
+    /// ```
+    pub fn begin_transaction(&mut self) -> DBTransaction {
+        DBTransaction { db: self, batch: WriteBatch::default() }
+    }
+}
+
+/// A set of write operations which are applied to the DB atomically.
+///
+/// The checks for existing keys are done against the DB at the time the operation is staged,
+/// keys staged earlier in the same transaction are not taken into account.
+/// Column families (addresses) that don't exist yet are created when staging,
+/// so they might stay empty if the transaction is never committed.
+pub struct DBTransaction<'a> {
+    db: &'a mut DB,
+    batch: WriteBatch,
+}
+
+impl<'a> DBTransaction<'a> {
+    /// Stages a new Key-Value pair, fails if the key already exists in the DB.
+    pub fn create<K: SplitKey>(&mut self, key: &K, value: &[u8]) -> Result<(), Error> {
+        key.as_split(|hash, index_key| {
+            trace!("DB: Transaction Create: contract_address: {}, key: {:?}, value: {:?}", hash, index_key, value);
+            let cf_key = match self.db.database.cf_handle(hash) {
+                Some(cf) => cf,
+                None => self.db.database.create_cf(hash, &self.db.options)?,
+            };
+            if self.db.database.get_cf(cf_key, &index_key)?.is_some() {
+                return Err(DBErr { command: "transaction_create".to_string(), kind: DBErrKind::KeyExists(hash.to_string()) }.into());
+            }
+            self.batch.put_cf(cf_key, &index_key, value)?;
+            Ok(())
+        })
+    }
+
+    /// Stages an update of an existing key, fails if the key doesn't exist in the DB.
+    pub fn update<K: SplitKey>(&mut self, key: &K, value: &[u8]) -> Result<(), Error> {
+        key.as_split(|hash, index_key| {
+            trace!("DB: Transaction Update: contract_address: {}, key: {:?}, value: {:?}", hash, index_key, value);
+            let cf_key = self.db.database.cf_handle(&hash)
+                .ok_or(DBErr { command: "transaction_update".to_string(), kind: DBErrKind::MissingKey(hash.to_string()) })?;
+            if self.db.database.get_cf(cf_key, &index_key)?.is_none() {
+                return Err(DBErr { command: "transaction_update".to_string(), kind: DBErrKind::MissingKey(hash.to_string()) }.into());
+            }
+            self.batch.put_cf(cf_key, &index_key, value)?;
+            Ok(())
+        })
+    }
+
+    /// Stages a write of the key whether it exists or not.
+    pub fn force_update<K: SplitKey>(&mut self, key: &K, value: &[u8]) -> Result<(), Error> {
+        key.as_split(|hash, index_key| {
+            trace!("DB: Transaction Force Update: contract_address: {}, key: {:?}, value: {:?}", hash, index_key, value);
+            let cf_key = match self.db.database.cf_handle(hash) {
+                Some(cf) => cf,
+                None => self.db.database.create_cf(hash, &self.db.options)?,
+            };
+            self.batch.put_cf(cf_key, &index_key, value)?;
+            Ok(())
+        })
+    }
+
+    /// Stages a removal of an existing key, fails if the key doesn't exist in the DB.
+    pub fn delete<K: SplitKey>(&mut self, key: &K) -> Result<(), Error> {
+        key.as_split(|hash, index_key| {
+            trace!("DB: Transaction Delete: contract_address: {}, key: {:?}", hash, index_key);
+            let cf_key = self.db.database.cf_handle(&hash)
+                .ok_or(DBErr { command: "transaction_delete".to_string(), kind: DBErrKind::MissingKey(hash.to_string()) })?;
+            if self.db.database.get_cf(cf_key, &index_key)?.is_none() {
+                return Err(DBErr { command: "transaction_delete".to_string(), kind: DBErrKind::MissingKey(hash.to_string()) }.into());
+            }
+            self.batch.delete_cf(cf_key, &index_key)?;
+            Ok(())
+        })
+    }
+
+    /// Stages a removal of all the keys of the contract, fails if the contract doesn't exist in the DB.
+    /// Unlike `CRUDInterface::delete_contract` the column family is kept,
+    /// so new keys of the same contract can be staged in the same transaction.
+    pub fn delete_contract<K: SplitKey>(&mut self, key: &K) -> Result<(), Error> {
+        key.as_split(|hash, _| {
+            trace!("DB: Transaction Delete Contract: contract_address: {}", hash);
+            let cf_key = self.db.database.cf_handle(&hash)
+                .ok_or(DBErr { command: "transaction_delete_contract".to_string(), kind: DBErrKind::MissingKey(hash.to_string()) })?;
+            for (index_key, _) in self.db.database.iterator_cf(cf_key, IteratorMode::Start)? {
+                self.batch.delete_cf(cf_key, &index_key)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The number of operations staged so far.
+    pub fn len(&self) -> usize { self.batch.len() }
+
+    pub fn is_empty(&self) -> bool { self.batch.is_empty() }
+
+    /// Writes all the staged operations to the DB in one atomic operation.
+    #[logfn(TRACE)]
+    pub fn commit(self) -> Result<(), Error> {
+        self.db.database.write_opt(self.batch, &self.db.write_options())?;
+        Ok(())
+    }
 }
 
 pub trait CRUDInterface<E, K, T, V> {
@@ -209,9 +391,7 @@ impl<'a, K: SplitKey> CRUDInterface<Error, &'a K, Vec<u8>, &'a [u8]> for DB {
             match self.database.get_cf(cf_key, &index_key)? {
                 Some(_) => Err(DBErr { command: "create".to_string(), kind: DBErrKind::KeyExists(hash.to_string()) }.into()),
                 None => {
-                    let mut write_options = WriteOptions::default();
-                    write_options.set_sync(SYNC);
-                    self.database.put_cf_opt(cf_key, &index_key, &value, &write_options)?;
+                    self.database.put_cf_opt(cf_key, &index_key, &value, &self.write_options())?;
                     Ok(())
                 }
             }
@@ -238,9 +418,7 @@ impl<'a, K: SplitKey> CRUDInterface<Error, &'a K, Vec<u8>, &'a [u8]> for DB {
                 return Err(DBErr { command: "update".to_string(), kind: DBErrKind::MissingKey(hash.to_string()) }.into());
             }
 
-            let mut write_options = WriteOptions::default();
-            write_options.set_sync(SYNC);
-            self.database.put_cf_opt(cf_key, &index_key, value, &write_options)?;
+            self.database.put_cf_opt(cf_key, &index_key, value, &self.write_options())?;
             Ok(())
         })
     }
@@ -277,9 +455,7 @@ impl<'a, K: SplitKey> CRUDInterface<Error, &'a K, Vec<u8>, &'a [u8]> for DB {
                 Some(cf) => cf,
                 None => self.database.create_cf(hash, &self.options)?,
             };
-            let mut write_options = WriteOptions::default();
-            write_options.set_sync(SYNC);
-            self.database.put_cf_opt(cf_key, &index_key, value, &write_options)?;
+            self.database.put_cf_opt(cf_key, &index_key, value, &self.write_options())?;
             Ok(())
         })
     }
@@ -510,6 +686,88 @@ mod test {
         db.delete(&Array32u8(arr)).unwrap();
     }
 
+    #[test]
+    fn test_transaction_commit() {
+        let (mut db, _dir) = create_test_db();
+
+        let contract_address: ContractAddress = [6u8; 32].into();
+        let dk_delta = DeltaKey::new(contract_address, Stype::Delta(0));
+        let dk_state = DeltaKey::new(contract_address, Stype::State);
+        let dk_other = Array32u8([9u8; 32]);
+        db.create(&dk_other, b"Enigma").unwrap();
+
+        let mut transaction = db.begin_transaction();
+        transaction.create(&dk_delta, b"delta").unwrap();
+        transaction.force_update(&dk_state, b"state").unwrap();
+        transaction.delete(&dk_other).unwrap();
+        assert_eq!(transaction.len(), 3);
+        transaction.commit().unwrap();
+
+        assert_eq!(db.read(&dk_delta).unwrap(), b"delta");
+        assert_eq!(db.read(&dk_state).unwrap(), b"state");
+        assert!(db.read(&dk_other).is_err());
+    }
+
+    #[test]
+    fn test_transaction_not_committed() {
+        let (mut db, _dir) = create_test_db();
+
+        let contract_address: ContractAddress = [6u8; 32].into();
+        let dk_delta = DeltaKey::new(contract_address, Stype::Delta(0));
+        let dk_state = DeltaKey::new(contract_address, Stype::State);
+        {
+            let mut transaction = db.begin_transaction();
+            transaction.create(&dk_delta, b"delta").unwrap();
+            transaction.force_update(&dk_state, b"state").unwrap();
+        }
+        assert!(db.read(&dk_delta).is_err());
+        assert!(db.read(&dk_state).is_err());
+    }
+
+    #[test]
+    fn test_transaction_failed_staging() {
+        let (mut db, _dir) = create_test_db();
+
+        let contract_address: ContractAddress = [6u8; 32].into();
+        let dk_delta = DeltaKey::new(contract_address, Stype::Delta(0));
+        let dk_state = DeltaKey::new(contract_address, Stype::State);
+        db.create(&dk_delta, b"delta").unwrap();
+
+        let mut transaction = db.begin_transaction();
+        transaction.force_update(&dk_state, b"state").unwrap();
+        assert!(transaction.create(&dk_delta, b"other delta").is_err());
+        assert!(transaction.update(&DeltaKey::new(contract_address, Stype::Delta(1)), b"delta").is_err());
+        assert_eq!(transaction.len(), 1);
+    }
+
+    #[test]
+    fn test_transaction_replace_contract() {
+        let (mut db, _dir) = create_test_db();
+
+        let contract_address: ContractAddress = [6u8; 32].into();
+        let dk_delta = DeltaKey::new(contract_address, Stype::Delta(0));
+        let dk_state = DeltaKey::new(contract_address, Stype::State);
+        db.create(&dk_delta, b"delta").unwrap();
+        db.create(&dk_state, b"state").unwrap();
+
+        {
+            let mut transaction = db.begin_transaction();
+            transaction.delete_contract(&dk_delta).unwrap();
+            transaction.force_update(&dk_state, b"new state").unwrap();
+            assert_eq!(transaction.len(), 3);
+        }
+        // nothing is removed without a commit.
+        assert_eq!(db.read(&dk_delta).unwrap(), b"delta");
+
+        let mut transaction = db.begin_transaction();
+        transaction.delete_contract(&dk_delta).unwrap();
+        transaction.force_update(&dk_state, b"new state").unwrap();
+        transaction.commit().unwrap();
+        assert!(db.read(&dk_delta).is_err());
+        assert_eq!(db.read(&dk_state).unwrap(), b"new state");
+        assert!(db.begin_transaction().delete_contract(&DeltaKey::new([7u8; 32].into(), Stype::State)).is_err());
+    }
+
     #[test]
     #[should_panic]
     fn test_fail_creating_exist() {
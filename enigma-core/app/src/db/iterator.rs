@@ -6,8 +6,11 @@ use failure::Error;
 use hex::{FromHex, ToHex};
 use rocksdb::DB as rocks_db;
 use rocksdb::{Direction, IteratorMode, ReadOptions, WriteBatch};
+use std::collections::BinaryHeap;
 
-const DELTA_PREFIX: &[u8] = &[1];
+pub(crate) const DELTA_PREFIX: &[u8] = &[1];
+const SNAPSHOT_PREFIX: &[u8] = &[4];
+const BYTECODE_VERSION_PREFIX: &[u8] = &[5];
 
 type ResultVec<T> = Result<Vec<T>, Error>;
 pub type ResultTypeVec<T> = Result<ResultType<Vec<T>>, Error>;
@@ -228,6 +231,137 @@ pub trait P2PCalls {
     /// ```
     fn get_deltas<K: SplitKey>(&self, from: K, to: K) -> ResultTypeVec<(K, Vec<u8>)>;
 
+    /// the same as `get_deltas` but returns at most `limit` deltas from the beginning of the range.
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # extern crate enigma_types;
+    /// # use enigma_core_app::db::{dal::DB, primitives::{DeltaKey, Stype}, iterator::P2PCalls};
+    /// # use enigma_types::ContractAddress;
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// # let mut db = DB::new(tempdir.path(), true).unwrap();
+    /// # let contract_address: ContractAddress = [2u8; 32].into();
+    /// # let dk1 = DeltaKey {contract_address, key_type: Stype::Delta(1)};
+    /// # let val1 = b"Enigma".to_vec();
+    /// # let dk2 = DeltaKey {contract_address, key_type: Stype::Delta(2)};
+    /// # let val2 = b"MPC".to_vec();
+    /// # let key_vals = vec![(dk1, val1.clone()), (dk2, val2.clone())];
+    /// # let _ = db.insert_tuples(&key_vals);
+    /// let dk3 = DeltaKey {contract_address, key_type: Stype::Delta(3)};
+    /// let deltas = db.get_deltas_limited(dk1, dk3, 1).unwrap().unwrap();
+    /// assert_eq!(deltas, vec![(dk1, val1)]);
+    /// ```
+    fn get_deltas_limited<K: SplitKey>(&self, from: K, to: K, limit: usize) -> ResultTypeVec<(K, Vec<u8>)>;
+
+    /// returns the latest delta of up to `limit` addresses, ordered by address.
+    /// only addresses that are bigger than `after` (not included!) are returned,
+    /// so the last address of a page can be used as the `after` of the next page.
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # extern crate enigma_types;
+    /// # use enigma_core_app::db::{dal::DB, primitives::{DeltaKey, Stype}, iterator::P2PCalls};
+    /// # use enigma_types::ContractAddress;
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// # let mut db = DB::new(tempdir.path(), true).unwrap();
+    /// # let contract_address: ContractAddress = [2u8; 32].into();
+    /// # let other_contract_address: ContractAddress = [4u8; 32].into();
+    /// let dk = DeltaKey {contract_address, key_type: Stype::Delta(1)};
+    /// let other_dk = DeltaKey {contract_address: other_contract_address, key_type: Stype::Delta(1)};
+    /// let _ = db.insert_tuples(&vec![(dk, b"Enigma".to_vec()), (other_dk, b"MPC".to_vec())]);
+    ///
+    /// let first_page: Vec<(DeltaKey, Vec<u8>)> = db.get_tips_page(None, 1).unwrap();
+    /// assert_eq!(first_page[0].0, dk);
+    /// let second_page: Vec<(DeltaKey, Vec<u8>)> = db.get_tips_page(Some(contract_address), 1).unwrap();
+    /// assert_eq!(second_page[0].0, other_dk);
+    /// ```
+    fn get_tips_page<K: SplitKey>(&self, after: Option<ContractAddress>, limit: usize) -> ResultVec<(K, Vec<u8>)>;
+
+    /// returns the newest snapshot of the address which was taken at a delta index up to `max_index` (included!).
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # extern crate enigma_types;
+    /// # use enigma_core_app::db::{dal::DB, primitives::{DeltaKey, Stype}, iterator::P2PCalls};
+    /// # use enigma_types::ContractAddress;
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// # let mut db = DB::new(tempdir.path(), true).unwrap();
+    /// # let contract_address: ContractAddress = [2u8; 32].into();
+    /// let first = DeltaKey {contract_address, key_type: Stype::Snapshot(100)};
+    /// let second = DeltaKey {contract_address, key_type: Stype::Snapshot(200)};
+    /// let _ = db.insert_tuples(&vec![(first, b"Enigma".to_vec()), (second, b"MPC".to_vec())]);
+    ///
+    /// let (key, _): (DeltaKey, Vec<u8>) = db.get_latest_snapshot(&contract_address, u32::max_value()).unwrap();
+    /// assert_eq!(key, second);
+    /// let (key, _): (DeltaKey, Vec<u8>) = db.get_latest_snapshot(&contract_address, 199).unwrap();
+    /// assert_eq!(key, first);
+    /// ```
+    fn get_latest_snapshot<K: SplitKey>(&self, address: &ContractAddress, max_index: u32) -> Result<(K, Vec<u8>), Error>;
+
+    /// returns the delta indexes of all the snapshots of the address, from the oldest to the newest.
+    fn get_snapshot_indexes(&self, address: &ContractAddress) -> ResultVec<u32>;
+
+    /// returns the delta indexes of all the previous bytecodes of the address (see `Stype::ByteCodeVersion`),
+    /// from the oldest to the newest.
+    fn get_bytecode_version_indexes(&self, address: &ContractAddress) -> ResultVec<u32>;
+
+    /// removes all the snapshots of the address except for the newest `keep` snapshots,
+    /// in one atomic operation, and returns the delta indexes of the removed snapshots.
+    ///
+    /// once the lowest deltas were pruned (see `prune_deltas`) the newest snapshot is the only way
+    /// to rebuild the state, so removing all the snapshots is only allowed while the first delta exists.
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # extern crate enigma_types;
+    /// # use enigma_core_app::db::{dal::DB, primitives::{DeltaKey, Stype}, iterator::P2PCalls};
+    /// # use enigma_types::ContractAddress;
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// # let mut db = DB::new(tempdir.path(), true).unwrap();
+    /// # let contract_address: ContractAddress = [2u8; 32].into();
+    /// let snapshots: Vec<_> = (1..4).map(|i| (DeltaKey {contract_address, key_type: Stype::Snapshot(i * 100)}, vec![i as u8])).collect();
+    /// let _ = db.insert_tuples(&snapshots);
+    ///
+    /// assert_eq!(db.prune_snapshots(&contract_address, 1).unwrap(), vec![100, 200]);
+    /// assert_eq!(db.get_snapshot_indexes(&contract_address).unwrap(), vec![300]);
+    /// ```
+    fn prune_snapshots(&mut self, address: &ContractAddress, keep: usize) -> ResultVec<u32>;
+
+    /// removes all the deltas of the address with an index below `below` (not included!),
+    /// in one atomic operation, and then compacts that range of the contract's column family.
+    /// returns the number of removed deltas.
+    ///
+    /// this is only allowed if the deltas are covered by a snapshot with a delta index of at least `below`.
+    /// the state doesn't count, the DB can't tell which delta it was built from
+    /// (the state status is reset to up to date whenever the DB is opened).
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # extern crate enigma_types;
+    /// # use enigma_core_app::db::{dal::DB, primitives::{DeltaKey, Stype}, iterator::P2PCalls};
+    /// # use enigma_types::ContractAddress;
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// # let mut db = DB::new(tempdir.path(), true).unwrap();
+    /// # let contract_address: ContractAddress = [2u8; 32].into();
+    /// let mut data: Vec<_> = (0..10).map(|i| (DeltaKey {contract_address, key_type: Stype::Delta(i)}, vec![i as u8])).collect();
+    /// data.push((DeltaKey {contract_address, key_type: Stype::Snapshot(5)}, b"snapshot".to_vec()));
+    /// let _ = db.insert_tuples(&data);
+    ///
+    /// assert!(db.prune_deltas(&contract_address, 6).is_err());
+    /// assert_eq!(db.prune_deltas(&contract_address, 5).unwrap(), 5);
+    /// ```
+    fn prune_deltas(&mut self, address: &ContractAddress, below: u32) -> Result<usize, Error>;
+
     /// Inserts a list of Key-Values into the DB in one atomic operation
     /// # Examples
     /// ```
@@ -341,10 +475,15 @@ impl P2PCalls for DB {
         self.get_tips(&_address_list[..])
     }
 
-    // input: addresses_range : [Tuple(K, K)] where K is usually a DeltaKey.
-    // output: all keys & values from the first key (included!) up to the second key (not included!!)
     #[logfn(TRACE)]
     fn get_deltas<K: SplitKey>(&self, from: K, to: K) -> ResultTypeVec<(K, Vec<u8>)> {
+        self.get_deltas_limited(from, to, usize::max_value())
+    }
+
+    // input: addresses_range : [Tuple(K, K)] where K is usually a DeltaKey.
+    // output: up to `limit` keys & values from the first key (included!) up to the second key (not included!!)
+    #[logfn(TRACE)]
+    fn get_deltas_limited<K: SplitKey>(&self, from: K, to: K, limit: usize) -> ResultTypeVec<(K, Vec<u8>)> {
         // a vector for the output values which will consist of tuples: (key: K, value/delta: D)
         // convert the key to the rocksdb representation
         from.as_split(|from_hash, from_key| {
@@ -365,6 +504,7 @@ impl P2PCalls for DB {
                 let db_iter =
                     self.database.iterator_cf_opt(cf_key, &read_opts, IteratorMode::From(&from_key, Direction::Forward))?;
                 let key_val: Vec<(K, Vec<u8>)> = db_iter
+                    .take(limit)
                     .map(|(key, val)| {
                         // creating from the string of the address and the
                         // key of each result in the iterator a K type.
@@ -391,6 +531,110 @@ impl P2PCalls for DB {
         })
     }
 
+    #[logfn(TRACE)]
+    fn get_tips_page<K: SplitKey>(&self, after: Option<ContractAddress>, limit: usize) -> ResultVec<(K, Vec<u8>)> {
+        // the column families aren't ordered, so the page is selected while going over the addresses once.
+        // the heap only keeps the smallest `limit` addresses after the cursor, instead of sorting all of them.
+        let mut page = BinaryHeap::new();
+        for address in self.get_all_addresses()? {
+            if after.map_or(false, |after| address <= after) {
+                continue;
+            }
+            page.push(address);
+            if page.len() > limit {
+                page.pop();
+            }
+        }
+        self.get_tips(&page.into_sorted_vec()[..])
+    }
+
+    #[logfn(TRACE)]
+    fn get_latest_snapshot<K: SplitKey>(&self, address: &ContractAddress, max_index: u32) -> Result<(K, Vec<u8>), Error> {
+        let str_addr = address.to_hex();
+        let missing = || DBErr { command: "get_latest_snapshot".to_string(), kind: DBErrKind::MissingKey(str_addr.clone()) };
+        let cf_key = self.database.cf_handle(&str_addr).ok_or_else(missing)?;
+
+        let iter = self.database.prefix_iterator_cf(cf_key, SNAPSHOT_PREFIX)?;
+        let snapshot = iter
+            .filter(|(key, _)| key_index(SNAPSHOT_PREFIX, key).map_or(false, |index| index <= max_index))
+            .last()
+            .ok_or_else(missing)?;
+        Ok((K::from_split(&str_addr, &*snapshot.0)?, (&*snapshot.1).to_vec()))
+    }
+
+    #[logfn(TRACE)]
+    fn get_snapshot_indexes(&self, address: &ContractAddress) -> ResultVec<u32> {
+        let str_addr = address.to_hex();
+        let cf_key = self.database.cf_handle(&str_addr)
+            .ok_or(DBErr { command: "get_snapshot_indexes".to_string(), kind: DBErrKind::MissingKey(str_addr.clone()) })?;
+        let iter = self.database.prefix_iterator_cf(cf_key, SNAPSHOT_PREFIX)?;
+        Ok(iter.filter_map(|(key, _)| key_index(SNAPSHOT_PREFIX, &key)).collect())
+    }
+
+    #[logfn(TRACE)]
+    fn get_bytecode_version_indexes(&self, address: &ContractAddress) -> ResultVec<u32> {
+        let str_addr = address.to_hex();
+        let cf_key = self.database.cf_handle(&str_addr)
+            .ok_or(DBErr { command: "get_bytecode_version_indexes".to_string(), kind: DBErrKind::MissingKey(str_addr.clone()) })?;
+        let iter = self.database.prefix_iterator_cf(cf_key, BYTECODE_VERSION_PREFIX)?;
+        Ok(iter.filter_map(|(key, _)| key_index(BYTECODE_VERSION_PREFIX, &key)).collect())
+    }
+
+    #[logfn(TRACE)]
+    fn prune_snapshots(&mut self, address: &ContractAddress, keep: usize) -> ResultVec<u32> {
+        let mut indexes = self.get_snapshot_indexes(address)?;
+        let remove_count = indexes.len().saturating_sub(keep);
+        indexes.truncate(remove_count);
+        if indexes.is_empty() {
+            return Ok(indexes);
+        }
+        if keep == 0 && self.read(&DeltaKey::new(*address, Stype::Delta(0))).is_err() {
+            let lowest = indexes[indexes.len() - 1];
+            return Err(DBErr { command: "prune_snapshots".to_string(), kind: DBErrKind::UncoveredDeltas(lowest) }.into());
+        }
+        let mut transaction = self.begin_transaction();
+        for index in &indexes {
+            transaction.delete(&DeltaKey::new(*address, Stype::Snapshot(*index)))?;
+        }
+        transaction.commit()?;
+        Ok(indexes)
+    }
+
+    #[logfn(TRACE)]
+    fn prune_deltas(&mut self, address: &ContractAddress, below: u32) -> Result<usize, Error> {
+        let str_addr = address.to_hex();
+        let cf_key = self.database.cf_handle(&str_addr)
+            .ok_or(DBErr { command: "prune_deltas".to_string(), kind: DBErrKind::MissingKey(str_addr.clone()) })?;
+
+        let snapshot_index = self.get_snapshot_indexes(address)?.last().cloned();
+        if snapshot_index.map_or(true, |index| index < below) {
+            return Err(DBErr { command: "prune_deltas".to_string(), kind: DBErrKind::UncoveredDeltas(below) }.into());
+        }
+
+        let from = DeltaKey::new(*address, Stype::Delta(0));
+        let to = DeltaKey::new(*address, Stype::Delta(below));
+        from.as_split(|_, from_key| {
+            to.as_split(|_, to_key| {
+                let mut read_opts = ReadOptions::default();
+                read_opts.set_iterate_upper_bound(&to_key);
+                let db_iter =
+                    self.database.iterator_cf_opt(cf_key, &read_opts, IteratorMode::From(&from_key, Direction::Forward))?;
+                let mut batch = WriteBatch::default();
+                let mut pruned = 0;
+                for (key, _) in db_iter {
+                    batch.delete_cf(cf_key, &key)?;
+                    pruned += 1;
+                }
+                if pruned > 0 {
+                    self.database.write_opt(batch, &self.write_options())?;
+                    // the deleted keys only leave tombstones, the compaction is what actually frees the space.
+                    self.database.compact_range_cf(cf_key, Some(from_key), Some(to_key));
+                }
+                Ok(pruned)
+            })
+        })
+    }
+
     #[logfn(TRACE)]
     fn insert_tuples<K: SplitKey, S: AsRef<[u8]>>(&mut self, key_vals: &[(K, S)]) -> Vec<Result<(), Error>> {
         let mut res = Vec::with_capacity(key_vals.len());
@@ -406,13 +650,23 @@ impl P2PCalls for DB {
             });
             res.push(tmp_res);
         }
-        match self.database.write(batch) {
+        match self.database.write_opt(batch, &self.write_options()) {
             Ok(_) => res,
             Err(e) => vec![Err(e.into())],
         }
     }
 }
 
+// returns the delta index of a snapshot or a bytecode version key, the key is [prefix, index as big endian].
+fn key_index(prefix: &[u8], key: &[u8]) -> Option<u32> {
+    if key.len() != 5 || key[..1] != *prefix {
+        return None;
+    }
+    let mut be_bytes = [0u8; 4];
+    be_bytes.copy_from_slice(&key[1..]);
+    Some(u32::from_be_bytes(be_bytes))
+}
+
 #[cfg(test)]
 mod test {
     use db::{CRUDInterface, P2PCalls, tests::create_test_db};
@@ -732,6 +986,119 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_get_deltas_limited() {
+        let (mut db, _dir) = create_test_db();
+
+        let contract_address: ContractAddress = [7u8; 32].into();
+        let data: Vec<(DeltaKey, Vec<u8>)> = (0..10)
+            .map(|i| (DeltaKey::new(contract_address, Stype::Delta(i)), vec![i as u8; 4]))
+            .collect();
+        for res in db.insert_tuples(&data) {
+            res.unwrap();
+        }
+
+        let from = DeltaKey::new(contract_address, Stype::Delta(2));
+        let to = DeltaKey::new(contract_address, Stype::Delta(9));
+        let accepted_deltas = db.get_deltas_limited(from, to, 3).unwrap().unwrap();
+        assert_eq!(accepted_deltas, data[2..5].to_vec());
+
+        // a limit which is bigger than the range returns the whole range
+        let accepted_deltas = db.get_deltas_limited(from, to, 50).unwrap().unwrap();
+        assert_eq!(accepted_deltas, data[2..9].to_vec());
+    }
+
+    #[test]
+    fn test_get_tips_page() {
+        let (mut db, _dir) = create_test_db();
+
+        let addresses: Vec<ContractAddress> = vec![[9u8; 32].into(), [3u8; 32].into(), [5u8; 32].into()];
+        for address in &addresses {
+            let data = vec![
+                (DeltaKey::new(*address, Stype::Delta(0)), b"Enigma".to_vec()),
+                (DeltaKey::new(*address, Stype::Delta(1)), b"MPC".to_vec()),
+            ];
+            db.insert_tuples(&data);
+        }
+
+        let first_page: Vec<(DeltaKey, Vec<u8>)> = db.get_tips_page(None, 2).unwrap();
+        assert_eq!(first_page.len(), 2);
+        assert_eq!(first_page[0].0, DeltaKey::new(addresses[1], Stype::Delta(1)));
+        assert_eq!(first_page[1].0, DeltaKey::new(addresses[2], Stype::Delta(1)));
+
+        let second_page: Vec<(DeltaKey, Vec<u8>)> = db.get_tips_page(Some(addresses[2]), 2).unwrap();
+        assert_eq!(second_page.len(), 1);
+        assert_eq!(second_page[0].0, DeltaKey::new(addresses[0], Stype::Delta(1)));
+
+        let last_page: Vec<(DeltaKey, Vec<u8>)> = db.get_tips_page(Some(addresses[0]), 2).unwrap();
+        assert!(last_page.is_empty());
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let (mut db, _dir) = create_test_db();
+
+        let address: ContractAddress = [6u8; 32].into();
+        let data = vec![
+            (DeltaKey::new(address, Stype::Delta(0)), b"delta0".to_vec()),
+            (DeltaKey::new(address, Stype::Delta(600)), b"delta600".to_vec()),
+            (DeltaKey::new(address, Stype::State), b"state".to_vec()),
+            (DeltaKey::new(address, Stype::Snapshot(250)), b"snapshot250".to_vec()),
+            (DeltaKey::new(address, Stype::Snapshot(500)), b"snapshot500".to_vec()),
+        ];
+        for res in db.insert_tuples(&data) {
+            res.unwrap();
+        }
+        // the snapshots don't affect the deltas.
+        let tip: (DeltaKey, Vec<u8>) = db.get_tip(&address).unwrap();
+        assert_eq!(tip.0, DeltaKey::new(address, Stype::Delta(600)));
+
+        let latest: (DeltaKey, Vec<u8>) = db.get_latest_snapshot(&address, u32::max_value()).unwrap();
+        assert_eq!(latest, (DeltaKey::new(address, Stype::Snapshot(500)), b"snapshot500".to_vec()));
+        let older: (DeltaKey, Vec<u8>) = db.get_latest_snapshot(&address, 499).unwrap();
+        assert_eq!(older.0, DeltaKey::new(address, Stype::Snapshot(250)));
+        assert!(db.get_latest_snapshot::<DeltaKey>(&address, 249).is_err());
+
+        assert_eq!(db.get_snapshot_indexes(&address).unwrap(), vec![250, 500]);
+        assert_eq!(db.prune_snapshots(&address, 2).unwrap(), Vec::<u32>::new());
+        assert_eq!(db.prune_snapshots(&address, 1).unwrap(), vec![250]);
+        assert_eq!(db.get_snapshot_indexes(&address).unwrap(), vec![500]);
+        assert_eq!(db.read(&DeltaKey::new(address, Stype::State)).unwrap(), b"state".to_vec());
+
+        // after the deltas below the snapshot are pruned, the snapshot can't be removed.
+        assert_eq!(db.prune_deltas(&address, 500).unwrap(), 1);
+        assert!(db.prune_snapshots(&address, 0).is_err());
+        assert_eq!(db.get_snapshot_indexes(&address).unwrap(), vec![500]);
+    }
+
+    #[test]
+    fn test_prune_deltas() {
+        let (mut db, _dir) = create_test_db();
+
+        let address: ContractAddress = [5u8; 32].into();
+        let mut data: Vec<_> = (0..20).map(|i| (DeltaKey::new(address, Stype::Delta(i)), vec![i as u8])).collect();
+        data.push((DeltaKey::new(address, Stype::Snapshot(10)), b"snapshot".to_vec()));
+        for res in db.insert_tuples(&data) {
+            res.unwrap();
+        }
+        // without a state only the snapshot covers the deltas.
+        assert!(db.prune_deltas(&address, 11).is_err());
+        assert_eq!(db.prune_deltas(&address, 10).unwrap(), 10);
+        let remaining: Vec<(DeltaKey, Vec<u8>)> =
+            db.get_deltas(DeltaKey::new(address, Stype::Delta(0)), DeltaKey::new(address, Stype::Delta(20))).unwrap().unwrap();
+        assert_eq!(remaining.len(), 10);
+        assert_eq!(remaining[0].0, DeltaKey::new(address, Stype::Delta(10)));
+
+        // the state doesn't cover any delta, even when it's marked as up to date.
+        db.create(&DeltaKey::new(address, Stype::State), &b"state"[..]).unwrap();
+        db.update_state_status(true);
+        assert!(db.prune_deltas(&address, 19).is_err());
+        db.create(&DeltaKey::new(address, Stype::Snapshot(19)), &b"snapshot"[..]).unwrap();
+        assert_eq!(db.prune_deltas(&address, 19).unwrap(), 9);
+        let tip: (DeltaKey, Vec<u8>) = db.get_tip(&address).unwrap();
+        assert_eq!(tip.0, DeltaKey::new(address, Stype::Delta(19)));
+    }
+
     #[should_panic]
     #[test]
     fn test_get_deltas_different_hashes() {
@@ -1,6 +1,9 @@
+pub mod archive;
+pub mod config;
 pub mod dal;
 pub mod iterator;
 pub mod primitives;
+pub mod stats;
 
 pub use crate::db::dal::*;
 pub use crate::db::iterator::*;
@@ -25,6 +25,10 @@ pub enum Stype {
     Delta(u32),
     State,
     ByteCode,
+    // an encrypted copy of the state after applying the delta with this index.
+    Snapshot(u32),
+    // the bytecode the contract had before an upgrade, it made the deltas up to (and including) this index.
+    ByteCodeVersion(u32),
 }
 
 impl Stype {
@@ -34,6 +38,13 @@ impl Stype {
             _ => panic!("called `Stype::unwrap()` on a non `Delta` value"),
         }
     }
+
+    pub fn unwrap_snapshot(self) -> u32 {
+        match self {
+            Stype::Snapshot(val) => val,
+            _ => panic!("called `Stype::unwrap_snapshot()` on a non `Snapshot` value"),
+        }
+    }
 }
 
 use std::fmt::Debug;
@@ -72,6 +83,14 @@ impl SplitKey for DeltaKey {
             }
             Stype::State => key.push(2),    //type
             Stype::ByteCode => key.push(3), //type
+            Stype::Snapshot(num) => {
+                key.push(4); //type
+                key.extend_from_slice(&num.to_be_bytes());
+            }
+            Stype::ByteCodeVersion(num) => {
+                key.push(5); //type
+                key.extend_from_slice(&num.to_be_bytes());
+            }
         }
         f(&cf, &key)
     }
@@ -85,6 +104,16 @@ impl SplitKey for DeltaKey {
             },
             2 => Stype::State,
             3 => Stype::ByteCode,
+            4 => {
+                let mut be_bytes = [0u8; 4];
+                be_bytes.copy_from_slice(&_key_type[1..]);
+                Stype::Snapshot(u32::from_be_bytes(be_bytes))
+            },
+            5 => {
+                let mut be_bytes = [0u8; 4];
+                be_bytes.copy_from_slice(&_key_type[1..]);
+                Stype::ByteCodeVersion(u32::from_be_bytes(be_bytes))
+            },
             _ => bail!("Failed parsing the Key, key does not contain a correct index"),
         };
         // if the address is not a correct hex then it not a correct address.
@@ -133,4 +162,22 @@ mod tests {
             assert_eq!(key, expected_key);
         });
     }
+
+    #[test]
+    fn test_snapshot_key_split() {
+        let contract_address = [7u8; 32].into();
+        let key = DeltaKey::new(contract_address, Stype::Snapshot(1_000));
+        let (hash, index_key) = key.as_split(|hash, index_key| (hash.to_string(), index_key.to_vec()));
+        assert_eq!(index_key, vec![4, 0, 0, 3, 232]);
+        assert_eq!(DeltaKey::from_split(&hash, &index_key).unwrap(), key);
+    }
+
+    #[test]
+    fn test_bytecode_version_key_split() {
+        let contract_address = [7u8; 32].into();
+        let key = DeltaKey::new(contract_address, Stype::ByteCodeVersion(258));
+        let (hash, index_key) = key.as_split(|hash, index_key| (hash.to_string(), index_key.to_vec()));
+        assert_eq!(index_key, vec![5, 0, 0, 1, 2]);
+        assert_eq!(DeltaKey::from_split(&hash, &index_key).unwrap(), key);
+    }
 }
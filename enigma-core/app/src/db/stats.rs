@@ -0,0 +1,162 @@
+use common_u::errors::{DBErr, DBErrKind};
+use db::dal::DB;
+use db::iterator::{P2PCalls, DELTA_PREFIX};
+use db::primitives::{DeltaKey, SplitKey, Stype};
+use enigma_types::ContractAddress;
+use failure::Error;
+use hex::ToHex;
+use rocksdb::ColumnFamily;
+
+// the RocksDB properties that are summed over all the column families.
+const ESTIMATED_KEYS: &str = "rocksdb.estimate-num-keys";
+const SST_FILES_SIZE: &str = "rocksdb.total-sst-files-size";
+const MEMTABLES_SIZE: &str = "rocksdb.cur-size-all-mem-tables";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractStats {
+    pub address: String,
+    pub delta_count: u64,
+    pub tip_index: Option<u32>,
+    pub deltas_size: u64,
+    pub state_size: u64,
+    pub bytecode_size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DbProperties {
+    pub estimated_keys: u64,
+    pub sst_files_size: u64,
+    pub memtables_size: u64,
+}
+
+impl DB {
+    /// Returns the number of deltas, the latest delta index and the sizes of the data stored for the contract.
+    /// # Examples
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate enigma_core_app;
+    /// # use enigma_core_app::db::{dal::DB, primitives::{DeltaKey, Stype}, iterator::P2PCalls};
+    ///
+    /// # let tempdir = tempfile::tempdir().unwrap();
+    /// # let mut db = DB::new(tempdir.path(), true).unwrap();
+    /// let contract_address = [2u8; 32].into();
+    /// let dk1 = DeltaKey {contract_address, key_type: Stype::Delta(0)};
+    /// let dk2 = DeltaKey {contract_address, key_type: Stype::Delta(1)};
+    /// let _ = db.insert_tuples(&vec![(dk1, b"Enigma".to_vec()), (dk2, b"MPC".to_vec())]);
+    ///
+    /// let stats = db.get_contract_stats(&contract_address).unwrap();
+    /// assert_eq!(stats.delta_count, 2);
+    /// assert_eq!(stats.tip_index, Some(1));
+    /// assert_eq!(stats.deltas_size, 9);
+    /// ```
+    pub fn get_contract_stats(&self, address: &ContractAddress) -> Result<ContractStats, Error> {
+        let str_addr = address.to_hex();
+        let cf_key = self.database.cf_handle(&str_addr)
+            .ok_or(DBErr { command: "get_contract_stats".to_string(), kind: DBErrKind::MissingKey(str_addr.clone()) })?;
+        self.contract_stats(cf_key, address)
+    }
+
+    fn contract_stats(&self, cf_key: ColumnFamily, address: &ContractAddress) -> Result<ContractStats, Error> {
+        let str_addr = address.to_hex();
+        let mut stats = ContractStats { address: str_addr.clone(), ..Default::default() };
+        for (key, value) in self.database.prefix_iterator_cf(cf_key, DELTA_PREFIX)? {
+            stats.delta_count += 1;
+            stats.deltas_size += value.len() as u64;
+            // the deltas are sorted by their index so the last one is the tip.
+            stats.tip_index = Some(DeltaKey::from_split(&str_addr, &key)?.key_type.unwrap_delta());
+        }
+        stats.state_size = self.value_size(cf_key, &DeltaKey::new(*address, Stype::State))?;
+        stats.bytecode_size = self.value_size(cf_key, &DeltaKey::new(*address, Stype::ByteCode))?;
+        Ok(stats)
+    }
+
+    /// Returns the stats of all the contracts and the RocksDB properties summed over all the column families.
+    pub fn get_db_stats(&self) -> Result<(Vec<ContractStats>, DbProperties), Error> {
+        // there are no addresses when the DB is empty.
+        let addresses = self.get_all_addresses().unwrap_or_default();
+        self.stats_of(&addresses)
+    }
+
+    fn stats_of(&self, addresses: &[ContractAddress]) -> Result<(Vec<ContractStats>, DbProperties), Error> {
+        let mut contracts = Vec::with_capacity(addresses.len());
+        let mut properties = self.get_properties(None)?;
+        for address in addresses {
+            // a contract might be removed by the writer after its address was listed.
+            let cf_key = match self.database.cf_handle(&address.to_hex()) {
+                Some(cf_key) => cf_key,
+                None => continue,
+            };
+            contracts.push(self.contract_stats(cf_key, address)?);
+            let cf_properties = self.get_properties(Some(cf_key))?;
+            properties.estimated_keys += cf_properties.estimated_keys;
+            properties.sst_files_size += cf_properties.sst_files_size;
+            properties.memtables_size += cf_properties.memtables_size;
+        }
+        Ok((contracts, properties))
+    }
+
+    /// Returns the properties of a single column family, `None` is the default column family.
+    fn get_properties(&self, cf_key: Option<ColumnFamily>) -> Result<DbProperties, Error> {
+        let int_property = |name: &str| -> Result<u64, Error> {
+            let value = match cf_key {
+                Some(cf_key) => self.database.property_value_cf(cf_key, name)?,
+                None => self.database.property_value(name)?,
+            };
+            Ok(value.and_then(|v| v.parse().ok()).unwrap_or(0))
+        };
+        Ok(DbProperties {
+            estimated_keys: int_property(ESTIMATED_KEYS)?,
+            sst_files_size: int_property(SST_FILES_SIZE)?,
+            memtables_size: int_property(MEMTABLES_SIZE)?,
+        })
+    }
+
+    fn value_size(&self, cf_key: ColumnFamily, key: &DeltaKey) -> Result<u64, Error> {
+        key.as_split(|_, index_key| Ok(self.database.get_cf(cf_key, index_key)?.map_or(0, |value| value.len() as u64)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use db::{CRUDInterface, P2PCalls, tests::create_test_db};
+    use db::primitives::{DeltaKey, Stype};
+    use enigma_types::ContractAddress;
+    use hex::ToHex;
+
+    #[test]
+    fn test_get_db_stats() {
+        let (mut db, _dir) = create_test_db();
+        let (stats, _) = db.get_db_stats().unwrap();
+        assert!(stats.is_empty());
+
+        let address: ContractAddress = [4u8; 32].into();
+        let data: Vec<_> = (0..3).map(|i| (DeltaKey::new(address, Stype::Delta(i)), vec![0u8; 10])).collect();
+        for res in db.insert_tuples(&data) {
+            res.unwrap();
+        }
+        db.create(&DeltaKey::new(address, Stype::State), &[1u8; 7][..]).unwrap();
+        db.create(&DeltaKey::new(address, Stype::ByteCode), &[2u8; 5][..]).unwrap();
+        let bytecode_only: ContractAddress = [6u8; 32].into();
+        db.create(&DeltaKey::new(bytecode_only, Stype::ByteCode), &[2u8; 5][..]).unwrap();
+
+        let (mut stats, properties) = db.get_db_stats().unwrap();
+        stats.sort_by(|a, b| a.address.cmp(&b.address));
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].delta_count, 3);
+        assert_eq!(stats[0].tip_index, Some(2));
+        assert_eq!(stats[0].deltas_size, 30);
+        assert_eq!(stats[0].state_size, 7);
+        assert_eq!(stats[0].bytecode_size, 5);
+        assert_eq!(stats[1].delta_count, 0);
+        assert_eq!(stats[1].tip_index, None);
+        assert!(properties.estimated_keys > 0);
+
+        // an address whose contract was removed in the meantime is skipped.
+        let removed: ContractAddress = [8u8; 32].into();
+        let (stats, _) = db.stats_of(&[address, removed]).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].address, address.to_hex());
+    }
+}
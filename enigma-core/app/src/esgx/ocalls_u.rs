@@ -1,5 +1,5 @@
 #![allow(unused_attributes)]
-use crate::db::{CRUDInterface, DeltaKey, P2PCalls, ResultType, ResultTypeVec, Stype, DB};
+use crate::db::{CRUDInterface, DBTransaction, DeltaKey, P2PCalls, ResultType, ResultTypeVec, Stype, DB};
 use enigma_tools_m::utils::LockExpectMutex;
 use enigma_crypto::hash::Sha256;
 use enigma_types::{ContractAddress, EnclaveReturn, Hash256, RawPointer};
@@ -57,6 +57,163 @@ pub unsafe extern "C" fn ocall_new_delta(db_ptr: *const RawPointer,
 }
 
 
+#[no_mangle]
+pub unsafe extern "C" fn ocall_new_delta_and_state(db_ptr: *const RawPointer, contract_address: &ContractAddress,
+                                                   enc_delta: *const u8, delta_len: usize, delta_index_: *const u32,
+                                                   enc_state: *const u8, state_len: usize) -> EnclaveReturn {
+    let delta_index = ptr::read(delta_index_);
+    let encrypted_delta = slice::from_raw_parts(enc_delta, delta_len);
+    let encrypted_state = slice::from_raw_parts(enc_state, state_len);
+    let delta_key = DeltaKey::new(*contract_address, Stype::Delta(delta_index));
+    let state_key = DeltaKey::new(*contract_address, Stype::State);
+    let db: &mut DB = match (*db_ptr).get_mut_ref() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    // both keys are written in one batch so the tip and the state can never disagree.
+    let mut transaction = db.begin_transaction();
+    let res = transaction.force_update(&delta_key, encrypted_delta)
+        .and_then(|_| transaction.force_update(&state_key, encrypted_state))
+        .and_then(|_| transaction.commit());
+    match res {
+        Ok(_) => EnclaveReturn::Success,
+        Err(e) => {
+            error!("Failed storing delta: {:?} and state in db with: \"{}\" ", &delta_key, &e);
+            EnclaveReturn::OcallDBError
+        }
+    }
+}
+
+
+#[no_mangle]
+pub unsafe extern "C" fn ocall_new_deltas_and_states(db_ptr: *const RawPointer, entries: *const u8, entries_len: usize) -> EnclaveReturn {
+    let entries = slice::from_raw_parts(entries, entries_len);
+    let db: &mut DB = match (*db_ptr).get_mut_ref() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    // all the deltas and the states of a task are written in one batch, so a task is never stored partially.
+    let mut transaction = db.begin_transaction();
+    if let Err(e) = stage_deltas_and_states(&mut transaction, entries) {
+        return e;
+    }
+    match transaction.commit() {
+        Ok(_) => EnclaveReturn::Success,
+        Err(e) => {
+            error!("Failed storing the deltas and the states in db with: \"{}\" ", &e);
+            EnclaveReturn::OcallDBError
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ocall_upgrade_contract(db_ptr: *const RawPointer, contract_address: &ContractAddress, exe_code: *const u8,
+                                                exe_code_len: usize, entries: *const u8, entries_len: usize) -> EnclaveReturn {
+    let exe_code = slice::from_raw_parts(exe_code, exe_code_len);
+    let entries = slice::from_raw_parts(entries, entries_len);
+    let db: &mut DB = match (*db_ptr).get_mut_ref() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    // the old bytecode made all the deltas up to the current tip, `migrate` might add a delta after it.
+    let old_bytecode = match db.get_contract(*contract_address) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            error!("Failed reading the bytecode of the upgraded contract with: \"{}\" ", &e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    let tip = match db.get_tip::<DeltaKey>(contract_address) {
+        Ok((key, _)) => key.key_type.unwrap_delta(),
+        Err(e) => {
+            error!("Failed reading the tip of the upgraded contract with: \"{}\" ", &e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    let version_key = DeltaKey::new(*contract_address, Stype::ByteCodeVersion(tip));
+    // if there's already a version at this index, it's the one that made the deltas, the current one never ran.
+    let has_version = db.read(&version_key).is_ok();
+
+    // the new ExeCode, the old one as a version and the delta and the state of `migrate` are written in one batch,
+    // so the migrated state never runs under the old code.
+    let mut transaction = db.begin_transaction();
+    let mut res = transaction.force_update(&DeltaKey::new(*contract_address, Stype::ByteCode), exe_code);
+    if !has_version {
+        res = res.and_then(|_| transaction.force_update(&version_key, &old_bytecode));
+    }
+    if let Err(e) = res {
+        error!("Failed storing the bytecode of the upgraded contract in db with: \"{}\" ", &e);
+        return EnclaveReturn::OcallDBError
+    }
+    if let Err(e) = stage_deltas_and_states(&mut transaction, entries) {
+        return e;
+    }
+    match transaction.commit() {
+        Ok(_) => EnclaveReturn::Success,
+        Err(e) => {
+            error!("Failed storing the upgrade in db with: \"{}\" ", &e);
+            EnclaveReturn::OcallDBError
+        }
+    }
+}
+
+fn stage_deltas_and_states(transaction: &mut DBTransaction, mut entries: &[u8]) -> Result<(), EnclaveReturn> {
+    while !entries.is_empty() {
+        let (address, delta_index, encrypted_delta, encrypted_state) = match read_delta_and_state(&mut entries) {
+            Some(entry) => entry,
+            None => {
+                error!("Failed reading the deltas and the states, {} bytes are left", entries.len());
+                return Err(EnclaveReturn::OcallDBError)
+            }
+        };
+        let delta_key = DeltaKey::new(address, Stype::Delta(delta_index));
+        let state_key = DeltaKey::new(address, Stype::State);
+        let res = transaction.force_update(&delta_key, encrypted_delta)
+            .and_then(|_| transaction.force_update(&state_key, encrypted_state));
+        if let Err(e) = res {
+            error!("Failed storing delta: {:?} and state in db with: \"{}\" ", &delta_key, &e);
+            return Err(EnclaveReturn::OcallDBError)
+        }
+    }
+    Ok(())
+}
+
+// reads the next entry serialized by `save_deltas_and_states` in the enclave:
+// [contract address: 32 bytes][delta index: u32 BE][delta length: u32 BE][delta][state length: u32 BE][state]
+fn read_delta_and_state<'a>(entries: &mut &'a [u8]) -> Option<(ContractAddress, u32, &'a [u8], &'a [u8])> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        if data.len() < len {
+            return None;
+        }
+        let (head, tail) = data.split_at(len);
+        *data = tail;
+        Some(head)
+    }
+    fn take_u32(data: &mut &[u8]) -> Option<u32> {
+        let mut be_bytes = [0u8; 4];
+        be_bytes.copy_from_slice(take(data, 4)?);
+        Some(u32::from_be_bytes(be_bytes))
+    }
+    let mut address = ContractAddress::default();
+    address.copy_from_slice(take(entries, 32)?);
+    let delta_index = take_u32(entries)?;
+    let delta_len = take_u32(entries)? as usize;
+    let delta = take(entries, delta_len)?;
+    let state_len = take_u32(entries)? as usize;
+    let state = take(entries, state_len)?;
+    Some((address, delta_index, delta, state))
+}
+
+
 #[no_mangle]
 pub unsafe extern "C" fn ocall_get_state_size(db_ptr: *const RawPointer, addr: &ContractAddress, state_size: *mut usize) -> EnclaveReturn {
     let mut cache_id = addr.to_vec();
@@ -114,6 +271,117 @@ pub unsafe extern "C" fn ocall_get_state(db_ptr: *const RawPointer, addr: &Contr
 }
 
 
+#[no_mangle]
+pub unsafe extern "C" fn ocall_new_snapshot(db_ptr: *const RawPointer, contract_address: &ContractAddress,
+                                            enc_state: *const u8, state_len: usize, delta_index_: *const u32) -> EnclaveReturn {
+    let delta_index = ptr::read(delta_index_);
+    let encrypted_state = slice::from_raw_parts(enc_state, state_len);
+    let key = DeltaKey::new(*contract_address, Stype::Snapshot(delta_index));
+    let db: &mut DB = match (*db_ptr).get_mut_ref() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    match db.force_update(&key, encrypted_state) {
+        Ok(_) => EnclaveReturn::Success,
+        Err(e) => {
+            error!("Failed creating key in db: {:?} with: \"{}\" ", &key, &e);
+            EnclaveReturn::OcallDBError
+        }
+    }
+}
+
+
+/// Finds the newest snapshot taken at a delta index up to `max_index` and returns its index and size.
+#[no_mangle]
+pub unsafe extern "C" fn ocall_get_snapshot_size(db_ptr: *const RawPointer, addr: &ContractAddress, max_index: *const u32,
+                                                 delta_index: *mut u32, state_size: *mut usize) -> EnclaveReturn {
+    let db: &mut DB = match (*db_ptr).get_mut_ref() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    match db.get_latest_snapshot::<DeltaKey>(addr, *max_index) {
+        Ok((key, snapshot)) => {
+            *delta_index = key.key_type.unwrap_snapshot();
+            *state_size = snapshot.len();
+            EnclaveReturn::Success
+        }
+        Err(_) => EnclaveReturn::OcallDBError,
+    }
+}
+
+
+#[no_mangle]
+pub unsafe extern "C" fn ocall_get_snapshot(db_ptr: *const RawPointer, addr: &ContractAddress, delta_index: *const u32,
+                                            state_ptr: *mut u8, state_size: usize) -> EnclaveReturn {
+    let db: &mut DB = match (*db_ptr).get_mut_ref() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    let key = DeltaKey::new(*addr, Stype::Snapshot(*delta_index));
+    match db.read(&key) {
+        Ok(snapshot) => {
+            if snapshot.len() != state_size {
+                return EnclaveReturn::OcallError;
+            }
+            enigma_types::write_ptr(&snapshot, state_ptr, state_size);
+            EnclaveReturn::Success
+        }
+        Err(_) => EnclaveReturn::OcallDBError,
+    }
+}
+
+
+#[no_mangle]
+pub unsafe extern "C" fn ocall_get_contract_size(db_ptr: *const RawPointer, addr: &ContractAddress, bytecode_size: *mut usize) -> EnclaveReturn {
+    let db: &mut DB = match (*db_ptr).get_mut_ref() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    match db.get_contract(*addr) {
+        Ok(bytecode) => {
+            *bytecode_size = bytecode.len();
+            EnclaveReturn::Success
+        }
+        Err(_) => EnclaveReturn::OcallDBError,
+    }
+}
+
+
+#[no_mangle]
+pub unsafe extern "C" fn ocall_get_contract(db_ptr: *const RawPointer, addr: &ContractAddress,
+                                            bytecode_ptr: *mut u8, bytecode_size: usize) -> EnclaveReturn {
+    let db: &mut DB = match (*db_ptr).get_mut_ref() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{}", e);
+            return EnclaveReturn::OcallDBError
+        }
+    };
+    match db.get_contract(*addr) {
+        Ok(bytecode) => {
+            if bytecode.len() != bytecode_size {
+                return EnclaveReturn::OcallError;
+            }
+            enigma_types::write_ptr(&bytecode, bytecode_ptr, bytecode_size);
+            EnclaveReturn::Success
+        }
+        Err(_) => EnclaveReturn::OcallDBError,
+    }
+}
+
+
 #[no_mangle]
 pub unsafe extern "C" fn ocall_get_deltas_sizes(db_ptr: *const RawPointer, addr: &ContractAddress,
                                                 start: *const u32, end: *const u32,
@@ -9,17 +9,18 @@ use std::str::FromStr;
 
 pub use enigma_core_app::*;
 pub use esgx::ocalls_u::{ocall_get_deltas, ocall_get_deltas_sizes, ocall_get_state, ocall_get_state_size,
-                                ocall_new_delta, ocall_update_state, ocall_remove_delta};
+                                ocall_new_delta, ocall_update_state, ocall_remove_delta,
+                                ocall_new_delta_and_state, ocall_new_deltas_and_states, ocall_upgrade_contract, ocall_new_snapshot, ocall_get_snapshot_size,
+                                ocall_get_snapshot, ocall_get_contract_size, ocall_get_contract};
 
 pub use enigma_tools_u::esgx::ocalls_u::{ocall_get_home, ocall_save_to_memory};
 use enigma_tools_u::common_u::logging;
 use enigma_tools_u::common_u::os;
 
-use networking::{ipc_listener, IpcListener};
-use db::DB;
-use cli::Opt;
+use networking::IpcListener;
+use db::{archive, config::DbConfig, DB};
+use cli::{Command, Opt};
 use structopt::StructOpt;
-use futures::Future;
 
 
 fn main() {
@@ -33,16 +34,40 @@ fn main() {
 
     debug!("CLI params: {:?}", opt);
 
+    let db_config = opt.db_config().expect("Failed loading the DB configuration");
+
+    if let Some(cmd) = opt.cmd {
+        run_command(datadir, &db_config, cmd).unwrap();
+        return;
+    }
 
     let enclave = esgx::general::init_enclave_wrapper().map_err(|e| {error!("Init Enclave Failed {:?}", e);}).unwrap();
     let eid = enclave.geteid();
     info!("Init Enclave Successful. Enclave id {}", eid);
 
-    let mut db = DB::new(datadir, true).expect("Failed initializing the DB");
+    if let Some(path) = &opt.gas_schedule {
+        let schedule = std::fs::read(path).expect("Failed reading the gas schedule");
+        let hash = wasm_u::wasm::set_gas_schedule(eid, &schedule).expect("Failed setting the gas schedule");
+        let hash: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+        info!("Using the gas schedule from {:?}, hash: {}", path, hash);
+    }
+
+    let db = DB::with_config(datadir, true, &db_config).expect("Failed initializing the DB");
     let server = IpcListener::new(&format!("tcp://*:{}", opt.port));
 
-    server
-        .run(move |multi| ipc_listener::handle_message(&mut db, multi, &opt.spid, eid, opt.retries))
-        .wait()
-        .unwrap();
-}
\ No newline at end of file
+    server.run_pool(db, opt.workers, opt.spid, eid, opt.retries).unwrap();
+}
+
+fn run_command(datadir: std::path::PathBuf, db_config: &DbConfig, cmd: Command) -> Result<(), failure::Error> {
+    match cmd {
+        // the DB is locked by a running node, so it has to be stopped before exporting.
+        Command::Export { address, file } => {
+            let db = DB::with_config(datadir, false, db_config)?;
+            archive::export_to_file(&db, address, file).map(|_| ())
+        }
+        Command::Import { file, force } => {
+            let mut db = DB::with_config(datadir, true, db_config)?;
+            archive::import_from_file(&mut db, file, force).map(|_| ())
+        }
+    }
+}
@@ -1,64 +1,248 @@
 use crate::networking::messages::*;
+use crate::common_u::errors::{P2PDecodeErr, P2PErr};
 use crate::db::DB;
 use futures::{Future, Stream};
 use sgx_types::sgx_enclave_id_t;
-use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, SendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use tokio_zmq::prelude::*;
 use tokio_zmq::{Error, Multipart, Rep};
 
+// the in-process endpoint that the workers of `IpcListener::run_pool` send their responses to.
+const RESPONSES_ENDPOINT: &str = "inproc://ipc-responses";
+
+/// A frame of a request as it was decoded, with its encoding so the response is encoded the same way.
+pub type DecodedFrame = (Encoding, Result<IpcMessageRequest, P2PDecodeErr>);
+
+/// A request that the router hands to a worker.
+struct Job {
+    /// the frames that route the response back to the client, i.e. [client identity, empty delimiter].
+    envelope: Vec<Vec<u8>>,
+    frames: Vec<DecodedFrame>,
+}
+
 pub struct IpcListener {
     _context: Arc<zmq::Context>,
-    rep_future: Box<dyn Future<Item = Rep, Error = Error>>,
+    conn_str: String,
 }
 
 impl IpcListener {
     pub fn new(conn_str: &str) -> Self {
         let _context = Arc::new(zmq::Context::new());
-        let rep_future = Rep::builder(_context.clone()).bind(conn_str).build();
-        debug!("Binded to socket: {}", conn_str);
-        IpcListener { _context, rep_future }
+        IpcListener { _context, conn_str: conn_str.to_string() }
     }
 
+    /// Handles all the requests one after the other using `f`.
     pub fn run<F>(self, f: F) -> impl Future<Item = (), Error = Error>
     where F: FnMut(Multipart) -> Multipart {
-        self.rep_future.and_then(|rep| {
+        let rep_future = Rep::builder(self._context.clone()).bind(&self.conn_str).build();
+        debug!("Binded to socket: {}", self.conn_str);
+        rep_future.and_then(|rep| {
             let (sink, stream) = rep.sink_stream(25).split();
             stream.map(f).forward(sink).map(|(_stream, _sink)| ())
         })
     }
+
+    /// Handles the requests with a pool of workers.
+    ///
+    /// The read only requests (see `IpcRequest::is_read_only`) are handled concurrently by `readers` workers,
+    /// each with its own handle to the DB, so a long running computation doesn't block them.
+    /// All the other requests go to a single worker which owns `db`,
+    /// so everything that writes to the DB or enters the enclave is still serialized.
+    ///
+    /// The socket is a ROUTER so the responses are routed back to the client that sent the request,
+    /// and every response carries the `id` of its request. Every frame is decoded once, here, and the workers
+    /// get the decoded requests. This blocks the current thread.
+    pub fn run_pool(self, mut db: DB, readers: usize, spid: String, eid: sgx_enclave_id_t, retries: u32) -> Result<(), zmq::Error> {
+        let frontend = self._context.socket(zmq::ROUTER)?;
+        frontend.bind(&self.conn_str)?;
+        debug!("Binded to socket: {}", self.conn_str);
+        // inproc endpoints have to be bound before any worker connects to them.
+        let responses = self._context.socket(zmq::PULL)?;
+        responses.bind(RESPONSES_ENDPOINT)?;
+
+        // the readers share one queue, so every job is taken by a reader which is free.
+        let (readers_queue, readers_jobs) = mpsc::channel();
+        let readers_jobs = Arc::new(Mutex::new(readers_jobs));
+        for _ in 0..readers.max(1) {
+            let reader_db = db.shared_handle();
+            let context = self._context.clone();
+            let jobs = readers_jobs.clone();
+            thread::spawn(move || {
+                run_worker(&context, || jobs.lock().ok()?.recv().ok(), |request| handle_read_request(&reader_db, request))
+            });
+        }
+        let (writer_queue, writer_jobs) = mpsc::channel();
+        let context = self._context.clone();
+        thread::spawn(move || {
+            run_worker(&context, || writer_jobs.recv().ok(), |request| handle_request(&mut db, request, &spid, eid, retries))
+        });
+
+        loop {
+            let mut items = [frontend.as_poll_item(zmq::POLLIN), responses.as_poll_item(zmq::POLLIN)];
+            zmq::poll(&mut items, -1)?;
+            if items[0].is_readable() {
+                let mut envelope = frontend.recv_multipart(0)?;
+                // the frames are [client identity, empty delimiter, request frames...]
+                let payload = envelope.iter().position(|f| f.is_empty()).map_or(envelope.len(), |i| i + 1);
+                let frames = envelope.split_off(payload).iter().map(|frame| decode_frame(frame)).collect();
+                let job = Job { envelope, frames };
+                let queue = if is_read_only_request(&job.frames) { &readers_queue } else { &writer_queue };
+                // the workers only stop if their socket fails, the client still gets an answer.
+                if let Err(SendError(Job { envelope, frames })) = queue.send(job) {
+                    let responses = handle_decoded(frames, |_| {
+                        Err(P2PErr { cmd: "run_pool".to_string(), msg: "The IPC worker stopped".to_string() }.into())
+                    });
+                    send_frames(&frontend, response_frames(envelope, responses))?;
+                }
+            }
+            if items[1].is_readable() {
+                send_frames(&frontend, responses.recv_multipart(0)?)?;
+            }
+        }
+    }
 }
 
-pub fn handle_message(db: &mut DB, request: Multipart, spid: &str, eid: sgx_enclave_id_t, retries: u32) -> Multipart {
+fn response_frames(mut envelope: Vec<Vec<u8>>, responses: Multipart) -> Vec<Vec<u8>> {
+    envelope.extend(responses.into_iter().map(|msg| msg.to_vec()));
+    envelope
+}
+
+fn decode_frame(frame: &[u8]) -> DecodedFrame {
+    let encoding = Encoding::detect(frame);
+    (encoding, IpcMessageRequest::from_frame(frame, encoding))
+}
+
+/// Returns true only if every frame of the request is a read only request,
+/// frames that can't be decoded are answered by the readers with an error.
+fn is_read_only_request(frames: &[DecodedFrame]) -> bool {
+    frames.iter().all(|(_, msg)| msg.as_ref().map(|msg| msg.request.is_read_only()).unwrap_or(true))
+}
+
+fn send_frames(socket: &zmq::Socket, frames: Vec<Vec<u8>>) -> Result<(), zmq::Error> {
+    let last = frames.len().saturating_sub(1);
+    for (i, frame) in frames.into_iter().enumerate() {
+        let flags = if i < last { zmq::SNDMORE } else { 0 };
+        socket.send(&frame[..], flags)?;
+    }
+    Ok(())
+}
+
+/// Handles the jobs from `next_job` with `f` until there are no more jobs,
+/// and sends the responses back to the router.
+fn run_worker<J, F>(context: &zmq::Context, mut next_job: J, mut f: F)
+where
+    J: FnMut() -> Option<Job>,
+    F: FnMut(IpcRequest) -> handling::ResponseResult,
+{
+    let mut run = || -> Result<(), zmq::Error> {
+        let socket = context.socket(zmq::PUSH)?;
+        socket.connect(RESPONSES_ENDPOINT)?;
+        while let Some(Job { envelope, frames }) = next_job() {
+            let responses = handle_decoded(frames, &mut f);
+            send_frames(&socket, response_frames(envelope, responses))?;
+        }
+        Ok(())
+    };
+    if let Err(e) = run() {
+        error!("IPC worker stopped: {}", e);
+    }
+}
+
+/// Decodes every frame, handles it with `f` and encodes the response in the same encoding as the request.
+fn handle_frames<F>(request: Multipart, f: F) -> Multipart
+where F: FnMut(IpcRequest) -> handling::ResponseResult {
+    let frames = request.into_iter().map(|msg| decode_frame(&msg)).collect();
+    handle_decoded(frames, f)
+}
+
+/// Handles every decoded frame with `f` and encodes the response in the same encoding as the request.
+/// A read only handler that panics is answered with an error, so a bad request can't take down a reader.
+/// The other handlers write to the DB and enter the enclave, and a panic in the middle may leave them inconsistent,
+/// so their panics aren't caught and take down the worker, after that the router answers the writes with an error.
+fn handle_decoded<F>(frames: Vec<DecodedFrame>, mut f: F) -> Multipart
+where F: FnMut(IpcRequest) -> handling::ResponseResult {
     let mut responses = Multipart::new();
-    for msg in request {
-        let msg: IpcMessageRequest = msg.into();
+    for (encoding, msg) in frames {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("{}", e);
+                let response: IpcMessageResponse = e.into();
+                responses.push_back(response.into_message(encoding));
+                continue;
+            }
+        };
         let id = msg.id.clone();
-        let response_msg = match msg.request {
-            IpcRequest::GetRegistrationParams => handling::get_registration_params(eid, spid, retries),
-            IpcRequest::GetTip { input } => handling::get_tip(db, &input),
-            IpcRequest::GetTips { input } => handling::get_tips(db, &input),
-            IpcRequest::GetAllTips => handling::get_all_tips(db),
-            IpcRequest::GetAllAddrs => handling::get_all_addrs(db),
-            IpcRequest::GetDelta { input } => handling::get_delta(db, input),
-            IpcRequest::GetDeltas { input } => handling::get_deltas(db, &input),
-            IpcRequest::GetContract { input } => handling::get_contract(db, &input),
-            IpcRequest::UpdateNewContract { address, bytecode } => handling::update_new_contract(db, address, &bytecode),
-            IpcRequest::UpdateNewContractOnDeployment { address, bytecode, delta } => handling::update_new_contract_on_deployment(db, address, &bytecode, delta),
-            IpcRequest::RemoveContract {address } => handling::remove_contract(db, address),
-            IpcRequest::UpdateDeltas { deltas } => handling::update_deltas(db, deltas),
-            IpcRequest::RemoveDeltas { input } => handling::remove_deltas(db, input),
-            IpcRequest::NewTaskEncryptionKey { user_pubkey } => handling::get_dh_user_key( &user_pubkey, eid),
-            IpcRequest::DeploySecretContract { input } => handling::deploy_contract(db, input, eid),
-            IpcRequest::ComputeTask { input } => handling::compute_task(db, input, eid),
-            IpcRequest::GetPTTRequest => handling::get_ptt_req(eid),
-            IpcRequest::PTTResponse { input } => handling::ptt_response(db, &input, eid),
+        let request = msg.request;
+        let response_msg = if request.is_read_only() {
+            panic::catch_unwind(AssertUnwindSafe(|| f(request))).unwrap_or_else(|cause| {
+                let cause = cause.downcast_ref::<&str>().map(|s| s.to_string()).or_else(|| cause.downcast_ref::<String>().cloned());
+                let msg = format!("The request panicked: {}", cause.unwrap_or_default());
+                Err(P2PErr { cmd: "handle_frames".to_string(), msg }.into())
+            })
+        } else {
+            f(request)
         };
         let msg = IpcMessageResponse::from_response(response_msg.unwrap_or_error(), id);
-        responses.push_back(msg.into());
+        responses.push_back(msg.into_message(encoding));
     }
     responses
 }
 
+pub fn handle_message(db: &mut DB, request: Multipart, spid: &str, eid: sgx_enclave_id_t, retries: u32) -> Multipart {
+    handle_frames(request, |request| handle_request(db, request, spid, eid, retries))
+}
+
+/// Handles only the read only requests, so it can run concurrently on a shared handle of the DB.
+pub fn handle_read_message(db: &DB, request: Multipart) -> Multipart {
+    handle_frames(request, |request| handle_read_request(db, request))
+}
+
+fn handle_request(db: &mut DB, request: IpcRequest, spid: &str, eid: sgx_enclave_id_t, retries: u32) -> handling::ResponseResult {
+    match request {
+        IpcRequest::GetRegistrationParams => handling::get_registration_params(eid, spid, retries),
+        IpcRequest::GetTip { input } => handling::get_tip(db, &input),
+        IpcRequest::GetTips { input } => handling::get_tips(db, &input),
+        IpcRequest::GetAllTips { limit, cursor } => handling::get_all_tips(db, limit, cursor),
+        IpcRequest::GetAllAddrs => handling::get_all_addrs(db),
+        IpcRequest::GetDelta { input } => handling::get_delta(db, input),
+        IpcRequest::GetDeltas { input, limit, cursor } => handling::get_deltas(db, &input, limit, cursor),
+        IpcRequest::GetContract { input } => handling::get_contract(db, &input),
+        IpcRequest::GetDbStats => handling::get_db_stats(db),
+        IpcRequest::UpdateNewContract { address, bytecode } => handling::update_new_contract(db, address, &bytecode),
+        IpcRequest::UpdateNewContractOnDeployment { address, bytecode, delta } => handling::update_new_contract_on_deployment(db, address, &bytecode, delta),
+        IpcRequest::RemoveContract {address } => handling::remove_contract(db, address),
+        IpcRequest::UpdateDeltas { deltas } => handling::update_deltas(db, deltas),
+        IpcRequest::RemoveDeltas { input } => handling::remove_deltas(db, input),
+        IpcRequest::NewTaskEncryptionKey { user_pubkey } => handling::get_dh_user_key( &user_pubkey, eid),
+        IpcRequest::DeploySecretContract { input } => handling::deploy_contract(db, input, eid),
+        IpcRequest::UpgradeSecretContract { input } => handling::upgrade_contract(db, input, eid),
+        IpcRequest::ComputeTask { input } => handling::compute_task(db, input, eid),
+        IpcRequest::QueryTask { input } => handling::query_task(db, input, eid),
+        IpcRequest::GetPTTRequest => handling::get_ptt_req(eid),
+        IpcRequest::PTTResponse { input } => handling::ptt_response(db, &input, eid),
+        IpcRequest::PruneSnapshots { address, keep } => handling::prune_snapshots(db, address, keep),
+        IpcRequest::PruneDeltas { address, below } => handling::prune_deltas(db, address, below),
+    }
+}
+
+fn handle_read_request(db: &DB, request: IpcRequest) -> handling::ResponseResult {
+    match request {
+        IpcRequest::GetTip { input } => handling::get_tip(db, &input),
+        IpcRequest::GetTips { input } => handling::get_tips(db, &input),
+        IpcRequest::GetAllTips { limit, cursor } => handling::get_all_tips(db, limit, cursor),
+        IpcRequest::GetAllAddrs => handling::get_all_addrs(db),
+        IpcRequest::GetDelta { input } => handling::get_delta(db, input),
+        IpcRequest::GetDeltas { input, limit, cursor } => handling::get_deltas(db, &input, limit, cursor),
+        IpcRequest::GetContract { input } => handling::get_contract(db, &input),
+        IpcRequest::GetDbStats => handling::get_db_stats(db),
+        _ => Err(P2PErr { cmd: "handle_read_message".to_string(), msg: "Not a read only request".to_string() }.into()),
+    }
+}
+
 
 // TODO: Make sure that every ? that doesn't require responding with a empty Message is replaced with an appropriate handling
 pub(self) mod handling {
@@ -72,7 +256,7 @@ pub(self) mod handling {
     use enigma_crypto::hash::Keccak256;
     use enigma_tools_u::esgx::equote as equote_tools;
     use enigma_tools_u::attestation_service::{service::AttestationService, constants::ATTESTATION_SERVICE_URL};
-    use enigma_types::ContractAddress;
+    use enigma_types::{ContractAddress, TaskContext, TaskOptions};
     use failure::Error;
     use hex::{FromHex, ToHex};
     use rmp_serde::Deserializer;
@@ -82,10 +266,14 @@ pub(self) mod handling {
     use std::str;
     use common_u::errors;
 
-    type ResponseResult = Result<IpcResponse, Error>;
+    pub type ResponseResult = Result<IpcResponse, Error>;
 
     static DEPLOYMENT_VALS_LEN: usize = 2;
     static FAILED_STATE: i64 = -1;
+    // the page size when a cursor is sent without a limit.
+    static DEFAULT_PAGE_LIMIT: usize = 500;
+    // the biggest page a client can ask for, so a page never takes more than a bounded amount of memory.
+    static MAX_PAGE_LIMIT: usize = 10_000;
 
     impl Into<IpcResponse> for WasmTaskFailure{
         fn into(self) -> IpcResponse {
@@ -93,36 +281,93 @@ pub(self) mod handling {
                 used_gas: self.used_gas,
                 output: self.output.to_hex(),
                 signature: self.signature.to_hex(),
+                gas_profile: self.gas_profile,
             };
             IpcResponse::FailedTask { result }
         }
     }
 
     impl WasmTaskResult {
+        /// The first ethereum payload, for the clients that only know a single one, and all of them.
+        fn ipc_eth_bridges(&self) -> (String, String, Vec<IpcEthBridge>) {
+            let bridges: Vec<IpcEthBridge> = self.eth_bridges
+                .iter()
+                .map(|bridge| IpcEthBridge { address: bridge.contract_addr.to_hex(), payload: bridge.payload.to_hex() })
+                .collect();
+            match bridges.first() {
+                Some(first) => (first.address.clone(), first.payload.clone(), bridges),
+                None => ([0u8; 20].to_hex(), String::new(), bridges),
+            }
+        }
+
         pub fn into_execute_response(self) -> IpcResponse {
+            let (ethereum_address, ethereum_payload, ethereum_bridges) = self.ipc_eth_bridges();
+            let called_deltas = self.called_deltas
+                .into_iter()
+                .map(|delta| IpcDelta { contract_address: Some(delta.key.contract_address.to_hex()), ..delta.into() })
+                .collect();
+            let events = self.events
+                .into_iter()
+                .map(|event| IpcEvent { address: event.contract_address.to_hex(), topic: event.topic.to_hex(), data: event.data.to_hex() })
+                .collect();
             let result = IpcResults::ComputeResult {
                 used_gas: self.used_gas,
                 output: self.output.to_hex(),
                 delta: self.delta.into(),
-                ethereum_address: self.eth_contract_addr.to_hex(),
-                ethereum_payload: self.eth_payload.to_hex(),
+                called_deltas,
+                events,
+                ethereum_address,
+                ethereum_payload,
+                ethereum_bridges,
                 signature: self.signature.to_hex(),
+                gas_profile: self.gas_profile,
             };
             IpcResponse::ComputeTask { result }
         }
 
+        pub fn into_query_response(self) -> IpcResponse {
+            let result = IpcResults::QueryResult {
+                used_gas: self.used_gas,
+                output: self.output.to_hex(),
+                signature: self.signature.to_hex(),
+                gas_profile: self.gas_profile,
+            };
+            IpcResponse::QueryTask { result }
+        }
+
         pub fn into_deploy_response(self, bytecode: &[u8]) -> IpcResponse {
+            let (ethereum_address, ethereum_payload, ethereum_bridges) = self.ipc_eth_bridges();
             let result = IpcResults::DeployResult {
                 pre_code_hash: bytecode.keccak256().to_hex(),
                 used_gas: self.used_gas,
                 output: self.output.to_hex(), // TODO: Return output
                 delta: self.delta.into(),
-                ethereum_address: self.eth_contract_addr.to_hex(),
-                ethereum_payload: self.eth_payload.to_hex(),
+                ethereum_address,
+                ethereum_payload,
+                ethereum_bridges,
                 signature: self.signature.to_hex(),
+                gas_profile: self.gas_profile,
             };
             IpcResponse::DeploySecretContract { result }
         }
+
+        pub fn into_upgrade_response(self, old_bytecode: &[u8], bytecode: &[u8]) -> IpcResponse {
+            let (ethereum_address, ethereum_payload, ethereum_bridges) = self.ipc_eth_bridges();
+            let result = IpcResults::UpgradeResult {
+                pre_code_hash: bytecode.keccak256().to_hex(),
+                old_code_hash: old_bytecode.keccak256().to_hex(),
+                new_code_hash: self.output.keccak256().to_hex(),
+                used_gas: self.used_gas,
+                output: self.output.to_hex(),
+                delta: self.delta.into(),
+                ethereum_address,
+                ethereum_payload,
+                ethereum_bridges,
+                signature: self.signature.to_hex(),
+                gas_profile: self.gas_profile,
+            };
+            IpcResponse::UpgradeSecretContract { result }
+        }
     }
 
     #[logfn(TRACE)]
@@ -163,7 +408,7 @@ pub(self) mod handling {
     #[logfn(TRACE)]
     pub fn get_tips(db: &DB, input: &[String]) -> ResponseResult {
         let mut tips_results = Vec::with_capacity(input.len());
-        let addresses : Vec<ContractAddress> = input.iter().map(|data| ContractAddress::from_hex(&data).unwrap()).collect();
+        let addresses = input.iter().map(|data| ContractAddress::from_hex(&data)).collect::<Result<Vec<_>, _>>()?;
         let tips = db.get_tips::<DeltaKey>(&addresses)?;
         for (key, data) in tips {
             let delta = IpcDelta::from_delta_key(key, &data)?;
@@ -173,14 +418,35 @@ pub(self) mod handling {
     }
 
     #[logfn(TRACE)]
-    pub fn get_all_tips(db: &DB) -> ResponseResult {
-        let tips = db.get_all_tips::<DeltaKey>().unwrap_or_default();
+    pub fn get_all_tips(db: &DB, limit: Option<u32>, cursor: Option<String>) -> ResponseResult {
+        if limit.is_none() && cursor.is_none() {
+            let tips = db.get_all_tips::<DeltaKey>().unwrap_or_default();
+            let mut tips_results = Vec::with_capacity(tips.len());
+            for (key, data) in tips {
+                let delta = IpcDelta::from_delta_key(key, &data)?;
+                tips_results.push(delta);
+            }
+            return Ok(IpcResponse::GetAllTips { result: IpcResults::Tips(tips_results), next_cursor: None });
+        }
+        let limit = get_page_limit("GetAllTips", limit)?;
+        let after = match cursor {
+            Some(cursor) => Some(ContractAddress::from_hex(&cursor)?),
+            None => None,
+        };
+        // one more tip is requested in order to know if there's another page.
+        let mut tips = db.get_tips_page::<DeltaKey>(after, limit + 1).unwrap_or_default();
+        let next_cursor = if tips.len() > limit {
+            tips.truncate(limit);
+            tips.last().map(|(key, _)| key.contract_address.to_hex())
+        } else {
+            None
+        };
         let mut tips_results = Vec::with_capacity(tips.len());
         for (key, data) in tips {
             let delta = IpcDelta::from_delta_key(key, &data)?;
             tips_results.push(delta);
         }
-        Ok(IpcResponse::GetAllTips { result: IpcResults::Tips(tips_results) })
+        Ok(IpcResponse::GetAllTips { result: IpcResults::Tips(tips_results), next_cursor })
     }
 
     #[logfn(TRACE)]
@@ -199,7 +465,10 @@ pub(self) mod handling {
     }
 
     #[logfn(TRACE)]
-    pub fn get_deltas(db: &DB, input: &[IpcDeltasRange]) -> ResponseResult {
+    pub fn get_deltas(db: &DB, input: &[IpcDeltasRange], limit: Option<u32>, cursor: Option<String>) -> ResponseResult {
+        if limit.is_some() || cursor.is_some() {
+            return get_deltas_page(db, input, limit, cursor);
+        }
         let mut results = Vec::with_capacity(input.len());
         for data in input {
             let address = ContractAddress::from_hex(&data.address)?;
@@ -217,7 +486,58 @@ pub(self) mod handling {
             }
         }
 
-        Ok(IpcResponse::GetDeltas { result: IpcResults::Deltas(results) })
+        Ok(IpcResponse::GetDeltas { result: IpcResults::Deltas(results), next_cursor: None })
+    }
+
+    /// Returns up to `limit` deltas of the ranges, starting from the position in the cursor.
+    /// Unlike the non paginated request, ranges without deltas are skipped.
+    fn get_deltas_page(db: &DB, input: &[IpcDeltasRange], limit: Option<u32>, cursor: Option<String>) -> ResponseResult {
+        let limit = get_page_limit("GetDeltas", limit)?;
+        let cursor = match cursor {
+            Some(cursor) => DeltasCursor::decode(&cursor)?,
+            None => DeltasCursor::default(),
+        };
+        let mut results = Vec::new();
+        let mut next_cursor = None;
+        for (i, data) in input.iter().enumerate().skip(cursor.range as usize) {
+            let address = ContractAddress::from_hex(&data.address)?;
+            let start = if i == cursor.range as usize { data.from.max(cursor.key) } else { data.from };
+            if start >= data.to {
+                continue;
+            }
+            let from = DeltaKey::new(address, Stype::Delta(start));
+            let to = DeltaKey::new(address, Stype::Delta(data.to));
+
+            // one more delta is requested in order to know where the next page starts.
+            let db_res = db.get_deltas_limited(from, to, limit - results.len() + 1)?;
+            if db_res.is_none() {
+                continue;
+            }
+            for (key, data) in db_res.unwrap() {
+                if results.len() == limit {
+                    next_cursor = Some(DeltasCursor { range: i as u32, key: key.key_type.unwrap_delta() }.encode());
+                    break;
+                }
+                let delta = IpcDelta::from_delta_key(key, &data)?;
+                results.push(delta);
+            }
+            if next_cursor.is_some() {
+                break;
+            }
+        }
+        Ok(IpcResponse::GetDeltas { result: IpcResults::Deltas(results), next_cursor })
+    }
+
+    // a limit out of range is answered with `IpcErrorCode::InvalidField`.
+    fn get_page_limit(cmd: &str, limit: Option<u32>) -> Result<usize, Error> {
+        match limit.map(|limit| limit as usize) {
+            Some(limit) if limit == 0 || limit > MAX_PAGE_LIMIT => {
+                let msg = format!("{}: limit should be between 1 and {}, got {}", cmd, MAX_PAGE_LIMIT, limit);
+                Err(errors::P2PDecodeErr { id: None, code: IpcErrorCode::InvalidField, msg }.into())
+            }
+            Some(limit) => Ok(limit),
+            None => Ok(DEFAULT_PAGE_LIMIT),
+        }
     }
 
     #[logfn(TRACE)]
@@ -227,6 +547,12 @@ pub(self) mod handling {
         Ok(IpcResponse::GetContract { result: IpcResults::GetContract{address: address.to_hex(), bytecode: data} })
     }
 
+    #[logfn(TRACE)]
+    pub fn get_db_stats(db: &DB) -> ResponseResult {
+        let (contracts, properties) = db.get_db_stats()?;
+        Ok(IpcResponse::GetDbStats { result: IpcResults::DbStats { contracts, properties } })
+    }
+
     #[logfn(TRACE)]
     pub fn update_new_contract(db: &mut DB, address: String, bytecode: &[u8]) -> ResponseResult {
         let address_arr = ContractAddress::from_hex(&address)?;
@@ -277,6 +603,21 @@ pub(self) mod handling {
         Ok( IpcResponse::RemoveContract { address, result } )
     }
 
+    #[logfn(TRACE)]
+    pub fn prune_snapshots(db: &mut DB, address: String, keep: u32) -> ResponseResult {
+        let addr_arr = ContractAddress::from_hex(&address)?;
+        let pruned = db.prune_snapshots(&addr_arr, keep as usize)?;
+        // the latest state isn't affected, so no need to update the state_updated flag
+        Ok(IpcResponse::PruneSnapshots { address, result: IpcResults::PrunedSnapshots(pruned) })
+    }
+
+    #[logfn(TRACE)]
+    pub fn prune_deltas(db: &mut DB, address: String, below: u32) -> ResponseResult {
+        let addr_arr = ContractAddress::from_hex(&address)?;
+        let pruned = db.prune_deltas(&addr_arr, below)?;
+        Ok(IpcResponse::PruneDeltas { address, result: IpcResults::PrunedDeltas(pruned as u64) })
+    }
+
     #[logfn(TRACE)]
     pub fn update_deltas(db: &mut DB, deltas: Vec<IpcDelta>) -> ResponseResult {
         let mut tuples = Vec::with_capacity(deltas.len());
@@ -351,8 +692,7 @@ pub(self) mod handling {
 
     #[logfn(TRACE)]
     pub fn get_dh_user_key(_user_pubkey: &str, eid: sgx_enclave_id_t) -> ResponseResult {
-        let mut user_pubkey = [0u8; 64];
-        user_pubkey.clone_from_slice(&_user_pubkey.from_hex().unwrap());
+        let user_pubkey = decode_user_key("NewTaskEncryptionKey", "userPubKey", _user_pubkey)?;
 
         let (msg, sig) = km_u::get_user_key(eid, &user_pubkey)?;
 
@@ -388,13 +728,44 @@ pub(self) mod handling {
         Ok(IpcResponse::PTTResponse {result})
     }
 
+    // the context fields which are missing from the task are zeros, the enclave sets the sender from the user's key.
+    fn task_context(cmd: &str, input: &IpcTask) -> Result<TaskContext, Error> {
+        let mut context = TaskContext { block_number: input.block_number, ..Default::default() };
+        decode_context_field(cmd, "epochNonce", &input.epoch_nonce, &mut context.epoch_nonce)?;
+        Ok(context)
+    }
+
+    fn decode_context_field(cmd: &str, field: &str, hex: &str, dest: &mut [u8]) -> Result<(), Error> {
+        if hex.is_empty() {
+            return Ok(());
+        }
+        decode_field(cmd, field, hex, dest)
+    }
+
+    fn decode_user_key(cmd: &str, field: &str, hex: &str) -> Result<[u8; 64], Error> {
+        let mut user_pubkey = [0u8; 64];
+        decode_field(cmd, field, hex, &mut user_pubkey)?;
+        Ok(user_pubkey)
+    }
+
+    // a field with the wrong length is answered with `IpcErrorCode::InvalidField`.
+    fn decode_field(cmd: &str, field: &str, hex: &str, dest: &mut [u8]) -> Result<(), Error> {
+        let bytes = hex.from_hex()?;
+        if bytes.len() != dest.len() {
+            let msg = format!("{}: {} should be {} bytes, got {}", cmd, field, dest.len(), bytes.len());
+            return Err(errors::P2PDecodeErr { id: None, code: IpcErrorCode::InvalidField, msg }.into());
+        }
+        dest.copy_from_slice(&bytes);
+        Ok(())
+    }
+
     pub fn deploy_contract(db: &mut DB, input: IpcTask, eid: sgx_enclave_id_t) -> ResponseResult {
-        let bytecode = input.pre_code.expect("Bytecode Missing");
+        let bytecode = input.pre_code.ok_or(P2PErr { cmd: "DeploySecretContract".to_string(), msg: "Bytecode Missing".to_string() })?;
         let contract_address = ContractAddress::from_hex(&input.address)?;
         let enc_args = input.encrypted_args.from_hex()?;
         let constructor = input.encrypted_fn.from_hex()?;
-        let mut user_pubkey = [0u8; 64];
-        user_pubkey.clone_from_slice(&input.user_dhkey.from_hex()?);
+        let user_pubkey = decode_user_key("DeploySecretContract", "userDHKey", &input.user_dhkey)?;
+        let context = task_context("DeploySecretContract", &input)?;
         let result = wasm::deploy(
             db,
             eid,
@@ -403,6 +774,8 @@ pub(self) mod handling {
             &enc_args,
             &contract_address,
             &user_pubkey,
+            &context,
+            &TaskOptions { gas_profiling: input.gas_profiling },
             input.gas_limit)?;
 
         match result {
@@ -422,13 +795,50 @@ pub(self) mod handling {
         }
     }
 
+    pub fn upgrade_contract(db: &mut DB, input: IpcTask, eid: sgx_enclave_id_t) -> ResponseResult {
+        let bytecode = input.pre_code.ok_or(P2PErr { cmd: "UpgradeSecretContract".to_string(), msg: "Bytecode Missing".to_string() })?;
+        let contract_address = ContractAddress::from_hex(&input.address)?;
+        let enc_args = input.encrypted_args.from_hex()?;
+        let callable = input.encrypted_fn.from_hex()?;
+        let user_pubkey = decode_user_key("UpgradeSecretContract", "userDHKey", &input.user_dhkey)?;
+        let context = task_context("UpgradeSecretContract", &input)?;
+
+        if !db.get_state_status() {
+            let _res = km_u::ptt_build_state(db, eid)?;
+            db.update_state_status(true);
+        }
+        let old_bytecode = db.get_contract(contract_address)?;
+
+        let result = wasm::upgrade(
+            db,
+            eid,
+            &bytecode,
+            &callable,
+            &enc_args,
+            &contract_address,
+            &user_pubkey,
+            &context,
+            &TaskOptions { gas_profiling: input.gas_profiling },
+            input.gas_limit)?;
+
+        match result {
+            WasmResult::WasmTaskResult(v) => {
+                // the enclave already stored the new ExeCode, and kept the old one as a version.
+                let ipc_response = v.into_upgrade_response(&old_bytecode, &bytecode);
+                debug!("upgrade_contract() => Ok({})", ipc_response.display_without_bytecode());
+                Ok(ipc_response)
+            },
+            WasmResult::WasmTaskFailure(v) => Ok(v.into()),
+        }
+    }
+
     #[logfn(DEBUG)]
     pub fn compute_task(db: &mut DB, input: IpcTask, eid: sgx_enclave_id_t) -> ResponseResult {
         let enc_args = input.encrypted_args.from_hex()?;
         let address = ContractAddress::from_hex(&input.address)?;
         let callable = input.encrypted_fn.from_hex()?;
-        let mut user_pubkey = [0u8; 64];
-        user_pubkey.clone_from_slice(&input.user_dhkey.from_hex()?);
+        let user_pubkey = decode_user_key("ComputeTask", "userDHKey", &input.user_dhkey)?;
+        let context = task_context("ComputeTask", &input)?;
 
         if !db.get_state_status() {
             let _res = km_u::ptt_build_state(db, eid)?;
@@ -445,6 +855,8 @@ pub(self) mod handling {
             &enc_args,
             &user_pubkey,
             &address,
+            &context,
+            &TaskOptions { gas_profiling: input.gas_profiling },
             input.gas_limit)?;
 
         match result {
@@ -453,17 +865,361 @@ pub(self) mod handling {
         }
     }
 
+    #[logfn(DEBUG)]
+    pub fn query_task(db: &mut DB, input: IpcTask, eid: sgx_enclave_id_t) -> ResponseResult {
+        let enc_args = input.encrypted_args.from_hex()?;
+        let address = ContractAddress::from_hex(&input.address)?;
+        let callable = input.encrypted_fn.from_hex()?;
+        let user_pubkey = decode_user_key("QueryTask", "userDHKey", &input.user_dhkey)?;
+        let context = task_context("QueryTask", &input)?;
+
+        if !db.get_state_status() {
+            let _res = km_u::ptt_build_state(db, eid)?;
+            db.update_state_status(true);
+        }
+        let bytecode = db.get_contract(address)?;
+
+        let result = wasm::query(
+            db,
+            eid,
+            &bytecode,
+            &callable,
+            &enc_args,
+            &user_pubkey,
+            &address,
+            &context,
+            &TaskOptions { gas_profiling: input.gas_profiling },
+            input.gas_limit)?;
+
+        match result {
+            WasmResult::WasmTaskResult(v) => Ok(v.into_query_response()),
+            WasmResult::WasmTaskFailure(v) => Ok(v.into())
+        }
+    }
+
 }
 
 #[cfg(test)]
 mod test {
+    extern crate rand;
     use super::*;
-    use crate::db::{DeltaKey, P2PCalls, Stype, tests::create_test_db};
+    use crate::db::{CRUDInterface, DeltaKey, P2PCalls, Stype, tests::create_test_db};
     use serde_json::Value;
+    use hex::ToHex;
     use enigma_types::ContractAddress;
+    use self::rand::Rng;
+    use zmq::Message;
 
     pub const SPID: &str = "B0335FD3BC1CCA8F804EB98A6420592D";
     pub const RETRIES: u32 = 10;
+    // none of the requests in these tests should ever reach the enclave.
+    const NO_ENCLAVE: sgx_enclave_id_t = 0;
+
+    fn handle_frames(db: &mut DB, frames: &[Vec<u8>]) -> Vec<Message> {
+        let mut request = Multipart::new();
+        for frame in frames {
+            request.push_back(Message::from(&frame[..]));
+        }
+        handle_message(db, request, SPID, NO_ENCLAVE, RETRIES).into_iter().collect()
+    }
+
+    fn error_code(response: &Message) -> Value {
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        assert_eq!(response["type"], "Error");
+        response["code"].clone()
+    }
+
+    #[test]
+    fn test_handle_arbitrary_bytes() {
+        let (mut db, _dir) = create_test_db();
+        let mut rng = rand::thread_rng();
+        for _ in 0..2000 {
+            let len = rng.gen_range(0, 256);
+            let mut frame: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            // half of the frames look like JSON so they go through the JSON decoder.
+            if rng.gen() {
+                frame.insert(0, b'{');
+            }
+            let responses = handle_frames(&mut db, &[frame]);
+            assert_eq!(responses.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_handle_arbitrary_json_fields() {
+        let (mut db, _dir) = create_test_db();
+        let mut rng = rand::thread_rng();
+        let types = ["GetTip", "GetTips", "GetDelta", "GetDeltas", "GetContract", "UpdateDeltas", "RemoveDeltas", "Unknown"];
+        for _ in 0..500 {
+            let input: String = (0..rng.gen_range(0, 80)).map(|_| rng.gen_range(b' ', b'~') as char).collect();
+            let msg_type = types[rng.gen_range(0, types.len())];
+            let frame = serde_json::json!({"id": "fuzz", "type": msg_type, "input": input}).to_string().into_bytes();
+            let responses = handle_frames(&mut db, &[frame]);
+            assert_eq!(responses.len(), 1);
+            let response: Value = serde_json::from_slice(&responses[0]).unwrap();
+            assert_eq!(response["id"], "fuzz");
+        }
+    }
+
+    #[test]
+    fn test_handle_malformed_messages() {
+        let (mut db, _dir) = create_test_db();
+        let frames = vec![
+            vec![b'{', 0xc3, 0x28, b'}'],
+            br#"{"id": "1", "type": "NoSuchRequest"}"#.to_vec(),
+            br#"{"id": "2", "type": "GetTip"}"#.to_vec(),
+            br#"{"id": "3", "type": "GetTip", "input": "not hex"}"#.to_vec(),
+            br#"{"id": "4", "ty"#.to_vec(),
+            br#"{"id": "5", "type": "GetAllAddrs"}"#.to_vec(),
+        ];
+        let responses = handle_frames(&mut db, &frames);
+        assert_eq!(responses.len(), frames.len());
+        assert_eq!(error_code(&responses[0]), "InvalidUtf8");
+        assert_eq!(error_code(&responses[1]), "UnknownType");
+        assert_eq!(error_code(&responses[2]), "MissingField");
+        assert_eq!(error_code(&responses[3]), "BadHex");
+        assert_eq!(error_code(&responses[4]), "MalformedMessage");
+
+        let ids: Vec<Value> = responses.iter().map(|r| serde_json::from_slice::<Value>(&r).unwrap()["id"].clone()).collect();
+        assert_eq!(ids, vec!["", "1", "2", "3", "", "5"]);
+        // a malformed message doesn't affect the other messages in the same multipart.
+        let last: Value = serde_json::from_slice(&responses[5]).unwrap();
+        assert_eq!(last["type"], "GetAllAddrs");
+    }
+
+    #[test]
+    fn test_handle_page_limits() {
+        let (mut db, _dir) = create_test_db();
+        let address = ContractAddress::from([7u8; 32]);
+        db.create(&DeltaKey::new(address, Stype::Delta(0)), &[1u8, 2, 3][..]).unwrap();
+        let range = serde_json::json!([{"address": address.to_hex(), "from": 0, "to": 1}]);
+        let frames = vec![
+            serde_json::json!({"id": "1", "type": "GetAllTips", "limit": u32::max_value()}).to_string().into_bytes(),
+            serde_json::json!({"id": "2", "type": "GetDeltas", "input": range, "limit": 0}).to_string().into_bytes(),
+            serde_json::json!({"id": "3", "type": "GetDeltas", "input": range, "limit": u32::max_value()}).to_string().into_bytes(),
+            serde_json::json!({"id": "4", "type": "GetDeltas", "input": range, "limit": 10_000}).to_string().into_bytes(),
+        ];
+        let responses = handle_frames(&mut db, &frames);
+        assert_eq!(error_code(&responses[0]), "InvalidField");
+        assert_eq!(error_code(&responses[1]), "InvalidField");
+        assert_eq!(error_code(&responses[2]), "InvalidField");
+        let last: Value = serde_json::from_slice(&responses[3]).unwrap();
+        assert_eq!(last["type"], "GetDeltas");
+        assert_eq!(last["result"]["deltas"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_handle_malformed_msgpack() {
+        let (mut db, _dir) = create_test_db();
+        let frame = rmp_serde::to_vec_named(&serde_json::json!({"id": "6", "type": "GetTip", "input": "zz"})).unwrap();
+        let responses = handle_frames(&mut db, &[frame]);
+        let response: Value = rmp_serde::from_slice(&responses[0]).unwrap();
+        assert_eq!(response["id"], "6");
+        assert_eq!(response["code"], "BadHex");
+    }
+
+    #[test]
+    fn test_handle_read_message() {
+        let (mut db, _dir) = create_test_db();
+        let address = ContractAddress::from([7u8; 32]);
+        db.create(&DeltaKey::new(address, Stype::ByteCode), &[1u8, 2, 3][..]).unwrap();
+        let reader = db.shared_handle();
+        let frames = vec![
+            serde_json::json!({"id": "1", "type": "GetContract", "input": address.to_hex()}).to_string().into_bytes(),
+            serde_json::json!({"id": "2", "type": "RemoveContract", "address": address.to_hex()}).to_string().into_bytes(),
+        ];
+        let mut request = Multipart::new();
+        for frame in &frames {
+            request.push_back(Message::from(&frame[..]));
+        }
+        let responses: Vec<Value> = handle_read_message(&reader, request).into_iter()
+            .map(|r| serde_json::from_slice(&r).unwrap())
+            .collect();
+        assert_eq!(responses[0]["type"], "GetContract");
+        assert_eq!(responses[0]["result"]["bytecode"], serde_json::json!([1, 2, 3]));
+        assert_eq!(responses[1]["type"], "Error");
+        assert_eq!(responses[1]["id"], "2");
+        // the contract wasn't removed by the reader.
+        assert!(db.get_contract(address).is_ok());
+    }
+
+    #[test]
+    fn test_prune_snapshots() {
+        let (mut db, _dir) = create_test_db();
+        let address = ContractAddress::from([8u8; 32]);
+        for index in &[500, 1000, 1500] {
+            db.create(&DeltaKey::new(address, Stype::Snapshot(*index)), &b"snapshot"[..]).unwrap();
+        }
+        let frame = serde_json::json!({"id": "1", "type": "PruneSnapshots", "address": address.to_hex(), "keep": 1}).to_string().into_bytes();
+        let responses = handle_frames(&mut db, &[frame]);
+        let response: Value = serde_json::from_slice(&responses[0]).unwrap();
+        assert_eq!(response["type"], "PruneSnapshots");
+        assert_eq!(response["result"]["prunedSnapshots"], serde_json::json!([500, 1000]));
+        assert_eq!(db.get_snapshot_indexes(&address).unwrap(), vec![1500]);
+    }
+
+    #[test]
+    fn test_upgrade_without_bytecode() {
+        let (mut db, _dir) = create_test_db();
+        let address = ContractAddress::from([8u8; 32]);
+        db.create(&DeltaKey::new(address, Stype::ByteCode), &[1u8, 2, 3][..]).unwrap();
+        let input = serde_json::json!({
+            "encryptedArgs": "a1b2", "encryptedFn": "c3d4", "userDHKey": [5u8; 64].to_hex(), "gasLimit": 100, "contractAddress": address.to_hex()
+        });
+        let frame = serde_json::json!({"id": "1", "type": "UpgradeSecretContract", "input": input}).to_string().into_bytes();
+        let responses = handle_frames(&mut db, &[frame]);
+        assert_eq!(error_code(&responses[0]), "Failure");
+        assert_eq!(db.get_contract(address).unwrap(), vec![1u8, 2, 3]);
+    }
+
+    #[test]
+    fn test_task_with_bad_context() {
+        let (mut db, _dir) = create_test_db();
+        let address = ContractAddress::from([8u8; 32]);
+        let input = serde_json::json!({
+            "encryptedArgs": "a1b2", "encryptedFn": "c3d4", "userDHKey": [5u8; 64].to_hex(), "gasLimit": 100, "contractAddress": address.to_hex(),
+            "blockNumber": 7, "epochNonce": [6u8; 20].to_hex()
+        });
+        let frame = serde_json::json!({"id": "1", "type": "ComputeTask", "input": input}).to_string().into_bytes();
+        let responses = handle_frames(&mut db, &[frame]);
+        assert_eq!(error_code(&responses[0]), "InvalidField");
+        let response: Value = serde_json::from_slice(&responses[0]).unwrap();
+        assert!(response["msg"].as_str().unwrap().contains("epochNonce should be 32 bytes"));
+    }
+
+    #[test]
+    fn test_handle_bad_addresses_and_keys() {
+        let (mut db, _dir) = create_test_db();
+        let address = ContractAddress::from([8u8; 32]).to_hex();
+        let task = |key: &str| serde_json::json!({
+            "encryptedArgs": "a1b2", "encryptedFn": "c3d4", "userDHKey": key, "gasLimit": 100, "contractAddress": address, "preCode": [1, 2, 3]
+        });
+        let frames = vec![
+            serde_json::json!({"id": "1", "type": "GetTips", "input": [address, "not hex"]}),
+            serde_json::json!({"id": "2", "type": "NewTaskEncryptionKey", "userPubKey": "zz"}),
+            serde_json::json!({"id": "3", "type": "NewTaskEncryptionKey", "userPubKey": [5u8; 63].to_hex()}),
+            serde_json::json!({"id": "4", "type": "ComputeTask", "input": task("zz")}),
+            serde_json::json!({"id": "5", "type": "QueryTask", "input": task(&[5u8; 65].to_hex())}),
+        ];
+        let frames: Vec<Vec<u8>> = frames.iter().map(|frame| frame.to_string().into_bytes()).collect();
+        let responses = handle_frames(&mut db, &frames);
+        let codes: Vec<Value> = responses.iter().map(error_code).collect();
+        assert_eq!(codes, vec!["BadHex", "BadHex", "InvalidField", "BadHex", "InvalidField"]);
+    }
+
+    #[test]
+    fn test_handle_arbitrary_keys() {
+        let (mut db, _dir) = create_test_db();
+        let mut rng = rand::thread_rng();
+        let types = ["NewTaskEncryptionKey", "DeploySecretContract", "UpgradeSecretContract", "ComputeTask", "QueryTask", "GetTips"];
+        let address = ContractAddress::from([8u8; 32]).to_hex();
+        for _ in 0..500 {
+            // never a valid key, so none of the requests reach the enclave.
+            let mut key: String = (0..rng.gen_range(0, 256)).map(|_| rng.gen_range(b' ', b'~') as char).collect();
+            if key.len() == 128 {
+                key.push('0');
+            }
+            let input = serde_json::json!({
+                "encryptedArgs": "a1b2", "encryptedFn": "c3d4", "userDHKey": key, "gasLimit": 100, "contractAddress": address, "preCode": [1, 2, 3]
+            });
+            let msg_type = types[rng.gen_range(0, types.len())];
+            let frame = match msg_type {
+                "NewTaskEncryptionKey" => serde_json::json!({"id": "fuzz", "type": msg_type, "userPubKey": key}),
+                "GetTips" => serde_json::json!({"id": "fuzz", "type": msg_type, "input": [address, key]}),
+                _ => serde_json::json!({"id": "fuzz", "type": msg_type, "input": input}),
+            };
+            let responses = handle_frames(&mut db, &[frame.to_string().into_bytes()]);
+            assert_eq!(responses.len(), 1);
+            let response: Value = serde_json::from_slice(&responses[0]).unwrap();
+            assert_eq!(response["id"], "fuzz");
+            assert_eq!(response["type"], "Error");
+        }
+    }
+
+    #[test]
+    fn test_prune_deltas() {
+        let (mut db, _dir) = create_test_db();
+        let address = ContractAddress::from([9u8; 32]);
+        for index in 0..5 {
+            db.create(&DeltaKey::new(address, Stype::Delta(index)), &b"delta"[..]).unwrap();
+        }
+        db.create(&DeltaKey::new(address, Stype::Snapshot(3)), &b"snapshot"[..]).unwrap();
+        let frames = vec![
+            serde_json::json!({"id": "1", "type": "PruneDeltas", "address": address.to_hex(), "below": 4}).to_string().into_bytes(),
+            serde_json::json!({"id": "2", "type": "PruneDeltas", "address": address.to_hex(), "below": 3}).to_string().into_bytes(),
+        ];
+        let responses = handle_frames(&mut db, &frames);
+        assert_eq!(error_code(&responses[0]), "Failure");
+        let response: Value = serde_json::from_slice(&responses[1]).unwrap();
+        assert_eq!(response["type"], "PruneDeltas");
+        assert_eq!(response["result"]["prunedDeltas"], 3);
+    }
+
+    #[test]
+    fn test_get_db_stats() {
+        let (mut db, _dir) = create_test_db();
+        let address = ContractAddress::from([10u8; 32]);
+        db.create(&DeltaKey::new(address, Stype::Delta(0)), &b"delta"[..]).unwrap();
+        let frame = serde_json::json!({"id": "1", "type": "GetDbStats"}).to_string().into_bytes();
+        let mut request = Multipart::new();
+        request.push_back(Message::from(&frame[..]));
+        let responses: Vec<Message> = handle_read_message(&db.shared_handle(), request).into_iter().collect();
+        let response: Value = serde_json::from_slice(&responses[0]).unwrap();
+        assert_eq!(response["type"], "GetDbStats");
+        let contract = &response["result"]["contracts"][0];
+        assert_eq!(contract["address"], address.to_hex());
+        assert_eq!(contract["deltaCount"], 1);
+        assert_eq!(contract["tipIndex"], 0);
+        assert!(response["result"]["properties"]["estimatedKeys"].is_u64());
+    }
+
+    #[test]
+    fn test_is_read_only_request() {
+        let get_tip = serde_json::json!({"id": "1", "type": "GetTip", "input": "00"}).to_string().into_bytes();
+        let get_all_addrs = serde_json::json!({"id": "2", "type": "GetAllAddrs"}).to_string().into_bytes();
+        let remove = serde_json::json!({"id": "3", "type": "RemoveContract", "address": "00"}).to_string().into_bytes();
+        let ptt_request = serde_json::json!({"id": "4", "type": "GetPTTRequest"}).to_string().into_bytes();
+        let decode = |frames: &[&[u8]]| frames.iter().map(|frame| decode_frame(frame)).collect::<Vec<_>>();
+        assert!(is_read_only_request(&decode(&[&get_tip[..], &get_all_addrs[..]])));
+        assert!(is_read_only_request(&decode(&[&b"not a request"[..]])));
+        assert!(!is_read_only_request(&decode(&[&get_tip[..], &remove[..]])));
+        assert!(!is_read_only_request(&decode(&[&ptt_request[..], &get_all_addrs[..]])));
+    }
+
+    #[test]
+    fn test_handler_panic() {
+        let mut request = Multipart::new();
+        for id in &["1", "2"] {
+            let frame = serde_json::json!({"id": id, "type": "GetAllAddrs"}).to_string();
+            request.push_back(Message::from(frame.as_bytes()));
+        }
+        let mut calls = 0;
+        let responses: Vec<Value> = super::handle_frames(request, |_| {
+            calls += 1;
+            if calls == 1 {
+                panic!("a bug in the handler");
+            }
+            Ok(IpcResponse::GetAllAddrs { result: IpcResults::Addresses(Vec::new()) })
+        })
+        .into_iter()
+        .map(|r| serde_json::from_slice(&r).unwrap())
+        .collect();
+        assert_eq!(responses[0]["type"], "Error");
+        assert_eq!(responses[0]["id"], "1");
+        assert!(responses[0]["msg"].as_str().unwrap().contains("a bug in the handler"));
+        // the panic doesn't affect the following requests.
+        assert_eq!(responses[1]["type"], "GetAllAddrs");
+        assert_eq!(responses[1]["id"], "2");
+    }
+
+    #[test]
+    #[should_panic(expected = "a bug in the writer")]
+    fn test_writer_panic() {
+        let mut request = Multipart::new();
+        let frame = serde_json::json!({"id": "1", "type": "RemoveContract", "address": "00"}).to_string();
+        request.push_back(Message::from(frame.as_bytes()));
+        super::handle_frames(request, |_| panic!("a bug in the writer"));
+    }
     #[ignore]
     #[test]
     fn test_the_listener() {
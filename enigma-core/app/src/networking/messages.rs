@@ -1,9 +1,36 @@
-use serde_json;
+use serde_json::{self, error::Category, Value};
+use rmp_serde;
 use serde_repr::{Serialize_repr, Deserialize_repr};
 use zmq::Message;
 use crate::db::{Delta, Stype, DeltaKey};
-use hex::ToHex;
+use crate::db::stats::{ContractStats, DbProperties};
+use hex::{FromHex, FromHexError, ToHex};
 use failure::Error;
+use std::str;
+use crate::common_u::errors::P2PDecodeErr;
+
+/// The encoding of a single IPC message.
+/// The response to a request is always sent with the same encoding as the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    MsgPack,
+}
+
+impl Default for Encoding {
+    fn default() -> Self { Encoding::Json }
+}
+
+impl Encoding {
+    /// A JSON message is always an object, so it starts with `{` (possibly after whitespaces),
+    /// a MessagePack message is a map, which can never start with any of these bytes.
+    pub fn detect(frame: &[u8]) -> Self {
+        match frame.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') | None => Encoding::Json,
+            Some(_) => Encoding::MsgPack,
+        }
+    }
+}
 
 // These attributes enable the status to be casted as an i8 object as well
 #[derive(Serialize_repr, Deserialize_repr, Clone, Debug)]
@@ -33,10 +60,18 @@ pub enum IpcResponse {
     GetRegistrationParams { #[serde(flatten)] result: IpcResults },
     GetTip { result: IpcDelta },
     GetTips { result: IpcResults },
-    GetAllTips { result: IpcResults },
+    GetAllTips {
+        result: IpcResults,
+        #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none", default)]
+        next_cursor: Option<String>,
+    },
     GetAllAddrs { result: IpcResults },
     GetDelta { result: IpcResults },
-    GetDeltas { result: IpcResults },
+    GetDeltas {
+        result: IpcResults,
+        #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none", default)]
+        next_cursor: Option<String>,
+    },
     GetContract { #[serde(flatten)] result: IpcResults },
     UpdateNewContract { address: String, result: IpcResults },
     UpdateNewContractOnDeployment { address: String, result: IpcResults },
@@ -45,11 +80,166 @@ pub enum IpcResponse {
     RemoveDeltas { #[serde(flatten)] result: IpcResults},
     NewTaskEncryptionKey { #[serde(flatten)] result: IpcResults },
     DeploySecretContract { #[serde(flatten)] result: IpcResults},
+    UpgradeSecretContract { #[serde(flatten)] result: IpcResults },
     ComputeTask { #[serde(flatten)] result: IpcResults },
+    QueryTask { #[serde(flatten)] result: IpcResults },
     FailedTask { #[serde(flatten)] result: IpcResults },
     GetPTTRequest { #[serde(flatten)] result: IpcResults },
     PTTResponse { result: IpcResults },
-    Error { msg: String },
+    PruneSnapshots { address: String, result: IpcResults },
+    PruneDeltas { address: String, result: IpcResults },
+    GetDbStats { #[serde(flatten)] result: IpcResults },
+    Error { msg: String, #[serde(default)] code: IpcErrorCode },
+}
+
+/// A machine readable reason for an `IpcResponse::Error`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcErrorCode {
+    /// a JSON message which isn't valid UTF-8.
+    InvalidUtf8,
+    /// a message which can't be parsed as JSON/MessagePack at all.
+    MalformedMessage,
+    /// a message with a `type` that doesn't exist.
+    UnknownType,
+    /// a message without one of the required fields.
+    MissingField,
+    /// a field with the wrong type or value.
+    InvalidField,
+    /// a field that should be hex but isn't.
+    BadHex,
+    /// the request was decoded but failed while handling it.
+    Failure,
+}
+
+impl Default for IpcErrorCode {
+    fn default() -> Self { IpcErrorCode::Failure }
+}
+
+impl IpcErrorCode {
+    fn from_json_err(err: &serde_json::Error, frame: &str) -> Self {
+        match err.classify() {
+            Category::Data => match serde_json::from_str::<Value>(frame) {
+                Ok(value) => mismatch::error_code(&value),
+                Err(_) => IpcErrorCode::MalformedMessage,
+            },
+            Category::Io | Category::Syntax | Category::Eof => IpcErrorCode::MalformedMessage,
+        }
+    }
+
+    fn from_msgpack_err(err: &rmp_serde::decode::Error, frame: &[u8]) -> Self {
+        use rmp_serde::decode::Error::*;
+        match err {
+            Utf8Error(_) => IpcErrorCode::InvalidUtf8,
+            TypeMismatch(_) | OutOfRange | LengthMismatch(_) => IpcErrorCode::InvalidField,
+            // the errors of the serde data model, e.g. an unknown variant, are all reported as `Syntax`.
+            Syntax(_) | Uncategorized(_) => match rmp_serde::from_slice::<Value>(frame) {
+                Ok(value) => mismatch::error_code(&value),
+                Err(_) => IpcErrorCode::InvalidField,
+            },
+            _ => IpcErrorCode::MalformedMessage,
+        }
+    }
+}
+
+/// Finds why a well formed message isn't a valid request.
+///
+/// The serde errors of serde_json and rmp_serde are only exposed as text, so the message is decoded again
+/// through a deserializer with its own error type, which records which `serde::de::Error` constructor failed it.
+mod mismatch {
+    use serde::de::value::{MapDeserializer, SeqDeserializer};
+    use serde::de::{self, Deserialize, Deserializer, IntoDeserializer, Visitor};
+    use serde_json::Value;
+    use std::fmt;
+    use super::{IpcErrorCode, IpcMessageRequest};
+
+    pub fn error_code(value: &Value) -> IpcErrorCode {
+        match IpcMessageRequest::deserialize(Probe(value)) {
+            Err(Mismatch::UnknownVariant(ref variant)) if value["type"] == *variant => IpcErrorCode::UnknownType,
+            Err(Mismatch::MissingField) => IpcErrorCode::MissingField,
+            // a request that decodes here failed only because of the encoding, e.g. a number out of range.
+            _ => IpcErrorCode::InvalidField,
+        }
+    }
+
+    #[derive(Debug)]
+    enum Mismatch {
+        UnknownVariant(String),
+        MissingField,
+        Invalid,
+    }
+
+    impl fmt::Display for Mismatch {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{:?}", self) }
+    }
+
+    impl ::std::error::Error for Mismatch {}
+
+    impl de::Error for Mismatch {
+        fn custom<T: fmt::Display>(_msg: T) -> Self { Mismatch::Invalid }
+
+        fn unknown_variant(variant: &str, _expected: &'static [&'static str]) -> Self {
+            Mismatch::UnknownVariant(variant.to_string())
+        }
+
+        fn missing_field(_field: &'static str) -> Self { Mismatch::MissingField }
+    }
+
+    struct Probe<'a>(&'a Value);
+
+    impl<'de, 'a> IntoDeserializer<'de, Mismatch> for Probe<'a> {
+        type Deserializer = Self;
+
+        fn into_deserializer(self) -> Self { self }
+    }
+
+    impl<'de, 'a> Deserializer<'de> for Probe<'a> {
+        type Error = Mismatch;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Mismatch> {
+            match self.0 {
+                Value::Null => visitor.visit_unit(),
+                Value::Bool(b) => visitor.visit_bool(*b),
+                Value::Number(n) => match (n.as_u64(), n.as_i64(), n.as_f64()) {
+                    (Some(n), _, _) => visitor.visit_u64(n),
+                    (_, Some(n), _) => visitor.visit_i64(n),
+                    (_, _, n) => visitor.visit_f64(n.unwrap_or_default()),
+                },
+                Value::String(s) => visitor.visit_str(s),
+                Value::Array(items) => {
+                    let mut seq = SeqDeserializer::new(items.iter().map(Probe));
+                    let value = visitor.visit_seq(&mut seq)?;
+                    seq.end()?;
+                    Ok(value)
+                }
+                Value::Object(entries) => {
+                    let mut map = MapDeserializer::new(entries.iter().map(|(key, value)| (key.as_str(), Probe(value))));
+                    let value = visitor.visit_map(&mut map)?;
+                    map.end()?;
+                    Ok(value)
+                }
+            }
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Mismatch> {
+            match self.0 {
+                Value::Null => visitor.visit_none(),
+                _ => visitor.visit_some(self),
+            }
+        }
+
+        // the requests only have unit variants, which are encoded as strings.
+        fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Mismatch> {
+            match self.0 {
+                Value::String(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+                _ => Err(Mismatch::Invalid),
+            }
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit unit_struct
+            newtype_struct seq tuple tuple_struct map struct identifier ignored_any
+        }
+    }
 }
 
 impl IpcResponse {
@@ -67,6 +257,14 @@ impl IpcResponse {
                     _ => "".to_string(),
                 }
             },
+            IpcResponse::UpgradeSecretContract {result: e} => {
+                match e {
+                    IpcResults::UpgradeResult { old_code_hash, new_code_hash, used_gas, delta, signature, .. } =>
+                        format!("IpcResponse {{ old_code_hash: {}, new_code_hash: {}, used_gas: {}, delta: {:?}, signature: {} }}",
+                        old_code_hash, new_code_hash, used_gas, delta, signature),
+                    _ => "".to_string(),
+                }
+            },
             _ => "".to_string(),
         }
     }
@@ -97,10 +295,15 @@ pub enum IpcResults {
     #[serde(rename = "result")]
     GetContract {
         address: String,
+        #[serde(with = "bytes")]
         bytecode: Vec<u8>,
     },
     Status(Status),
     Tips(Vec<IpcDelta>),
+    PrunedSnapshots(Vec<u32>),
+    PrunedDeltas(u64),
+    #[serde(rename = "result")]
+    DbStats { contracts: Vec<ContractStats>, properties: DbProperties },
     #[serde(rename = "result")]
     DeltasResult { status: Status, errors: Vec<IpcStatusResult> },
     #[serde(rename = "result")]
@@ -113,11 +316,28 @@ pub enum IpcResults {
         used_gas: u64,
         output: String,
         delta: IpcDelta,
+        #[serde(rename = "calledDeltas", default, skip_serializing_if = "Vec::is_empty")]
+        called_deltas: Vec<IpcDelta>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        events: Vec<IpcEvent>,
         #[serde(rename = "ethereumAddress")]
         ethereum_address: String,
         #[serde(rename = "ethereumPayload")]
         ethereum_payload: String,
+        #[serde(rename = "ethereumBridges", default, skip_serializing_if = "Vec::is_empty")]
+        ethereum_bridges: Vec<IpcEthBridge>,
         signature: String,
+        #[serde(rename = "gasProfile", default, skip_serializing_if = "Option::is_none")]
+        gas_profile: Option<serde_json::Value>,
+    },
+    #[serde(rename = "result")]
+    QueryResult {
+        #[serde(rename = "usedGas")]
+        used_gas: u64,
+        output: String,
+        signature: String,
+        #[serde(rename = "gasProfile", default, skip_serializing_if = "Option::is_none")]
+        gas_profile: Option<serde_json::Value>,
     },
     #[serde(rename = "result")]
     DeployResult {
@@ -131,7 +351,35 @@ pub enum IpcResults {
         ethereum_address: String,
         #[serde(rename = "ethereumPayload")]
         ethereum_payload: String,
+        #[serde(rename = "ethereumBridges", default, skip_serializing_if = "Vec::is_empty")]
+        ethereum_bridges: Vec<IpcEthBridge>,
+        signature: String,
+        #[serde(rename = "gasProfile", default, skip_serializing_if = "Option::is_none")]
+        gas_profile: Option<serde_json::Value>,
+    },
+    #[serde(rename = "result")]
+    UpgradeResult {
+        #[serde(rename = "preCodeHash")]
+        pre_code_hash: String,
+        /// the hash of the bytecode the contract had before the upgrade.
+        #[serde(rename = "oldCodeHash")]
+        old_code_hash: String,
+        /// the hash of the new bytecode, which is the output.
+        #[serde(rename = "newCodeHash")]
+        new_code_hash: String,
+        #[serde(rename = "usedGas")]
+        used_gas: u64,
+        output: String,
+        delta: IpcDelta,
+        #[serde(rename = "ethereumAddress")]
+        ethereum_address: String,
+        #[serde(rename = "ethereumPayload")]
+        ethereum_payload: String,
+        #[serde(rename = "ethereumBridges", default, skip_serializing_if = "Vec::is_empty")]
+        ethereum_bridges: Vec<IpcEthBridge>,
         signature: String,
+        #[serde(rename = "gasProfile", default, skip_serializing_if = "Option::is_none")]
+        gas_profile: Option<serde_json::Value>,
     },
     #[serde(rename = "result")]
     FailedTask {
@@ -139,6 +387,8 @@ pub enum IpcResults {
         #[serde(rename = "usedGas")]
         used_gas: u64,
         signature: String,
+        #[serde(rename = "gasProfile", default, skip_serializing_if = "Option::is_none")]
+        gas_profile: Option<serde_json::Value>,
     },
 }
 
@@ -148,27 +398,46 @@ pub enum IpcRequest {
     GetRegistrationParams,
     GetTip { input: String },
     GetTips { input: Vec<String> },
-    GetAllTips,
+    GetAllTips {
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        limit: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        cursor: Option<String>,
+    },
     GetAllAddrs,
     GetDelta { input: IpcDelta },
-    GetDeltas { input: Vec<IpcDeltasRange> },
+    GetDeltas {
+        input: Vec<IpcDeltasRange>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        limit: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        cursor: Option<String>,
+    },
     GetContract { input: String },
-    UpdateNewContract { address: String, bytecode: Vec<u8> },
+    UpdateNewContract { address: String, #[serde(with = "bytes")] bytecode: Vec<u8> },
     UpdateNewContractOnDeployment {address: String, bytecode: String, delta: IpcDelta},
     RemoveContract { address: String },
     UpdateDeltas { deltas: Vec<IpcDelta> },
     RemoveDeltas { input: Vec<IpcDeltasRange> },
     NewTaskEncryptionKey { #[serde(rename = "userPubKey")] user_pubkey: String },
     DeploySecretContract { input: IpcTask},
+    /// Replaces the bytecode of a deployed contract with `preCode`, `encryptedFn` and `encryptedArgs` are for its `migrate`.
+    /// The current code of the contract has to approve the upgrade with its `authorize_upgrade`.
+    UpgradeSecretContract { input: IpcTask },
     ComputeTask { input: IpcTask },
+    /// Runs a function without changing the state, `gasLimit` only bounds the execution.
+    QueryTask { input: IpcTask },
     GetPTTRequest,
     PTTResponse {  input: PrincipalResponse },
+    PruneSnapshots { address: String, keep: u32 },
+    PruneDeltas { address: String, below: u32 },
+    GetDbStats,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct IpcTask {
     #[serde(rename = "preCode")]
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default, with = "bytes::option")]
     pub pre_code: Option<Vec<u8>>,
     #[serde(rename = "encryptedArgs")]
     pub encrypted_args: String,
@@ -180,6 +449,14 @@ pub struct IpcTask {
     pub gas_limit: u64,
     #[serde(rename = "contractAddress")]
     pub address: String,
+    /// The number of the Ethereum block the task was submitted in, the context fields are zeros when they're missing.
+    #[serde(rename = "blockNumber", default)]
+    pub block_number: u64,
+    #[serde(rename = "epochNonce", default, skip_serializing_if = "String::is_empty")]
+    pub epoch_nonce: String,
+    /// Reports how much gas every part of the contract used, only a debug enclave can profile.
+    #[serde(rename = "gasProfiling", default)]
+    pub gas_profiling: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -196,10 +473,25 @@ pub struct IpcDelta {
     #[serde(rename = "address")]
     pub contract_address: Option<String>,
     pub key: u32,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default, with = "bytes::option")]
     pub data: Option<Vec<u8>>,
 }
 
+/// An ethereum payload of a task, `ethereumAddress` and `ethereumPayload` of the result are the first of them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IpcEthBridge {
+    pub address: String,
+    pub payload: String,
+}
+
+/// An event emitted by a secret contract, the topic and the data are encrypted with the user's key.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IpcEvent {
+    pub address: String,
+    pub topic: String,
+    pub data: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct IpcDeltasRange {
     pub address: String,
@@ -207,6 +499,36 @@ pub struct IpcDeltasRange {
     pub to: u32,
 }
 
+/// The position of the next delta to return in a paginated `GetDeltas` request.
+/// It is sent to the client as an opaque hex string in `nextCursor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeltasCursor {
+    /// the index of the range in the request's input
+    pub range: u32,
+    /// the key of the first delta in that range which wasn't returned yet
+    pub key: u32,
+}
+
+impl DeltasCursor {
+    pub fn encode(&self) -> String {
+        let mut cursor = self.range.to_be_bytes().to_vec();
+        cursor.extend_from_slice(&self.key.to_be_bytes());
+        cursor.to_hex()
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, Error> {
+        let bytes: Vec<u8> = cursor.from_hex()?;
+        if bytes.len() != 8 {
+            bail!("Invalid cursor length: {}", bytes.len());
+        }
+        let mut range = [0u8; 4];
+        let mut key = [0u8; 4];
+        range.copy_from_slice(&bytes[..4]);
+        key.copy_from_slice(&bytes[4..]);
+        Ok(DeltasCursor { range: u32::from_be_bytes(range), key: u32::from_be_bytes(key) })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PrincipalResponse {
     pub response: String,
@@ -235,6 +557,38 @@ impl IpcMessageRequest {
     }
 }
 
+impl IpcRequest {
+    /// Returns true for the requests that only read from the DB and never reach the enclave,
+    /// these can be handled concurrently with any other request.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            IpcRequest::GetTip { .. }
+            | IpcRequest::GetTips { .. }
+            | IpcRequest::GetAllTips { .. }
+            | IpcRequest::GetAllAddrs
+            | IpcRequest::GetDelta { .. }
+            | IpcRequest::GetDeltas { .. }
+            | IpcRequest::GetContract { .. }
+            | IpcRequest::GetDbStats => true,
+            IpcRequest::GetRegistrationParams
+            | IpcRequest::UpdateNewContract { .. }
+            | IpcRequest::UpdateNewContractOnDeployment { .. }
+            | IpcRequest::RemoveContract { .. }
+            | IpcRequest::UpdateDeltas { .. }
+            | IpcRequest::RemoveDeltas { .. }
+            | IpcRequest::NewTaskEncryptionKey { .. }
+            | IpcRequest::DeploySecretContract { .. }
+            | IpcRequest::UpgradeSecretContract { .. }
+            | IpcRequest::ComputeTask { .. }
+            | IpcRequest::QueryTask { .. }
+            | IpcRequest::GetPTTRequest
+            | IpcRequest::PTTResponse { .. }
+            | IpcRequest::PruneSnapshots { .. }
+            | IpcRequest::PruneDeltas { .. } => false,
+        }
+    }
+}
+
 
 
 impl IpcDelta {
@@ -256,33 +610,343 @@ impl From<Delta> for IpcDelta {
     }
 }
 
-impl From<Message> for IpcMessageRequest {
-    fn from(msg: Message) -> Self {
-        let msg_str = msg.as_str().unwrap();
-        let req: Self = serde_json::from_str(msg_str).expect(msg_str);
-        req
+/// Only used to recover the id of a request that can't be decoded.
+#[derive(Deserialize)]
+struct IpcMessageId {
+    id: String,
+}
+
+impl IpcMessageRequest {
+    /// Decodes a single frame received from the p2p.
+    /// A malformed frame returns an error with a code that can be sent back to the p2p, and never panics.
+    pub fn from_frame(frame: &[u8], encoding: Encoding) -> Result<Self, P2PDecodeErr> {
+        match encoding {
+            Encoding::Json => {
+                let msg_str = str::from_utf8(frame)
+                    .map_err(|e| P2PDecodeErr { id: None, code: IpcErrorCode::InvalidUtf8, msg: e.to_string() })?;
+                serde_json::from_str(msg_str).map_err(|e| {
+                    let id = serde_json::from_str::<IpcMessageId>(msg_str).ok().map(|m| m.id);
+                    P2PDecodeErr { id, code: IpcErrorCode::from_json_err(&e, msg_str), msg: e.to_string() }
+                })
+            }
+            Encoding::MsgPack => rmp_serde::from_slice(frame).map_err(|e| {
+                let id = rmp_serde::from_slice::<IpcMessageId>(frame).ok().map(|m| m.id);
+                P2PDecodeErr { id, code: IpcErrorCode::from_msgpack_err(&e, frame), msg: e.to_string() }
+            }),
+        }
     }
 }
 
 impl Into<Message> for IpcMessageResponse {
     fn into(self) -> Message {
-        let msg = serde_json::to_vec(&self).unwrap();
+        self.into_message(Encoding::Json)
+    }
+}
+
+impl From<P2PDecodeErr> for IpcMessageResponse {
+    fn from(err: P2PDecodeErr) -> Self {
+        let response = IpcResponse::Error { msg: err.msg, code: err.code };
+        // an empty id means that the id of the request couldn't be recovered.
+        IpcMessageResponse::from_response(response, err.id.unwrap_or_default())
+    }
+}
+
+impl IpcMessageResponse {
+    pub fn into_message(self, encoding: Encoding) -> Message {
+        let msg = match encoding {
+            Encoding::Json => serde_json::to_vec(&self).unwrap(),
+            // structs are encoded as maps (and not as arrays) to keep the field names like in JSON.
+            Encoding::MsgPack => rmp_serde::to_vec_named(&self).unwrap(),
+        };
         Message::from(&msg)
     }
 }
 
+impl IpcMessageRequest {
+    pub fn into_message(self, encoding: Encoding) -> Message {
+        let msg = match encoding {
+            Encoding::Json => serde_json::to_vec(&self).unwrap(),
+            Encoding::MsgPack => rmp_serde::to_vec_named(&self).unwrap(),
+        };
+        Message::from(&msg)
+    }
+}
+
+/// (De)serializes byte fields natively, so in MessagePack they're encoded as binary and not as an array of numbers.
+/// JSON doesn't have a binary type, so there they're still an array of numbers.
+mod bytes {
+    use serde::de::{self, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{Serialize, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+
+    struct Bytes<'a>(&'a [u8]);
+
+    impl<'a> Serialize for Bytes<'a> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("a byte array") }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> { Ok(v.to_vec()) }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> { Ok(v) }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+
+    pub mod option {
+        use serde::de::{self, Deserializer, Visitor};
+        use serde::ser::Serializer;
+        use std::fmt;
+        use super::Bytes;
+
+        pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+            match bytes {
+                Some(bytes) => serializer.serialize_some(&Bytes(bytes)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+            deserializer.deserialize_option(OptionVisitor)
+        }
+
+        struct OptionVisitor;
+
+        impl<'de> Visitor<'de> for OptionVisitor {
+            type Value = Option<Vec<u8>>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("an optional byte array") }
+
+            fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> { Ok(None) }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> { Ok(None) }
+
+            fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+                super::deserialize(deserializer).map(Some)
+            }
+        }
+    }
+}
+
 pub(crate) trait UnwrapError<T> {
     fn unwrap_or_error(self) -> T;
 }
 
-impl<E: std::fmt::Display> UnwrapError<IpcResponse> for Result<IpcResponse, E> {
+impl UnwrapError<IpcResponse> for Result<IpcResponse, Error> {
     fn unwrap_or_error(self) -> IpcResponse {
         match self {
             Ok(m) => m,
             Err(e) => {
                 error!("Unwrapped p2p Message failed: {}", e);
-                IpcResponse::Error {msg: format!("{}", e)}
+                let code = if e.downcast_ref::<FromHexError>().is_some() {
+                    IpcErrorCode::BadHex
+                } else if let Some(err) = e.downcast_ref::<P2PDecodeErr>() {
+                    err.code
+                } else {
+                    IpcErrorCode::Failure
+                };
+                IpcResponse::Error {msg: format!("{}", e), code}
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value;
+
+    fn delta() -> IpcDelta {
+        IpcDelta { contract_address: Some([3u8; 32].to_hex()), key: 7, data: Some(vec![1, 2, 3, 255]) }
+    }
+
+    fn deltas_range() -> IpcDeltasRange {
+        IpcDeltasRange { address: [4u8; 32].to_hex(), from: 1, to: 5 }
+    }
+
+    fn task(pre_code: Option<Vec<u8>>) -> IpcTask {
+        IpcTask {
+            pre_code,
+            encrypted_args: "a1b2".to_string(),
+            encrypted_fn: "c3d4".to_string(),
+            user_dhkey: [5u8; 64].to_hex(),
+            gas_limit: 100_000,
+            address: [6u8; 32].to_hex(),
+            block_number: 1_234,
+            epoch_nonce: [7u8; 32].to_hex(),
+            gas_profiling: true,
+        }
+    }
+
+    /// One instance of every request, any new variant should be added here.
+    fn all_requests() -> Vec<IpcRequest> {
+        vec![
+            IpcRequest::GetRegistrationParams,
+            IpcRequest::GetTip { input: [1u8; 32].to_hex() },
+            IpcRequest::GetTips { input: vec![[1u8; 32].to_hex(), [2u8; 32].to_hex()] },
+            IpcRequest::GetAllTips { limit: None, cursor: None },
+            IpcRequest::GetAllTips { limit: Some(10), cursor: Some([1u8; 32].to_hex()) },
+            IpcRequest::GetAllAddrs,
+            IpcRequest::GetDelta { input: delta() },
+            IpcRequest::GetDeltas { input: vec![deltas_range(), deltas_range()], limit: None, cursor: None },
+            IpcRequest::GetDeltas { input: vec![deltas_range()], limit: Some(3), cursor: Some(DeltasCursor { range: 0, key: 2 }.encode()) },
+            IpcRequest::GetContract { input: [1u8; 32].to_hex() },
+            IpcRequest::UpdateNewContract { address: [1u8; 32].to_hex(), bytecode: vec![0, 97, 115, 109, 1, 0, 0, 0] },
+            IpcRequest::UpdateNewContractOnDeployment { address: [1u8; 32].to_hex(), bytecode: "0061736d".to_string(), delta: delta() },
+            IpcRequest::RemoveContract { address: [1u8; 32].to_hex() },
+            IpcRequest::UpdateDeltas { deltas: vec![delta(), IpcDelta { data: None, ..delta() }] },
+            IpcRequest::RemoveDeltas { input: vec![deltas_range()] },
+            IpcRequest::NewTaskEncryptionKey { user_pubkey: [5u8; 64].to_hex() },
+            IpcRequest::DeploySecretContract { input: task(Some(vec![0, 97, 115, 109])) },
+            IpcRequest::UpgradeSecretContract { input: task(Some(vec![0, 97, 115, 109])) },
+            IpcRequest::ComputeTask { input: task(None) },
+            IpcRequest::QueryTask { input: task(None) },
+            IpcRequest::GetPTTRequest,
+            IpcRequest::PTTResponse { input: PrincipalResponse { response: "deadbeef".to_string() } },
+            IpcRequest::PruneSnapshots { address: [1u8; 32].to_hex(), keep: 2 },
+            IpcRequest::PruneDeltas { address: [1u8; 32].to_hex(), below: 500 },
+            IpcRequest::GetDbStats,
+        ]
+    }
+
+    fn round_trip(request: IpcRequest, encoding: Encoding) {
+        let msg = IpcMessageRequest::from_request(request, "42".to_string());
+        let expected: Value = serde_json::to_value(&msg).unwrap();
+
+        let frame = msg.into_message(encoding);
+        assert_eq!(Encoding::detect(&frame), encoding);
+        let decoded = IpcMessageRequest::from_frame(&frame, encoding).unwrap();
+        assert_eq!(decoded.id, "42");
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_round_trip_json() {
+        for request in all_requests() {
+            round_trip(request, Encoding::Json);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_msgpack() {
+        for request in all_requests() {
+            round_trip(request, Encoding::MsgPack);
+        }
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(Encoding::detect(b"{\"id\":\"1\",\"type\":\"GetAllAddrs\"}"), Encoding::Json);
+        assert_eq!(Encoding::detect(b" \n{\"id\":\"1\"}"), Encoding::Json);
+        let msgpack = rmp_serde::to_vec_named(&IpcMessageRequest::from_request(IpcRequest::GetAllAddrs, "1".to_string())).unwrap();
+        assert_eq!(Encoding::detect(&msgpack), Encoding::MsgPack);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let err = IpcMessageRequest::from_frame(&[b'{', 0xff, 0xfe, b'}'], Encoding::Json).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::InvalidUtf8);
+        assert_eq!(err.id, None);
+
+        let err = IpcMessageRequest::from_frame(b"{\"id\": \"12\", \"type\": \"NoSuchRequest\"}", Encoding::Json).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::UnknownType);
+        assert_eq!(err.id, Some("12".to_string()));
+
+        let err = IpcMessageRequest::from_frame(b"{\"id\": \"13\", \"type\": \"GetTip\"}", Encoding::Json).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::MissingField);
+        assert_eq!(err.id, Some("13".to_string()));
+
+        let err = IpcMessageRequest::from_frame(b"{\"type\": \"GetAllAddrs\"}", Encoding::Json).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::MissingField);
+        assert_eq!(err.id, None);
+
+        let err = IpcMessageRequest::from_frame(b"{\"id\": \"14\", \"type\": \"GetTip\", \"input\": 5}", Encoding::Json).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::InvalidField);
+        assert_eq!(err.id, Some("14".to_string()));
+
+        let err = IpcMessageRequest::from_frame(b"{\"id\": \"15\", \"ty", Encoding::Json).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::MalformedMessage);
+        assert_eq!(err.id, None);
+
+        let msgpack = rmp_serde::to_vec_named(&serde_json::json!({"id": "16", "type": "NoSuchRequest"})).unwrap();
+        let err = IpcMessageRequest::from_frame(&msgpack, Encoding::MsgPack).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::UnknownType);
+        assert_eq!(err.id, Some("16".to_string()));
+
+        let msgpack = rmp_serde::to_vec_named(&serde_json::json!({"id": "18", "type": "PruneDeltas", "address": "00"})).unwrap();
+        let err = IpcMessageRequest::from_frame(&msgpack, Encoding::MsgPack).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::MissingField);
+
+        let msgpack = rmp_serde::to_vec_named(&serde_json::json!({"id": "19", "type": "PruneDeltas", "address": "00", "below": -1})).unwrap();
+        let err = IpcMessageRequest::from_frame(&msgpack, Encoding::MsgPack).unwrap_err();
+        assert_eq!(err.code, IpcErrorCode::InvalidField);
+    }
+
+    #[test]
+    fn test_decode_error_response() {
+        let err = P2PDecodeErr { id: Some("17".to_string()), code: IpcErrorCode::MissingField, msg: "missing field `input`".to_string() };
+        let response: IpcMessageResponse = err.into();
+        let response = serde_json::to_value(&response).unwrap();
+        assert_eq!(response, serde_json::json!({"id": "17", "type": "Error", "msg": "missing field `input`", "code": "MissingField"}));
+    }
+
+    #[test]
+    fn test_task_without_context() {
+        let frame = br#"{"id": "3", "type": "ComputeTask", "input": {"encryptedArgs": "a1", "encryptedFn": "b2", "userDHKey": "c3", "gasLimit": 10, "contractAddress": "d4"}}"#;
+        let input = match IpcMessageRequest::from_frame(frame, Encoding::Json).unwrap().request {
+            IpcRequest::ComputeTask { input } => input,
+            request => panic!("expected a ComputeTask, got: {:?}", request),
+        };
+        assert_eq!(input.block_number, 0);
+        assert!(input.epoch_nonce.is_empty());
+    }
+
+    #[test]
+    fn test_msgpack_encodes_bytes_natively() {
+        let bytecode = vec![0xAAu8; 1024];
+        let request = IpcRequest::UpdateNewContract { address: [1u8; 32].to_hex(), bytecode: bytecode.clone() };
+        let msg = IpcMessageRequest::from_request(request, "1".to_string());
+        let msgpack = rmp_serde::to_vec_named(&msg).unwrap();
+        let json = serde_json::to_vec(&msg).unwrap();
+        // the bytecode is stored as is with a small header, while in JSON every byte takes 4 characters.
+        assert!(msgpack.len() < bytecode.len() + 128);
+        assert!(json.len() > bytecode.len() * 3);
+    }
+
+    #[test]
+    fn test_response_encodings() {
+        let result = IpcResults::Addresses(vec![[1u8; 32].to_hex(), [2u8; 32].to_hex()]);
+        let response = IpcMessageResponse::from_response(IpcResponse::GetAllAddrs { result }, "5".to_string());
+        let expected = serde_json::to_value(&response).unwrap();
+
+        let json = response.clone().into_message(Encoding::Json);
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json, expected);
+
+        let msgpack = response.into_message(Encoding::MsgPack);
+        let msgpack: Value = rmp_serde::from_slice(&msgpack).unwrap();
+        assert_eq!(msgpack, expected);
+    }
+}
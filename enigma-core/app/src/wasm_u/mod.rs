@@ -5,6 +5,7 @@ use crate::db::{Delta, DeltaKey, Stype};
 use std::{fmt, convert::TryFrom};
 use enigma_types::{EnclaveReturn, ExecuteResult, ContractAddress};
 use failure::Error;
+use serde_json::{self, Value};
 use sgx_types::*;
 
 #[derive(Clone)]
@@ -12,16 +13,36 @@ pub struct WasmTaskResult {
     pub bytecode: Box<[u8]>,
     pub output: Box<[u8]>, // On Deploy this will be the exeCode
     pub delta: Delta,
-    pub eth_payload: Box<[u8]>,
-    pub eth_contract_addr: [u8; 20],
+    /// The ethereum payloads in the order they were written, empty if the execution didn't call ethereum.
+    pub eth_bridges: Vec<EthBridge>,
     pub signature: [u8; 65],
     pub used_gas: u64,
+    /// The deltas of the other contracts that the execution called and changed.
+    pub called_deltas: Vec<Delta>,
+    /// The events emitted during the execution, the topic and the data are encrypted with the user's key.
+    pub events: Vec<Event>,
+    /// Where the gas was used, only debug enclaves report it.
+    pub gas_profile: Option<Value>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EthBridge {
+    pub payload: Vec<u8>,
+    pub contract_addr: [u8; 20],
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    pub contract_address: ContractAddress,
+    pub topic: Vec<u8>,
+    pub data: Vec<u8>,
 }
 
 pub struct WasmTaskFailure {
     pub output: Box<[u8]>,
     pub signature: [u8; 65],
     pub used_gas: u64,
+    pub gas_profile: Option<Value>,
 }
 
 #[derive(Debug)]
@@ -36,10 +57,12 @@ impl Default for WasmTaskResult {
             bytecode: Default::default(),
             output: Default::default(),
             delta: Default::default(),
-            eth_payload: Default::default(),
-            eth_contract_addr: Default::default(),
+            eth_bridges: Default::default(),
             signature: [0u8; 65],
-            used_gas: Default::default()
+            used_gas: Default::default(),
+            called_deltas: Default::default(),
+            events: Default::default(),
+            gas_profile: Default::default(),
         }
     }
 }
@@ -49,7 +72,8 @@ impl Default for  WasmTaskFailure {
         WasmTaskFailure {
             output: Default::default(),
             signature: [0u8; 65],
-            used_gas: Default::default()
+            used_gas: Default::default(),
+            gas_profile: Default::default(),
         }
     }
 }
@@ -60,10 +84,12 @@ impl fmt::Debug for WasmTaskResult {
         debug_builder.field("bytecode", &self.bytecode);
         debug_builder.field("output", &self.output);
         debug_builder.field("delta", &self.delta);
-        debug_builder.field("eth_payload", &self.eth_payload);
-        debug_builder.field("eth_contract_addr", &self.eth_contract_addr);
+        debug_builder.field("eth_bridges", &self.eth_bridges);
         debug_builder.field("signature", &(&self.signature[..]));
         debug_builder.field("used_gas", &self.used_gas);
+        debug_builder.field("called_deltas", &self.called_deltas);
+        debug_builder.field("events", &self.events);
+        debug_builder.field("gas_profile", &self.gas_profile);
         debug_builder.finish()
     }
 }
@@ -74,6 +100,7 @@ impl fmt::Debug for WasmTaskFailure{
         debug_builder.field("output", &self.output);
         debug_builder.field("signature", &(&self.signature[..]));
         debug_builder.field("used_gas", &self.used_gas);
+        debug_builder.field("gas_profile", &self.gas_profile);
         debug_builder.finish()
     }
 }
@@ -94,13 +121,14 @@ impl TryFrom<(ExecuteResult, ContractAddress, EnclaveReturn, sgx_status_t)> for
             result.output = get_output(exec.0)?;
             result.signature = exec.0.signature;
             result.used_gas = exec.0.used_gas;
+            result.gas_profile = get_gas_profile(exec.0)?;
             Ok(WasmResult::WasmTaskFailure(result))
         }
         else if exec.2 != EnclaveReturn::Success || exec.3 != sgx_status_t::SGX_SUCCESS {
             Err(EnclaveFailError { err: exec.2, status: exec.3 }.into())
         }
         else {
-            if exec.0.ethereum_payload_ptr.is_null() || exec.0.delta_ptr.is_null() {
+            if exec.0.ethereum_bridges_ptr.is_null() || exec.0.delta_ptr.is_null() {
                 bail!("One of the pointers in ExecuteResult is null: {:?}", exec.0);
             }
 
@@ -111,14 +139,10 @@ impl TryFrom<(ExecuteResult, ContractAddress, EnclaveReturn, sgx_status_t)> for
             result.used_gas = exec.0.used_gas;
 
             // If there is no call to any ethereum contract in the execution, then
-            // `eth_contract_addr` is all zeros
-            result.eth_contract_addr = exec.0.ethereum_address;
-
-            // If there is no call to any ethereum contract in the execution, then
-            // `ethereum_payload_ptr` points to empty array []
-            let box_payload_ptr = exec.0.ethereum_payload_ptr as *mut Box<[u8]>;
-            let payload = unsafe { Box::from_raw(box_payload_ptr) };
-            result.eth_payload = *payload;
+            // `ethereum_bridges_ptr` points to empty array []
+            let box_payload_ptr = exec.0.ethereum_bridges_ptr as *mut Box<[u8]>;
+            let payloads = unsafe { Box::from_raw(box_payload_ptr) };
+            result.eth_bridges = parse_eth_bridges(&payloads)?;
 
             // If state was not changed by the execution (which means that delta is empty),
             // then `delta_ptr` points to empty array []
@@ -128,7 +152,172 @@ impl TryFrom<(ExecuteResult, ContractAddress, EnclaveReturn, sgx_status_t)> for
             result.delta.value = delta_data.to_vec();
             result.delta.key = DeltaKey::new(exec.1, Stype::Delta(exec.0.delta_index));
 
+            // Only executions return the deltas of the called contracts, so it's null after a deployment.
+            if !exec.0.called_deltas_ptr.is_null() {
+                let box_ptr = exec.0.called_deltas_ptr as *mut Box<[u8]>;
+                let called_deltas = unsafe { Box::from_raw(box_ptr) };
+                result.called_deltas = parse_called_deltas(&called_deltas)?;
+            }
+            // Same for the events.
+            if !exec.0.events_ptr.is_null() {
+                let box_ptr = exec.0.events_ptr as *mut Box<[u8]>;
+                let events = unsafe { Box::from_raw(box_ptr) };
+                result.events = parse_events(&events)?;
+            }
+            result.gas_profile = get_gas_profile(exec.0)?;
+
             Ok(WasmResult::WasmTaskResult(result))
         }
     }
 }
+
+/// The gas profile is null unless the enclave was built in debug mode.
+fn get_gas_profile(exec_result: ExecuteResult) -> Result<Option<Value>, Error> {
+    if exec_result.gas_profile_ptr.is_null() {
+        return Ok(None);
+    }
+    let box_ptr = exec_result.gas_profile_ptr as *mut Box<[u8]>;
+    let profile = unsafe { Box::from_raw(box_ptr) };
+    Ok(Some(serde_json::from_slice(&profile)?))
+}
+
+/// Parses the deltas of the called contracts, the format is described in `ExecuteResult::called_deltas_ptr`.
+fn parse_called_deltas(mut data: &[u8]) -> Result<Vec<Delta>, Error> {
+    // address + index + length
+    const HEADER_SIZE: usize = 32 + 4 + 4;
+    let mut deltas = Vec::new();
+    while !data.is_empty() {
+        if data.len() < HEADER_SIZE {
+            bail!("The called deltas are truncated, {} bytes left", data.len());
+        }
+        let mut address = [0u8; 32];
+        address.copy_from_slice(&data[..32]);
+        let mut index = [0u8; 4];
+        index.copy_from_slice(&data[32..36]);
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&data[36..HEADER_SIZE]);
+        let end = HEADER_SIZE + u32::from_be_bytes(len) as usize;
+        if data.len() < end {
+            bail!("The called deltas are truncated, expected {} bytes but got {}", end, data.len());
+        }
+        let key = DeltaKey::new(address.into(), Stype::Delta(u32::from_be_bytes(index)));
+        deltas.push(Delta { key, value: data[HEADER_SIZE..end].to_vec() });
+        data = &data[end..];
+    }
+    Ok(deltas)
+}
+
+/// Parses the ethereum payloads, the format is described in `ExecuteResult::ethereum_bridges_ptr`.
+fn parse_eth_bridges(mut data: &[u8]) -> Result<Vec<EthBridge>, Error> {
+    // address + length
+    const HEADER_SIZE: usize = 20 + 4;
+    let mut bridges = Vec::new();
+    while !data.is_empty() {
+        if data.len() < HEADER_SIZE {
+            bail!("The ethereum payloads are truncated, {} bytes left", data.len());
+        }
+        let mut contract_addr = [0u8; 20];
+        contract_addr.copy_from_slice(&data[..20]);
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&data[20..HEADER_SIZE]);
+        let end = HEADER_SIZE + u32::from_be_bytes(len) as usize;
+        if data.len() < end {
+            bail!("The ethereum payloads are truncated, expected {} bytes but got {}", end, data.len());
+        }
+        bridges.push(EthBridge { payload: data[HEADER_SIZE..end].to_vec(), contract_addr });
+        data = &data[end..];
+    }
+    Ok(bridges)
+}
+
+/// Parses the events, the format is described in `ExecuteResult::events_ptr`.
+fn parse_events(mut data: &[u8]) -> Result<Vec<Event>, Error> {
+    fn take_field<'a>(data: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+        if data.len() < 4 {
+            bail!("The events are truncated, {} bytes left", data.len());
+        }
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&data[..4]);
+        let end = 4 + u32::from_be_bytes(len) as usize;
+        if data.len() < end {
+            bail!("The events are truncated, expected {} bytes but got {}", end, data.len());
+        }
+        let field = &data[4..end];
+        *data = &data[end..];
+        Ok(field)
+    }
+
+    let mut events = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 32 {
+            bail!("The events are truncated, {} bytes left", data.len());
+        }
+        let mut address = [0u8; 32];
+        address.copy_from_slice(&data[..32]);
+        data = &data[32..];
+        let topic = take_field(&mut data)?.to_vec();
+        let event_data = take_field(&mut data)?.to_vec();
+        events.push(Event { contract_address: address.into(), topic, data: event_data });
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_called_deltas() {
+        let mut data = Vec::new();
+        for (i, value) in [&b"first"[..], &b"second"[..]].iter().enumerate() {
+            data.extend_from_slice(&[i as u8 + 1; 32]);
+            data.extend_from_slice(&(i as u32 + 5).to_be_bytes());
+            data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            data.extend_from_slice(value);
+        }
+        let deltas = parse_called_deltas(&data).unwrap();
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[1].key, DeltaKey::new([2u8; 32].into(), Stype::Delta(6)));
+        assert_eq!(deltas[1].value, b"second".to_vec());
+
+        assert!(parse_called_deltas(&data[..data.len() - 1]).is_err());
+        assert!(parse_called_deltas(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_eth_bridges() {
+        let mut data = Vec::new();
+        for (i, payload) in [&b"first"[..], &b"second"[..]].iter().enumerate() {
+            data.extend_from_slice(&[i as u8 + 1; 20]);
+            data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            data.extend_from_slice(payload);
+        }
+        let bridges = parse_eth_bridges(&data).unwrap();
+        assert_eq!(bridges, vec![
+            EthBridge { payload: b"first".to_vec(), contract_addr: [1u8; 20] },
+            EthBridge { payload: b"second".to_vec(), contract_addr: [2u8; 20] },
+        ]);
+
+        assert!(parse_eth_bridges(&data[..data.len() - 1]).is_err());
+        assert!(parse_eth_bridges(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let mut data = Vec::new();
+        for (topic, value) in [(&b"Transfer"[..], &b"first"[..]), (&b""[..], &b""[..])].iter() {
+            data.extend_from_slice(&[7u8; 32]);
+            data.extend_from_slice(&(topic.len() as u32).to_be_bytes());
+            data.extend_from_slice(topic);
+            data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            data.extend_from_slice(value);
+        }
+        let events = parse_events(&data).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], Event { contract_address: [7u8; 32].into(), topic: b"Transfer".to_vec(), data: b"first".to_vec() });
+        assert!(events[1].topic.is_empty() && events[1].data.is_empty());
+
+        assert!(parse_events(&data[..data.len() - 1]).is_err());
+        assert!(parse_events(&data[..40]).is_err());
+    }
+}
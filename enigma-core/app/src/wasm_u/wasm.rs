@@ -1,14 +1,28 @@
-use enigma_types::{ContractAddress, EnclaveReturn, ExecuteResult, PubKey, RawPointer, traits::SliceCPtr};
+use enigma_types::{ContractAddress, EnclaveReturn, ExecuteResult, PubKey, RawPointer, TaskContext, TaskOptions, traits::SliceCPtr};
 use super::WasmResult;
 use crate::db::DB;
 use std::convert::TryInto;
 use failure::Error;
 use sgx_types::*;
-use crate::auto_ffi::{ecall_deploy, ecall_execute};
+use crate::auto_ffi::{ecall_deploy, ecall_execute, ecall_query, ecall_set_gas_schedule, ecall_upgrade};
+use crate::common_u::errors::EnclaveFailError;
+
+/// Replaces the gas schedule of all the following tasks, `schedule` is the JSON of the schedule.
+/// Returns the hash of the schedule, which is part of the signature of every task.
+#[logfn(TRACE)]
+pub fn set_gas_schedule(eid: sgx_enclave_id_t, schedule: &[u8]) -> Result<[u8; 32], Error> {
+    let mut retval = EnclaveReturn::Success;
+    let mut schedule_hash = [0u8; 32];
+    let status = unsafe { ecall_set_gas_schedule(eid, &mut retval, schedule.as_c_ptr(), schedule.len(), &mut schedule_hash) };
+    if retval != EnclaveReturn::Success || status != sgx_status_t::SGX_SUCCESS {
+        return Err(EnclaveFailError { err: retval, status }.into());
+    }
+    Ok(schedule_hash)
+}
 
 #[logfn(TRACE)]
 pub fn deploy(db: &mut DB, eid: sgx_enclave_id_t,  bytecode: &[u8], constructor: &[u8], args: &[u8],
-              contract_address: &ContractAddress, user_pubkey: &PubKey, gas_limit: u64)-> Result<WasmResult, Error> {
+              contract_address: &ContractAddress, user_pubkey: &PubKey, context: &TaskContext, options: &TaskOptions, gas_limit: u64)-> Result<WasmResult, Error> {
     let mut retval = EnclaveReturn::Success;
     let mut result = ExecuteResult::default();
     let db_ptr = unsafe { RawPointer::new_mut(db) };
@@ -24,6 +38,8 @@ pub fn deploy(db: &mut DB, eid: sgx_enclave_id_t,  bytecode: &[u8], constructor:
                      args.len(),
                      contract_address,
                      user_pubkey.as_ptr() as _,
+                     context,
+                     options,
                      &gas_limit as *const u64,
                      &db_ptr as *const RawPointer,
                      &mut result)
@@ -31,9 +47,39 @@ pub fn deploy(db: &mut DB, eid: sgx_enclave_id_t,  bytecode: &[u8], constructor:
     (result, *contract_address, retval, status).try_into()
 }
 
+/// Upgrades the contract to the new pre-code `bytecode`, running its `migrate` against the state.
+/// The enclave loads the current bytecode from the DB, and the contract has to authorize the upgrade.
+/// The output of the result is the new bytecode, it isn't saved into the DB here.
+#[logfn(TRACE)]
+pub fn upgrade(db: &mut DB, eid: sgx_enclave_id_t, bytecode: &[u8], callable: &[u8], args: &[u8],
+               contract_address: &ContractAddress, user_pubkey: &PubKey, context: &TaskContext, options: &TaskOptions, gas_limit: u64)-> Result<WasmResult, Error> {
+    let mut retval = EnclaveReturn::Success;
+    let mut result = ExecuteResult::default();
+    let db_ptr = unsafe { RawPointer::new_mut(db) };
+
+    let status = unsafe {
+        ecall_upgrade(eid,
+                      &mut retval,
+                      bytecode.as_c_ptr(),
+                      bytecode.len(),
+                      callable.as_c_ptr(),
+                      callable.len(),
+                      args.as_c_ptr(),
+                      args.len(),
+                      contract_address,
+                      user_pubkey.as_ptr() as _,
+                      context,
+                      options,
+                      &gas_limit as *const u64,
+                      &db_ptr as *const RawPointer,
+                      &mut result)
+    };
+    (result, *contract_address, retval, status).try_into()
+}
+
 #[logfn(TRACE)]
 pub fn execute(db: &mut DB, eid: sgx_enclave_id_t,  bytecode: &[u8], callable: &[u8], args: &[u8],
-               user_pubkey: &PubKey, contract_address: &ContractAddress, gas_limit: u64)-> Result<WasmResult,Error> {
+               user_pubkey: &PubKey, contract_address: &ContractAddress, context: &TaskContext, options: &TaskOptions, gas_limit: u64)-> Result<WasmResult,Error> {
     let mut retval = EnclaveReturn::Success;
     let mut result = ExecuteResult::default();
     let db_ptr = unsafe { RawPointer::new_mut(db) };
@@ -49,6 +95,8 @@ pub fn execute(db: &mut DB, eid: sgx_enclave_id_t,  bytecode: &[u8], callable: &
                       args.len(),
                       user_pubkey.as_ptr() as _,
                       contract_address,
+                      context,
+                      options,
                       &gas_limit as *const u64,
                       &db_ptr as *const RawPointer,
                       &mut result)
@@ -57,6 +105,36 @@ pub fn execute(db: &mut DB, eid: sgx_enclave_id_t,  bytecode: &[u8], callable: &
     (result, *contract_address, retval, status).try_into()
 }
 
+/// Runs a function of the contract against its current state without changing it,
+/// the result never has a delta and the user's DH key can be reused.
+#[logfn(TRACE)]
+pub fn query(db: &mut DB, eid: sgx_enclave_id_t,  bytecode: &[u8], callable: &[u8], args: &[u8],
+             user_pubkey: &PubKey, contract_address: &ContractAddress, context: &TaskContext, options: &TaskOptions, gas_limit: u64)-> Result<WasmResult,Error> {
+    let mut retval = EnclaveReturn::Success;
+    let mut result = ExecuteResult::default();
+    let db_ptr = unsafe { RawPointer::new_mut(db) };
+
+    let status = unsafe {
+        ecall_query(eid,
+                    &mut retval,
+                    bytecode.as_c_ptr() as *const u8,
+                    bytecode.len(),
+                    callable.as_c_ptr() as *const u8,
+                    callable.len(),
+                    args.as_c_ptr() as *const u8,
+                    args.len(),
+                    user_pubkey.as_ptr() as _,
+                    contract_address,
+                    context,
+                    options,
+                    &gas_limit as *const u64,
+                    &db_ptr as *const RawPointer,
+                    &mut result)
+    };
+
+    (result, *contract_address, retval, status).try_into()
+}
+
 #[cfg(test)]
 mod tests {
     extern crate ethabi;
@@ -69,7 +147,7 @@ mod tests {
     use crate::db::{DB, tests::create_test_db};
     use crate::wasm_u::wasm;
     use self::ethabi::{Contract, Token, token::{LenientTokenizer, Tokenizer}};
-    use enigma_types::{ContractAddress, DhKey, PubKey};
+    use enigma_types::{ContractAddress, DhKey, PubKey, TaskContext, TaskOptions};
     use enigma_crypto::symmetric;
     use hex::FromHex;
     use sgx_types::*;
@@ -92,7 +170,7 @@ mod tests {
         let wasm_code = get_bytecode_from_path(test_path);
         println!("Bytecode size: {}KB\n", wasm_code.len() / 1024);
 
-        wasm::deploy(db, eid, &wasm_code, constructor, args, &contract_address, &user_pubkey, GAS_LIMIT).expect("Deploy Failed")
+        wasm::deploy(db, eid, &wasm_code, constructor, args, &contract_address, &user_pubkey, &TaskContext::default(), &TaskOptions::default(), GAS_LIMIT).expect("Deploy Failed")
     }
 
     fn compile_deploy_execute(db: &mut DB,
@@ -132,6 +210,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &contract_address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -187,6 +267,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &contract_address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -222,6 +304,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -240,6 +324,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -275,6 +361,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -289,6 +377,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -348,6 +438,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &contract_address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed");
 
@@ -359,6 +451,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_contract_error() {
+        let (mut db, _dir) = create_test_db();
+        let address = generate_contract_address();
+        let (enclave, deploy_res) = compile_deploy_contract_execute(
+            &mut db,
+            "../../examples/eng_wasm_contracts/simplest",
+            address,
+            "construct(uint)",
+            &[Token::Uint(17.into())],
+        );
+
+        let (keys, shared_key, _, _) = exchange_keys(enclave.geteid());
+        let encrypted_callable = symmetric::encrypt(b"subtraction(uint256,uint256)", &shared_key).unwrap();
+        let encrypted_args = symmetric::encrypt(&ethabi::encode(&[Token::Uint(2.into()), Token::Uint(5.into())]), &shared_key).unwrap();
+        let result = wasm::execute(
+            &mut db,
+            enclave.geteid(),
+            &deploy_res.output,
+            &encrypted_callable,
+            &encrypted_args,
+            &keys.get_pubkey(),
+            &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
+            GAS_LIMIT
+        ).expect("Execution failed");
+
+        match result {
+            WasmResult::WasmTaskFailure(failure) => {
+                assert!(failure.used_gas > 0);
+                let error = symmetric::decrypt(&failure.output, &shared_key).unwrap();
+                assert_eq!(String::from_utf8(error).unwrap(), "The contract returned an error: the result would be negative");
+            }
+            WasmResult::WasmTaskResult(_) => panic!("expected the contract to return an error"),
+        }
+    }
+
     #[test]
     fn test_print_simple() {
         let (mut db, _dir) = create_test_db();
@@ -405,6 +535,8 @@ mod tests {
             &encrypted_args2,
             &keys2.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -424,6 +556,8 @@ mod tests {
             &encrypted_args3,
             &keys3.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -443,6 +577,8 @@ mod tests {
             &encrypted_args4,
             &keys4.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -641,6 +777,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -683,6 +821,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -731,6 +871,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed");
 
@@ -745,6 +887,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -762,6 +906,8 @@ mod tests {
             &encrypted_args,
             &keys.get_pubkey(),
             &address,
+            &TaskContext::default(),
+            &TaskOptions::default(),
             GAS_LIMIT
         ).expect("Execution failed").unwrap_result();
 
@@ -789,8 +935,9 @@ mod tests {
 
         let payload = create_eth_payload("../../examples/eng_wasm_contracts/contract_with_eth_calls/Test.json",
                                          "getBryn", &["1".to_string(), "[]".to_string()]);
-        assert_eq!(&payload[..], &*result.eth_payload);
-        assert_eq!("123f681646d4a755815f9cb19e1acc8565a0c2ac".from_hex().unwrap(), result.eth_contract_addr);
+        assert_eq!(result.eth_bridges.len(), 1);
+        assert_eq!(payload, result.eth_bridges[0].payload);
+        assert_eq!("123f681646d4a755815f9cb19e1acc8565a0c2ac".from_hex().unwrap(), result.eth_bridges[0].contract_addr);
     }
 
     #[test]
@@ -1019,7 +1166,7 @@ mod tests {
 
         let payload = create_eth_payload("../../examples/eng_wasm_contracts/voting_demo/VotingETH.json",
                                          "validateCastVote", &["0".to_string()]);
-        assert_eq!(&payload[..], &*compute_res.eth_payload);
+        assert_eq!(payload, compute_res.eth_bridges[0].payload);
 
         let voter_two_addr = generate_user_address().0;
         let (compute_res, _) = compile_compute_task_execute(
@@ -1033,7 +1180,7 @@ mod tests {
 
         let payload = create_eth_payload("../../examples/eng_wasm_contracts/voting_demo/VotingETH.json",
                                          "validateCastVote", &["0".to_string()]);
-        assert_eq!(&payload[..], &*compute_res.eth_payload);
+        assert_eq!(payload, compute_res.eth_bridges[0].payload);
 
         let (compute_res, _) = compile_compute_task_execute(
             &mut db,
@@ -1046,6 +1193,6 @@ mod tests {
 
         let payload = create_eth_payload("../../examples/eng_wasm_contracts/voting_demo/VotingETH.json",
                                          "validateTallyPoll", &["0".to_string(), "50".to_string()]);
-        assert_eq!(&payload[..], &*compute_res.eth_payload);
+        assert_eq!(payload, compute_res.eth_bridges[0].payload);
     }
 }
@@ -58,6 +58,20 @@ pub fn run_core(port: &'static str) {
     });
 }
 
+/// Like `run_core` but with the concurrent listener, handling the read only requests with `workers` workers.
+pub fn run_core_pool(port: &'static str, workers: usize) {
+    thread::spawn(move || {
+        let enclave = esgx::general::init_enclave_wrapper().expect("Init Enclave Failed");
+        let eid = enclave.geteid();
+
+        let (db, _datadir) = create_test_db();
+        let server = IpcListener::new(&format!("tcp://*:{}", port));
+        let spid = "B0335FD3BC1CCA8F804EB98A6420592D".to_string();
+        let retries = 10;
+        server.run_pool(db, workers, spid, eid, retries).unwrap();
+    });
+}
+
 pub fn generate_job_id() -> String {
     let mut rng = thread_rng();
     let id: u32 = rng.gen();
@@ -128,6 +142,24 @@ pub fn deltas_msg(input: &[(String, u64, u64)], msg_type: &str) -> Value {
     json!({"id": &generate_job_id(), "type": msg_type, "input": input})
 }
 
+pub fn paged_deltas_msg(input: &[(String, u64, u64)], limit: u32, cursor: Option<&str>) -> Value {
+    let mut msg = deltas_msg(input, "GetDeltas");
+    msg["limit"] = json!(limit);
+    if let Some(cursor) = cursor {
+        msg["cursor"] = json!(cursor);
+    }
+    msg
+}
+
+pub fn paged_all_tips_msg(limit: u32, cursor: Option<&str>) -> Value {
+    let mut msg = get_simple_msg_format("GetAllTips");
+    msg["limit"] = json!(limit);
+    if let Some(cursor) = cursor {
+        msg["cursor"] = json!(cursor);
+    }
+    msg
+}
+
 pub fn get_msg_format_update_contract(addr: &str, bytecode: Vec<u8>) -> Value {
     json!({"id": &generate_job_id(), "type": "UpdateNewContract", "address": addr, "bytecode": bytecode})
 }
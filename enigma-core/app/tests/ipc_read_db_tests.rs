@@ -2,7 +2,8 @@ pub mod integration_utils;
 
 use integration_utils::{run_core, full_simple_deployment, deploy_and_compute_few_contracts,
                         conn_and_call_ipc, get_msg_format_with_input, get_get_tips_msg, get_delta_msg,
-                        deltas_msg, get_simple_msg_format, decrypt_delta_to_value};
+                        deltas_msg, get_simple_msg_format, decrypt_delta_to_value, paged_deltas_msg,
+                        paged_all_tips_msg, run_core_pool};
 pub extern crate enigma_core_app as app;
 extern crate serde;
 extern crate rustc_hex as hex;
@@ -10,6 +11,7 @@ extern crate rustc_hex as hex;
 use self::app::serde_json;
 use app::serde_json::*;
 use hex::{ToHex, FromHex};
+use std::thread;
 
 #[test]
 fn test_ipc_get_tip() {
@@ -125,6 +127,55 @@ fn test_ipc_get_deltas() {
     assert_eq!(second_key, 0);
 }
 
+#[test]
+fn test_ipc_get_deltas_paginated() {
+    let port =  "5581";
+    run_core(port);
+
+    let addresses = deploy_and_compute_few_contracts(port);
+
+    let input: Vec<(String, u64, u64)> = addresses.iter().map(|addr| (addr.to_hex(), 0, 2)).collect();
+    let mut accepted = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let msg = paged_deltas_msg(&input, 4, cursor.as_ref().map(|c| c.as_str()));
+        let res: Value = conn_and_call_ipc(&msg.to_string(), port);
+        let deltas = res["result"]["deltas"].as_array().unwrap();
+        assert!(deltas.len() <= 4);
+        for delta in deltas {
+            accepted.push((delta["address"].as_str().unwrap().to_string(), delta["key"].as_u64().unwrap()));
+        }
+        match res["nextCursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    let expected: Vec<(String, u64)> = addresses.iter().flat_map(|addr| vec![(addr.to_hex(), 0), (addr.to_hex(), 1)]).collect();
+    assert_eq!(accepted, expected);
+}
+
+#[test]
+fn test_ipc_get_all_tips_paginated() {
+    let port =  "5582";
+    run_core(port);
+
+    let mut addresses: Vec<String> = deploy_and_compute_few_contracts(port).iter().map(|addr| addr.to_hex()).collect();
+    addresses.sort();
+
+    let res: Value = conn_and_call_ipc(&paged_all_tips_msg(2, None).to_string(), port);
+    let tips = res["result"]["tips"].as_array().unwrap();
+    assert_eq!(tips.len(), 2);
+    let cursor = res["nextCursor"].as_str().unwrap();
+
+    let res: Value = conn_and_call_ipc(&paged_all_tips_msg(2, Some(cursor)).to_string(), port);
+    let last_tips = res["result"]["tips"].as_array().unwrap();
+    assert_eq!(last_tips.len(), 1);
+    assert!(res["nextCursor"].is_null());
+
+    let accepted: Vec<&str> = tips.iter().chain(last_tips.iter()).map(|tip| tip["address"].as_str().unwrap()).collect();
+    assert_eq!(accepted, addresses);
+}
+
 #[test]
 fn test_ipc_get_contract() {
     let port =  "5567";
@@ -143,3 +194,25 @@ fn test_ipc_get_contract() {
     assert_eq!(type_accepted, type_msg);
     assert_eq!(deployed_bytecode, accepted_bytecode.to_hex());
 }
+
+#[test]
+fn test_ipc_concurrent_reads() {
+    let port =  "5583";
+    run_core_pool(port, 4);
+
+    let (_, contract_address): (_, [u8; 32]) = full_simple_deployment(port);
+    let handles: Vec<_> = (0..8).map(|_| {
+        let address = contract_address.to_hex();
+        thread::spawn(move || {
+            let msg = get_msg_format_with_input("GetTip", &address);
+            let res: Value = conn_and_call_ipc(&msg.to_string(), port);
+            // every client gets the response of its own request.
+            assert_eq!(res["id"], msg["id"]);
+            assert_eq!(res["type"], "GetTip");
+            assert_eq!(res["result"]["key"].as_u64().unwrap(), 0);
+        })
+    }).collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
@@ -26,6 +26,37 @@ extern "C" {
         delta_index: *mut u32,
     ) -> sgx_status_t;
 }
+extern "C" {
+    pub fn ocall_new_delta_and_state(
+        retval: *mut EnclaveReturn,
+        db_ptr: *const RawPointer,
+        contract_address: *const ContractAddress,
+        enc_delta: *const u8,
+        delta_len: usize,
+        delta_index: *mut u32,
+        enc_state: *const u8,
+        state_len: usize,
+    ) -> sgx_status_t;
+}
+extern "C" {
+    pub fn ocall_new_deltas_and_states(
+        retval: *mut EnclaveReturn,
+        db_ptr: *const RawPointer,
+        entries: *const u8,
+        entries_len: usize,
+    ) -> sgx_status_t;
+}
+extern "C" {
+    pub fn ocall_upgrade_contract(
+        retval: *mut EnclaveReturn,
+        db_ptr: *const RawPointer,
+        contract_address: *const ContractAddress,
+        exe_code: *const u8,
+        exe_code_len: usize,
+        entries: *const u8,
+        entries_len: usize,
+    ) -> sgx_status_t;
+}
 extern "C" {
     pub fn ocall_save_to_memory(retval: *mut u64, data_ptr: *const u8, data_len: usize) -> sgx_status_t;
 }
@@ -76,3 +107,50 @@ extern "C" {
         delta_index_: *mut u32,
     ) -> sgx_status_t;
 }
+extern "C" {
+    pub fn ocall_new_snapshot(
+        retval: *mut EnclaveReturn,
+        db_ptr: *const RawPointer,
+        contract_address: *const ContractAddress,
+        enc_state: *const u8,
+        state_len: usize,
+        delta_index: *mut u32,
+    ) -> sgx_status_t;
+}
+extern "C" {
+    pub fn ocall_get_snapshot_size(
+        retval: *mut EnclaveReturn,
+        db_ptr: *const RawPointer,
+        addr: *const ContractAddress,
+        max_index: *const u32,
+        delta_index: *mut u32,
+        state_size: *mut usize,
+    ) -> sgx_status_t;
+}
+extern "C" {
+    pub fn ocall_get_snapshot(
+        retval: *mut EnclaveReturn,
+        db_ptr: *const RawPointer,
+        addr: *const ContractAddress,
+        delta_index: *const u32,
+        state_pt: *mut u8,
+        state_len: usize,
+    ) -> sgx_status_t;
+}
+extern "C" {
+    pub fn ocall_get_contract_size(
+        retval: *mut EnclaveReturn,
+        db_ptr: *const RawPointer,
+        addr: *const ContractAddress,
+        bytecode_size: *mut usize,
+    ) -> sgx_status_t;
+}
+extern "C" {
+    pub fn ocall_get_contract(
+        retval: *mut EnclaveReturn,
+        db_ptr: *const RawPointer,
+        addr: *const ContractAddress,
+        bytecode_pt: *mut u8,
+        bytecode_len: usize,
+    ) -> sgx_status_t;
+}
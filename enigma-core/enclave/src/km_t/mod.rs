@@ -13,6 +13,10 @@ use enigma_types::{ContractAddress, RawPointer, StateKey};
 use std::collections::HashMap;
 use std::sync::SgxMutex;
 
+/// Every `SNAPSHOT_INTERVAL` deltas an encrypted snapshot of the state is stored alongside the deltas,
+/// so rebuilding the state never needs to replay more than that many deltas.
+pub const SNAPSHOT_INTERVAL: u32 = 500;
+
 lazy_static! {
     pub static ref STATE_KEYS: SgxMutex<HashMap<ContractAddress, StateKey>> = SgxMutex::new(HashMap::new());
 }
@@ -41,4 +45,34 @@ pub fn get_state(db_ptr: *const RawPointer, addr: ContractAddress) -> Result<Con
     let state = ContractState::decrypt(enc_state, key)?;
 
     Ok(state)
-}
\ No newline at end of file
+}
+
+/// Returns true if a snapshot of the state should be taken after applying the delta at `delta_index`.
+pub fn is_snapshot_index(delta_index: u32) -> bool {
+    delta_index != 0 && delta_index % SNAPSHOT_INTERVAL == 0
+}
+
+/// Stores the encrypted state as a snapshot at `delta_index`.
+/// Snapshots are only used to speed up rebuilding the state, so a failure is logged and ignored.
+pub fn save_snapshot(db_ptr: *const RawPointer, enc_state: &EncryptedContractState<u8>, delta_index: u32) {
+    if let Err(e) = runtime_ocalls_t::save_snapshot(db_ptr, enc_state, delta_index) {
+        debug_println!("Failed saving a snapshot at delta {}: {:?}", delta_index, e);
+    }
+}
+
+/// Returns the newest snapshot of the contract that can be decrypted,
+/// or None if the contract has no valid snapshots.
+pub fn get_snapshot_state(db_ptr: *const RawPointer, addr: ContractAddress, key: &StateKey) -> Option<ContractState> {
+    let mut max_index = u32::max_value();
+    while let Ok((index, enc_state)) = runtime_ocalls_t::get_snapshot(db_ptr, addr, max_index) {
+        match ContractState::decrypt(enc_state, key) {
+            Ok(ref state) if state.delta_index == index => return Some(state.clone()),
+            _ => debug_println!("Skipping a corrupted snapshot at delta {}", index),
+        }
+        if index == 0 {
+            break;
+        }
+        max_index = index - 1;
+    }
+    None
+}
@@ -1,4 +1,4 @@
-use super::STATE_KEYS;
+use super::{get_snapshot_state, is_snapshot_index, save_snapshot, STATE_KEYS};
 use crate::SIGNING_KEY;
 use enigma_runtime_t::data::{ContractState, DeltasInterface};
 use enigma_runtime_t::ocalls_t as runtime_ocalls_t;
@@ -59,15 +59,22 @@ pub(crate) unsafe fn ecall_build_state_internal(db_ptr: *const RawPointer) -> Re
     'contract: for (addrs, key) in guard.iter() {
         // Get the state and decrypt it.
         // if no state exists create a new one and if failed decrypting, push to failed_contracts and move on.
+        // if the state is missing or corrupted start from the newest snapshot instead.
         let (mut start, mut state ) = match runtime_ocalls_t::get_state(db_ptr, *addrs) {
             Ok(enc_state) => match ContractState::decrypt(enc_state, &key) {
                 Ok(state) => (state.delta_index+1, state),
-                Err(_) => {
-                    failed_contracts.push(*addrs);
-                    continue 'contract;
+                Err(_) => match get_snapshot_state(db_ptr, *addrs, key) {
+                    Some(state) => (state.delta_index+1, state),
+                    None => {
+                        failed_contracts.push(*addrs);
+                        continue 'contract;
+                    }
                 }
             }, // don't throw error if only one failed, somehow tell that but continue
-            Err(_) => (0, ContractState::new(*addrs)),
+            Err(_) => match get_snapshot_state(db_ptr, *addrs, key) {
+                Some(state) => (state.delta_index+1, state),
+                None => (0, ContractState::new(*addrs)),
+            },
         };
 
         'deltas: while start < u32::MAX {
@@ -94,7 +101,11 @@ pub(crate) unsafe fn ecall_build_state_internal(db_ptr: *const RawPointer) -> Re
             // If failed, encrypt the latest state and move on.
             for delta in deltas {
                 match state.apply_delta(delta, key) {
-                    Ok(()) => (),
+                    Ok(()) => if is_snapshot_index(state.delta_index) {
+                        if let Ok(enc) = state.clone().encrypt(key) {
+                            save_snapshot(db_ptr, &enc, state.delta_index);
+                        }
+                    },
                     Err(e) => {
                         debug_println!("Failed applying delta: {:?}", e);
                         let enc = match state.encrypt(key) {
@@ -180,6 +191,30 @@ pub mod tests {
         assert_eq!(ecall_build_state_internal(db_ptr).unwrap(), vec![address[2]])
     }
 
+    pub unsafe fn test_state_from_snapshot(db_ptr: *const RawPointer) {
+        let address = b"test_state_from_snapshot".sha256();
+        let key = *b"snapshot_key".sha256();
+        STATE_KEYS.lock_expect("State Keys").insert(address, key);
+
+        let mut state = ContractState::new(address);
+        for i in 0..3 {
+            let old_state = state.clone();
+            state.write_key(&i.to_string(), &json!(i)).unwrap();
+            let delta = ContractState::generate_delta_and_update_state(&old_state, &mut state, &key).unwrap();
+            runtime_ocalls_t::save_delta(db_ptr, &delta).unwrap();
+            if i == 1 {
+                runtime_ocalls_t::save_snapshot(db_ptr, &state.clone().encrypt(&key).unwrap(), i).unwrap();
+            }
+        }
+        // a corrupted state would fail the rebuild if there wasn't a snapshot.
+        let gibrish_state = EncryptedContractState { contract_address: address, json: vec![8u8; 65] };
+        runtime_ocalls_t::save_state(db_ptr, &gibrish_state).unwrap();
+
+        assert!(!ecall_build_state_internal(db_ptr).unwrap().contains(&address));
+        let enc_state = runtime_ocalls_t::get_state(db_ptr, address).unwrap();
+        assert_eq!(ContractState::decrypt(enc_state, &key).unwrap(), state);
+    }
+
     fn get_states_deltas(address: &[ContractAddress], keys: &[StateKey]) -> Vec<Vec<EncryptedPatch>> {
         let jsons: Vec<serde_json::Value> = vec![
             json!({"widget":{"debug":"on","window":{"title":"Sample Konfabulator Widget","name":"main_window","width":500,"height":500},"image":{"src":"Images/Sun.png","name":"sun1","hOffset":250,"vOffset":250,"alignment":"center"},"text":{"data":"Click Here","size":36,"style":"bold","name":"text1","hOffset":250,"vOffset":100,"alignment":"center","onMouseUp":"sun1.opacity = (sun1.opacity / 100) * 90;"}}}),
@@ -33,32 +33,36 @@ use enigma_crypto::{asymmetric, hash::Keccak256, symmetric, CryptoError};
 use enigma_runtime_t::{
     data::{ContractState, EncryptedPatch},
     wasm_execution::WasmEngine,
-    EthereumData,
+    CallContext, CalledContract, EthereumData, Event, GasProfile, GasSchedule,
 };
 use enigma_tools_m::utils::{EthereumAddress, LockExpectMutex};
 use enigma_tools_t::{
     build_arguments_g::*,
     common::errors_t::{
         EnclaveError::{self, *},
+        EnclaveSystemError::StateError,
         FailedTaskError::*,
     },
     esgx::ocalls_t,
     quote_t, storage_t,
 };
 use enigma_types::{
-    ContractAddress, DhKey, EnclaveReturn, ExecuteResult, Hash256, PubKey, RawPointer, ResultStatus,
+    ContractAddress, DhKey, EnclaveReturn, ExecuteResult, Hash256, PubKey, RawPointer, ResultStatus, TaskContext, TaskOptions,
 };
 
 use sgx_types::*;
 use std::{
     boxed::Box, slice, str,
     string::String,
+    sync::SgxMutex,
     vec::Vec,
 };
 
 lazy_static! {
     pub(crate) static ref SIGNING_KEY: asymmetric::KeyPair = get_sealed_keys_wrapper();
     pub(crate) static ref ETHEREUM_KEY: asymmetric::KeyPair = get_ethereum_keys_wrapper();
+    /// The gas schedule of all the tasks, it's the default one unless the app sets another one at startup.
+    pub(crate) static ref GAS_SCHEDULE: SgxMutex<GasSchedule> = SgxMutex::new(GasSchedule::default());
 }
 
 #[no_mangle]
@@ -80,6 +84,8 @@ pub extern "C" fn ecall_get_signing_address(pubkey: &mut [u8; 20]) { pubkey.copy
 /// * `args_len` - the length of the `args`
 /// * `user_key` - the DH key of the user to decrypt `callable` and `args`
 /// * `contract_address` - the address of the deployed contract with code `bytecode`
+/// * `context` - the context of the task, which the contract can read
+/// * `options` - the options of the task, which don't change its result
 /// * `gas_limit` - the gas limit for the function execution
 /// * `result` - the result of the function invocation
 // TODO: add arguments of callable.
@@ -92,6 +98,8 @@ pub unsafe extern "C" fn ecall_execute(
     args_len: usize,
     user_key: &[u8; 64],
     contract_address: &ContractAddress,
+    context: &TaskContext,
+    options: &TaskOptions,
     gas_limit: *const u64,
     db_ptr: *const RawPointer,
     result: &mut ExecuteResult,
@@ -116,6 +124,8 @@ pub unsafe extern "C" fn ecall_execute(
         user_key,
         &io_key,
         (*contract_address).into(),
+        &sender_context(context, user_key),
+        options,
         *gas_limit,
         db_ptr,
         result,
@@ -127,6 +137,70 @@ pub unsafe extern "C" fn ecall_execute(
     internal_result.into()
 }
 
+#[no_mangle]
+/// Ecall for querying the external function `callable` of a deployed contract without changing its state.
+/// The function runs against the current state, writing to the state fails the query and no delta is produced.
+/// Unlike `ecall_execute` the DH key of the user isn't consumed, so it can be used for more queries.
+/// arguments:
+/// * `bytecode` - WASM bytecode of the deployed contract
+/// * `bytecode_len` - the length of the `bytecode`.
+/// * `callable` - the encrypted signature of the contract function to call
+/// * `callable_len` - the length of the `callable`
+/// * `args` - the encrypted arguments for the function
+/// * `args_len` - the length of the `args`
+/// * `user_key` - the DH key of the user to decrypt `callable` and `args`
+/// * `contract_address` - the address of the deployed contract with code `bytecode`
+/// * `context` - the context of the task, which the contract can read
+/// * `options` - the options of the task, which don't change its result
+/// * `gas_limit` - the gas limit for the function execution, it only bounds the query and isn't paid for
+/// * `result` - the result of the query, the delta and ethereum pointers point to empty arrays
+pub unsafe extern "C" fn ecall_query(
+    bytecode: *const u8,
+    bytecode_len: usize,
+    callable: *const u8,
+    callable_len: usize,
+    args: *const u8,
+    args_len: usize,
+    user_key: &[u8; 64],
+    contract_address: &ContractAddress,
+    context: &TaskContext,
+    options: &TaskOptions,
+    gas_limit: *const u64,
+    db_ptr: *const RawPointer,
+    result: &mut ExecuteResult,
+) -> EnclaveReturn
+{
+    let bytecode = slice::from_raw_parts(bytecode, bytecode_len);
+    let callable = slice::from_raw_parts(callable, callable_len);
+    let args = slice::from_raw_parts(args, args_len);
+
+    let mut pre_execution_data = vec![];
+    let io_key = match peek_io_key(user_key) {
+        Ok(v) => v,
+        Err(e) => return e.into(),
+    };
+
+    let mut internal_result = ecall_query_internal(
+        &mut pre_execution_data,
+        bytecode,
+        callable,
+        args,
+        user_key,
+        &io_key,
+        (*contract_address).into(),
+        &sender_context(context, user_key),
+        options,
+        *gas_limit,
+        db_ptr,
+        result,
+    );
+    if let Err(e) = &internal_result {
+        debug_println!("Error in query of secret contract function: {}", e);
+        internal_result = output_task_failure(&pre_execution_data, *gas_limit, e, result, &io_key);
+    }
+    internal_result.into()
+}
+
 #[no_mangle]
 /// Ecall for deploying contract.
 /// arguments:
@@ -138,6 +212,8 @@ pub unsafe extern "C" fn ecall_execute(
 /// * `args_len` - the length of `args`
 /// * `address` - the address of the contract to be deployed
 /// * `user_key` - the DH key of the user to decrypt `constructor` and `args`
+/// * `context` - the context of the task, which the constructor can read
+/// * `options` - the options of the task, which don't change its result
 /// * `gas_limit` - the gas limit for the constructor execution
 /// * `result` - the result of the deployment
 pub unsafe extern "C" fn ecall_deploy(
@@ -149,6 +225,8 @@ pub unsafe extern "C" fn ecall_deploy(
     args_len: usize,
     address: &ContractAddress,
     user_key: &PubKey,
+    context: &TaskContext,
+    options: &TaskOptions,
     gas_limit: *const u64,
     db_ptr: *const RawPointer,
     result: &mut ExecuteResult,
@@ -171,6 +249,8 @@ pub unsafe extern "C" fn ecall_deploy(
         (*address).into(),
         user_key,
         &io_key,
+        &sender_context(context, user_key),
+        options,
         *gas_limit,
         db_ptr,
         result,
@@ -182,6 +262,83 @@ pub unsafe extern "C" fn ecall_deploy(
     internal_result.into()
 }
 
+#[no_mangle]
+/// Ecall for upgrading a deployed contract to new code while keeping its state.
+/// The code the contract is deployed with now is loaded from the DB, and its `authorize_upgrade` has to approve the upgrade.
+/// arguments:
+/// * `bytecode` - WASM pre-deployed bytecode of the new code
+/// * `bytecode_len` - the length of `bytecode`
+/// * `callable` - the encrypted signature of the `migrate` function
+/// * `callable_len` - the length of `callable`
+/// * `args` - the encrypted arguments for `migrate`
+/// * `args_len` - the length of `args`
+/// * `address` - the address of the upgraded contract
+/// * `user_key` - the DH key of the user to decrypt `callable` and `args`
+/// * `context` - the context of the task, which `migrate` can read
+/// * `options` - the options of the task, which don't change its result
+/// * `gas_limit` - the gas limit for the migration
+/// * `result` - the result of the upgrade, its output is the new bytecode
+pub unsafe extern "C" fn ecall_upgrade(
+    bytecode: *const u8,
+    bytecode_len: usize,
+    callable: *const u8,
+    callable_len: usize,
+    args: *const u8,
+    args_len: usize,
+    address: &ContractAddress,
+    user_key: &PubKey,
+    context: &TaskContext,
+    options: &TaskOptions,
+    gas_limit: *const u64,
+    db_ptr: *const RawPointer,
+    result: &mut ExecuteResult,
+) -> EnclaveReturn
+{
+    let bytecode = slice::from_raw_parts(bytecode, bytecode_len);
+    let callable = slice::from_raw_parts(callable, callable_len);
+    let args = slice::from_raw_parts(args, args_len);
+    let mut pre_execution_data = vec![];
+    let io_key = match get_io_key(user_key) {
+        Ok(v) => v,
+        Err(e) => return e.into(),
+    };
+    let mut internal_result = ecall_upgrade_internal(
+        &mut pre_execution_data,
+        bytecode,
+        callable,
+        args,
+        (*address).into(),
+        user_key,
+        &io_key,
+        &sender_context(context, user_key),
+        options,
+        *gas_limit,
+        db_ptr,
+        result,
+    );
+    if let Err(e) = &internal_result {
+        debug_println!("Error in upgrade of secret contract: {}", e);
+        internal_result = output_task_failure(&pre_execution_data, *gas_limit, e, result, &io_key);
+    }
+    internal_result.into()
+}
+
+#[no_mangle]
+/// Ecall for replacing the gas schedule of the tasks.
+/// arguments:
+/// * `schedule` - the gas schedule as JSON, the missing costs take the default values
+/// * `schedule_len` - the length of `schedule`
+/// * `schedule_hash` - the hash of the schedule which is now used, it's part of every task signature
+pub unsafe extern "C" fn ecall_set_gas_schedule(schedule: *const u8, schedule_len: usize, schedule_hash: &mut [u8; 32]) -> EnclaveReturn {
+    let schedule = match GasSchedule::from_json(slice::from_raw_parts(schedule, schedule_len)) {
+        Ok(schedule) => schedule,
+        Err(e) => return e.into(),
+    };
+    schedule_hash.copy_from_slice(&*schedule.hash());
+    *GAS_SCHEDULE.lock_expect("Gas Schedule") = schedule;
+    EnclaveReturn::Success
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn ecall_ptt_req(sig: &mut [u8; 65], serialized_ptr: *mut u64) -> EnclaveReturn {
     let msg = match ecall_ptt_req_internal(sig) {
@@ -236,6 +393,16 @@ fn get_io_key(user_key: &PubKey) -> Result<DhKey, EnclaveError> {
     Ok(io_key)
 }
 
+/// Like `get_io_key` but leaves the key in place, for the requests that don't consume it.
+fn peek_io_key(user_key: &PubKey) -> Result<DhKey, EnclaveError> {
+    let io_key = km_t::users::DH_KEYS
+        .lock_expect("User DH Key")
+        .get(&user_key[..])
+        .cloned()
+        .ok_or(CryptoError::MissingKeyError { key_type: "DH Key" })?;
+    Ok(io_key)
+}
+
 fn decrypt_inputs(callable: &[u8], args: &[u8], inputs_key: &DhKey) -> Result<(Vec<u8>, String), EnclaveError> {
     let decrypted_callable = decrypt_callable(callable, &inputs_key)?;
     let decrypted_args = decrypt_args(&args, &inputs_key)?;
@@ -263,21 +430,139 @@ unsafe fn store_delta_and_state(
     match delta {
         Some(d) => {
             let enc_state = km_t::encrypt_state(state.clone())?;
-            enigma_runtime_t::ocalls_t::save_delta(db_ptr, d)?;
-            // if the state isn't able to be stored, then remove the delta as well and fail the task
-            enigma_runtime_t::ocalls_t::save_state(db_ptr, &enc_state).
-                or_else(|_| enigma_runtime_t::ocalls_t::remove_delta(db_ptr, d))
+            // the delta and the state are stored atomically, so a failure can't leave the tip ahead of the state
+            enigma_runtime_t::ocalls_t::save_delta_and_state(db_ptr, d, &enc_state)?;
+            if km_t::is_snapshot_index(d.index) {
+                km_t::save_snapshot(db_ptr, &enc_state, d.index);
+            }
+            Ok(())
         }
         None => Ok(()),
     }
 }
 
-fn create_eth_data_to_sign(input: Option<EthereumData>) -> (Vec<u8>, [u8; 20]) {
-    if let Some(bridge) = input {
-        (bridge.ethereum_payload, bridge.ethereum_contract_addr)
-    } else {
-        (vec![], [0u8; 20])
+/// Stores the deltas and the updated states of several contracts in one atomic DB write,
+/// so a failure can't leave only some of the contracts changed.
+unsafe fn store_deltas_and_states(db_ptr: *const RawPointer, changes: &[(&EncryptedPatch, &ContractState)]) -> Result<(), EnclaveError> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut entries = Vec::with_capacity(changes.len());
+    for (delta, state) in changes {
+        entries.push((*delta, km_t::encrypt_state((*state).clone())?));
+    }
+    enigma_runtime_t::ocalls_t::save_deltas_and_states(db_ptr, &entries)?;
+    for (delta, enc_state) in &entries {
+        if km_t::is_snapshot_index(delta.index) {
+            km_t::save_snapshot(db_ptr, enc_state, delta.index);
+        }
+    }
+    Ok(())
+}
+
+/// Stores the new code of an upgraded contract together with the delta and the state of its `migrate`
+/// in one atomic DB write, so a failure can't leave the migrated state with the old code.
+unsafe fn store_upgrade(
+    db_ptr: *const RawPointer,
+    address: ContractAddress,
+    exe_code: &[u8],
+    delta: &Option<EncryptedPatch>,
+    state: &ContractState,
+) -> Result<(), EnclaveError>
+{
+    let mut entries = Vec::with_capacity(1);
+    if let Some(d) = delta {
+        entries.push((d, km_t::encrypt_state(state.clone())?));
+    }
+    enigma_runtime_t::ocalls_t::save_upgrade(db_ptr, address, exe_code, &entries)?;
+    for (delta, enc_state) in &entries {
+        if km_t::is_snapshot_index(delta.index) {
+            km_t::save_snapshot(db_ptr, enc_state, delta.index);
+        }
+    }
+    Ok(())
+}
+
+/// The hash of the address, the exe code hash, the previous delta hash and the new delta hash of every called contract,
+/// in the order of the calls. The new delta hash is all zeros for a contract that didn't change its state.
+fn hash_called_contracts(called_contracts: &[CalledContract]) -> Hash256 {
+    let delta_hashes: Vec<Hash256> = called_contracts.iter().map(|c| get_enc_delta(&c.state_delta)).collect();
+    let mut to_hash: Vec<&[u8]> = Vec::with_capacity(called_contracts.len() * 4);
+    for (called, delta_hash) in called_contracts.iter().zip(&delta_hashes) {
+        to_hash.push(&called.updated_state.contract_address[..]);
+        to_hash.push(&called.exe_code_hash[..]);
+        to_hash.push(&called.pre_delta_hash[..]);
+        to_hash.push(&delta_hash[..]);
+    }
+    enigma_crypto::hash::prepare_hash_multiple(&to_hash).keccak256()
+}
+
+/// Serializes the deltas of the called contracts as described in `ExecuteResult::called_deltas_ptr`.
+fn serialize_called_deltas(called_contracts: &[CalledContract]) -> Vec<u8> {
+    let mut res = Vec::new();
+    for delta in called_contracts.iter().filter_map(|called| called.state_delta.as_ref()) {
+        res.extend_from_slice(&delta.contract_address[..]);
+        res.extend_from_slice(&delta.index.to_be_bytes());
+        res.extend_from_slice(&(delta.data.len() as u32).to_be_bytes());
+        res.extend_from_slice(&delta.data);
+    }
+    res
+}
+
+/// Encrypts the topic and the data of every event with the user's key, the emitting contract stays visible.
+fn encrypt_events(events: Vec<Event>, key: &DhKey) -> Result<Vec<Event>, EnclaveError> {
+    let mut encrypted = Vec::with_capacity(events.len());
+    for event in events {
+        let topic = symmetric::encrypt(&event.topic, key)?;
+        let data = symmetric::encrypt(&event.data, key)?;
+        encrypted.push(Event { contract_address: event.contract_address, topic, data });
     }
+    Ok(encrypted)
+}
+
+/// The hash of the address, the encrypted topic and the encrypted data of every event, in the order they were emitted.
+fn hash_events(events: &[Event]) -> Hash256 {
+    let mut to_hash: Vec<&[u8]> = Vec::with_capacity(events.len() * 3);
+    for event in events {
+        to_hash.push(&event.contract_address[..]);
+        to_hash.push(&event.topic);
+        to_hash.push(&event.data);
+    }
+    enigma_crypto::hash::prepare_hash_multiple(&to_hash).keccak256()
+}
+
+/// Serializes the events as described in `ExecuteResult::events_ptr`.
+fn serialize_events(events: &[Event]) -> Vec<u8> {
+    let mut res = Vec::new();
+    for event in events {
+        res.extend_from_slice(&event.contract_address[..]);
+        res.extend_from_slice(&(event.topic.len() as u32).to_be_bytes());
+        res.extend_from_slice(&event.topic);
+        res.extend_from_slice(&(event.data.len() as u32).to_be_bytes());
+        res.extend_from_slice(&event.data);
+    }
+    res
+}
+
+/// The ethereum data in the signature, every payload followed by its address in the order they were written.
+/// Without any payload it's an empty payload and a zero address, like a single payload was always signed.
+fn eth_data_to_sign(bridges: &[EthereumData]) -> Vec<&[u8]> {
+    static NO_ETHEREUM_ADDRESS: [u8; 20] = [0u8; 20];
+    if bridges.is_empty() {
+        return vec![&[][..], &NO_ETHEREUM_ADDRESS[..]];
+    }
+    bridges.iter().flat_map(|bridge| vec![&bridge.ethereum_payload[..], &bridge.ethereum_contract_addr[..]]).collect()
+}
+
+/// Serializes the ethereum payloads as described in `ExecuteResult::ethereum_bridges_ptr`.
+fn serialize_eth_bridges(bridges: &[EthereumData]) -> Vec<u8> {
+    let mut res = Vec::new();
+    for bridge in bridges {
+        res.extend_from_slice(&bridge.ethereum_contract_addr);
+        res.extend_from_slice(&(bridge.ethereum_payload.len() as u32).to_be_bytes());
+        res.extend_from_slice(&bridge.ethereum_payload);
+    }
+    res
 }
 
 fn output_task_failure(
@@ -288,7 +573,8 @@ fn output_task_failure(
     key: &DhKey,
 ) -> Result<(), EnclaveError>
 {
-    // Signing: S(pre-execution data, usedGas, Failure)
+    // Signing: S(pre-execution data, gasLimit, usedGas, Failure)
+    // the pre-execution data always ends with the gas schedule hash.
     result.used_gas = 0;
     let return_error = match err {
         FailedTaskError(_) => err.clone(),
@@ -321,6 +607,8 @@ unsafe fn ecall_execute_internal(
     user_key: &PubKey,
     io_key: &DhKey,
     address: ContractAddress,
+    context: &TaskContext,
+    options: &TaskOptions,
     gas_limit: u64,
     db_ptr: *const RawPointer,
     result: &mut ExecuteResult,
@@ -329,10 +617,13 @@ unsafe fn ecall_execute_internal(
     // TODO: make sure the state is up to date.
     // TODO: Should this be here or on the untrusted side via build_state?;
 
-    let inputs_hash = enigma_crypto::hash::prepare_hash_multiple(&[callable, args, &*address, user_key]).keccak256();
+    let inputs_hash = hash_inputs(&[callable, args, &*address, user_key], context);
     let exe_code_hash = bytecode.keccak256();
+    let schedule = GAS_SCHEDULE.lock_expect("Gas Schedule").clone();
+    let schedule_hash = schedule.hash();
     pre_execution_data.push(Box::new(*inputs_hash));
     pre_execution_data.push(Box::new(*exe_code_hash));
+    pre_execution_data.push(Box::new(*schedule_hash));
     let pre_execution_state = km_t::get_state(db_ptr, address)?;
 
     let (decrypted_args, function_name) =
@@ -340,32 +631,117 @@ unsafe fn ecall_execute_internal(
 
     let state_key = km_t::get_state_key(address)?;
     let mut engine =
-        WasmEngine::new_compute(&bytecode, gas_limit, decrypted_args.clone(), pre_execution_state.clone(), function_name, state_key)?;
-    engine.compute()?;
+        WasmEngine::new_compute(&bytecode, gas_limit, decrypted_args.clone(), pre_execution_state.clone(), function_name, state_key, &schedule)?;
+    let call_context = CallContext {
+        db_ptr,
+        get_state_key: km_t::get_state_key,
+        get_contract: enigma_runtime_t::ocalls_t::get_contract,
+        get_state: enigma_runtime_t::ocalls_t::get_state,
+        call_stack: vec![address],
+    };
+    engine.runtime.set_call_context(call_context);
+    engine.runtime.set_task_context(*context);
+    enable_gas_profiling(&mut engine, options);
+    let compute_result = engine.compute();
+    save_gas_profile(engine.gas_profile(), result)?;
+    compute_result?;
     let exec_res = engine.into_result()?;
 
     let delta_hash = get_enc_delta(&exec_res.state_delta);
     let encrypted_output = symmetric::encrypt(&exec_res.result, io_key)?;
-    prepare_wasm_result(&exec_res.state_delta, &encrypted_output, exec_res.ethereum_bridge.clone(), exec_res.used_gas, result)?;
+    prepare_wasm_result(&exec_res.state_delta, &encrypted_output, &exec_res.ethereum_bridges, exec_res.used_gas, result)?;
+    result.called_deltas_ptr = ocalls_t::save_to_untrusted_memory(&serialize_called_deltas(&exec_res.called_contracts))? as *const u8;
+    let events = encrypt_events(exec_res.events, io_key)?;
+    result.events_ptr = ocalls_t::save_to_untrusted_memory(&serialize_events(&events))? as *const u8;
+
+    // Signing: S(exeCodeHash, inputsHash, delta(X-1)Hash, deltaXHash, calledContractsHash, eventsHash, outputHash, gasScheduleHash, gasLimit, usedGas, ethereumData..., Success)
+    // both hashes are always signed, without called contracts or events they're the hash of an empty list,
+    // so a signature can't be taken as one over the other.
+    let used_gas = result.used_gas.to_be_bytes();
+    let output_hash = encrypted_output.keccak256();
+    let called_contracts_hash = hash_called_contracts(&exec_res.called_contracts);
+    let events_hash = hash_events(&events);
+    let gas_limit = gas_limit.to_be_bytes();
+    let status = [ResultStatus::Ok as u8];
+    let mut to_sign: Vec<&[u8]> = vec![
+        &exe_code_hash[..],
+        &inputs_hash[..],
+        &pre_execution_state.delta_hash[..],
+        &delta_hash[..],
+        &called_contracts_hash[..],
+        &events_hash[..],
+        &output_hash[..],
+        &schedule_hash[..],
+        &gas_limit[..],
+        &used_gas[..],
+    ];
+    to_sign.extend(eth_data_to_sign(&exec_res.ethereum_bridges));
+    to_sign.push(&status[..]);
+    result.signature = SIGNING_KEY.sign_multiple(&to_sign)?;
+    let mut changes: Vec<(&EncryptedPatch, &ContractState)> = Vec::with_capacity(exec_res.called_contracts.len() + 1);
+    if let Some(delta) = &exec_res.state_delta {
+        changes.push((delta, &exec_res.updated_state));
+    }
+    changes.extend(exec_res.called_contracts.iter().filter_map(|called| called.state_delta.as_ref().map(|delta| (delta, &called.updated_state))));
+    store_deltas_and_states(db_ptr, &changes)?;
+    Ok(())
+}
+
+unsafe fn ecall_query_internal(
+    pre_execution_data: &mut Vec<Box<[u8]>>,
+    bytecode: &[u8],
+    callable: &[u8],
+    args: &[u8],
+    user_key: &PubKey,
+    io_key: &DhKey,
+    address: ContractAddress,
+    context: &TaskContext,
+    options: &TaskOptions,
+    gas_limit: u64,
+    db_ptr: *const RawPointer,
+    result: &mut ExecuteResult,
+) -> Result<(), EnclaveError>
+{
+    let inputs_hash = hash_inputs(&[callable, args, &*address, user_key], context);
+    let exe_code_hash = bytecode.keccak256();
+    let schedule = GAS_SCHEDULE.lock_expect("Gas Schedule").clone();
+    let schedule_hash = schedule.hash();
+    pre_execution_data.push(Box::new(*inputs_hash));
+    pre_execution_data.push(Box::new(*exe_code_hash));
+    pre_execution_data.push(Box::new(*schedule_hash));
+    let state = km_t::get_state(db_ptr, address)?;
+
+    let (decrypted_args, function_name) =
+        decrypt_inputs(callable, args, io_key).map_err(|e| FailedTaskError(InputError { message: format!("{}", e) }))?;
+
+    let state_key = km_t::get_state_key(address)?;
+    let mut engine = WasmEngine::new_query(&bytecode, gas_limit, decrypted_args, state.clone(), function_name, state_key, &schedule)?;
+    engine.runtime.set_task_context(*context);
+    enable_gas_profiling(&mut engine, options);
+    let compute_result = engine.compute();
+    save_gas_profile(engine.gas_profile(), result)?;
+    compute_result?;
+    let exec_res = engine.into_result()?;
+
+    let encrypted_output = symmetric::encrypt(&exec_res.result, io_key)?;
+    // a query never changes the state, so there's no delta and nothing for ethereum.
+    prepare_wasm_result(&None, &encrypted_output, &[], exec_res.used_gas, result)?;
 
-    let (ethereum_payload, ethereum_address) = create_eth_data_to_sign(exec_res.ethereum_bridge);
-    // Signing: S(exeCodeHash, inputsHash, delta(X-1)Hash, deltaXHash, outputHash, gasLimit, usedGas, optionalEthereumData, Success)
+    // Signing: S(exeCodeHash, inputsHash, deltaHash, outputHash, gasScheduleHash, gasLimit, usedGas, Success)
+    // `deltaHash` is the hash of the latest delta, the state version the query ran against.
     let used_gas = result.used_gas.to_be_bytes();
     let output_hash = encrypted_output.keccak256();
     let to_sign: &[&[u8]] = &[
         &*exe_code_hash,
         &*inputs_hash,
-        &*pre_execution_state.delta_hash,
-        &*delta_hash,
+        &*state.delta_hash,
         &*output_hash,
+        &*schedule_hash,
         &gas_limit.to_be_bytes(),
         &used_gas,
-        &ethereum_payload,
-        &ethereum_address,
         &[ResultStatus::Ok as u8],
     ];
     result.signature = SIGNING_KEY.sign_multiple(to_sign)?;
-    store_delta_and_state(db_ptr, &exec_res.state_delta, &exec_res.updated_state)?;
     Ok(())
 }
 
@@ -377,53 +753,156 @@ unsafe fn ecall_deploy_internal(
     address: ContractAddress,
     user_key: &PubKey,
     io_key: &DhKey,
+    context: &TaskContext,
+    options: &TaskOptions,
     gas_limit: u64,
     db_ptr: *const RawPointer,
     result: &mut ExecuteResult,
 ) -> Result<(), EnclaveError>
 {
     let pre_code_hash = bytecode.keccak256();
-    let inputs_hash = enigma_crypto::hash::prepare_hash_multiple(&[constructor, args, &pre_code_hash[..], user_key][..]).keccak256();
+    let inputs_hash = hash_inputs(&[constructor, args, &pre_code_hash[..], user_key], context);
+    let schedule = GAS_SCHEDULE.lock_expect("Gas Schedule").clone();
+    let schedule_hash = schedule.hash();
     pre_execution_data.push(Box::new(*inputs_hash));
+    pre_execution_data.push(Box::new(*schedule_hash));
 
     let (decrypted_args, function_name) =
         decrypt_inputs(constructor, args, io_key).map_err(|e| FailedTaskError(InputError { message: format!("{}", e) }))?;
 
-    let state = ContractState::new(address);
+    // new contracts use the key-value state, the existing ones keep the version they were deployed with.
+    let state = ContractState::new_key_value(address);
 
     let state_key = km_t::get_state_key(address)?;
-    let mut engine = WasmEngine::new_deploy(bytecode, gas_limit, decrypted_args.clone(), state, function_name, state_key)?;
-    engine.deploy()?;
+    let mut engine = WasmEngine::new_deploy(bytecode, gas_limit, decrypted_args.clone(), state, function_name, state_key, &schedule)?;
+    engine.runtime.set_task_context(*context);
+    enable_gas_profiling(&mut engine, options);
+    let deploy_result = engine.deploy();
+    save_gas_profile(engine.gas_profile(), result)?;
+    deploy_result?;
     let exec_res = engine.into_result()?;
 
     let exe_code = &exec_res.result[..];
 
     let delta_hash = get_enc_delta(&exec_res.state_delta);
 
-    prepare_wasm_result(&exec_res.state_delta, exe_code, exec_res.ethereum_bridge.clone(), exec_res.used_gas, result)?;
+    prepare_wasm_result(&exec_res.state_delta, exe_code, &exec_res.ethereum_bridges, exec_res.used_gas, result)?;
 
-    // Signing: S(inputsHash, exeCodeHash, delta0Hash, gasLimit, usedGas, optionalEthereumData, Success)
+    // Signing: S(inputsHash, exeCodeHash, delta0Hash, gasScheduleHash, gasLimit, usedGas, ethereumData..., Success)
     let used_gas = result.used_gas.to_be_bytes();
-    let (ethereum_payload, ethereum_address) = create_eth_data_to_sign(exec_res.ethereum_bridge);
-    let to_sign: &[&[u8]] = &[
-        &*inputs_hash,
-        &*exec_res.result.keccak256(),
-        &*delta_hash,
-        &gas_limit.to_be_bytes(),
-        &used_gas,
-        &ethereum_payload,
-        &ethereum_address,
-        &[ResultStatus::Ok as u8],
-    ];
-    result.signature = SIGNING_KEY.sign_multiple(to_sign)?;
+    let exe_code_hash = exec_res.result.keccak256();
+    let gas_limit = gas_limit.to_be_bytes();
+    let status = [ResultStatus::Ok as u8];
+    let mut to_sign: Vec<&[u8]> = vec![&inputs_hash[..], &exe_code_hash[..], &delta_hash[..], &schedule_hash[..], &gas_limit[..], &used_gas[..]];
+    to_sign.extend(eth_data_to_sign(&exec_res.ethereum_bridges));
+    to_sign.push(&status[..]);
+    result.signature = SIGNING_KEY.sign_multiple(&to_sign)?;
     store_delta_and_state(db_ptr, &exec_res.state_delta, &exec_res.updated_state)?;
     Ok(())
 }
 
+unsafe fn ecall_upgrade_internal(
+    pre_execution_data: &mut Vec<Box<[u8]>>,
+    bytecode: &[u8],
+    callable: &[u8],
+    args: &[u8],
+    address: ContractAddress,
+    user_key: &PubKey,
+    io_key: &DhKey,
+    context: &TaskContext,
+    options: &TaskOptions,
+    gas_limit: u64,
+    db_ptr: *const RawPointer,
+    result: &mut ExecuteResult,
+) -> Result<(), EnclaveError>
+{
+    let pre_code_hash = bytecode.keccak256();
+    let inputs_hash = hash_inputs(&[callable, args, &*address, &pre_code_hash[..], user_key], context);
+    // the current code is loaded here and not taken from the host, it signs the upgrade from it.
+    let old_bytecode = enigma_runtime_t::ocalls_t::get_contract(db_ptr, address)?;
+    let old_exe_code_hash = old_bytecode.keccak256();
+    let schedule = GAS_SCHEDULE.lock_expect("Gas Schedule").clone();
+    let schedule_hash = schedule.hash();
+    pre_execution_data.push(Box::new(*inputs_hash));
+    pre_execution_data.push(Box::new(*old_exe_code_hash));
+    pre_execution_data.push(Box::new(*schedule_hash));
+    let pre_execution_state = km_t::get_state(db_ptr, address)?;
+
+    let (decrypted_args, function_name) =
+        decrypt_inputs(callable, args, io_key).map_err(|e| FailedTaskError(InputError { message: format!("{}", e) }))?;
+
+    // `migrate` runs on the existing state, so its delta continues the delta chain of the contract.
+    let state_key = km_t::get_state_key(address)?;
+    let mut engine =
+        WasmEngine::new_upgrade(bytecode, gas_limit, decrypted_args, pre_execution_state.clone(), function_name, state_key, &schedule)?;
+    engine.runtime.set_task_context(*context);
+    enable_gas_profiling(&mut engine, options);
+    let upgrade_result = engine.upgrade(&old_bytecode);
+    save_gas_profile(engine.gas_profile(), result)?;
+    upgrade_result?;
+    let exec_res = engine.into_result()?;
+
+    let delta_hash = get_enc_delta(&exec_res.state_delta);
+    prepare_wasm_result(&exec_res.state_delta, &exec_res.result, &exec_res.ethereum_bridges, exec_res.used_gas, result)?;
+
+    // Signing: S(inputsHash, oldExeCodeHash, newExeCodeHash, delta(X-1)Hash, deltaXHash, gasScheduleHash, gasLimit, usedGas, ethereumData..., Success)
+    // `deltaXHash` is all zeros when the contract has no `migrate` or it didn't change the state.
+    let used_gas = result.used_gas.to_be_bytes();
+    let exe_code_hash = exec_res.result.keccak256();
+    let gas_limit = gas_limit.to_be_bytes();
+    let status = [ResultStatus::Ok as u8];
+    let mut to_sign: Vec<&[u8]> = vec![
+        &inputs_hash[..],
+        &old_exe_code_hash[..],
+        &exe_code_hash[..],
+        &pre_execution_state.delta_hash[..],
+        &delta_hash[..],
+        &schedule_hash[..],
+        &gas_limit[..],
+        &used_gas[..],
+    ];
+    to_sign.extend(eth_data_to_sign(&exec_res.ethereum_bridges));
+    to_sign.push(&status[..]);
+    result.signature = SIGNING_KEY.sign_multiple(&to_sign)?;
+    store_upgrade(db_ptr, address, &exec_res.result, &exec_res.state_delta, &exec_res.updated_state)?;
+    Ok(())
+}
+
+/// The context of a task with the user who encrypted it as the sender, whatever sender the host put in it.
+fn sender_context(context: &TaskContext, user_key: &PubKey) -> TaskContext {
+    TaskContext { sender_pubkey: *user_key, ..*context }
+}
+
+/// The hash of the inputs of a task, followed by its context: the block number (u64 BE), the epoch nonce and the sender's public key.
+/// The context is signed as part of the inputs, so the contract can trust what it reads from it.
+fn hash_inputs(inputs: &[&[u8]], context: &TaskContext) -> Hash256 {
+    let block_number = context.block_number.to_be_bytes();
+    let mut to_hash = inputs.to_vec();
+    to_hash.extend_from_slice(&[&block_number[..], &context.epoch_nonce[..], &context.sender_pubkey[..]]);
+    enigma_crypto::hash::prepare_hash_multiple(&to_hash).keccak256()
+}
+
+/// The gas profile is only reported by debug builds, to tasks that ask for it, it's there to help optimizing contracts.
+fn enable_gas_profiling(engine: &mut WasmEngine, options: &TaskOptions) {
+    if cfg!(debug_assertions) && options.gas_profiling {
+        engine.enable_gas_profiling();
+    }
+}
+
+/// Saves the gas profile as JSON for `ExecuteResult::gas_profile_ptr`, it's saved before the result is checked
+/// so failed tasks report where their gas went too.
+unsafe fn save_gas_profile(profile: Option<GasProfile>, result: &mut ExecuteResult) -> Result<(), EnclaveError> {
+    if let Some(profile) = profile {
+        let json = serde_json::to_vec(&profile).map_err(|e| SystemError(StateError { err: format!("{}", e) }))?;
+        result.gas_profile_ptr = ocalls_t::save_to_untrusted_memory(&json)? as *const u8;
+    }
+    Ok(())
+}
+
 unsafe fn prepare_wasm_result(
     delta_option: &Option<EncryptedPatch>,
     execute_result: &[u8],
-    ethereum_bridge: Option<EthereumData>,
+    ethereum_bridges: &[EthereumData],
     used_gas: u64,
     result: &mut ExecuteResult,
 ) -> Result<(), EnclaveError>
@@ -441,16 +920,7 @@ unsafe fn prepare_wasm_result(
         }
     }
 
-    match ethereum_bridge {
-        Some(ethereum_bridge) => {
-            result.ethereum_payload_ptr = ocalls_t::save_to_untrusted_memory(&ethereum_bridge.ethereum_payload)? as *const u8;
-            result.ethereum_address.clone_from_slice(&ethereum_bridge.ethereum_contract_addr);
-        }
-        None => {
-            result.ethereum_payload_ptr = ocalls_t::save_to_untrusted_memory(&[])? as *const u8;
-            result.ethereum_address = [0u8; 20];
-        }
-    }
+    result.ethereum_bridges_ptr = ocalls_t::save_to_untrusted_memory(&serialize_eth_bridges(ethereum_bridges))? as *const u8;
     Ok(())
 }
 
@@ -492,7 +962,7 @@ pub mod tests {
 
         use self::sgx_tunittest::*;
         use crate::km_t::principal::tests::*;
-        use enigma_runtime_t::{data::tests::*, ocalls_t::tests::*, wasm_execution::tests::*};
+        use enigma_runtime_t::{data::tests::*, gas::tests::*, ocalls_t::tests::*, wasm_execution::tests::*};
         use enigma_tools_t::storage_t::tests::*;
         use enigma_types::{RawPointer, ResultStatus};
         use std::{panic::UnwindSafe, string::String, vec::Vec};
@@ -515,13 +985,27 @@ pub mod tests {
             core_unitests(&mut ctr, &mut failures, test_encrypt_decrypt_patch, "test_encrypt_decrypt_patch");
             core_unitests(&mut ctr, &mut failures, test_apply_delta, "test_apply_delta");
             core_unitests(&mut ctr, &mut failures, test_generate_delta, "test_generate_delta");
+            core_unitests(&mut ctr, &mut failures, test_key_value_state, "test_key_value_state");
+            core_unitests(&mut ctr, &mut failures, test_key_value_delta, "test_key_value_delta");
+            core_unitests(&mut ctr, &mut failures, test_encrypt_decrypt_key_value_state, "test_encrypt_decrypt_key_value_state");
+            core_unitests(&mut ctr, &mut failures, test_binary_state_values, "test_binary_state_values");
             core_unitests(&mut ctr, &mut failures, || test_me(db_ptr), "test_me");
             core_unitests(&mut ctr, &mut failures, test_execute_contract, "test_execute_contract");
+            core_unitests(&mut ctr, &mut failures, test_query_contract_cant_write, "test_query_contract_cant_write");
+            core_unitests(&mut ctr, &mut failures, test_gas_profile, "test_gas_profile");
+            core_unitests(&mut ctr, &mut failures, test_upgrade_without_authorization, "test_upgrade_without_authorization");
+            core_unitests(&mut ctr, &mut failures, test_gas_schedule_from_json, "test_gas_schedule_from_json");
+            core_unitests(&mut ctr, &mut failures, test_gas_schedule_hash, "test_gas_schedule_hash");
             core_unitests(&mut ctr, &mut failures, || test_get_deltas(db_ptr), "test_get_deltas");
             core_unitests(&mut ctr, &mut failures, || test_get_deltas_more(db_ptr), "test_get_deltas_more");
             core_unitests(&mut ctr, &mut failures, || test_state_internal(db_ptr), "test_state_internal");
             core_unitests(&mut ctr, &mut failures, || test_state(db_ptr), "test_state");
             core_unitests(&mut ctr, &mut failures, || {test_remove_delta(db_ptr)}, "test_remove_delta");
+            core_unitests(&mut ctr, &mut failures, || test_save_delta_and_state(db_ptr), "test_save_delta_and_state");
+            core_unitests(&mut ctr, &mut failures, || test_save_deltas_and_states(db_ptr), "test_save_deltas_and_states");
+            core_unitests(&mut ctr, &mut failures, || test_save_upgrade_without_contract(db_ptr), "test_save_upgrade_without_contract");
+            core_unitests(&mut ctr, &mut failures, || test_snapshots(db_ptr), "test_snapshots");
+            core_unitests(&mut ctr, &mut failures, || test_state_from_snapshot(db_ptr), "test_state_from_snapshot");
             let result = failures.is_empty();
             rsgx_unit_test_end(ctr, failures);
             result.into()
@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(crate = "crate::serde")]
 pub struct StatePatch {
     pub patch: json_patch::Patch,
     pub previous_hash: Hash256,
@@ -15,9 +16,22 @@ pub struct StatePatch {
     pub contract_address: ContractAddress,
     #[serde(skip)]
     pub index: u32,
+    // The operations of a `StateVersion::KeyValue` delta, the `patch` is empty in that case.
+    // It must stay the last field so JSON deltas are serialized exactly as they were before it was added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ops: Option<Vec<StateOp>>,
+}
+
+/// A single change to a key of a `StateVersion::KeyValue` state.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(crate = "crate::serde")]
+pub enum StateOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[serde(crate = "crate::serde")]
 pub struct EncryptedPatch {
     pub data: Vec<u8>,
     pub contract_address: ContractAddress,
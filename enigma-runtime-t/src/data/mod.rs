@@ -1,8 +1,8 @@
 mod delta;
 mod state;
 
-pub use data::delta::{EncryptedPatch, StatePatch};
-pub use data::state::{ContractState, EncryptedContractState};
+pub use data::delta::{EncryptedPatch, StateOp, StatePatch};
+pub use data::state::{ContractState, EncryptedContractState, KeyValueMap, StateVersion, ValueType, BINARY_VALUE_TAG};
 use serde::Deserialize;
 use serde_json::{Error, Value};
 
@@ -10,6 +10,10 @@ pub trait IOInterface<E, U> {
     fn read_key<T>(&self, key: &str) -> Result<T, Error> where for<'de> T: Deserialize<'de>;
     fn write_key(&mut self, key: &str, value: &Value) -> Result<(), E>;
     fn remove_key(&mut self, key: &str);
+    /// Returns the JSON encoded value of the key, `null` if the key doesn't exist.
+    fn read_raw(&self, key: &str) -> Vec<U>;
+    /// Writes a value which is already JSON encoded.
+    fn write_raw(&mut self, key: &str, value: &[U]) -> Result<(), E>;
 }
 
 pub trait DeltasInterface<E, T, K> {
@@ -99,7 +103,7 @@ pub mod tests {
         let before = json!({ "title": "Goodbye!","author" : { "name1" : "John", "name2" : "Doe"}, "tags":[ "first", "second" ] });
         let after = json!({ "author" : {"name1" : "John", "name2" : "Lennon"},"tags": [ "first", "second", "third"] });
         let patch =
-            StatePatch { patch: json_patch::diff(&before, &after), previous_hash: [0u8; 32].into(), contract_address: [1u8; 32].into(), index: 0 };
+            StatePatch { patch: json_patch::diff(&before, &after), previous_hash: [0u8; 32].into(), contract_address: [1u8; 32].into(), index: 0, ops: None };
         assert_eq!(serde_json::to_string(&patch.patch).unwrap(), "[{\"op\":\"replace\",\"path\":\"/author/name2\",\"value\":\"Lennon\"},{\"op\":\"add\",\"path\":\"/tags/2\",\"value\":\"third\"},{\"op\":\"remove\",\"path\":\"/title\"}]");
     }
 
@@ -107,7 +111,7 @@ pub mod tests {
         let s = "[{\"op\":\"replace\",\"path\":\"/author/name2\",\"value\":\"Lennon\"},{\"op\":\"add\",\"path\":\"/tags/2\",\"value\":\"third\"},{\"op\":\"remove\",\"path\":\"/title\"}]";
         let contract_address: ContractAddress = [1u8; 32].into();
         let index = 99;
-        let patch = StatePatch { patch: serde_json::from_str(s).unwrap(), previous_hash: [0u8; 32].into(), contract_address, index };
+        let patch = StatePatch { patch: serde_json::from_str(s).unwrap(), previous_hash: [0u8; 32].into(), contract_address, index, ops: None };
 
         let key = b"EnigmaMPC".sha256();
         let iv = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
@@ -121,7 +125,7 @@ pub mod tests {
     pub fn test_decrypt_patch() {
         let s = "[{\"op\":\"replace\",\"path\":\"/author/name2\",\"value\":\"Lennon\"},{\"op\":\"add\",\"path\":\"/tags/2\",\"value\":\"third\"},{\"op\":\"remove\",\"path\":\"/title\"}]";
         let contract_address: ContractAddress = [1u8; 32].into();
-        let patch = StatePatch { patch: serde_json::from_str(s).unwrap(), previous_hash: [0u8; 32].into(), contract_address, index: 0 };
+        let patch = StatePatch { patch: serde_json::from_str(s).unwrap(), previous_hash: [0u8; 32].into(), contract_address, index: 0, ops: None };
 
         let key = b"EnigmaMPC".sha256();
         let enc_data = vec![196, 39, 143, 237, 10, 117, 249, 235, 174, 84, 130, 219, 214, 92, 182, 148, 87, 171, 131, 69, 32, 201, 192, 190, 253, 176, 230, 5, 20, 221, 171, 31, 37, 51, 29, 231, 134, 147, 234, 255, 104, 144, 161, 110, 192, 28, 187, 143, 184, 188, 211, 219, 36, 117, 28, 51, 160, 204, 97, 250, 153, 193, 86, 194, 169, 111, 124, 202, 195, 44, 170, 109, 98, 164, 203, 177, 27, 246, 129, 8, 132, 12, 232, 104, 130, 98, 155, 7, 137, 89, 113, 187, 197, 211, 191, 246, 97, 112, 71, 240, 162, 35, 176, 216, 26, 97, 90, 218, 197, 244, 94, 225, 184, 235, 75, 198, 205, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
@@ -133,7 +137,7 @@ pub mod tests {
 
     pub fn test_encrypt_decrypt_patch() {
         let s = "[{\"op\":\"replace\",\"path\":\"/author/name2\",\"value\":\"Lennon\"},{\"op\":\"add\",\"path\":\"/tags/2\",\"value\":\"third\"},{\"op\":\"remove\",\"path\":\"/title\"}]";
-        let patch = StatePatch { patch: serde_json::from_str(s).unwrap(), previous_hash: [0u8; 32].into(), contract_address: [1u8; 32].into(), index: 0 };
+        let patch = StatePatch { patch: serde_json::from_str(s).unwrap(), previous_hash: [0u8; 32].into(), contract_address: [1u8; 32].into(), index: 0, ops: None };
 
         let key = b"EnigmaMPC".sha256();
         let iv = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
@@ -146,7 +150,7 @@ pub mod tests {
         let p = "[{\"op\":\"replace\",\"path\":\"/author/name2\",\"value\":\"Lennon\"},{\"op\":\"add\",\"path\":\"/tags/2\",\"value\":\"third\"},{\"op\":\"remove\",\"path\":\"/title\"}]";
         let contract_address = b"Enigma".sha256();
         let key = [1u8; 32];
-        let patch = StatePatch { patch: serde_json::from_str(p).unwrap(), previous_hash: [4u8; 32].into(), contract_address, index: 1 };
+        let patch = StatePatch { patch: serde_json::from_str(p).unwrap(), previous_hash: [4u8; 32].into(), contract_address, index: 1, ops: None };
         let enc_patch = patch.encrypt(&key).unwrap();
         let delta_hash = enc_patch.keccak256_patch();
         let mut contract = ContractState {
@@ -154,6 +158,7 @@ pub mod tests {
             json: json!({ "title": "Goodbye!","author" : { "name1" : "John", "name2" : "Doe"}, "tags":[ "first", "second" ] }),
             delta_hash: [4u8; 32].into(),
             delta_index: 0,
+            ..Default::default()
         };
         contract.apply_delta(enc_patch, &key).unwrap();
         assert_eq!(
@@ -163,6 +168,7 @@ pub mod tests {
                 json: json!({ "author" : {"name1" : "John", "name2" : "Lennon"},"tags": [ "first", "second", "third"] }),
                 delta_hash,
                 delta_index: 1,
+                ..Default::default()
             }
         );
     }
@@ -171,22 +177,118 @@ pub mod tests {
         let p = "[{\"op\":\"replace\",\"path\":\"/author/name2\",\"value\":\"Lennon\"},{\"op\":\"add\",\"path\":\"/tags/2\",\"value\":\"third\"},{\"op\":\"remove\",\"path\":\"/title\"}]";
         let contract_address = b"Enigma".sha256();
         let key = [1u8; 32];
-        let result = StatePatch { patch: serde_json::from_str(p).unwrap(), previous_hash: [4u8; 32].into(), contract_address, index: 1 };
+        let result = StatePatch { patch: serde_json::from_str(p).unwrap(), previous_hash: [4u8; 32].into(), contract_address, index: 1, ops: None };
         let before = ContractState {
             contract_address,
             json: json!({ "title": "Goodbye!","author" : { "name1" : "John", "name2" : "Doe"}, "tags":[ "first", "second" ] }),
             delta_hash: [4u8; 32].into(),
             delta_index: 0,
+            ..Default::default()
         };
         let mut after = ContractState {
             contract_address,
             json: json!({ "author" : {"name1" : "John", "name2" : "Lennon"},"tags": [ "first", "second", "third"] }),
             delta_hash: [4u8; 32].into(),
             delta_index: 0,
+            ..Default::default()
         };
 
         let delta = ContractState::generate_delta_and_update_state(&before, &mut after, &key).unwrap();
         let delta = StatePatch::decrypt(delta, &key).unwrap();
         assert_eq!(delta, result);
     }
+
+    pub fn test_key_value_state() {
+        let mut con = ContractState::new_key_value(b"Enigma".sha256());
+        assert_eq!(con.version(), StateVersion::KeyValue);
+        con.write_key("code", &json!(200)).unwrap();
+        con.write_raw("payload", b"{\"features\":[\"serde\",\"json\"]}").unwrap();
+        assert_eq!(con.read_key::<u64>("code").unwrap(), 200);
+        assert_eq!(con.read_key::<Map<String, Value>>("payload").unwrap()["features"], json!(["serde", "json"]));
+        assert_eq!(con.read_raw("code"), b"200".to_vec());
+        assert_eq!(con.read_raw("missing"), b"null".to_vec());
+        assert!(con.write_raw("invalid", b"{\"features\":").is_err());
+        assert_eq!(con.read_raw("invalid"), b"null".to_vec());
+        // a JSON value can't start with the tag of a binary value.
+        assert!(con.write_raw("fake", &[BINARY_VALUE_TAG, 1]).is_err());
+        assert_eq!(con.value_type("fake"), None);
+
+        con.remove_key("code");
+        assert_eq!(con.read_key::<Option<u64>>("code").unwrap(), None);
+        assert!(con.json.is_null());
+    }
+
+    pub fn test_key_value_delta() {
+        let contract_address = b"Enigma".sha256();
+        let key = [1u8; 32];
+        let mut before = ContractState::new_key_value(contract_address);
+        before.write_key("a", &json!(1)).unwrap();
+        before.write_key("b", &json!(2)).unwrap();
+        before.write_key("c", &json!(3)).unwrap();
+        let initial = ContractState::new_key_value(contract_address);
+        let delta0 = ContractState::generate_delta_and_update_state(&initial, &mut before, &key).unwrap();
+
+        let mut after = before.clone();
+        after.remove_key("a");
+        after.write_key("b", &json!("two")).unwrap();
+        after.write_key("d", &json!(4)).unwrap();
+        let delta1 = ContractState::generate_delta_and_update_state(&before, &mut after, &key).unwrap();
+        let patch = StatePatch::decrypt(delta1.clone(), &key).unwrap();
+        assert!(patch.patch.0.is_empty());
+        assert_eq!(patch.ops.unwrap(), vec![
+            StateOp::Delete(b"a".to_vec()),
+            StateOp::Put(b"b".to_vec(), b"\"two\"".to_vec()),
+            StateOp::Put(b"d".to_vec(), b"4".to_vec()),
+        ]);
+
+        // a state built from scratch takes the version of the first delta.
+        let mut rebuilt = ContractState::new(contract_address);
+        rebuilt.apply_delta(delta0, &key).unwrap();
+        rebuilt.apply_delta(delta1, &key).unwrap();
+        assert_eq!(rebuilt, after);
+
+        // the JSON states can't take key-value deltas.
+        let mut json_state = ContractState::new(contract_address);
+        json_state.delta_hash = after.delta_hash;
+        json_state.delta_index = after.delta_index;
+        let mut next = after.clone();
+        next.write_key("e", &json!(5)).unwrap();
+        let delta2 = ContractState::generate_delta_and_update_state(&after, &mut next, &key).unwrap();
+        assert!(json_state.apply_delta(delta2, &key).is_err());
+    }
+
+    pub fn test_encrypt_decrypt_key_value_state() {
+        let mut con = ContractState::new_key_value(b"Enigma".sha256());
+        con.write_key("code", &json!(200)).unwrap();
+        let key = b"EnigmaMPC".sha256();
+        let enc = con.clone().encrypt(&key).unwrap();
+        assert_eq!(ContractState::decrypt(enc, &key).unwrap(), con)
+    }
+
+    pub fn test_binary_state_values() {
+        let contract_address = b"Enigma".sha256();
+        let key = [1u8; 32];
+        let initial = ContractState::new_key_value(contract_address);
+        let mut con = initial.clone();
+        con.write_typed("balance", &[2, 0x03, 0xe8], ValueType::Binary).unwrap();
+        con.write_typed("name", b"\"enigma\"", ValueType::Json).unwrap();
+        assert_eq!(con.read_raw("balance"), vec![BINARY_VALUE_TAG, 2, 0x03, 0xe8]);
+        assert_eq!(con.read_binary("balance"), Some(&[2u8, 0x03, 0xe8][..]));
+        assert_eq!(con.value_type("balance"), Some(ValueType::Binary));
+        assert_eq!(con.read_binary("name"), None);
+        assert_eq!(con.value_type("name"), Some(ValueType::Json));
+        assert_eq!(con.value_type("missing"), None);
+        assert_eq!(con.read_key::<String>("name").unwrap(), "enigma");
+
+        // the binary values go through the deltas like any other value.
+        let delta = ContractState::generate_delta_and_update_state(&initial, &mut con, &key).unwrap();
+        let mut rebuilt = ContractState::new(contract_address);
+        rebuilt.apply_delta(delta, &key).unwrap();
+        assert_eq!(rebuilt.read_binary("balance"), Some(&[2u8, 0x03, 0xe8][..]));
+
+        // the JSON states can't hold binary values.
+        let mut json_state = ContractState::new(contract_address);
+        assert!(json_state.write_typed("balance", &[1, 1], ValueType::Binary).is_err());
+        assert_eq!(json_state.value_type("balance"), None);
+    }
 }
@@ -1,23 +1,56 @@
-use crate::data::{DeltasInterface, IOInterface, StatePatch};
-use enigma_tools_t::common::errors_t::{EnclaveError, EnclaveError::*, EnclaveSystemError::*};
+use crate::data::{DeltasInterface, IOInterface, StateOp, StatePatch};
+use enigma_tools_t::common::errors_t::{EnclaveError, EnclaveError::*, EnclaveSystemError::*, FailedTaskError::WasmCodeExecutionError};
 use enigma_types::{ContractAddress, StateKey};
 use enigma_crypto::{symmetric, Encryption};
 use enigma_types::Hash256;
 use json_patch;
 use rmps::{Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
-use serde_json::{from_value, Error, Value};
+use serde::de::IgnoredAny;
+use serde_json::{from_slice, from_value, Error, Value};
+use std::collections::BTreeMap;
 use std::string::ToString;
 use std::vec::Vec;
 use data::EncryptedPatch;
 
+/// A sorted map of the keys to the JSON encoded values.
+pub type KeyValueMap = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// The model used to store the state of a contract.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StateVersion {
+    /// The whole state is a single JSON document and the deltas are JSON patches over it.
+    Json,
+    /// The state is a `KeyValueMap` and the deltas are per-key puts/deletes.
+    KeyValue,
+}
+
+/// The first byte of a binary value in a `KeyValueMap`.
+/// A JSON value can't start with it, so both kinds of values are kept in the same map.
+pub const BINARY_VALUE_TAG: u8 = 0;
+
+/// The encoding of a value in the state.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ValueType {
+    /// The value is JSON, it is parsed when written to a `StateVersion::Json` state.
+    Json,
+    /// The value is in a compact binary encoding of the contract, it is stored as is after `BINARY_VALUE_TAG`.
+    /// Only a `StateVersion::KeyValue` state can hold binary values.
+    Binary,
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
+#[serde(crate = "crate::serde")]
 pub struct ContractState {
     #[serde(skip)]
     pub contract_address: ContractAddress,
     pub json: Value,
     pub delta_hash: Hash256,
     pub delta_index: u32,
+    // This is the version tag, `None` is a `StateVersion::Json` state.
+    // It must stay the last field so JSON states are serialized exactly as they were before it was added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kv: Option<KeyValueMap>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -32,25 +65,160 @@ impl ContractState {
         ContractState { contract_address, json,.. Default::default() }
     }
 
+    /// A new state which uses the `StateVersion::KeyValue` model.
+    pub fn new_key_value(contract_address: ContractAddress) -> ContractState {
+        ContractState { contract_address, json: Value::Null, kv: Some(KeyValueMap::new()), .. Default::default() }
+    }
+
     pub fn is_initial(&self) -> bool{
         self.delta_index == 0 && self.delta_hash.is_zero()
     }
+
+    pub fn version(&self) -> StateVersion {
+        match self.kv {
+            Some(_) => StateVersion::KeyValue,
+            None => StateVersion::Json,
+        }
+    }
+
+    /// The type of the value under `key`, `None` if there's no such key.
+    pub fn value_type(&self, key: &str) -> Option<ValueType> {
+        match self.kv {
+            Some(ref kv) => kv.get(key.as_bytes()).map(|value| match value.first() {
+                Some(&BINARY_VALUE_TAG) => ValueType::Binary,
+                _ => ValueType::Json,
+            }),
+            None => self.json.get(key).map(|_| ValueType::Json),
+        }
+    }
+
+    /// The binary value under `key` without its tag, `None` if there's no such key or it isn't a binary value.
+    pub fn read_binary(&self, key: &str) -> Option<&[u8]> {
+        match self.kv.as_ref()?.get(key.as_bytes()) {
+            Some(value) if value.first() == Some(&BINARY_VALUE_TAG) => Some(&value[1..]),
+            _ => None,
+        }
+    }
+
+    /// Writes `value` under `key` in the encoding of `value_type`.
+    pub fn write_typed(&mut self, key: &str, value: &[u8], value_type: ValueType) -> Result<(), EnclaveError> {
+        match (value_type, &mut self.kv) {
+            (ValueType::Json, _) => self.write_raw(key, value),
+            (ValueType::Binary, Some(kv)) => {
+                let mut tagged = Vec::with_capacity(value.len() + 1);
+                tagged.push(BINARY_VALUE_TAG);
+                tagged.extend_from_slice(value);
+                kv.insert(key.as_bytes().to_vec(), tagged);
+                Ok(())
+            }
+            (ValueType::Binary, None) => Err(FailedTaskError(WasmCodeExecutionError {
+                err: "binary values can only be written to a key-value state".to_string(),
+            })),
+        }
+    }
+
+    fn set_version(&mut self, version: StateVersion) {
+        if self.version() == version {
+            return;
+        }
+        match version {
+            StateVersion::Json => {
+                self.kv = None;
+                self.json = serde_json::from_str("{}").unwrap();
+            }
+            StateVersion::KeyValue => {
+                self.kv = Some(KeyValueMap::new());
+                self.json = Value::Null;
+            }
+        }
+    }
+}
+
+/// Returns the operations which turn `old` into `new`, both maps are sorted so they're walked together once.
+fn diff_key_values(old: &KeyValueMap, new: &KeyValueMap) -> Vec<StateOp> {
+    let mut ops = Vec::new();
+    let mut old_iter = old.iter().peekable();
+    let mut new_iter = new.iter().peekable();
+    loop {
+        let (old_key, new_key) = match (old_iter.peek(), new_iter.peek()) {
+            (None, None) => break,
+            (old_entry, new_entry) => (old_entry.map(|(k, _)| *k), new_entry.map(|(k, _)| *k)),
+        };
+        match (old_key, new_key) {
+            (Some(o), Some(n)) if o == n => {
+                let (_, old_value) = old_iter.next().unwrap();
+                let (key, new_value) = new_iter.next().unwrap();
+                if old_value != new_value {
+                    ops.push(StateOp::Put(key.clone(), new_value.clone()));
+                }
+            }
+            // the key exists only in the old map.
+            (Some(o), Some(n)) if o < n => ops.push(StateOp::Delete(old_iter.next().unwrap().0.clone())),
+            (Some(_), None) => ops.push(StateOp::Delete(old_iter.next().unwrap().0.clone())),
+            // the key exists only in the new map.
+            _ => {
+                let (key, value) = new_iter.next().unwrap();
+                ops.push(StateOp::Put(key.clone(), value.clone()));
+            }
+        }
+    }
+    ops
 }
 
 impl IOInterface<EnclaveError, u8> for ContractState {
     fn read_key<T>(&self, key: &str) -> Result<T, Error>
     where for<'de> T: Deserialize<'de> {
-        from_value(self.json[key].clone())
+        match self.kv {
+            Some(ref kv) => match kv.get(key.as_bytes()) {
+                Some(value) => from_slice(value),
+                None => from_value(Value::Null),
+            },
+            None => from_value(self.json[key].clone()),
+        }
     }
 
     fn write_key(&mut self, key: &str, value: &Value) -> Result<(), EnclaveError> {
-        self.json[key] = value.clone();
+        match self.kv {
+            Some(ref mut kv) => {
+                let value = serde_json::to_vec(value).map_err(|e| SystemError(StateError { err: format!("{}", e) }))?;
+                kv.insert(key.as_bytes().to_vec(), value);
+            }
+            None => self.json[key] = value.clone(),
+        }
         Ok(())
     }
 
     fn remove_key(&mut self, key: &str) {
-        if let Some(ref mut v) = self.json.as_object_mut() {
-            v.remove(key);
+        match self.kv {
+            Some(ref mut kv) => {
+                kv.remove(key.as_bytes());
+            }
+            None => if let Some(ref mut v) = self.json.as_object_mut() {
+                v.remove(key);
+            }
+        }
+    }
+
+    fn read_raw(&self, key: &str) -> Vec<u8> {
+        match self.kv {
+            Some(ref kv) => kv.get(key.as_bytes()).cloned().unwrap_or_else(|| b"null".to_vec()),
+            None => serde_json::to_vec(&self.json[key]).expect("Failed converting Value to vec while reading state"),
+        }
+    }
+
+    fn write_raw(&mut self, key: &str, value: &[u8]) -> Result<(), EnclaveError> {
+        match self.kv {
+            // the values are kept encoded, they're only validated so the state never holds invalid JSON
+            // and a JSON value can't pass for a binary one.
+            Some(ref mut kv) => {
+                from_slice::<IgnoredAny>(value).map_err(|e| SystemError(StateError { err: format!("{}", e) }))?;
+                kv.insert(key.as_bytes().to_vec(), value.to_vec());
+                Ok(())
+            }
+            None => {
+                let value: Value = from_slice(value).map_err(|e| SystemError(StateError { err: format!("{}", e) }))?;
+                self.write_key(key, &value)
+            }
         }
     }
 }
@@ -62,7 +230,20 @@ impl<'a> DeltasInterface<EnclaveError, EncryptedPatch, &'a StateKey> for Contrac
         if dec_delta.previous_hash != self.delta_hash {
             return Err(SystemError(StateError { err: "Hashes don't match, Failed Applying the delta".to_string() }));
         }
-        json_patch::patch(&mut self.json, &dec_delta.patch)?;
+        // the first delta decides the version of a state which is built from scratch.
+        if self.is_initial() {
+            self.set_version(if dec_delta.ops.is_some() { StateVersion::KeyValue } else { StateVersion::Json });
+        }
+        match (dec_delta.ops, &mut self.kv) {
+            (None, None) => json_patch::patch(&mut self.json, &dec_delta.patch)?,
+            (Some(ops), Some(kv)) => for op in ops {
+                match op {
+                    StateOp::Put(key, value) => { kv.insert(key, value); }
+                    StateOp::Delete(key) => { kv.remove(&key); }
+                }
+            },
+            _ => return Err(SystemError(StateError { err: "The delta doesn't match the state version, Failed Applying the delta".to_string() })),
+        }
         self.delta_hash = delta_hash;
         self.delta_index = dec_delta.index;
         Ok(())
@@ -74,11 +255,17 @@ impl<'a> DeltasInterface<EnclaveError, EncryptedPatch, &'a StateKey> for Contrac
         } else {
             new.delta_index = &old.delta_index+1;
         }
+        let (patch, ops) = match (&old.kv, &new.kv) {
+            (None, None) => (json_patch::diff(&old.json, &new.json), None),
+            (Some(old_kv), Some(new_kv)) => (json_patch::Patch(Vec::new()), Some(diff_key_values(old_kv, new_kv))),
+            _ => return Err(SystemError(StateError { err: "The state version can't change between deltas".to_string() })),
+        };
         let delta = StatePatch{
-            patch: json_patch::diff(&old.json, &new.json),
+            patch,
             previous_hash: old.delta_hash,
             contract_address: old.contract_address,
             index: new.delta_index,
+            ops,
         };
         let enc_delta = delta.encrypt(key)?;
         new.delta_hash = enc_delta.keccak256_patch();
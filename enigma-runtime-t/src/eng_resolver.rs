@@ -22,6 +22,15 @@ pub mod ids {
     pub const RAND_FUNC: usize = 15;
     pub const ENCRYPT_FUNC: usize = 16;
     pub const DECRYPT_FUNC: usize = 17;
+    pub const CALL_CONTRACT_FUNC: usize = 18;
+    pub const FETCH_CALL_RESULT_FUNC: usize = 19;
+    pub const EMIT_EVENT_FUNC: usize = 20;
+    pub const BLOCK_NUMBER_FUNC: usize = 21;
+    pub const EPOCH_NONCE_FUNC: usize = 22;
+    pub const SENDER_PUBKEY_FUNC: usize = 23;
+    pub const SELF_ADDRESS_FUNC: usize = 24;
+    pub const REVERT_FUNC: usize = 25;
+    pub const WRITE_STATE_WITH_FLAGS_FUNC: usize = 26;
 }
 
 pub mod signatures {
@@ -34,6 +43,8 @@ pub mod signatures {
 
     pub const WRITE_STATE: StaticSignature = StaticSignature(&[I32, I32, I32, I32], None);
 
+    pub const WRITE_STATE_WITH_FLAGS: StaticSignature = StaticSignature(&[I32, I32, I32, I32, I32], None);
+
     pub const READ_STATE_LEN: StaticSignature = StaticSignature(&[I32, I32], Some(I32));
 
     pub const READ_STATE: StaticSignature = StaticSignature(&[I32, I32, I32], None);
@@ -60,6 +71,22 @@ pub mod signatures {
 
     pub const DECRYPT: StaticSignature = StaticSignature(&[I32, I32, I32, I32], None);
 
+    pub const CALL_CONTRACT: StaticSignature = StaticSignature(&[I32, I32, I32, I32, I32], Some(I32));
+
+    pub const FETCH_CALL_RESULT: StaticSignature = StaticSignature(&[I32], None);
+
+    pub const EMIT_EVENT: StaticSignature = StaticSignature(&[I32, I32, I32, I32], None);
+
+    pub const BLOCK_NUMBER: StaticSignature = StaticSignature(&[], Some(I64));
+
+    pub const EPOCH_NONCE: StaticSignature = StaticSignature(&[I32], None);
+
+    pub const SENDER_PUBKEY: StaticSignature = StaticSignature(&[I32], None);
+
+    pub const SELF_ADDRESS: StaticSignature = StaticSignature(&[I32], None);
+
+    pub const REVERT: StaticSignature = StaticSignature(&[I32, I32], None);
+
     impl Into<wasmi::Signature> for StaticSignature {
         fn into(self) -> wasmi::Signature { wasmi::Signature::new(self.0, self.1) }
     }
@@ -106,6 +133,7 @@ impl ModuleImportResolver for ImportResolver {
         let (signature, id) = match field_name {
             "ret" => (signatures::RET, ids::RET_FUNC),
             "write_state" => (signatures::WRITE_STATE, ids::WRITE_STATE_FUNC),
+            "write_state_with_flags" => (signatures::WRITE_STATE_WITH_FLAGS, ids::WRITE_STATE_WITH_FLAGS_FUNC),
             "read_state_len" => (signatures::READ_STATE_LEN, ids::READ_STATE_LEN_FUNC),
             "read_state" => (signatures::READ_STATE, ids::READ_STATE_FUNC),
             "remove_from_state" => (signatures::REMOVE_STATE, ids::REMOVE_STATE_FUNC),
@@ -119,6 +147,14 @@ impl ModuleImportResolver for ImportResolver {
             "rand" => (signatures::RAND, ids::RAND_FUNC),
             "encrypt" => (signatures::ENCRYPT, ids::ENCRYPT_FUNC),
             "decrypt" => (signatures::DECRYPT, ids::DECRYPT_FUNC),
+            "call_contract" => (signatures::CALL_CONTRACT, ids::CALL_CONTRACT_FUNC),
+            "fetch_call_result" => (signatures::FETCH_CALL_RESULT, ids::FETCH_CALL_RESULT_FUNC),
+            "emit_event" => (signatures::EMIT_EVENT, ids::EMIT_EVENT_FUNC),
+            "block_number" => (signatures::BLOCK_NUMBER, ids::BLOCK_NUMBER_FUNC),
+            "epoch_nonce" => (signatures::EPOCH_NONCE, ids::EPOCH_NONCE_FUNC),
+            "sender_pubkey" => (signatures::SENDER_PUBKEY, ids::SENDER_PUBKEY_FUNC),
+            "self_address" => (signatures::SELF_ADDRESS, ids::SELF_ADDRESS_FUNC),
+            "revert" => (signatures::REVERT, ids::REVERT_FUNC),
             _ => return Err(wasmi::Error::Instantiation(format!("Export {} not found", field_name))),
         };
 
@@ -1,7 +1,50 @@
 
 pub use pwasm_utils::{inject_gas_counter, rules};
+use enigma_crypto::hash::Keccak256;
+use enigma_tools_t::common::errors_t::{EnclaveError, EnclaveError::*, EnclaveSystemError::*};
+use enigma_types::Hash256;
+use rmps::Serializer;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// The version of the default gas schedule, it should be incremented whenever one of the default costs changes.
+pub const GAS_SCHEDULE_VERSION: u32 = 1;
+
+/// The costs of everything a task is charged for, the tasks are signed together with its hash
+/// so anyone verifying a result knows which costs were applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "crate::serde", default, rename_all = "camelCase")]
+pub struct GasSchedule {
+    pub version: u32,
+    pub wasm: WasmCosts,
+    pub runtime: RuntimeWasmCosts,
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        GasSchedule { version: GAS_SCHEDULE_VERSION, wasm: WasmCosts::default(), runtime: RuntimeWasmCosts::default() }
+    }
+}
+
+impl GasSchedule {
+    /// Parses a schedule from JSON, the missing costs take the default values.
+    pub fn from_json(json: &[u8]) -> Result<Self, EnclaveError> {
+        serde_json::from_slice(json).map_err(|e| SystemError(GasScheduleError { err: format!("{}", e) }))
+    }
+
+    /// The keccak of the MessagePack encoding of the schedule.
+    pub fn hash(&self) -> Hash256 {
+        let mut buf = Vec::new();
+        self.serialize(&mut Serializer::new(&mut buf)).expect("the gas schedule is always serializable");
+        buf.keccak256()
+    }
+}
 
 /// Wasm cost table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "crate::serde", default, rename_all = "camelCase")]
 pub struct WasmCosts {
     /// Default opcode cost
     pub regular: u32,
@@ -61,12 +104,24 @@ pub fn gas_rules(wasm_costs: &WasmCosts) -> rules::Set {
     //.with_forbidden_floats()
 }
 
-#[derive(Debug, Clone)]
+/// The costs of the host functions, each one is charged a fixed cost and a cost per byte it handles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "crate::serde", default, rename_all = "camelCase")]
 pub struct RuntimeWasmCosts {
     pub write_value: u64,
     pub write_additional_byte: u64,
     pub deploy_byte: u64,
     pub execution: u64,
+    pub emit_event: u64,
+    pub emit_event_byte: u64,
+    pub read_value: u64,
+    pub read_additional_byte: u64,
+    pub crypto: u64,
+    pub crypto_byte: u64,
+    pub rand: u64,
+    pub rand_byte: u64,
+    pub eprint: u64,
+    pub eprint_byte: u64,
 }
 
 impl Default for RuntimeWasmCosts {
@@ -76,14 +131,104 @@ impl Default for RuntimeWasmCosts {
             write_additional_byte: 1,
             deploy_byte: 1,
             execution: 10_000,
+            emit_event: 10,
+            emit_event_byte: 1,
+            read_value: 5,
+            read_additional_byte: 1,
+            crypto: 50,
+            crypto_byte: 1,
+            rand: 10,
+            rand_byte: 1,
+            eprint: 1,
+            eprint_byte: 0,
         }
     }
 }
 
+/// What the gas is charged for, used to build the `GasProfile`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GasCategory {
+    /// The instruction metering that `inject_gas_counter` adds to the contract.
+    Instructions,
+    StateWrites,
+    Deployment,
+    Execution,
+    /// One of the host functions, by its import name.
+    HostCall(&'static str),
+}
+
+impl GasCategory {
+    pub fn name(self) -> &'static str {
+        match self {
+            GasCategory::Instructions => "instructions",
+            GasCategory::StateWrites => "state_writes",
+            GasCategory::Deployment => "deployment",
+            GasCategory::Execution => "execution",
+            GasCategory::HostCall(name) => name,
+        }
+    }
+}
+
+/// Where the gas of a task went, it's only collected when profiling is enabled on the engine.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "crate::serde", rename_all = "camelCase")]
+pub struct GasProfile {
+    pub instructions: u64,
+    /// The writes before the refunds, the refunds are counted separately.
+    pub state_writes: u64,
+    pub refunds: u64,
+    pub deployment: u64,
+    pub execution: u64,
+    pub host_calls: BTreeMap<String, u64>,
+    /// The gas used by every contract function that ran, including the gas of the functions it called.
+    pub functions: BTreeMap<String, u64>,
+    /// What was being charged when the gas ran out.
+    pub out_of_gas: Option<String>,
+}
+
+impl GasProfile {
+    pub fn charge(&mut self, category: GasCategory, amount: u64) {
+        let total = match category {
+            GasCategory::Instructions => &mut self.instructions,
+            GasCategory::StateWrites => &mut self.state_writes,
+            GasCategory::Deployment => &mut self.deployment,
+            GasCategory::Execution => &mut self.execution,
+            GasCategory::HostCall(name) => self.host_calls.entry(name.to_string()).or_insert(0),
+        };
+        *total = total.saturating_add(amount);
+    }
+
+    pub fn add_function(&mut self, function: &str, used_gas: u64) {
+        let total = self.functions.entry(function.to_string()).or_insert(0);
+        *total = total.saturating_add(used_gas);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RuntimeGas {
     pub counter: u64,
     pub limit: u64,
     pub refund: u64,
     pub costs: RuntimeWasmCosts,
-}
\ No newline at end of file
+}
+#[cfg(debug_assertions)]
+pub mod tests {
+    use super::*;
+
+    pub fn test_gas_schedule_from_json() {
+        let schedule = GasSchedule::from_json(br#"{"version": 2, "runtime": {"readValue": 7}}"#).unwrap();
+        assert_eq!(schedule.version, 2);
+        assert_eq!(schedule.runtime.read_value, 7);
+        assert_eq!(schedule.runtime.write_value, RuntimeWasmCosts::default().write_value);
+        assert_eq!(schedule.wasm, WasmCosts::default());
+        assert!(GasSchedule::from_json(b"{\"version\": \"2\"}").is_err());
+    }
+
+    pub fn test_gas_schedule_hash() {
+        let default = GasSchedule::default();
+        assert_eq!(default.hash(), GasSchedule::default().hash());
+        let mut changed = default.clone();
+        changed.runtime.crypto_byte += 1;
+        assert_ne!(default.hash(), changed.hash());
+    }
+}
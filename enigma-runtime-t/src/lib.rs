@@ -1,40 +1,79 @@
-#![no_std]
+#![cfg_attr(feature = "sgx", no_std)]
 #![warn(unused_extern_crates)]
 
+#[cfg(all(feature = "sgx", feature = "std"))]
+compile_error!("the `sgx` and `std` features are mutually exclusive, enable only one of them");
 
 /// Enigma runtime implementation
+#[cfg(feature = "sgx")]
 #[macro_use]
 extern crate sgx_tstd as std;
+#[cfg(feature = "sgx")]
 extern crate sgx_types;
+#[cfg(feature = "sgx")]
 extern crate sgx_trts;
 #[macro_use]
-extern crate serde_json;
-#[macro_use]
 extern crate enigma_tools_t;
 extern crate enigma_crypto;
 extern crate enigma_types;
-extern crate json_patch;
-extern crate rmp_serde as rmps;
-extern crate serde;
-extern crate wasmi;
-extern crate parity_wasm;
+
+#[cfg(feature = "sgx")]
+#[macro_use]
+extern crate serde_json_sgx as serde_json;
+#[cfg(feature = "sgx")]
+extern crate json_patch_sgx as json_patch;
+#[cfg(feature = "sgx")]
+extern crate rmp_serde_sgx as rmps;
+#[cfg(feature = "sgx")]
+extern crate serde_sgx as serde;
+#[cfg(feature = "sgx")]
+extern crate wasmi_sgx as wasmi;
+#[cfg(feature = "sgx")]
+extern crate parity_wasm_sgx as parity_wasm;
 /// This module builds Wasm code for contract deployment from the Wasm contract.
 /// The contract should be written in rust and then compiled to Wasm with wasm32-unknown-unknown target.
 /// The code is based on Parity wasm_utils::cli.
-extern crate pwasm_utils;
+#[cfg(feature = "sgx")]
+extern crate pwasm_utils_sgx as pwasm_utils;
 
-use crate::data::{ContractState, DeltasInterface, IOInterface, EncryptedPatch};
-use enigma_types::{StateKey, SymmetricKey, SYMMETRIC_KEY_SIZE};
+#[cfg(feature = "std")]
+#[macro_use]
+extern crate serde_json_std as serde_json;
+#[cfg(feature = "std")]
+extern crate json_patch_std as json_patch;
+#[cfg(feature = "std")]
+extern crate rmp_serde_std as rmps;
+#[cfg(feature = "std")]
+extern crate serde_std as serde;
+#[cfg(feature = "std")]
+extern crate wasmi_std as wasmi;
+#[cfg(feature = "std")]
+extern crate parity_wasm_std as parity_wasm;
+#[cfg(feature = "std")]
+extern crate pwasm_utils_std as pwasm_utils;
+#[cfg(feature = "std")]
+extern crate rand_std as rand;
+
+use crate::data::{ContractState, DeltasInterface, IOInterface, EncryptedContractState, EncryptedPatch, ValueType};
+use crate::wasm_execution::WasmEngine;
+use enigma_crypto::hash::Keccak256;
+use enigma_types::{ContractAddress, Hash256, RawPointer, StateKey, SymmetricKey, TaskContext, SYMMETRIC_KEY_SIZE};
 use enigma_tools_t::common::errors_t::{EnclaveError, EnclaveError::*, EnclaveSystemError::*, WasmError};
+use enigma_tools_t::common::errors_t::FailedTaskError::{ContractError, WasmCodeExecutionError};
+use enigma_crypto::Encryption;
 
-use std::{str, vec::Vec};
+use std::{mem, str, vec::Vec};
 use std::string::{String, ToString};
 use wasmi::{MemoryRef, RuntimeArgs, RuntimeValue};
+#[cfg(feature = "sgx")]
 use sgx_trts::trts::rsgx_read_rand;
+#[cfg(feature = "std")]
+use rand::{rngs::StdRng, RngCore, SeedableRng};
 use enigma_crypto::symmetric::{encrypt, decrypt};
 
 pub mod data;
 pub mod eng_resolver;
+#[cfg(feature = "sgx")]
 pub mod ocalls_t;
 pub mod gas;
 pub mod wasm_execution;
@@ -47,13 +86,64 @@ pub struct EthereumData {
     pub ethereum_contract_addr: [u8; 20],
 }
 
+/// The flags of `write_state_with_flags`.
+pub mod state_flags {
+    /// The value is in a binary encoding of the contract instead of JSON, see `data::ValueType::Binary`.
+    pub const BINARY_VALUE: u32 = 1;
+}
+
+/// The maximum number of contracts which can run at the same time, including the one the task was sent to.
+pub const MAX_CALL_DEPTH: usize = 4;
+
 #[derive(Debug, Clone)]
 pub struct RuntimeResult {
     pub state_delta: Option<EncryptedPatch>,
     pub updated_state: ContractState,
     pub result: Vec<u8>,
-    pub ethereum_bridge: Option<EthereumData>,
+    /// The ethereum payloads written by the contract and by the contracts it called, in the order they were written.
+    pub ethereum_bridges: Vec<EthereumData>,
     pub used_gas: u64,
+    /// The contracts called with `call_contract`, including the ones that didn't change their state, in the order of the calls.
+    pub called_contracts: Vec<CalledContract>,
+    /// The events emitted by the contract and by the contracts it called, in the order they were emitted.
+    pub events: Vec<Event>,
+    /// `None` unless gas profiling was enabled on the engine.
+    pub gas_profile: Option<GasProfile>,
+}
+
+/// An event emitted by a contract with `emit_event`, the topic and the data are opaque to the runtime.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    /// The contract that emitted the event.
+    pub contract_address: ContractAddress,
+    pub topic: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// A contract which was called by another contract, with the code and the state it ran with.
+/// A contract which was called more than once appears once, at its first call,
+/// with a single delta from its state before the task to its state after the last call.
+#[derive(Debug, Clone)]
+pub struct CalledContract {
+    /// The hash of the code the host handed for the contract, every call in the task runs the same code.
+    pub exe_code_hash: Hash256,
+    /// The delta hash of the state before the call.
+    pub pre_delta_hash: Hash256,
+    /// `None` if none of the calls changed the state.
+    pub state_delta: Option<EncryptedPatch>,
+    pub updated_state: ContractState,
+}
+
+/// Everything the runtime needs to run the contracts that the executed contract calls.
+/// In the enclave the contracts and their states are loaded with ocalls, outside of it they can come from memory.
+#[derive(Debug, Clone)]
+pub struct CallContext {
+    pub db_ptr: *const RawPointer,
+    pub get_state_key: fn(ContractAddress) -> ::std::result::Result<StateKey, EnclaveError>,
+    pub get_contract: fn(*const RawPointer, ContractAddress) -> ::std::result::Result<Vec<u8>, EnclaveError>,
+    pub get_state: fn(*const RawPointer, ContractAddress) -> ::std::result::Result<EncryptedContractState<u8>, EnclaveError>,
+    /// The contracts which are running right now, the contract the task was sent to is the first.
+    pub call_stack: Vec<ContractAddress>,
 }
 
 #[derive(Debug, Clone)]
@@ -66,6 +156,21 @@ pub struct Runtime {
     post_execution_state: ContractState,
     key: StateKey,
     gas : RuntimeGas,
+    // `None` when calling other contracts isn't allowed (e.g. in deployments).
+    call_context: Option<CallContext>,
+    // the output of the last `call_contract`.
+    call_result: Vec<u8>,
+    // queries can't change the state, so writing to it traps.
+    read_only: bool,
+    // the called contracts run with the same schedule.
+    schedule: GasSchedule,
+    // `None` unless gas profiling was enabled, see `enable_gas_profiling`.
+    gas_profile: Option<GasProfile>,
+    // the called contracts run in the same context, only `self_address` changes.
+    task_context: TaskContext,
+    // outside of the enclave the randomness is seeded so the executions can be reproduced.
+    #[cfg(feature = "std")]
+    rng: StdRng,
 }
 
 type Result<T> = ::std::result::Result<T, WasmError>;
@@ -73,23 +178,75 @@ type Result<T> = ::std::result::Result<T, WasmError>;
 impl Runtime {
 
     pub fn new(memory: MemoryRef, gas_limit: u64, args: Vec<u8>, state: ContractState,
-                          function_name: String, key: StateKey, costs: RuntimeWasmCosts) -> Runtime {
+                          function_name: String, key: StateKey, schedule: GasSchedule) -> Runtime {
         let pre_execution_state = state.clone();
         let post_execution_state = state;
         let result = RuntimeResult {
             result: Vec::new(),
             state_delta: None,
             updated_state: Default::default(),
-            ethereum_bridge: Default::default(),
+            ethereum_bridges: Vec::new(),
             used_gas: 0,
+            called_contracts: Vec::new(),
+            events: Vec::new(),
+            gas_profile: None,
         };
         let gas = RuntimeGas{
             counter: 0,
             limit: gas_limit,
             refund: 0,
-            costs,
+            costs: schedule.runtime.clone(),
         };
-        Runtime { memory, function_name, args, result, pre_execution_state, post_execution_state, key, gas }
+        Runtime {
+            memory, function_name, args, result, pre_execution_state, post_execution_state, key, gas,
+            call_context: None, call_result: Vec::new(), read_only: false, schedule,
+            gas_profile: None,
+            task_context: TaskContext::default(),
+            #[cfg(feature = "std")]
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    pub fn set_call_context(&mut self, context: CallContext) {
+        self.call_context = Some(context);
+    }
+
+    /// Sets what `block_number`, `epoch_nonce` and `sender_pubkey` return, they're all zeros unless it's set.
+    pub fn set_task_context(&mut self, context: TaskContext) {
+        self.task_context = context;
+    }
+
+    pub fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    /// Reseeds the randomness of `rand`, the seed is 0 unless it's set.
+    #[cfg(feature = "std")]
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Keeps track of what every unit of gas is charged for, the contracts it calls are profiled too.
+    pub fn enable_gas_profiling(&mut self) {
+        self.gas_profile = Some(GasProfile::default());
+    }
+
+    /// The profile of the gas used so far, `None` if profiling isn't enabled.
+    pub fn gas_profile(&self) -> Option<GasProfile> {
+        let mut profile = self.gas_profile.clone()?;
+        profile.refunds = self.gas.refund;
+        profile.add_function(&self.function_name, self.gas.counter);
+        Some(profile)
+    }
+
+    /// Replaces the result of the execution, e.g. an upgrade results in the new code whatever `migrate` returned.
+    pub fn set_result(&mut self, result: Vec<u8>) {
+        self.result.result = result;
+    }
+
+    /// Takes the result of the execution out of the runtime, leaving it empty.
+    pub fn take_result(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.result.result, Vec::new())
     }
 
     pub fn get_used_gas(&self) -> u64 {
@@ -114,6 +271,30 @@ impl Runtime {
         Ok(())
     }
 
+    fn fetch_block_number(&mut self) -> RuntimeValue { RuntimeValue::I64(self.task_context.block_number as i64) }
+
+    fn fetch_epoch_nonce(&mut self, args: RuntimeArgs) -> Result<()> {
+        let ptr: u32 = args.nth_checked(0)?;
+
+        self.memory.set(ptr, &self.task_context.epoch_nonce)?;
+        Ok(())
+    }
+
+    fn fetch_sender_pubkey(&mut self, args: RuntimeArgs) -> Result<()> {
+        let ptr: u32 = args.nth_checked(0)?;
+
+        self.memory.set(ptr, &self.task_context.sender_pubkey)?;
+        Ok(())
+    }
+
+    // a called contract gets its own address, not the address of the contract the task was sent to.
+    fn fetch_self_address(&mut self, args: RuntimeArgs) -> Result<()> {
+        let ptr: u32 = args.nth_checked(0)?;
+
+        self.memory.set(ptr, &self.pre_execution_state.contract_address[..])?;
+        Ok(())
+    }
+
     fn read_state_key_from_memory(&self, args: &RuntimeArgs, arg_index: usize, arg_len_index: usize) -> Result<String>{
         let key = args.nth_checked(arg_index)?;
         let key_len: u32 = args.nth_checked(arg_len_index)?;
@@ -128,9 +309,7 @@ impl Runtime {
     pub fn read_state_len(&self, args: RuntimeArgs) -> Result<i32> {
         // TODO: Handle the error here, should we return len=0?;
         let key = self.read_state_key_from_memory(&args, 0, 1)?;
-        let value_vec =
-            serde_json::to_vec(&self.post_execution_state.json[&key]).
-                expect("Failed converting Value to vec in Runtime while reading state");
+        let value_vec = self.post_execution_state.read_raw(&key);
         Ok(value_vec.len() as i32)
     }
 
@@ -146,8 +325,9 @@ impl Runtime {
         let key = self.read_state_key_from_memory(&args, 0, 1)?;
         let value_holder: u32 = args.nth_checked(2)?;
 
-        let value_vec =
-            serde_json::to_vec(&self.post_execution_state.json[key]).expect("Failed converting Value to vec in Runtime while reading state");
+        let value_vec = self.post_execution_state.read_raw(&key);
+        let (cost, cost_per_byte) = (self.gas.costs.read_value, self.gas.costs.read_additional_byte);
+        self.charge_host_call(cost, cost_per_byte, value_vec.len() as u64, "read_state")?;
         self.memory.set(value_holder, &value_vec)?;
         Ok(())
     }
@@ -158,6 +338,7 @@ impl Runtime {
     ///
     /// Read `key` from the memory, then remove the `key` from the state
     pub fn remove_from_state(&mut self, args: RuntimeArgs) -> Result<()> {
+        self.check_writable()?;
         let key = self.read_state_key_from_memory(&args, 0, 1)?;
 
         self.post_execution_state.remove_key(&key);
@@ -173,18 +354,50 @@ impl Runtime {
     /// Read `key` and `value` from memory, and write (key, value) pair to the state
     /// the cost of writing into the state is calculated by `calculate_gas_for_writing`
     pub fn write_state(&mut self, args: RuntimeArgs) -> Result<()> {
-        let key = self.read_state_key_from_memory(&args, 0, 1)?;
+        self.write_state_typed(&args, ValueType::Json)
+    }
+
+    /// args:
+    /// * `key` - the start address of key in memory
+    /// * `key_len` - the length of the key
+    /// * `value` - the start address of value in memory
+    /// * `value_len` - the length of the value
+    /// * `flags` - the `state_flags` of the value
+    ///
+    /// Like `write_state`, with `state_flags::BINARY_VALUE` the value is stored as is instead of as JSON.
+    pub fn write_state_with_flags(&mut self, args: RuntimeArgs) -> Result<()> {
+        let flags: u32 = args.nth_checked(4)?;
+        if flags & !state_flags::BINARY_VALUE != 0 {
+            return Err(FailedTaskError(WasmCodeExecutionError { err: format!("unknown flags for writing to the state: {:#x}", flags) }).into());
+        }
+        let value_type = if flags & state_flags::BINARY_VALUE != 0 { ValueType::Binary } else { ValueType::Json };
+        self.write_state_typed(&args, value_type)
+    }
+
+    fn write_state_typed(&mut self, args: &RuntimeArgs, value_type: ValueType) -> Result<()> {
+        self.check_writable()?;
+        let key = self.read_state_key_from_memory(args, 0, 1)?;
         let value: u32 = args.nth_checked(2)?;
         let value_len: u32 = args.nth_checked(3)?;
 
         let mut val = vec![0u8; value_len as usize];
-        let gas_amount = self.calculate_gas_for_writing(value_len as u64, &key)?;
-        self.charge_gas(gas_amount)?;
+        // a binary value is stored with its tag, which is charged like the rest of the value.
+        let stored_len = match value_type {
+            ValueType::Json => value_len as u64,
+            ValueType::Binary => value_len as u64 + 1,
+        };
+        let gas_amount = self.calculate_gas_for_writing(stored_len, &key)?;
+        self.charge_gas(gas_amount, GasCategory::StateWrites)?;
         self.memory.get_into(value, &mut val[..])?;
 
-        let value: serde_json::Value =
-            serde_json::from_slice(&val).expect("Failed converting into Value while writing state in Runtime");
-        self.post_execution_state.write_key(&key, &value)?;
+        self.post_execution_state.write_typed(&key, &val, value_type)?;
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(FailedTaskError(WasmCodeExecutionError { err: "the state can't be changed in a query".to_string() }).into());
+        }
         Ok(())
     }
 
@@ -216,12 +429,10 @@ impl Runtime {
    /// If the new value is smaller than the old one, then the gas is returned for the removed bytes.
     fn calculate_gas_for_writing(&mut self, new_value_len: u64, key: &str) -> Result<u64> {
         let mut result = Some(0);
-        let val = self.post_execution_state.json[key].clone();
+        let old_value_vec = self.post_execution_state.read_raw(key);
         let mut old_value_len = 0;
         // forcing the length of Null value to be 0, since it is not 0.
-        if !val.is_null() {
-            let old_value_vec =
-                serde_json::to_vec(&val).expect("Failed converting Value to vec in Runtime while reading state");
+        if old_value_vec != b"null" {
             old_value_len = old_value_vec.len() as u64;
         }
         // If the new value is larger than the old one, the gas should be charged
@@ -250,7 +461,7 @@ impl Runtime {
     /// * `payload_len` - the length of the payload
     /// * `address` - the start address of address in memory
     ///
-    /// Read `payload` and `address` from memory, and write it to result
+    /// Read `payload` and `address` from memory, and add them to the ethereum payloads of the result
     pub fn write_eth_bridge(&mut self, args: RuntimeArgs) -> Result<()> {
         let payload = args.nth_checked(0)?;
         let payload_len: u32 = args.nth_checked(1)?;
@@ -260,7 +471,7 @@ impl Runtime {
 
         self.memory.get_into(payload, &mut bridge.ethereum_payload[..])?;
         self.memory.get_into(address, &mut bridge.ethereum_contract_addr[..])?;
-        self.result.ethereum_bridge = Some(bridge);
+        self.result.ethereum_bridges.push(bridge);
         Ok(())
     }
 
@@ -277,20 +488,198 @@ impl Runtime {
         Ok(())
     }
 
+    /// args:
+    /// * `ptr` - the start address of the error message in memory
+    /// * `len` - the length of the error message
+    ///
+    /// Fails the task with the error the contract returned, the message is returned to the user instead of the result.
+    pub fn revert(&mut self, args: RuntimeArgs) -> Result<()> {
+        let ptr: u32 = args.nth_checked(0)?;
+        let len: u32 = args.nth_checked(1)?;
+
+        let message = self.memory.get(ptr, len as usize)?;
+        let message = String::from_utf8_lossy(&message).into_owned();
+        Err(FailedTaskError(ContractError { message }).into())
+    }
+
     pub fn rand(&mut self, args: RuntimeArgs) -> Result<()> {
         let ptr: u32 = args.nth_checked(0)?;
         let len: u32 = args.nth_checked(1)?;
+        let (cost, cost_per_byte) = (self.gas.costs.rand, self.gas.costs.rand_byte);
+        self.charge_host_call(cost, cost_per_byte, len as u64, "rand")?;
 
         let mut buf = vec![0u8; len as usize];
-        match rsgx_read_rand(&mut buf[..]) {
-            Ok(_) => {
-                self.memory.set(ptr, &buf[..])?;
-                Ok(())
-            }
+        self.fill_random(&mut buf)?;
+        self.memory.set(ptr, &buf[..])?;
+        Ok(())
+    }
+
+    #[cfg(feature = "sgx")]
+    fn fill_random(&mut self, buf: &mut [u8]) -> Result<()> {
+        match rsgx_read_rand(buf) {
+            Ok(_) => Ok(()),
             Err(e) => Err(SystemError(SgxError { err: format!("{}", e), description: e.__description().to_string() }))?
         }
     }
 
+    #[cfg(feature = "std")]
+    fn fill_random(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.rng.fill_bytes(buf);
+        Ok(())
+    }
+
+    /// args:
+    /// * `address` - the start address in memory of the 32 bytes address of the called contract
+    /// * `name` - the start address in memory of the function name
+    /// * `name_len` - the length of the function name
+    /// * `args` - the start address in memory of the arguments for the function
+    /// * `args_len` - the length of the arguments
+    ///
+    /// Runs the function of the other contract under the remaining gas of this execution,
+    /// the gas it used is charged from this execution and its output is kept for `fetch_call_result`.
+    /// Returns the length of the output.
+    pub fn call_contract(&mut self, args: RuntimeArgs) -> Result<i32> {
+        let address_ptr: u32 = args.nth_checked(0)?;
+        let name_ptr: u32 = args.nth_checked(1)?;
+        let name_len: u32 = args.nth_checked(2)?;
+        let args_ptr: u32 = args.nth_checked(3)?;
+        let args_len: u32 = args.nth_checked(4)?;
+
+        let mut address = ContractAddress::default();
+        self.memory.get_into(address_ptr, &mut address[..])?;
+        let name = self.memory.get(name_ptr, name_len as usize)?;
+        let function_name = str::from_utf8(&name).map_err(EnclaveError::from)?.to_string();
+        let call_args = self.memory.get(args_ptr, args_len as usize)?;
+
+        let mut context = match self.call_context.clone() {
+            Some(context) => context,
+            None => return Err(Self::call_error("calling other contracts is not allowed here")),
+        };
+        if context.call_stack.len() >= MAX_CALL_DEPTH {
+            return Err(Self::call_error(&format!("the maximum call depth is {}", MAX_CALL_DEPTH)));
+        }
+        if context.call_stack.contains(&address) {
+            return Err(Self::call_error("a contract can't be called while it's running"));
+        }
+        context.call_stack.push(address);
+        let (db_ptr, get_state) = (context.db_ptr, context.get_state);
+
+        let key = (context.get_state_key)(address)?;
+        let bytecode = (context.get_contract)(context.db_ptr, address)?;
+        let exe_code_hash = bytecode.keccak256();
+        // a contract which was already called by this task continues from its state after that call,
+        // and the code and the state it was called with first are the ones that get signed.
+        let state = match self.result.called_contracts.iter().find(|c| c.updated_state.contract_address == address) {
+            Some(called) if called.exe_code_hash != exe_code_hash => {
+                return Err(Self::call_error("the code of a called contract changed during the task"));
+            }
+            Some(called) => called.updated_state.clone(),
+            None => ContractState::decrypt((context.get_state)(context.db_ptr, address)?, &key)?,
+        };
+        let pre_delta_hash = state.delta_hash;
+
+        let remaining_gas = self.gas.limit.saturating_sub(self.gas.counter);
+        let mut engine = WasmEngine::new_compute(&bytecode, remaining_gas, call_args, state, function_name, key, &self.schedule)?;
+        engine.runtime.set_call_context(context);
+        engine.runtime.set_task_context(self.task_context);
+        if self.gas_profile.is_some() {
+            engine.runtime.enable_gas_profiling();
+        }
+        #[cfg(feature = "std")]
+        engine.runtime.set_rng_seed(self.rng.next_u64());
+        // the called contract keeps track of all the changed contracts, so nested calls see their updated states.
+        engine.runtime.result.called_contracts = mem::replace(&mut self.result.called_contracts, Vec::new());
+        if let Err(e) = engine.compute() {
+            let (used_gas, err) = match e {
+                FailedTaskErrorWithGas { used_gas, err } => (used_gas, FailedTaskError(err)),
+                e => (engine.runtime.get_used_gas(), e),
+            };
+            self.gas.counter = self.gas.counter.saturating_add(used_gas).min(self.gas.limit);
+            self.merge_callee_profile(engine.runtime.gas_profile(), used_gas);
+            return Err(err.into());
+        }
+        let callee = engine.into_result()?;
+        self.charge_gas(callee.used_gas, GasCategory::HostCall("call_contract"))?;
+        self.merge_callee_profile(callee.gas_profile, 0);
+
+        self.result.called_contracts = callee.called_contracts;
+        self.result.events.extend(callee.events);
+        self.result.ethereum_bridges.extend(callee.ethereum_bridges);
+        let mut updated_state = callee.updated_state;
+        match self.result.called_contracts.iter().position(|c| c.updated_state.contract_address == address) {
+            // the contract was already called by this task, so its delta is regenerated from its stored state.
+            Some(i) if callee.state_delta.is_some() => {
+                let stored_state = ContractState::decrypt(get_state(db_ptr, address)?, &key)?;
+                let called = &mut self.result.called_contracts[i];
+                if stored_state.delta_hash != called.pre_delta_hash {
+                    return Err(Self::call_error("the state of a called contract changed during the task"));
+                }
+                called.state_delta = Some(ContractState::generate_delta_and_update_state(&stored_state, &mut updated_state, &key)?);
+                called.updated_state = updated_state;
+            }
+            Some(_) => (),
+            None => {
+                let state_delta = callee.state_delta;
+                self.result.called_contracts.push(CalledContract { exe_code_hash, pre_delta_hash, state_delta, updated_state });
+            }
+        }
+        self.call_result = callee.result;
+        Ok(self.call_result.len() as i32)
+    }
+
+    /// args:
+    /// * `ptr` - the start address in memory to copy the output to
+    ///
+    /// Copies the output of the last `call_contract` to the memory.
+    pub fn fetch_call_result(&mut self, args: RuntimeArgs) -> Result<()> {
+        let ptr: u32 = args.nth_checked(0)?;
+        self.memory.set(ptr, &self.call_result)?;
+        Ok(())
+    }
+
+    /// args:
+    /// * `topic` - the start address in memory of the topic
+    /// * `topic_len` - the length of the topic
+    /// * `data` - the start address in memory of the data
+    /// * `data_len` - the length of the data
+    ///
+    /// Adds an event to the result, events can't be emitted in a query.
+    pub fn emit_event(&mut self, args: RuntimeArgs) -> Result<()> {
+        self.check_writable()?;
+        let topic_ptr: u32 = args.nth_checked(0)?;
+        let topic_len: u32 = args.nth_checked(1)?;
+        let data_ptr: u32 = args.nth_checked(2)?;
+        let data_len: u32 = args.nth_checked(3)?;
+
+        let (cost, cost_per_byte) = (self.gas.costs.emit_event, self.gas.costs.emit_event_byte);
+        self.charge_host_call(cost, cost_per_byte, topic_len as u64 + data_len as u64, "emit_event")?;
+
+        let topic = self.memory.get(topic_ptr, topic_len as usize)?;
+        let data = self.memory.get(data_ptr, data_len as usize)?;
+        let contract_address = self.pre_execution_state.contract_address;
+        self.result.events.push(Event { contract_address, topic, data });
+        Ok(())
+    }
+
+    // the gas of the called contract is charged as `call_contract`, only its functions are added to this profile.
+    fn merge_callee_profile(&mut self, callee: Option<GasProfile>, failed_call_gas: u64) {
+        if let (Some(profile), Some(callee)) = (self.gas_profile.as_mut(), callee) {
+            if failed_call_gas > 0 {
+                profile.charge(GasCategory::HostCall("call_contract"), failed_call_gas);
+            }
+            if profile.out_of_gas.is_none() {
+                profile.out_of_gas = callee.out_of_gas;
+            }
+            for (function, used_gas) in callee.functions {
+                profile.add_function(&function, used_gas);
+            }
+        }
+    }
+
+    fn call_error(err: &str) -> WasmError {
+        FailedTaskError(WasmCodeExecutionError { err: err.to_string() }).into()
+    }
+
     /// Destroy the runtime, create state delta, update the used gas and return result of the execution
     pub fn into_result(mut self) -> ::std::result::Result<RuntimeResult, EnclaveError> {
         if self.gas.counter >= self.gas.refund {
@@ -307,6 +696,7 @@ impl Runtime {
                 None
             }
         };
+        self.result.gas_profile = self.gas_profile();
         self.result.updated_state = self.post_execution_state;
         Ok(self.result)
     }
@@ -314,6 +704,8 @@ impl Runtime {
     pub fn eprint(&mut self, args: RuntimeArgs) -> Result<()> {
         let msg_ptr: u32 = args.nth_checked(0)?;
         let msg_len: u32 = args.nth_checked(1)?;
+        let (cost, cost_per_byte) = (self.gas.costs.eprint, self.gas.costs.eprint_byte);
+        self.charge_host_call(cost, cost_per_byte, msg_len as u64, "eprint")?;
         let res = self.memory.get(msg_ptr, msg_len as usize)?;
         // This should not fail if printing is done properly through eng_wasm eprint!
         let st = str::from_utf8(&res).unwrap_or_default();
@@ -323,25 +715,40 @@ impl Runtime {
 
     pub fn gas(&mut self, args: RuntimeArgs) -> Result<()> {
         let amount: u32 = args.nth_checked(0)?;
-        self.charge_gas(amount as u64)
+        self.charge_gas(amount as u64, GasCategory::Instructions)
     }
 
     pub fn charge_deployment(&mut self) -> Result<()> {
         let deployed_bytecode_len = self.result.result.len() as u64;
         let gas_for_byte = self.gas.costs.deploy_byte;
-        self.charge_gas(deployed_bytecode_len * gas_for_byte)
+        self.charge_gas(deployed_bytecode_len * gas_for_byte, GasCategory::Deployment)
     }
 
     pub fn charge_execution(&mut self) -> Result<()> {
         let initial_execution_gas = self.gas.costs.execution;
-        self.charge_gas(initial_execution_gas)
+        self.charge_gas(initial_execution_gas, GasCategory::Execution)
+    }
+
+    /// Charges the fixed cost of a host function and its cost for each of the `len` bytes it handles.
+    fn charge_host_call(&mut self, cost: u64, cost_per_byte: u64, len: u64, name: &'static str) -> Result<()> {
+        let checked_val = len.checked_mul(cost_per_byte);
+        self.treat_gas_overflow(&checked_val)?;
+        let gas_amount = cost.checked_add(checked_val.unwrap());
+        self.treat_gas_overflow(&gas_amount)?;
+        self.charge_gas(gas_amount.unwrap(), GasCategory::HostCall(name))
     }
 
-    fn charge_gas(&mut self, amount: u64) -> Result<()> {
+    fn charge_gas(&mut self, amount: u64, category: GasCategory) -> Result<()> {
         if self.charge_gas_if_enough(amount) {
+            if let Some(profile) = self.gas_profile.as_mut() {
+                profile.charge(category, amount);
+            }
             Ok(())
         } else {
             self.gas.counter = self.gas.limit;
+            if let Some(profile) = self.gas_profile.as_mut() {
+                profile.out_of_gas = Some(category.name().to_string());
+            }
             Err(WasmError::GasLimit)
         }
     }
@@ -361,6 +768,8 @@ impl Runtime {
     pub fn encrypt(&mut self, args: RuntimeArgs) -> Result<()> {
         let message_ptr: u32 = args.nth_checked(0)?;
         let message_len: u32 = args.nth_checked(1)?;
+        let (cost, cost_per_byte) = (self.gas.costs.crypto, self.gas.costs.crypto_byte);
+        self.charge_host_call(cost, cost_per_byte, message_len as u64, "encrypt")?;
         let message = self.memory.get(message_ptr, message_len as usize)?;
         debug_println!("In encrypt: {:?}", message);
 
@@ -378,6 +787,8 @@ impl Runtime {
     pub fn decrypt(&mut self, args: RuntimeArgs) -> Result<()> {
         let cipheriv_ptr: u32 = args.nth_checked(0)?;
         let cipheriv_len: u32 = args.nth_checked(1)?;
+        let (cost, cost_per_byte) = (self.gas.costs.crypto, self.gas.costs.crypto_byte);
+        self.charge_host_call(cost, cost_per_byte, cipheriv_len as u64, "decrypt")?;
         let cipheriv = self.memory.get(cipheriv_ptr, cipheriv_len as usize)?;
 
         let key_ptr: u32 = args.nth_checked(2)?;
@@ -406,6 +817,10 @@ mod ext_impl {
                     Runtime::write_state(self, args)?;
                     Ok(None)
                 }
+                eng_resolver::ids::WRITE_STATE_WITH_FLAGS_FUNC => {
+                    Runtime::write_state_with_flags(self, args)?;
+                    Ok(None)
+                }
                 eng_resolver::ids::READ_STATE_LEN_FUNC => {
                     let res = Runtime::read_state_len(self, args)?;
                     Ok(Some(RuntimeValue::I32(res)))
@@ -462,6 +877,43 @@ mod ext_impl {
                     Ok(None)
                 }
 
+                eng_resolver::ids::CALL_CONTRACT_FUNC => {
+                    let res = Runtime::call_contract(self, args)?;
+                    Ok(Some(RuntimeValue::I32(res)))
+                }
+
+                eng_resolver::ids::FETCH_CALL_RESULT_FUNC => {
+                    Runtime::fetch_call_result(self, args)?;
+                    Ok(None)
+                }
+
+                eng_resolver::ids::EMIT_EVENT_FUNC => {
+                    Runtime::emit_event(self, args)?;
+                    Ok(None)
+                }
+
+                eng_resolver::ids::BLOCK_NUMBER_FUNC => Ok(Some(Runtime::fetch_block_number(self))),
+
+                eng_resolver::ids::EPOCH_NONCE_FUNC => {
+                    Runtime::fetch_epoch_nonce(self, args)?;
+                    Ok(None)
+                }
+
+                eng_resolver::ids::SENDER_PUBKEY_FUNC => {
+                    Runtime::fetch_sender_pubkey(self, args)?;
+                    Ok(None)
+                }
+
+                eng_resolver::ids::SELF_ADDRESS_FUNC => {
+                    Runtime::fetch_self_address(self, args)?;
+                    Ok(None)
+                }
+
+                eng_resolver::ids::REVERT_FUNC => {
+                    Runtime::revert(self, args)?;
+                    Ok(None)
+                }
+
                 _ => unimplemented!("Unimplemented function at {}", index),
             }
         }
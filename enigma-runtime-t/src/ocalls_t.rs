@@ -17,10 +17,27 @@ extern "C" {
                        contract_address: &ContractAddress, delta_index_: *const u32) -> sgx_status_t;
     fn ocall_remove_delta(retval: *mut EnclaveReturn, db_ptr: *const RawPointer,
                        contract_address: &ContractAddress, delta_index_: *const u32) -> sgx_status_t;
+    fn ocall_new_delta_and_state(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, contract_address: &ContractAddress,
+                                 enc_delta: *const u8, delta_len: usize, delta_index_: *const u32,
+                                 enc_state: *const u8, state_len: usize) -> sgx_status_t;
+    fn ocall_new_deltas_and_states(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, entries: *const u8, entries_len: usize) -> sgx_status_t;
+    fn ocall_upgrade_contract(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, contract_address: &ContractAddress,
+                              exe_code: *const u8, exe_code_len: usize, entries: *const u8, entries_len: usize) -> sgx_status_t;
 
     fn ocall_get_state_size(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, addr: &ContractAddress, state_len: *mut usize) -> sgx_status_t;
     fn ocall_get_state(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, addr: &ContractAddress, state_ptr: *mut u8, state_len: usize) -> sgx_status_t;
     fn ocall_update_state(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, id: &ContractAddress, enc_delta: *const u8, delta_len: usize) -> sgx_status_t;
+
+    fn ocall_new_snapshot(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, contract_address: &ContractAddress,
+                          enc_state: *const u8, state_len: usize, delta_index: *const u32) -> sgx_status_t;
+    fn ocall_get_snapshot_size(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, addr: &ContractAddress, max_index: *const u32,
+                               delta_index: *mut u32, state_size: *mut usize) -> sgx_status_t;
+    fn ocall_get_snapshot(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, addr: &ContractAddress, delta_index: *const u32,
+                          state_ptr: *mut u8, state_len: usize) -> sgx_status_t;
+
+    fn ocall_get_contract_size(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, addr: &ContractAddress, bytecode_len: *mut usize) -> sgx_status_t;
+    fn ocall_get_contract(retval: *mut EnclaveReturn, db_ptr: *const RawPointer, addr: &ContractAddress,
+                          bytecode_ptr: *mut u8, bytecode_len: usize) -> sgx_status_t;
 }
 
 pub unsafe fn save_state(db_ptr: *const RawPointer, enc: &EncryptedContractState<u8>) -> Result<(), EnclaveError> {
@@ -55,6 +72,103 @@ pub fn save_delta(db_ptr: *const RawPointer, enc: &EncryptedPatch) -> Result<(),
     }
 }
 
+/// Saves the delta and the state of the same contract together in one atomic DB write.
+pub fn save_delta_and_state(db_ptr: *const RawPointer, delta: &EncryptedPatch, state: &EncryptedContractState<u8>) -> Result<(), EnclaveError> {
+    if delta.contract_address != state.contract_address {
+        return Err(SystemError(OcallError { command: "ocall_new_delta_and_state".to_string(),
+                                            err: "the delta and the state belong to different contracts".to_string() }));
+    }
+    let mut res = EnclaveReturn::default();
+    let res_status = unsafe {
+        ocall_new_delta_and_state(&mut res, db_ptr, &delta.contract_address,
+                                  delta.data.as_c_ptr(), delta.data.len(), &delta.index as *const u32,
+                                  state.json.as_c_ptr(), state.json.len())
+    };
+
+    match res {
+        EnclaveReturn::Success => (), // 0 is the OK result
+        EnclaveReturn::OcallDBError => {
+            return Err(SystemError(OcallError { command: "ocall_new_delta_and_state".to_string(), err: "unable to store the delta and the state".to_string() }))
+        }
+        _ => return Err(SystemError(OcallError { command: "ocall_new_delta_and_state".to_string(), err: format!("return result is: {}", &res) })),
+    }
+
+    match res_status {
+        sgx_status_t::SGX_SUCCESS => Ok(()),
+        _ => Err(SystemError(OcallError { command: "ocall_new_delta_and_state".to_string(), err: res_status.__description().to_string() })),
+    }
+}
+
+/// Saves the deltas and the states of several contracts together in one atomic DB write,
+/// every delta is paired with the state of the same contract.
+///
+/// The entries are serialized one after the other as
+/// `[contract address: 32 bytes][delta index: u32 BE][delta length: u32 BE][delta][state length: u32 BE][state]`.
+pub fn save_deltas_and_states(db_ptr: *const RawPointer, entries: &[(&EncryptedPatch, EncryptedContractState<u8>)]) -> Result<(), EnclaveError> {
+    let serialized = serialize_deltas_and_states("ocall_new_deltas_and_states", entries)?;
+    let mut res = EnclaveReturn::default();
+    let res_status = unsafe { ocall_new_deltas_and_states(&mut res, db_ptr, serialized.as_c_ptr(), serialized.len()) };
+
+    match res {
+        EnclaveReturn::Success => (), // 0 is the OK result
+        EnclaveReturn::OcallDBError => {
+            return Err(SystemError(OcallError { command: "ocall_new_deltas_and_states".to_string(), err: "unable to store the deltas and the states".to_string() }))
+        }
+        _ => return Err(SystemError(OcallError { command: "ocall_new_deltas_and_states".to_string(), err: format!("return result is: {}", &res) })),
+    }
+
+    match res_status {
+        sgx_status_t::SGX_SUCCESS => Ok(()),
+        _ => Err(SystemError(OcallError { command: "ocall_new_deltas_and_states".to_string(), err: res_status.__description().to_string() })),
+    }
+}
+
+/// Replaces the bytecode of the contract with `exe_code` and saves the delta and the state of its `migrate`,
+/// if it made one, in one atomic DB write, so the migrated state never runs under the old code.
+/// The host keeps the replaced bytecode as the version which made the deltas up to the current tip.
+pub fn save_upgrade(db_ptr: *const RawPointer, contract_address: ContractAddress, exe_code: &[u8],
+                    entries: &[(&EncryptedPatch, EncryptedContractState<u8>)]) -> Result<(), EnclaveError> {
+    if entries.iter().any(|(delta, _)| delta.contract_address != contract_address) {
+        return Err(SystemError(OcallError { command: "ocall_upgrade_contract".to_string(),
+                                            err: "the delta belongs to a different contract".to_string() }));
+    }
+    let serialized = serialize_deltas_and_states("ocall_upgrade_contract", entries)?;
+    let mut res = EnclaveReturn::default();
+    let res_status = unsafe {
+        ocall_upgrade_contract(&mut res, db_ptr, &contract_address, exe_code.as_c_ptr(), exe_code.len(), serialized.as_c_ptr(), serialized.len())
+    };
+
+    match res {
+        EnclaveReturn::Success => (), // 0 is the OK result
+        EnclaveReturn::OcallDBError => {
+            return Err(SystemError(OcallError { command: "ocall_upgrade_contract".to_string(), err: "unable to store the upgrade".to_string() }))
+        }
+        _ => return Err(SystemError(OcallError { command: "ocall_upgrade_contract".to_string(), err: format!("return result is: {}", &res) })),
+    }
+
+    match res_status {
+        sgx_status_t::SGX_SUCCESS => Ok(()),
+        _ => Err(SystemError(OcallError { command: "ocall_upgrade_contract".to_string(), err: res_status.__description().to_string() })),
+    }
+}
+
+fn serialize_deltas_and_states(command: &str, entries: &[(&EncryptedPatch, EncryptedContractState<u8>)]) -> Result<Vec<u8>, EnclaveError> {
+    let mut serialized = Vec::new();
+    for (delta, state) in entries {
+        if delta.contract_address != state.contract_address {
+            return Err(SystemError(OcallError { command: command.to_string(),
+                                                err: "the delta and the state belong to different contracts".to_string() }));
+        }
+        serialized.extend_from_slice(&delta.contract_address[..]);
+        serialized.extend_from_slice(&delta.index.to_be_bytes());
+        serialized.extend_from_slice(&(delta.data.len() as u32).to_be_bytes());
+        serialized.extend_from_slice(&delta.data);
+        serialized.extend_from_slice(&(state.json.len() as u32).to_be_bytes());
+        serialized.extend_from_slice(&state.json);
+    }
+    Ok(serialized)
+}
+
 pub fn remove_delta(db_ptr: *const RawPointer, enc: &EncryptedPatch) -> Result<(), EnclaveError> {
     let mut res = EnclaveReturn::default();
     let res_status =
@@ -96,6 +210,74 @@ pub fn get_state(db_ptr: *const RawPointer, contract_address: ContractAddress) -
     Ok(EncryptedContractState { contract_address, json: state })
 }
 
+/// Returns the deployed bytecode of the contract.
+pub fn get_contract(db_ptr: *const RawPointer, contract_address: ContractAddress) -> Result<Vec<u8>, EnclaveError> {
+    let mut retval = EnclaveReturn::default();
+    let mut bytecode_len = 0usize;
+    let status = unsafe { ocall_get_contract_size(&mut retval, db_ptr, &contract_address, &mut bytecode_len) };
+    if retval != EnclaveReturn::Success || status != sgx_status_t::SGX_SUCCESS {
+        return Err(SystemError(OcallError {
+            command: "get_contract_size".to_string(),
+            err: format!("Error with SGX, retval: {}, status: {:?}", retval, status),
+        }));
+    }
+    let mut bytecode = vec![0u8; bytecode_len];
+    let status = unsafe { ocall_get_contract(&mut retval, db_ptr, &contract_address, bytecode.as_mut_ptr(), bytecode_len) };
+    if retval != EnclaveReturn::Success || status != sgx_status_t::SGX_SUCCESS {
+        return Err(SystemError(OcallError {
+            command: "get_contract".to_string(),
+            err: format!("Error with SGX, retval: {}, status: {:?}", retval, status),
+        }));
+    }
+    Ok(bytecode)
+}
+
+/// Saves an encrypted copy of the state as a snapshot of the contract at `delta_index`.
+pub fn save_snapshot(db_ptr: *const RawPointer, enc: &EncryptedContractState<u8>, delta_index: u32) -> Result<(), EnclaveError> {
+    let mut res = EnclaveReturn::default();
+    let res_status = unsafe {
+        ocall_new_snapshot(&mut res, db_ptr, &enc.contract_address, enc.json.as_c_ptr(), enc.json.len(), &delta_index as *const u32)
+    };
+
+    match res {
+        EnclaveReturn::Success => (), // 0 is the OK result
+        _ => return Err(SystemError(OcallError { command: "ocall_new_snapshot".to_string(), err: format!("return result is: {}", &res) })),
+    }
+
+    match res_status {
+        sgx_status_t::SGX_SUCCESS => Ok(()),
+        _ => Err(SystemError(OcallError { command: "ocall_new_snapshot".to_string(), err: res_status.__description().to_string() })),
+    }
+}
+
+/// Returns the newest snapshot of the contract that was taken at a delta index up to `max_index`, together with its index.
+pub fn get_snapshot(db_ptr: *const RawPointer, contract_address: ContractAddress, max_index: u32) -> Result<(u32, EncryptedContractState<u8>), EnclaveError> {
+    let mut retval = EnclaveReturn::default();
+    let mut delta_index = 0u32;
+    let mut state_len = 0usize;
+    let status = unsafe {
+        ocall_get_snapshot_size(&mut retval, db_ptr, &contract_address, &max_index as *const u32, &mut delta_index, &mut state_len)
+    };
+    if retval != EnclaveReturn::Success || status != sgx_status_t::SGX_SUCCESS {
+        return Err(SystemError(OcallError {
+            command: "get_snapshot_size".to_string(),
+            err: format!("Error with SGX, retval: {}, status: {:?}", retval, status),
+        }));
+    }
+    let mut state = vec![0u8; state_len];
+    let status = unsafe {
+        ocall_get_snapshot(&mut retval, db_ptr, &contract_address, &delta_index as *const u32, state.as_mut_ptr(), state_len)
+    };
+    if retval != EnclaveReturn::Success || status != sgx_status_t::SGX_SUCCESS {
+        return Err(SystemError(OcallError {
+            command: "get_snapshot".to_string(),
+            err: format!("Error with SGX, retval: {}, status: {:?}", retval, status),
+        }));
+    }
+
+    Ok((delta_index, EncryptedContractState { contract_address, json: state }))
+}
+
 pub fn get_deltas(db_ptr: *const RawPointer, contract_address: ContractAddress, start: u32, end: u32) -> Result<Vec<EncryptedPatch>, EnclaveError> {
     let len = (end - start) as usize;
     let mut deltas_buff = vec![0usize; len];
@@ -135,7 +317,8 @@ pub fn get_deltas(db_ptr: *const RawPointer, contract_address: ContractAddress,
 
 #[cfg(debug_assertions)]
 pub mod tests {
-    use super::{get_deltas, get_state, save_delta, save_state, EncryptedContractState, EncryptedPatch};
+    use super::{get_contract, get_deltas, get_snapshot, get_state, save_delta, save_delta_and_state, save_snapshot, save_state, save_upgrade,
+                EncryptedContractState, EncryptedPatch};
     use crate::data::ContractState;
     use enigma_types::{ContractAddress, RawPointer};
     use enigma_crypto::hash::Sha256;
@@ -193,6 +376,65 @@ pub mod tests {
         assert!(res.is_ok());
     }
 
+    pub fn test_save_delta_and_state(db_ptr: *const RawPointer) {
+        let contract_address = b"test_save_delta_and_state".sha256();
+        let delta = EncryptedPatch { data: b"delta".sha256().to_vec(), contract_address, index: 0 };
+        let state = EncryptedContractState { contract_address, json: b"state".sha256().to_vec() };
+        save_delta_and_state(db_ptr, &delta, &state).unwrap();
+
+        assert_eq!(get_state(db_ptr, contract_address).unwrap(), state);
+        assert_eq!(get_deltas(db_ptr, contract_address, 0, 1).unwrap(), vec![delta]);
+    }
+
+    pub fn test_save_deltas_and_states(db_ptr: *const RawPointer) {
+        let addresses = [b"test_save_deltas_and_states_a".sha256(), b"test_save_deltas_and_states_b".sha256()];
+        let deltas: Vec<_> = addresses.iter()
+            .map(|&contract_address| EncryptedPatch { data: contract_address.to_vec(), contract_address, index: 0 })
+            .collect();
+        let states: Vec<_> = addresses.iter()
+            .map(|&contract_address| EncryptedContractState { contract_address, json: b"state".sha256().to_vec() })
+            .collect();
+        let entries: Vec<_> = deltas.iter().zip(states.iter().cloned()).collect();
+        save_deltas_and_states(db_ptr, &entries).unwrap();
+        for ((address, delta), state) in addresses.iter().zip(deltas).zip(states) {
+            assert_eq!(get_state(db_ptr, *address).unwrap(), state);
+            assert_eq!(get_deltas(db_ptr, *address, 0, 1).unwrap(), vec![delta]);
+        }
+
+        // a mismatched pair fails the whole batch.
+        let other = b"test_save_deltas_and_states_c".sha256();
+        let delta = EncryptedPatch { data: b"delta".to_vec(), contract_address: other, index: 0 };
+        let state = EncryptedContractState { contract_address: addresses[0], json: b"state".to_vec() };
+        assert!(save_deltas_and_states(db_ptr, &[(&delta, state)]).is_err());
+        assert_eq!(get_state(db_ptr, addresses[0]).unwrap().json, b"state".sha256().to_vec());
+    }
+
+    pub fn test_save_upgrade_without_contract(db_ptr: *const RawPointer) {
+        let contract_address = b"test_save_upgrade_without_contract".sha256();
+        let delta = EncryptedPatch { data: b"delta".to_vec(), contract_address, index: 0 };
+        let state = EncryptedContractState { contract_address, json: b"state".to_vec() };
+        // there's no bytecode to replace, so nothing is stored.
+        assert!(save_upgrade(db_ptr, contract_address, b"new code", &[(&delta, state)]).is_err());
+        assert!(get_state(db_ptr, contract_address).is_err());
+        assert!(get_contract(db_ptr, contract_address).is_err());
+
+        let other = b"test_save_upgrade_other".sha256();
+        let state = EncryptedContractState { contract_address, json: b"state".to_vec() };
+        assert!(save_upgrade(db_ptr, other, b"new code", &[(&delta, state)]).is_err());
+    }
+
+    pub fn test_snapshots(db_ptr: *const RawPointer) {
+        let contract_address = b"test_snapshots".sha256();
+        let first = EncryptedContractState { contract_address, json: b"first".sha256().to_vec() };
+        let second = EncryptedContractState { contract_address, json: b"second".sha256().to_vec() };
+        save_snapshot(db_ptr, &first, 500).unwrap();
+        save_snapshot(db_ptr, &second, 1000).unwrap();
+
+        assert_eq!(get_snapshot(db_ptr, contract_address, u32::max_value()).unwrap(), (1000, second));
+        assert_eq!(get_snapshot(db_ptr, contract_address, 999).unwrap(), (500, first));
+        assert!(get_snapshot(db_ptr, contract_address, 499).is_err());
+    }
+
     unsafe fn save_deltas(db_ptr: *const RawPointer, start: u32, end: u32, contract_address: &ContractAddress) -> Vec<EncryptedPatch> {
         let mut deltas = Vec::new();
         for i in start..end {
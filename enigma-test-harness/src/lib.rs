@@ -0,0 +1,235 @@
+#![deny(unused_extern_crates, missing_docs)]
+//! # Enigma Test Harness
+//! Runs secret contracts in a regular process, so they can be unit tested without building an enclave. <br>
+//! The contracts run on `enigma-runtime-t` built with the `std` feature, their states are kept in memory
+//! and the randomness of `rand` is seeded, so every run of a test is the same. <br>
+//! Nothing is encrypted on the way in and out, the arguments are the ABI encoded `Token`s that eng-wasm decodes. e.g.
+//! ```no_run
+//! use enigma_test_harness::{build_contract, Harness, Token};
+//!
+//! let wasm = build_contract("examples/eng_wasm_contracts/simplest").unwrap();
+//! let mut harness = Harness::new();
+//! let address = harness.deploy(&wasm, "construct(uint)", &[Token::Uint(1.into())]).unwrap();
+//! let result = harness.compute(address, "addition(uint256,uint256)", &[Token::Uint(1.into()), Token::Uint(2.into())]).unwrap();
+//! ```
+//!
+//! This crate is Rust 2018 Edition,
+//! meaning there's no `extern crate` and `use` statements need to start with `crate`/`self`/`super`.
+
+use enigma_crypto::{hash::Keccak256, Encryption};
+use enigma_runtime_t::data::{ContractState, EncryptedContractState};
+use enigma_runtime_t::wasm_execution::WasmEngine;
+use enigma_runtime_t::CallContext;
+use enigma_tools_t::build_arguments_g::get_types;
+use enigma_tools_t::common::errors_t::{EnclaveError::*, EnclaveSystemError::*};
+use enigma_types::{RawPointer, StateKey};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+pub use enigma_runtime_t::{GasProfile, GasSchedule, RuntimeResult};
+pub use enigma_tools_t::common::errors_t::{EnclaveError, FailedTaskError};
+pub use enigma_types::{ContractAddress, TaskContext};
+pub use ethabi::{self, Token};
+
+/// The gas limit of the tasks unless it's changed with `set_gas_limit`.
+pub const DEFAULT_GAS_LIMIT: u64 = 100_000_000;
+
+// the `db_ptr` of the runtime points to the deployed contracts.
+type Contracts = HashMap<ContractAddress, DeployedContract>;
+
+#[derive(Debug, Clone)]
+struct DeployedContract {
+    bytecode: Vec<u8>,
+    state: ContractState,
+}
+
+/// The deployed contracts and the settings of the tasks that run on them.
+#[derive(Debug, Clone)]
+pub struct Harness {
+    contracts: Contracts,
+    schedule: GasSchedule,
+    gas_limit: u64,
+    seed: u64,
+    gas_profiling: bool,
+    context: TaskContext,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Harness {
+            contracts: HashMap::new(),
+            schedule: GasSchedule::default(),
+            gas_limit: DEFAULT_GAS_LIMIT,
+            seed: 0,
+            gas_profiling: false,
+            context: TaskContext::default(),
+        }
+    }
+}
+
+impl Harness {
+    /// A harness without any contracts, the tasks run with the default gas schedule.
+    pub fn new() -> Self { Self::default() }
+
+    /// The seed of the randomness in the next tasks, every task with the same seed gets the same random bytes.
+    pub fn set_seed(&mut self, seed: u64) { self.seed = seed; }
+
+    /// The gas limit of the next tasks.
+    pub fn set_gas_limit(&mut self, gas_limit: u64) { self.gas_limit = gas_limit; }
+
+    /// The gas schedule of the next tasks.
+    pub fn set_gas_schedule(&mut self, schedule: GasSchedule) { self.schedule = schedule; }
+
+    /// The block number, epoch nonce and sender of the next tasks, they're all zeros unless they're set.
+    pub fn set_task_context(&mut self, context: TaskContext) { self.context = context; }
+
+    /// Adds a `GasProfile` to the results of the next tasks.
+    pub fn enable_gas_profiling(&mut self) { self.gas_profiling = true; }
+
+    /// Deploys the contract under an address derived from its code and the number of deployed contracts.
+    /// `constructor` is the signature of the constructor, e.g. `construct(uint)`.
+    pub fn deploy(&mut self, wasm: &[u8], constructor: &str, args: &[Token]) -> Result<ContractAddress, EnclaveError> {
+        let address = [&wasm.keccak256()[..], &(self.contracts.len() as u64).to_be_bytes()[..]].concat().keccak256();
+        self.deploy_at(address, wasm, constructor, args)?;
+        Ok(address)
+    }
+
+    /// Deploys the contract under the given address, replacing the contract which was there.
+    /// Returns the result of the constructor, its output is the deployed bytecode.
+    pub fn deploy_at(&mut self, address: ContractAddress, wasm: &[u8], constructor: &str, args: &[Token]) -> Result<RuntimeResult, EnclaveError> {
+        let (_, function_name) = get_types(constructor)?;
+        let state = ContractState::new_key_value(address);
+        let mut engine =
+            WasmEngine::new_deploy(wasm, self.gas_limit, ethabi::encode(args), state, function_name, state_key(address)?, &self.schedule)?;
+        self.prepare(&mut engine);
+        engine.deploy()?;
+        let result = engine.into_result()?;
+        let contract = DeployedContract { bytecode: result.result.clone(), state: result.updated_state.clone() };
+        self.contracts.insert(address, contract);
+        Ok(result)
+    }
+
+    /// Upgrades a deployed contract to new code, its `migrate` function runs against the existing state if it has one.
+    /// The current code has to approve the upgrade with its `authorize_upgrade` function, which gets the sender of the task context.
+    /// `migrate` is the signature of the migrate function, e.g. `migrate(uint)`, the output of the result is the new bytecode.
+    pub fn upgrade(&mut self, address: ContractAddress, wasm: &[u8], migrate: &str, args: &[Token]) -> Result<RuntimeResult, EnclaveError> {
+        let (_, function_name) = get_types(migrate)?;
+        let contract = self.contracts.get(&address).ok_or_else(|| missing_contract(address))?;
+        let (bytecode, state) = (contract.bytecode.clone(), contract.state.clone());
+        let mut engine =
+            WasmEngine::new_upgrade(wasm, self.gas_limit, ethabi::encode(args), state, function_name, state_key(address)?, &self.schedule)?;
+        self.prepare(&mut engine);
+        engine.upgrade(&bytecode)?;
+        let result = engine.into_result()?;
+        let contract = DeployedContract { bytecode: result.result.clone(), state: result.updated_state.clone() };
+        self.contracts.insert(address, contract);
+        Ok(result)
+    }
+
+    /// Runs a function of a deployed contract and keeps the changes of its state and of the states of the contracts it called.
+    /// `function` is the signature of the function, e.g. `addition(uint256,uint256)`.
+    pub fn compute(&mut self, address: ContractAddress, function: &str, args: &[Token]) -> Result<RuntimeResult, EnclaveError> {
+        let result = self.run(address, function, args, false)?;
+        if result.state_delta.is_some() {
+            self.update_state(result.updated_state.clone());
+        }
+        for called in &result.called_contracts {
+            self.update_state(called.updated_state.clone());
+        }
+        Ok(result)
+    }
+
+    /// Runs a function of a deployed contract like a `QueryTask`, writing to the state fails.
+    pub fn query(&self, address: ContractAddress, function: &str, args: &[Token]) -> Result<RuntimeResult, EnclaveError> {
+        self.run(address, function, args, true)
+    }
+
+    /// The current state of a deployed contract.
+    pub fn state(&self, address: &ContractAddress) -> Option<&ContractState> {
+        self.contracts.get(address).map(|contract| &contract.state)
+    }
+
+    fn run(&self, address: ContractAddress, function: &str, args: &[Token], read_only: bool) -> Result<RuntimeResult, EnclaveError> {
+        let (_, function_name) = get_types(function)?;
+        let contract = self.contracts.get(&address).ok_or_else(|| missing_contract(address))?;
+        let (bytecode, state, key, args) = (&contract.bytecode, contract.state.clone(), state_key(address)?, ethabi::encode(args));
+        let mut engine = if read_only {
+            WasmEngine::new_query(bytecode, self.gas_limit, args, state, function_name, key, &self.schedule)?
+        } else {
+            WasmEngine::new_compute(bytecode, self.gas_limit, args, state, function_name, key, &self.schedule)?
+        };
+        self.prepare(&mut engine);
+        let db_ptr = unsafe { RawPointer::new(&self.contracts) };
+        if !read_only {
+            let context = CallContext { db_ptr: &db_ptr, get_state_key: state_key, get_contract, get_state, call_stack: vec![address] };
+            engine.runtime.set_call_context(context);
+        }
+        engine.compute()?;
+        engine.into_result()
+    }
+
+    fn prepare(&self, engine: &mut WasmEngine) {
+        engine.runtime.set_rng_seed(self.seed);
+        engine.runtime.set_task_context(self.context);
+        if self.gas_profiling {
+            engine.enable_gas_profiling();
+        }
+    }
+
+    fn update_state(&mut self, state: ContractState) {
+        if let Some(contract) = self.contracts.get_mut(&state.contract_address) {
+            contract.state = state;
+        }
+    }
+}
+
+/// Builds the contract in `path` like the examples in `examples/eng_wasm_contracts` are built,
+/// the crate should be named `contract` and target `wasm32-unknown-unknown` in its `.cargo/config`.
+pub fn build_contract<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    build_contract_with_features(path, &[])
+}
+
+/// Like `build_contract`, with cargo features of the contract enabled, e.g. to build another variant of it.
+/// Every variant is built into its own target directory, so the tests that build it at the same time don't mix them up.
+pub fn build_contract_with_features<P: AsRef<Path>>(path: P, features: &[&str]) -> io::Result<Vec<u8>> {
+    let path = path.as_ref();
+    let target_dir = match features {
+        [] => path.join("target"),
+        _ => path.join("target").join(features.join("-")),
+    };
+    let mut command = Command::new("cargo");
+    command.current_dir(path).args(&["build", "--release"]).arg("--target-dir").arg(&target_dir);
+    if !features.is_empty() {
+        command.arg("--features").arg(features.join(" "));
+    }
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("Failed compiling the contract in {:?}: {}", path, status)));
+    }
+    fs::read(target_dir.join("wasm32-unknown-unknown/release/contract.wasm"))
+}
+
+// the states are encrypted like in the enclave, with a key that only depends on the address.
+fn state_key(address: ContractAddress) -> Result<StateKey, EnclaveError> {
+    Ok(*address.keccak256())
+}
+
+fn missing_contract(address: ContractAddress) -> EnclaveError {
+    SystemError(StateError { err: format!("There's no contract deployed at {:?}", address) })
+}
+
+fn contracts<'a>(db_ptr: *const RawPointer) -> &'a Contracts {
+    unsafe { (*db_ptr).get_ref::<Contracts>() }
+}
+
+fn get_contract(db_ptr: *const RawPointer, address: ContractAddress) -> Result<Vec<u8>, EnclaveError> {
+    contracts(db_ptr).get(&address).map(|contract| contract.bytecode.clone()).ok_or_else(|| missing_contract(address))
+}
+
+fn get_state(db_ptr: *const RawPointer, address: ContractAddress) -> Result<EncryptedContractState<u8>, EnclaveError> {
+    let contract = contracts(db_ptr).get(&address).ok_or_else(|| missing_contract(address))?;
+    contract.state.clone().encrypt(&state_key(address)?)
+}
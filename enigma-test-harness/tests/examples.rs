@@ -0,0 +1,369 @@
+use enigma_crypto::hash::Keccak256;
+use enigma_runtime_t::data::IOInterface;
+use enigma_runtime_t::MAX_CALL_DEPTH;
+use enigma_test_harness::{build_contract, build_contract_with_features, ethabi, ContractAddress, EnclaveError, FailedTaskError, Harness, TaskContext, Token};
+
+const SIMPLEST: &str = "../examples/eng_wasm_contracts/simplest";
+const ERC20: &str = "../examples/eng_wasm_contracts/erc20";
+const ETH_CALLS: &str = "../examples/eng_wasm_contracts/contract_with_eth_calls";
+const CONTRACT_CALLS: &str = "../examples/eng_wasm_contracts/contract_calls";
+
+fn deploy_simplest(harness: &mut Harness) -> ContractAddress {
+    let wasm = build_contract(SIMPLEST).unwrap();
+    harness.deploy(&wasm, "construct(uint)", &[Token::Uint(1.into())]).unwrap()
+}
+
+// deploys `count` instances of `contract_calls` at the addresses [1; 32], [2; 32]..., returns their addresses and their bytecode.
+fn deploy_contract_calls(harness: &mut Harness, count: u8) -> (Vec<ContractAddress>, Vec<u8>) {
+    let wasm = build_contract(CONTRACT_CALLS).unwrap();
+    let (mut addresses, mut bytecode) = (Vec::new(), Vec::new());
+    for i in 1..=count {
+        let address = [i; 32].into();
+        bytecode = harness.deploy_at(address, &wasm, "construct()", &[]).unwrap().result;
+        addresses.push(address);
+    }
+    (addresses, bytecode)
+}
+
+fn call_chain_args(callees: &[ContractAddress]) -> [Token; 1] {
+    [Token::Array(callees.iter().map(|callee| Token::FixedBytes(callee.to_vec())).collect())]
+}
+
+#[test]
+fn test_compute_addition() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+
+    let args = [Token::Uint(100.into()), Token::Uint(23.into())];
+    let result = harness.compute(address, "addition(uint256,uint256)", &args).unwrap();
+    let output = ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap();
+    assert_eq!(output, vec![Token::Uint(123.into())]);
+    assert!(result.used_gas > 0);
+
+    let sum: u64 = harness.state(&address).unwrap().read_key("curr_sum").unwrap();
+    assert_eq!(sum, 123);
+}
+
+#[test]
+fn test_rand_is_seeded() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+
+    harness.set_seed(7);
+    let first = harness.compute(address, "choose_rand_color()", &[]).unwrap();
+    let second = harness.compute(address, "choose_rand_color()", &[]).unwrap();
+    assert_eq!(first.result, second.result);
+}
+
+#[test]
+fn test_query_cant_write() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+
+    let args = [Token::Uint(1.into()), Token::Uint(2.into())];
+    match harness.query(address, "addition(uint256,uint256)", &args) {
+        Err(EnclaveError::FailedTaskErrorWithGas { .. }) => (),
+        res => panic!("expected the query to fail on writing to the state, got: {:?}", res),
+    }
+}
+
+#[test]
+fn test_gas_profile() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+
+    harness.enable_gas_profiling();
+    let args = [Token::Uint(1.into()), Token::Uint(2.into())];
+    let result = harness.compute(address, "addition(uint256,uint256)", &args).unwrap();
+    let profile = result.gas_profile.unwrap();
+    assert!(profile.instructions > 0);
+    assert!(profile.functions.contains_key("addition"));
+}
+
+#[test]
+fn test_upgrade_keeps_the_state() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+    let args = [Token::Uint(100.into()), Token::Uint(23.into())];
+    harness.compute(address, "addition(uint256,uint256)", &args).unwrap();
+
+    // `simplest` has no `migrate`, so upgrading to the same code only replaces the bytecode.
+    let wasm = build_contract(SIMPLEST).unwrap();
+    let result = harness.upgrade(address, &wasm, "migrate()", &[]).unwrap();
+    assert!(result.state_delta.is_none());
+    assert!(!result.result.is_empty());
+
+    let sum: u64 = harness.state(&address).unwrap().read_key("curr_sum").unwrap();
+    assert_eq!(sum, 123);
+    harness.compute(address, "addition(uint256,uint256)", &args).unwrap();
+}
+
+#[test]
+fn test_upgrade_needs_authorization() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+
+    // `simplest` only lets the sender that deployed it upgrade it.
+    harness.set_task_context(TaskContext { sender_pubkey: [2u8; 64], ..Default::default() });
+    let wasm = build_contract(SIMPLEST).unwrap();
+    match harness.upgrade(address, &wasm, "migrate()", &[]) {
+        Err(EnclaveError::FailedTaskErrorWithGas { used_gas, err: FailedTaskError::ContractError { message } }) => {
+            assert!(used_gas > 0);
+            assert_eq!(message, "only the owner can upgrade the contract");
+        }
+        res => panic!("expected the upgrade to be refused, got: {:?}", res),
+    }
+}
+
+#[test]
+fn test_task_context() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+    harness.set_task_context(TaskContext { block_number: 4_500_000, epoch_nonce: [1u8; 32], sender_pubkey: [2u8; 64] });
+
+    let result = harness.compute(address, "get_block_number()", &[]).unwrap();
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap(), vec![Token::Uint(4_500_000.into())]);
+    let result = harness.compute(address, "get_self_address()", &[]).unwrap();
+    assert_eq!(result.result, address.to_vec());
+    let result = harness.compute(address, "get_sender_pubkey()", &[]).unwrap();
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Bytes], &result.result).unwrap(), vec![Token::Bytes(vec![2u8; 64])]);
+}
+
+#[test]
+fn test_contract_error() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+
+    let result = harness.compute(address, "subtraction(uint256,uint256)", &[Token::Uint(5.into()), Token::Uint(2.into())]).unwrap();
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap(), vec![Token::Uint(3.into())]);
+    match harness.compute(address, "subtraction(uint256,uint256)", &[Token::Uint(2.into()), Token::Uint(5.into())]) {
+        Err(EnclaveError::FailedTaskErrorWithGas { used_gas, err: FailedTaskError::ContractError { message } }) => {
+            assert!(used_gas > 0);
+            assert_eq!(message, "the result would be negative");
+        }
+        res => panic!("expected the contract to return an error, got: {:?}", res),
+    }
+    match harness.compute(address, "no_such_function()", &[]) {
+        Err(EnclaveError::FailedTaskErrorWithGas { err: FailedTaskError::ContractError { message }, .. }) => {
+            assert_eq!(message, "Unknown method called:\"no_such_function\"");
+        }
+        res => panic!("expected an unknown method error, got: {:?}", res),
+    }
+}
+
+#[test]
+fn test_storage_vec() {
+    let mut harness = Harness::new();
+    let address = deploy_simplest(&mut harness);
+
+    harness.compute(address, "push_history(uint256)", &[Token::Uint(7.into())]).unwrap();
+    let result = harness.compute(address, "push_history(uint256)", &[Token::Uint(9.into())]).unwrap();
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap(), vec![Token::Uint(2.into())]);
+
+    let result = harness.query(address, "get_history()", &[]).unwrap();
+    let history = ethabi::decode(&[ethabi::ParamType::Array(Box::new(ethabi::ParamType::Uint(256)))], &result.result).unwrap();
+    assert_eq!(history, vec![Token::Array(vec![Token::Uint(7.into()), Token::Uint(9.into())])]);
+
+    // every element is kept under its own key
+    let state = harness.state(&address).unwrap();
+    assert_eq!(state.read_key::<u64>("history:len").unwrap(), 2);
+}
+
+#[test]
+fn test_erc20_binary_balances() {
+    let owner = [7u8; 32];
+    let supply = 1_000_000u64;
+    let address = [1u8; 32].into();
+    let balance_key = format!("balances:\"0x{}\"", owner.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    let deploy = |wasm: &[u8]| {
+        let mut harness = Harness::new();
+        harness.enable_gas_profiling();
+        let args = [Token::FixedBytes(owner.to_vec()), Token::Uint(supply.into())];
+        let result = harness.deploy_at(address, wasm, "construct(bytes32,uint256)", &args).unwrap();
+        (harness.state(&address).unwrap().read_raw(&balance_key), result.gas_profile.unwrap().state_writes)
+    };
+
+    // the same deployment writes the balance of the owner once in binary and once in JSON.
+    let (binary_balance, binary_gas) = deploy(&build_contract(ERC20).unwrap());
+    let (json_balance, json_gas) = deploy(&build_contract_with_features(ERC20, &["json_balances"]).unwrap());
+    // the binary balance is the tag, the number of significant bytes and the bytes of 0x0f4240.
+    assert_eq!(binary_balance, vec![0, 3, 0x0f, 0x42, 0x40]);
+    assert_eq!(json_balance, format!("\"{:#x}\"", supply).into_bytes());
+
+    // the other writes are the same, and every stored byte costs `write_additional_byte`.
+    assert!(binary_gas < json_gas);
+    assert_eq!(json_gas - binary_gas, (json_balance.len() - binary_balance.len()) as u64);
+}
+
+#[test]
+fn test_decode_eth_event() {
+    let mut harness = Harness::new();
+    let wasm = build_contract(ETH_CALLS).unwrap();
+    let address = harness.deploy(&wasm, "construct()", &[]).unwrap();
+
+    let mut sender = [0u8; 32];
+    sender[12..].copy_from_slice(&[5u8; 20]);
+    let signature = b"Recorded(address,uint256)".keccak256();
+    let data = ethabi::encode(&[Token::Uint(42.into())]);
+    let log = |topics: &[[u8; 32]], data: &[u8]| {
+        [Token::Array(topics.iter().map(|topic| Token::FixedBytes(topic.to_vec())).collect()), Token::Bytes(data.to_vec())]
+    };
+
+    let result = harness.compute(address, "recorded_sum(bytes32[],bytes)", &log(&[*signature, sender], &data[..])).unwrap();
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap(), vec![Token::Uint(42.into())]);
+
+    // the log of another event.
+    match harness.compute(address, "recorded_sum(bytes32[],bytes)", &log(&[[1u8; 32], sender], &data[..])) {
+        Err(EnclaveError::FailedTaskErrorWithGas { err: FailedTaskError::ContractError { message }, .. }) => {
+            assert_eq!(message, "the topics don't match the event");
+        }
+        res => panic!("expected the log to be rejected, got: {:?}", res),
+    }
+    // the data is truncated.
+    match harness.compute(address, "recorded_sum(bytes32[],bytes)", &log(&[*signature, sender], &data[..31])) {
+        Err(EnclaveError::FailedTaskErrorWithGas { err: FailedTaskError::ContractError { message }, .. }) => {
+            assert_eq!(message, "the data isn't a valid ABI encoding");
+        }
+        res => panic!("expected the data to be rejected, got: {:?}", res),
+    }
+}
+
+#[test]
+fn test_callee_delta() {
+    let mut harness = Harness::new();
+    let (addresses, bytecode) = deploy_contract_calls(&mut harness, 2);
+    let (caller, callee) = (addresses[0], addresses[1]);
+    let pre_delta_hash = harness.state(&callee).unwrap().delta_hash;
+
+    let args = [Token::FixedBytes(callee.to_vec()), Token::Uint(5.into())];
+    let result = harness.compute(caller, "call_increment(bytes32,uint256)", &args).unwrap();
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap(), vec![Token::Uint(5.into())]);
+    // only the callee changed its state, its delta follows the delta of its deployment.
+    assert!(result.state_delta.is_none());
+    assert_eq!(result.called_contracts.len(), 1);
+    let called = &result.called_contracts[0];
+    assert_eq!(called.updated_state.contract_address, callee);
+    assert_eq!(called.exe_code_hash, bytecode.keccak256());
+    assert_eq!(called.pre_delta_hash, pre_delta_hash);
+    assert_eq!(called.state_delta.as_ref().unwrap().index, 1);
+
+    let count: u64 = harness.state(&callee).unwrap().read_key("count").unwrap();
+    assert_eq!(count, 5);
+    let result = harness.compute(caller, "call_increment(bytes32,uint256)", &args).unwrap();
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap(), vec![Token::Uint(10.into())]);
+}
+
+#[test]
+fn test_read_only_callee() {
+    let mut harness = Harness::new();
+    let (addresses, bytecode) = deploy_contract_calls(&mut harness, 2);
+    let (caller, callee) = (addresses[0], addresses[1]);
+
+    // a callee that didn't change its state is still recorded with the code and the state it ran with.
+    let result = harness.compute(caller, "call_get_count(bytes32)", &[Token::FixedBytes(callee.to_vec())]).unwrap();
+    assert_eq!(result.called_contracts.len(), 1);
+    let called = &result.called_contracts[0];
+    assert!(called.state_delta.is_none());
+    assert_eq!(called.exe_code_hash, bytecode.keccak256());
+    assert_eq!(called.pre_delta_hash, harness.state(&callee).unwrap().delta_hash);
+}
+
+#[test]
+fn test_repeated_calls_merge_deltas() {
+    let mut harness = Harness::new();
+    let (addresses, _) = deploy_contract_calls(&mut harness, 2);
+    let (caller, callee) = (addresses[0], addresses[1]);
+    let pre_delta_hash = harness.state(&callee).unwrap().delta_hash;
+
+    let args = [Token::FixedBytes(callee.to_vec()), Token::Uint(5.into())];
+    let result = harness.compute(caller, "call_increment_twice(bytes32,uint256)", &args).unwrap();
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap(), vec![Token::Uint(10.into())]);
+    // both calls are in one delta from the state before the task.
+    assert_eq!(result.called_contracts.len(), 1);
+    let called = &result.called_contracts[0];
+    assert_eq!(called.pre_delta_hash, pre_delta_hash);
+    assert_eq!(called.state_delta.as_ref().unwrap().index, 1);
+    let count: u64 = harness.state(&callee).unwrap().read_key("count").unwrap();
+    assert_eq!(count, 10);
+}
+
+#[test]
+fn test_call_depth_and_reentrancy() {
+    let mut harness = Harness::new();
+    let (addresses, _) = deploy_contract_calls(&mut harness, MAX_CALL_DEPTH as u8 + 1);
+
+    // the first contract and the ones it calls are all running at the deepest call.
+    let result = harness.compute(addresses[0], "call_chain(bytes32[])", &call_chain_args(&addresses[1..MAX_CALL_DEPTH])).unwrap();
+    let calls = Token::Uint((MAX_CALL_DEPTH as u64 - 1).into());
+    assert_eq!(ethabi::decode(&[ethabi::ParamType::Uint(256)], &result.result).unwrap(), vec![calls]);
+
+    match harness.compute(addresses[0], "call_chain(bytes32[])", &call_chain_args(&addresses[1..])) {
+        Err(EnclaveError::FailedTaskErrorWithGas { err: FailedTaskError::WasmCodeExecutionError { err }, .. }) => {
+            assert_eq!(err, format!("the maximum call depth is {}", MAX_CALL_DEPTH));
+        }
+        res => panic!("expected the call to be too deep, got: {:?}", res),
+    }
+    match harness.compute(addresses[0], "call_chain(bytes32[])", &call_chain_args(&[addresses[1], addresses[0]])) {
+        Err(EnclaveError::FailedTaskErrorWithGas { err: FailedTaskError::WasmCodeExecutionError { err }, .. }) => {
+            assert_eq!(err, "a contract can't be called while it's running");
+        }
+        res => panic!("expected the re-entrant call to be rejected, got: {:?}", res),
+    }
+}
+
+#[test]
+fn test_callee_gas_is_charged() {
+    let mut harness = Harness::new();
+    let (addresses, _) = deploy_contract_calls(&mut harness, 3);
+    harness.enable_gas_profiling();
+
+    // the callee and the twin it's compared to are in the same state.
+    let args = [Token::Uint(5.into())];
+    let callee_gas = harness.compute(addresses[2], "increment(uint256)", &args).unwrap().used_gas;
+    let args = [Token::FixedBytes(addresses[1].to_vec()), Token::Uint(5.into())];
+    let result = harness.compute(addresses[0], "call_increment(bytes32,uint256)", &args).unwrap();
+    assert_eq!(result.gas_profile.unwrap().host_calls["call_contract"], callee_gas);
+    assert!(result.used_gas > callee_gas);
+}
+
+#[test]
+fn test_failed_callee() {
+    let mut harness = Harness::new();
+    let (addresses, _) = deploy_contract_calls(&mut harness, 2);
+
+    let callee_gas = match harness.compute(addresses[1], "fail()", &[]) {
+        Err(EnclaveError::FailedTaskErrorWithGas { used_gas, .. }) => used_gas,
+        res => panic!("expected the callee to fail, got: {:?}", res),
+    };
+    // the task fails with the error of the callee, and the gas the callee used is charged.
+    match harness.compute(addresses[0], "call_fail(bytes32)", &[Token::FixedBytes(addresses[1].to_vec())]) {
+        Err(EnclaveError::FailedTaskErrorWithGas { used_gas, err: FailedTaskError::ContractError { message } }) => {
+            assert_eq!(message, "the callee failed");
+            assert!(used_gas > callee_gas);
+        }
+        res => panic!("expected the callee to fail the task, got: {:?}", res),
+    }
+}
+
+#[test]
+fn test_events_and_bridges_order() {
+    let mut harness = Harness::new();
+    let (addresses, _) = deploy_contract_calls(&mut harness, 2);
+    let (caller, callee) = (addresses[0], addresses[1]);
+    let eth_addr = [5u8; 20];
+
+    // the events and the payloads of the callee are between the ones the caller wrote before and after the call.
+    let args = [Token::FixedBytes(callee.to_vec()), Token::Address(eth_addr.into())];
+    let result = harness.compute(caller, "call_record(bytes32,address)", &args).unwrap();
+    let events: Vec<_> = result.events.iter().map(|event| (event.contract_address, &event.topic[..], &event.data[..])).collect();
+    assert_eq!(events, vec![(caller, &b"before"[..], &b"null"[..]), (callee, &b"recorded"[..], &b"7"[..]), (caller, &b"after"[..], &b"null"[..])]);
+    let payloads: Vec<_> = result.ethereum_bridges.iter().map(|bridge| bridge.ethereum_payload.clone()).collect();
+    assert_eq!(payloads, vec![b"before".to_vec(), ethabi::encode(&[Token::Uint(7.into())]), b"after".to_vec()]);
+    assert!(result.ethereum_bridges.iter().all(|bridge| bridge.ethereum_contract_addr == eth_addr));
+
+    // a query can't emit events.
+    match harness.query(callee, "record(uint256,address)", &[Token::Uint(7.into()), Token::Address(eth_addr.into())]) {
+        Err(EnclaveError::FailedTaskErrorWithGas { .. }) => (),
+        res => panic!("expected the query to fail on emitting an event, got: {:?}", res),
+    }
+}
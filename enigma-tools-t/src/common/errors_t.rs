@@ -2,6 +2,7 @@ use enigma_types::{EnclaveReturn, ResultToEnclaveReturn};
 use enigma_tools_m::ToolsError;
 use json_patch;
 use pwasm_utils as wasm_utils;
+#[cfg(feature = "sgx")]
 use sgx_types::sgx_status_t;
 use enigma_crypto::CryptoError;
 use std::str;
@@ -141,6 +142,9 @@ pub enum FailedTaskError {
 
     #[fail(display = "Invocation resulted in gas limit violated")]
     GasLimitError,
+
+    #[fail(display = "The contract returned an error: {}", message)]
+    ContractError { message: String },
 }
 
 #[derive(Debug, Fail, Clone)]
@@ -168,6 +172,9 @@ pub enum EnclaveSystemError {
 
     #[fail(display = "Failed to provide state key: {}", err)]
     KeyProvisionError { err: String },
+
+    #[fail(display = "Invalid gas schedule: {}", err)]
+    GasScheduleError { err: String },
 }
 
 impl From<CryptoError> for EnclaveError {
@@ -184,6 +191,7 @@ impl From<ToolsError> for EnclaveError {
     }
 }
 
+#[cfg(feature = "sgx")]
 impl From<sgx_status_t> for EnclaveError {
     fn from(err: sgx_status_t) -> EnclaveError {
         EnclaveError::SystemError(EnclaveSystemError::SgxError { err: err.as_str().to_string(), description: err.__description().to_string() })
@@ -250,6 +258,7 @@ impl Into<EnclaveReturn> for EnclaveError {
                     }
                     WorkerAuthError { .. } => EnclaveReturn::WorkerAuthError,
                     KeyProvisionError { .. } => EnclaveReturn::KeyProvisionError,
+                    GasScheduleError { .. } => EnclaveReturn::Other,
                  }
 
              }
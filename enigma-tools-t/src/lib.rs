@@ -1,26 +1,51 @@
-#![no_std]
+#![cfg_attr(feature = "sgx", no_std)]
 #![crate_type = "lib"]
 #![warn(unused_extern_crates)]
 
+#[cfg(all(feature = "sgx", feature = "std"))]
+compile_error!("the `sgx` and `std` features are mutually exclusive, enable only one of them");
+
 extern crate enigma_types;
 extern crate enigma_crypto;
 extern crate enigma_tools_m;
 
+#[cfg(feature = "sgx")]
 #[macro_use]
 extern crate sgx_tstd as std;
 #[macro_use]
 extern crate failure;
-extern crate json_patch;
-extern crate parity_wasm;
-extern crate pwasm_utils;
+#[cfg(feature = "sgx")]
 extern crate sgx_tse;
+#[cfg(feature = "sgx")]
 extern crate sgx_tseal;
+#[cfg(feature = "sgx")]
 extern crate sgx_types;
 
+#[cfg(feature = "sgx")]
+extern crate json_patch_sgx as json_patch;
+#[cfg(feature = "sgx")]
+extern crate parity_wasm_sgx as parity_wasm;
+#[cfg(feature = "sgx")]
+extern crate pwasm_utils_sgx as pwasm_utils;
+#[cfg(feature = "sgx")]
+extern crate rmp_serde_sgx as rmp_serde;
+#[cfg(feature = "sgx")]
+extern crate wasmi_sgx as wasmi;
+
+#[cfg(feature = "std")]
+extern crate json_patch_std as json_patch;
+#[cfg(feature = "std")]
+extern crate parity_wasm_std as parity_wasm;
+#[cfg(feature = "std")]
+extern crate pwasm_utils_std as pwasm_utils;
+#[cfg(feature = "std")]
+extern crate rmp_serde_std as rmp_serde;
+#[cfg(feature = "std")]
+extern crate wasmi_std as wasmi;
+
 extern crate bigint;
 extern crate hexutil;
 extern crate rlp;
-extern crate wasmi;
 extern crate rustc_hex;
 
 #[macro_use]
@@ -28,9 +53,13 @@ pub mod macros;
 
 pub mod build_arguments_g;
 pub mod common;
+#[cfg(feature = "sgx")]
 pub mod quote_t;
+#[cfg(feature = "sgx")]
 pub mod document_storage_t; //TODO: Copy of storage_t with more generic naming convention
+#[cfg(feature = "sgx")]
 pub mod storage_t;
+#[cfg(feature = "sgx")]
 pub mod esgx;
 
 
@@ -25,6 +25,12 @@ pub struct Web3Error {
     pub message: String,
 }
 
+#[derive(Fail, Debug)]
+#[fail(display = "Error in the interface of the contract = ({})", message)]
+pub struct ContractInterfaceErr {
+    pub message: String,
+}
+
 #[derive(Fail, Debug)]
 #[fail(display = "SGX Ecall Failed function: {}, status: {}", function, status)]
 pub struct SgxError {
@@ -0,0 +1,235 @@
+//! The interface of a secret contract, as `#[pub_interface]` embeds it in the `enigma_interface` custom section.
+//! It's used to check the callable and the arguments of a task before they're encrypted and sent,
+//! so a typo in the callable or a wrongly encoded argument fails on the client and not in the enclave.
+
+use ethabi::param_type::Reader;
+use ethabi::ParamType;
+use failure::Error;
+use serde_json;
+
+use super::wasm_sections::custom_section;
+use common_u::errors::ContractInterfaceErr;
+
+/// The name of the custom wasm section the interface is embedded in.
+pub const INTERFACE_SECTION: &str = "enigma_interface";
+
+// the interface lists them like the other functions, but only deployments and upgrades run them.
+const RESERVED_FUNCTIONS: &[&str] = &["construct", "migrate", "authorize_upgrade"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractInterface {
+    pub constructor: Option<String>,
+    pub functions: Vec<InterfaceFunction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InterfaceFunction {
+    pub name: String,
+    pub inputs: Vec<InterfaceParam>,
+    pub outputs: Vec<InterfaceParam>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InterfaceParam {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+impl ContractInterface {
+    /// Extracts the interface from a compiled contract.
+    pub fn from_wasm(wasm: &[u8]) -> Result<Self, Error> {
+        let section = custom_section(wasm, INTERFACE_SECTION)?
+            .ok_or_else(|| interface_err(format!("The contract has no `{}` section", INTERFACE_SECTION)))?;
+        Ok(serde_json::from_slice(section)?)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&InterfaceFunction> { self.functions.iter().find(|f| f.name == name) }
+
+    /// Checks that a callable (e.g. `transfer(bytes32,uint256)`) is a function of the contract that a compute task can call,
+    /// and that `args` are its arguments ABI encoded.
+    pub fn validate(&self, callable: &str, args: &[u8]) -> Result<&InterfaceFunction, Error> {
+        let (name, types) = parse_callable(callable)?;
+        if RESERVED_FUNCTIONS.contains(&name.as_str()) {
+            return Err(interface_err(format!("`{}` can't be called by a compute task", name)));
+        }
+        self.validate_function(&name, &types, args)
+    }
+
+    /// Same as `validate`, for the callable of a `DeployTask`, which should be the constructor.
+    /// A contract without a constructor can only be deployed without arguments.
+    pub fn validate_deploy(&self, callable: &str, args: &[u8]) -> Result<Option<&InterfaceFunction>, Error> {
+        match self.constructor {
+            Some(ref constructor) => {
+                let (name, types) = parse_callable(callable)?;
+                if &name != constructor {
+                    return Err(interface_err(format!("The constructor is `{}`, not `{}`", constructor, name)));
+                }
+                self.validate_function(&name, &types, args).map(Some)
+            }
+            None if args.is_empty() => Ok(None),
+            None => Err(interface_err("The contract has no constructor, it can't be deployed with arguments".to_string())),
+        }
+    }
+
+    fn validate_function(&self, name: &str, types: &[ParamType], args: &[u8]) -> Result<&InterfaceFunction, Error> {
+        let function = self.function(name).ok_or_else(|| interface_err(format!("The contract has no function `{}`", name)))?;
+        function.validate_types(types)?;
+        validate_args(types, args)?;
+        Ok(function)
+    }
+}
+
+impl InterfaceFunction {
+    /// The types of the inputs, as `ethabi` reads them.
+    pub fn input_types(&self) -> Result<Vec<ParamType>, Error> {
+        self.inputs.iter().map(|input| read_type(&input.kind)).collect()
+    }
+
+    /// The types of the outputs, so the results of the task can be decoded.
+    pub fn output_types(&self) -> Result<Vec<ParamType>, Error> {
+        self.outputs.iter().map(|output| read_type(&output.kind)).collect()
+    }
+
+    fn validate_types(&self, types: &[ParamType]) -> Result<(), Error> {
+        let expected = self.input_types()?;
+        if expected.as_slice() != types {
+            return Err(interface_err(format!(
+                "`{}` takes ({}), but the callable has ({})",
+                self.name,
+                join_types(&expected),
+                join_types(types)
+            )));
+        }
+        Ok(())
+    }
+}
+
+// splits `name(type1,type2)` into the name and the types, aliases like `uint` are read as `uint256`.
+fn parse_callable(callable: &str) -> Result<(String, Vec<ParamType>), Error> {
+    let callable = callable.trim();
+    let open = callable.find('(');
+    let (name, types) = match open {
+        Some(open) if callable.ends_with(')') => (&callable[..open], &callable[open + 1..callable.len() - 1]),
+        _ => return Err(interface_err(format!("The callable `{}` isn't of the form `name(type1,type2)`", callable))),
+    };
+    let types = match types.trim() {
+        "" => Vec::new(),
+        types => types.split(',').map(|t| read_type(t.trim())).collect::<Result<_, _>>()?,
+    };
+    Ok((name.trim().to_string(), types))
+}
+
+fn validate_args(types: &[ParamType], args: &[u8]) -> Result<(), Error> {
+    let tokens = ethabi::decode(types, args)
+        .map_err(|e| interface_err(format!("The arguments don't decode as ({}): {}", join_types(types), e)))?;
+    // decoding ignores trailing bytes, only arguments that encode back the same are exactly the expected ones.
+    if ethabi::encode(&tokens) != args {
+        return Err(interface_err(format!("The arguments aren't exactly ABI encoded ({})", join_types(types))));
+    }
+    Ok(())
+}
+
+fn read_type(kind: &str) -> Result<ParamType, Error> {
+    Reader::read(kind).map_err(|e| interface_err(format!("`{}` isn't an ABI type: {}", kind, e)))
+}
+
+fn join_types(types: &[ParamType]) -> String { types.iter().map(ToString::to_string).collect::<Vec<_>>().join(",") }
+
+fn interface_err(message: String) -> Error { ContractInterfaceErr { message }.into() }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethabi::Token;
+
+    const INTERFACE: &str = r#"{"constructor":"construct","functions":[
+        {"name":"construct","inputs":[{"name":"owner","type":"bytes32"}],"outputs":[]},
+        {"name":"transfer","inputs":[{"name":"to","type":"bytes32"},{"name":"amount","type":"uint256"}],"outputs":[]},
+        {"name":"balance_of","inputs":[{"name":"owner","type":"bytes32"}],"outputs":[{"type":"uint256"}]}
+    ]}"#;
+
+    // a module with only a type section and the interface section.
+    fn wasm_with_interface(interface: &str) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend_from_slice(&[1, 4, 1, 0x60, 0, 0]);
+        let mut section = vec![INTERFACE_SECTION.len() as u8];
+        section.extend_from_slice(INTERFACE_SECTION.as_bytes());
+        section.extend_from_slice(interface.as_bytes());
+        wasm.push(0);
+        let mut size = section.len();
+        while size >= 0x80 {
+            wasm.push((size as u8 & 0x7f) | 0x80);
+            size >>= 7;
+        }
+        wasm.push(size as u8);
+        wasm.extend(section);
+        wasm
+    }
+
+    fn interface() -> ContractInterface { ContractInterface::from_wasm(&wasm_with_interface(INTERFACE)).unwrap() }
+
+    #[test]
+    fn test_from_wasm() {
+        let interface = interface();
+        assert_eq!(interface.constructor, Some("construct".to_string()));
+        assert_eq!(interface.functions.len(), 3);
+        let balance_of = interface.function("balance_of").unwrap();
+        assert_eq!(balance_of.output_types().unwrap(), vec![ParamType::Uint(256)]);
+    }
+
+    #[test]
+    fn test_from_wasm_without_interface() {
+        let wasm = b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0".to_vec();
+        assert!(ContractInterface::from_wasm(&wasm).is_err());
+        assert!(ContractInterface::from_wasm(b"not wasm").is_err());
+        let mut truncated = wasm_with_interface(INTERFACE);
+        truncated.truncate(30);
+        assert!(ContractInterface::from_wasm(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let interface = interface();
+        let args = ethabi::encode(&[Token::FixedBytes(vec![1; 32]), Token::Uint(5.into())]);
+        assert_eq!(interface.validate("transfer(bytes32,uint256)", &args).unwrap().name, "transfer");
+        assert_eq!(interface.validate("transfer(bytes32, uint)", &args).unwrap().name, "transfer");
+    }
+
+    #[test]
+    fn test_validate_wrong_callable() {
+        let interface = interface();
+        let args = ethabi::encode(&[Token::FixedBytes(vec![1; 32]), Token::Uint(5.into())]);
+        assert!(interface.validate("transfer", &args).is_err());
+        assert!(interface.validate("transfers(bytes32,uint256)", &args).is_err());
+        assert!(interface.validate("transfer(bytes32,uint64)", &args).is_err());
+        assert!(interface.validate("transfer(uint256)", &args).is_err());
+        // the constructor is in the interface, but only a deployment runs it.
+        let args = ethabi::encode(&[Token::FixedBytes(vec![1; 32])]);
+        assert!(interface.validate_deploy("construct(bytes32)", &args).is_ok());
+        assert!(interface.validate("construct(bytes32)", &args).is_err());
+    }
+
+    #[test]
+    fn test_validate_wrong_args() {
+        let interface = interface();
+        let args = ethabi::encode(&[Token::FixedBytes(vec![1; 32])]);
+        assert!(interface.validate("transfer(bytes32,uint256)", &args).is_err());
+        let mut args = ethabi::encode(&[Token::FixedBytes(vec![1; 32]), Token::Uint(5.into())]);
+        args.push(0);
+        assert!(interface.validate("transfer(bytes32,uint256)", &args).is_err());
+    }
+
+    #[test]
+    fn test_validate_deploy() {
+        let interface = interface();
+        let args = ethabi::encode(&[Token::FixedBytes(vec![1; 32])]);
+        assert!(interface.validate_deploy("construct(bytes32)", &args).unwrap().is_some());
+        assert!(interface.validate_deploy("balance_of(bytes32)", &args).is_err());
+
+        let interface = ContractInterface::from_wasm(&wasm_with_interface(r#"{"constructor":null,"functions":[]}"#)).unwrap();
+        assert!(interface.validate_deploy("construct()", &[]).unwrap().is_none());
+        assert!(interface.validate_deploy("construct(bytes32)", &args).is_err());
+    }
+}
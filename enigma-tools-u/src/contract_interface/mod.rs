@@ -0,0 +1,2 @@
+mod wasm_sections;
+pub mod interface;
@@ -0,0 +1,64 @@
+use failure::Error;
+
+use common_u::errors::ContractInterfaceErr;
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+const WASM_VERSION: &[u8] = &[1, 0, 0, 0];
+const CUSTOM_SECTION_ID: u8 = 0;
+
+/// Finds the content of the custom section with the given name in a wasm module.
+/// Only the section headers are read, so the code itself isn't validated.
+pub fn custom_section<'a>(wasm: &'a [u8], name: &str) -> Result<Option<&'a [u8]>, Error> {
+    if wasm.len() < 8 || &wasm[..4] != WASM_MAGIC || &wasm[4..8] != WASM_VERSION {
+        return Err(malformed("the module doesn't start with the wasm magic and version"));
+    }
+    let mut reader = Reader { bytes: wasm, position: 8 };
+    while !reader.is_empty() {
+        let id = reader.take(1)?[0];
+        let size = reader.read_leb128()? as usize;
+        let mut section = Reader { bytes: reader.take(size)?, position: 0 };
+        if id != CUSTOM_SECTION_ID {
+            continue;
+        }
+        let name_len = section.read_leb128()? as usize;
+        if section.take(name_len)? == name.as_bytes() {
+            return Ok(Some(section.rest()));
+        }
+    }
+    Ok(None)
+}
+
+fn malformed(message: &str) -> Error { ContractInterfaceErr { message: format!("Malformed wasm module, {}", message) }.into() }
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool { self.position >= self.bytes.len() }
+
+    fn rest(&self) -> &'a [u8] { &self.bytes[self.position..] }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() - self.position < len {
+            return Err(malformed("a section ends after the end of the module"));
+        }
+        let taken = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(taken)
+    }
+
+    // an unsigned LEB128 encoded integer, the sizes in wasm are at most 32 bits (5 bytes).
+    fn read_leb128(&mut self) -> Result<u32, Error> {
+        let mut result = 0u32;
+        for i in 0..5 {
+            let byte = self.take(1)?[0];
+            result |= u32::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(malformed("an integer is longer than 32 bits"))
+    }
+}
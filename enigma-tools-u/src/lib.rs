@@ -32,6 +32,7 @@ extern crate gethostname;
 
 pub mod attestation_service;
 pub mod common_u;
+pub mod contract_interface;
 pub mod esgx;
 pub mod web3_utils;
 
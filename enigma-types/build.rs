@@ -15,6 +15,8 @@ fn main() {
         .include_item("EnclaveReturn")
         .include_item("ResultStatus")
         .include_item("ExecuteResult")
+        .include_item("TaskContext")
+        .include_item("TaskOptions")
         .include_item("Hash256")
         .include_item("StateKey")
         .include_item("ContractAddress")
@@ -90,14 +90,44 @@ pub struct ExecuteResult {
     pub delta_ptr: *const u8,
     /// The delta index number.
     pub delta_index: u32,
-    /// A pointer to the Ethereum payload using [`ocall_save_to_memory`](../replace_me) (on the untrusted stack)
-    pub ethereum_payload_ptr: *const u8,
-    /// The ethereum address that the payload belongs to.
-    pub ethereum_address: [u8; 20],
+    /// A pointer to the Ethereum payloads using [`ocall_save_to_memory`](../replace_me) (on the untrusted stack).
+    /// Each payload is the ethereum address it belongs to (20 bytes), the payload length (u32 BE) and the payload.
+    pub ethereum_bridges_ptr: *const u8,
     /// A signature by the enclave on all of the results.
     pub signature: [u8; 65],
     /// The gas used by the execution.
     pub used_gas: u64,
+    /// A pointer to the deltas of the contracts called by the execution using [`ocall_save_to_memory`](../replace_me) (on the untrusted stack).
+    /// Each delta is the contract address (32 bytes), the delta index (u32 BE), the data length (u32 BE) and the data.
+    pub called_deltas_ptr: *const u8,
+    /// A pointer to the encrypted events emitted by the execution using [`ocall_save_to_memory`](../replace_me) (on the untrusted stack).
+    /// Each event is the contract address (32 bytes), the topic length (u32 BE), the topic, the data length (u32 BE) and the data.
+    pub events_ptr: *const u8,
+    /// A pointer to the gas profile of the execution as JSON using [`ocall_save_to_memory`](../replace_me) (on the untrusted stack).
+    /// It's null unless the task asked for it with `TaskOptions::gas_profiling` and the enclave was built in debug mode.
+    pub gas_profile_ptr: *const u8,
+}
+
+/// The context of the task a contract runs in, the contract can read it with the `eng_wasm` host functions.
+/// It's part of the inputs hash of the task, so the result is only valid for this context.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TaskContext {
+    /// The number of the Ethereum block the task was submitted in.
+    pub block_number: u64,
+    /// The nonce of the epoch the task belongs to.
+    pub epoch_nonce: [u8; 32],
+    /// The public key of the user who sent the task, the enclave always sets it to the DH key of the user.
+    pub sender_pubkey: PubKey,
+}
+
+/// Options of a task which only affect what is reported back, and not its result,
+/// so unlike the `TaskContext` they aren't part of the inputs hash.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaskOptions {
+    /// Report where the gas of the task went in `ExecuteResult::gas_profile_ptr`, it's ignored unless the enclave was built in debug mode.
+    pub gas_profiling: bool,
 }
 
 /// This struct is a wrapper to a raw pointer.
@@ -181,22 +211,43 @@ impl Default for ExecuteResult {
         ExecuteResult {
             output: ptr::null(),
             delta_ptr: ptr::null(),
-            ethereum_payload_ptr: ptr::null(),
+            ethereum_bridges_ptr: ptr::null(),
+            called_deltas_ptr: ptr::null(),
+            events_ptr: ptr::null(),
+            gas_profile_ptr: ptr::null(),
             .. unsafe { mem::zeroed() }
         }
     }
 }
 
+impl Default for TaskContext {
+    fn default() -> TaskContext {
+        TaskContext { block_number: 0, epoch_nonce: [0u8; 32], sender_pubkey: [0u8; 64] }
+    }
+}
+
+impl fmt::Debug for TaskContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug_trait_builder = f.debug_struct("TaskContext");
+        debug_trait_builder.field("block_number", &(self.block_number));
+        debug_trait_builder.field("epoch_nonce", &(self.epoch_nonce));
+        debug_trait_builder.field("sender_pubkey", &(&self.sender_pubkey[..]));
+        debug_trait_builder.finish()
+    }
+}
+
 impl fmt::Debug for ExecuteResult {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut debug_trait_builder = f.debug_struct("ExecuteResult");
         debug_trait_builder.field("output", &(self.output));
         debug_trait_builder.field("delta_ptr", &(self.delta_ptr));
         debug_trait_builder.field("delta_index", &(self.delta_index));
-        debug_trait_builder.field("ethereum_payload_ptr", &(self.ethereum_payload_ptr));
-        debug_trait_builder.field("ethereum_address", &(self.ethereum_address));
+        debug_trait_builder.field("ethereum_bridges_ptr", &(self.ethereum_bridges_ptr));
         debug_trait_builder.field("signature", &(&self.signature[..]));
         debug_trait_builder.field("used_gas", &(self.used_gas));
+        debug_trait_builder.field("called_deltas_ptr", &(self.called_deltas_ptr));
+        debug_trait_builder.field("events_ptr", &(self.events_ptr));
+        debug_trait_builder.field("gas_profile_ptr", &(self.gas_profile_ptr));
         debug_trait_builder.finish()
     }
 }
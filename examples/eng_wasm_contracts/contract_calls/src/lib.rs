@@ -0,0 +1,121 @@
+#![no_std]
+
+extern crate eng_wasm;
+extern crate eng_wasm_derive;
+
+use eng_wasm::*;
+use eng_wasm_derive::pub_interface;
+
+static COUNT: &str = "count";
+
+// Calls other instances of itself, the called functions get their arguments ABI encoded like the tasks do.
+#[pub_interface]
+pub trait ContractInterface{
+    fn construct();
+    /// adds the amount to the count and returns the new count
+    fn increment(amount: U256) -> U256;
+    fn get_count() -> U256;
+    fn fail() -> Result<(), String>;
+    /// emits the value as an event and writes it as an ethereum payload
+    fn record(value: U256, eth_addr: Address);
+    fn call_increment(callee: H256, amount: U256) -> U256;
+    /// increments the count of the callee twice in the same task
+    fn call_increment_twice(callee: H256, amount: U256) -> U256;
+    fn call_get_count(callee: H256) -> U256;
+    fn call_fail(callee: H256);
+    /// emits events and writes ethereum payloads before and after calling `record` of the callee
+    fn call_record(callee: H256, eth_addr: Address);
+    /// calls `call_chain` of the first callee with the rest of them, returns the number of calls
+    fn call_chain(callees: Vec<H256>) -> U256;
+}
+
+pub struct Contract;
+
+impl Contract {
+    fn uint_word(value: U256) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        value.to_big_endian(&mut word);
+        word
+    }
+
+    fn address_word(address: &Address) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[12..].copy_from_slice(&address[..]);
+        word
+    }
+
+    // a dynamic array is the offset of its data, its length and its elements.
+    fn encode_addresses(addresses: &[H256]) -> Vec<u8> {
+        let mut args = Vec::with_capacity(64 + 32 * addresses.len());
+        args.extend_from_slice(&Self::uint_word(U256::from(32u64)));
+        args.extend_from_slice(&Self::uint_word(U256::from(addresses.len() as u64)));
+        for address in addresses {
+            args.extend_from_slice(&address[..]);
+        }
+        args
+    }
+}
+
+impl ContractInterface for Contract {
+    fn construct() {
+        write_state!(COUNT => 0u64);
+    }
+
+    fn increment(amount: U256) -> U256 {
+        let count: u64 = read_state!(COUNT).unwrap_or_default();
+        let count = count + amount.as_u64();
+        write_state!(COUNT => count);
+        count.into()
+    }
+
+    fn get_count() -> U256 {
+        let count: u64 = read_state!(COUNT).unwrap_or_default();
+        count.into()
+    }
+
+    fn fail() -> Result<(), String> {
+        Err("the callee failed".to_string())
+    }
+
+    fn record(value: U256, eth_addr: Address) {
+        emit!("recorded", value.as_u64());
+        write_ethereum_bridge(&Self::uint_word(value), &eth_addr);
+    }
+
+    fn call_increment(callee: H256, amount: U256) -> U256 {
+        U256::from_big_endian(&call_contract(&callee, "increment", &Self::uint_word(amount)))
+    }
+
+    fn call_increment_twice(callee: H256, amount: U256) -> U256 {
+        call_contract(&callee, "increment", &Self::uint_word(amount));
+        U256::from_big_endian(&call_contract(&callee, "increment", &Self::uint_word(amount)))
+    }
+
+    fn call_get_count(callee: H256) -> U256 {
+        U256::from_big_endian(&call_contract(&callee, "get_count", &[]))
+    }
+
+    fn call_fail(callee: H256) {
+        call_contract(&callee, "fail", &[]);
+    }
+
+    fn call_record(callee: H256, eth_addr: Address) {
+        emit!("before");
+        write_ethereum_bridge(b"before", &eth_addr);
+        let mut args = Self::uint_word(U256::from(7u64)).to_vec();
+        args.extend_from_slice(&Self::address_word(&eth_addr));
+        call_contract(&callee, "record", &args);
+        emit!("after");
+        write_ethereum_bridge(b"after", &eth_addr);
+    }
+
+    fn call_chain(callees: Vec<H256>) -> U256 {
+        match callees.split_first() {
+            None => U256::from(0u64),
+            Some((next, rest)) => {
+                let calls = U256::from_big_endian(&call_contract(next, "call_chain", &Self::encode_addresses(rest)));
+                calls + U256::from(1u64)
+            }
+        }
+    }
+}
@@ -24,6 +24,7 @@ pub trait ContractInterface{
     fn construct();
     fn sum_and_call(x: U256, y: U256, eth_addr: H160) -> U256;
     fn get_last_sum() -> U256;
+    fn recorded_sum(topics: Vec<H256>, data: Vec<u8>) -> Result<U256, eth_abi::Error>;
 }
 
 pub struct Contract;
@@ -77,4 +78,10 @@ impl ContractInterface for Contract {
         let sum: u64 = read_state!("sum").unwrap_or_default();
         sum.into()
     }
+
+    /// Decodes the sum from a log of the `Recorded` event, which is passed to the contract as its topics and data.
+    fn recorded_sum(topics: Vec<H256>, data: Vec<u8>) -> Result<U256, eth_abi::Error> {
+        let (_sender, sum) = EthContract::decode_recorded_event(&topics, &data)?;
+        Ok(sum)
+    }
 }
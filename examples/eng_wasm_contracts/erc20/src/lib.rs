@@ -3,19 +3,15 @@
 extern crate eng_wasm;
 extern crate eng_wasm_derive;
 extern crate enigma_crypto;
-#[macro_use]
-extern crate serde;
-extern crate rustc_hex;
 
 use eng_wasm::*;
 use eng_wasm_derive::pub_interface;
-use eng_wasm::String;
-use std::collections::HashMap;
 use enigma_crypto::{KeyPair, hash::Keccak256};
-use rustc_hex::ToHex;
 
 static TOTAL_SUPPLY: &str = "total_supply";
 static CONTRACT_OWNER: &str = "owner";
+static BALANCES: &str = "balances";
+static ALLOWANCES: &str = "allowances";
 
 #[pub_interface]
 pub trait Erc20Interface{
@@ -40,34 +36,24 @@ pub trait Erc20Interface{
     fn transfer_from(owner: H256, spender: H256, to: H256, tokens: U256, sig: Vec<u8>);
 }
 
-/// User object holds all information of a user address
-#[derive(Serialize, Deserialize, Default)]
-pub struct User {
-    /// number of tokens a user owns
-    balance : U256,
-    /// a HashMap which contains all authorised spenders by the user
-    approved : HashMap<String, U256>,
-}
+pub struct Contract;
 
-impl User {
-    pub fn get_approved(&self, addr: H256) -> U256 {
-        let addr: String = addr.to_hex();
-        *self.approved.get(&addr).unwrap_or(&Default::default())
+impl Contract {
+    /// the balance of every address, each one is kept under its own key in the state.
+    /// the balances are binary encoded, which is a few bytes instead of a hex string and cheaper to write.
+    #[cfg(not(feature = "json_balances"))]
+    fn balances() -> StorageMap<H256, U256, storage::Binary> {
+        StorageMap::new_binary(BALANCES)
     }
 
-    pub fn insert_approved(&mut self, addr: H256, amount: U256) {
-        let addr: String = addr.to_hex();
-        self.approved.insert(addr, amount);
+    #[cfg(feature = "json_balances")]
+    fn balances() -> StorageMap<H256, U256> {
+        StorageMap::new(BALANCES)
     }
-}
 
-pub struct Contract;
-
-impl Contract {
-    /// receives an address and returns it's User object,
-    /// if it does not exist, it creates a new object.
-    fn get_user(user: H256) -> User {
-        read_state!(&user.to_hex::<String>()).unwrap_or_default()
+    /// the amount of tokens that the spender (second) is allowed to spend from the balance of the owner (first).
+    fn allowances() -> StorageMap<(H256, H256), U256, storage::Binary> {
+        StorageMap::new_binary(ALLOWANCES)
     }
 
     // todo: change this function in enigma-crypto so it will
@@ -100,82 +86,76 @@ impl Contract {
 impl Erc20Interface for Contract {
 
     fn construct(owner_of_the_contract: H256, total_supply: U256) {
-        let mut user_addr = Self::get_user(owner_of_the_contract);
-        user_addr.balance = total_supply;
-        let user_addr_str: String = owner_of_the_contract.to_hex();
-        write_state!(TOTAL_SUPPLY => total_supply,
-                     CONTRACT_OWNER => owner_of_the_contract,
-                     &user_addr_str => user_addr
-        );
+        StorageValue::new(TOTAL_SUPPLY).set(total_supply);
+        StorageValue::new(CONTRACT_OWNER).set(owner_of_the_contract);
+        Self::balances().insert(owner_of_the_contract, total_supply);
     }
 
     fn mint(owner: H256, addr: H256, tokens: U256, sig: Vec<u8>) {
         // verify the owner is the one who is minting.
-        let contract_owner: H256 = read_state!(CONTRACT_OWNER).unwrap();
+        let contract_owner: H256 = StorageValue::new(CONTRACT_OWNER).get().unwrap();
         assert_eq!(owner, contract_owner);
         assert!(Self::verify(owner, addr, tokens, sig));
 
-        let total_supply: U256 = read_state!(TOTAL_SUPPLY).unwrap_or_default();
-        let mut user_addr = Self::get_user(addr);
+        let mut total_supply = StorageValue::new(TOTAL_SUPPLY);
+        let supply: U256 = total_supply.get().unwrap_or_default();
+        let mut balances = Self::balances();
+        let balance = balances.get(&addr).unwrap_or_default();
 
-        // update the user object and write to the state
-        user_addr.balance = user_addr.balance + tokens;
-        write_state!(&addr.to_hex::<String>() => user_addr, TOTAL_SUPPLY => total_supply + tokens);
+        // update the balance and the total supply in the state
+        balances.insert(addr, balance + tokens);
+        total_supply.set(supply + tokens);
     }
 
     fn total_supply() -> U256 {
-        read_state!(TOTAL_SUPPLY).unwrap_or_default()
+        StorageValue::new(TOTAL_SUPPLY).get().unwrap_or_default()
     }
 
     fn balance_of(token_owner: H256) -> U256 {
-        let user: User = Self::get_user(token_owner);
-        user.balance
+        Self::balances().get(&token_owner).unwrap_or_default()
     }
 
     fn allowance(owner: H256, spender: H256) -> U256 {
-        let user: User = Self::get_user(owner);
-        user.get_approved(spender)
+        Self::allowances().get(&(owner, spender)).unwrap_or_default()
     }
 
     fn transfer(from: H256, to: H256, tokens: U256, sig: Vec<u8>) {
         assert!(Self::verify(from.clone(), to.clone(), tokens, sig));
-        let mut from_user : User = Self::get_user(from);
+        let mut balances = Self::balances();
+        let from_balance = balances.get(&from).unwrap_or_default();
 
         // panic if the 'from' address does not have enough tokens.
-        assert!(from_user.balance >= tokens, "invalid action: user does not have enough tokens");
-        let mut to_user : User = Self::get_user(to);
+        assert!(from_balance >= tokens, "invalid action: user does not have enough tokens");
 
-        // update the balances and write the user objects to the state
-        from_user.balance = from_user.balance - tokens;
-        to_user.balance = to_user.balance + tokens;
-        write_state!(&from.to_hex::<String>() => from_user, &to.to_hex::<String>() => to_user);
+        // update the balances in the state, only the two entries are written.
+        balances.insert(from, from_balance - tokens);
+        let to_balance = balances.get(&to).unwrap_or_default();
+        balances.insert(to, to_balance + tokens);
     }
 
     fn approve(token_owner: H256, spender: H256, tokens: U256, sig: Vec<u8>) {
         assert!(Self::verify(token_owner.clone(), spender.clone(), tokens, sig));
-        let mut owner_user : User = Self::get_user(token_owner);
-        assert!(owner_user.balance >= tokens, "invalid action: owner does not have enough tokens");
-        // update the object and write it to the state
-        owner_user.insert_approved(spender, tokens);
-        write_state!(&token_owner.to_hex::<String>() => owner_user);
+        let owner_balance = Self::balances().get(&token_owner).unwrap_or_default();
+        assert!(owner_balance >= tokens, "invalid action: owner does not have enough tokens");
+        Self::allowances().insert((token_owner, spender), tokens);
     }
 
     fn transfer_from(owner: H256, spender: H256, to: H256, tokens: U256, sig: Vec<u8>) {
         assert!(Self::verify(spender.clone(), to.clone(), tokens, sig));
-        let mut owner_user : User = Self::get_user(owner);
+        let mut balances = Self::balances();
+        let owner_balance = balances.get(&owner).unwrap_or_default();
         // panic if the owner does not own the amount of tokens
-        assert!(owner_user.balance >= tokens, "invalid action: owner does not have enough tokens");
+        assert!(owner_balance >= tokens, "invalid action: owner does not have enough tokens");
 
-        let allowed_balance = owner_user.get_approved(spender);
+        let mut allowances = Self::allowances();
+        let allowed_balance = allowances.get(&(owner, spender)).unwrap_or_default();
         // panic if the spender is not approved to spend as much as tokens
         assert!(allowed_balance >= tokens, "invalid action: user is not allowed to spend this amount of tokens");
 
-        let mut to_user: User = Self::get_user(to);
-
-        // update the objects and write to the state
-        to_user.balance = to_user.balance + tokens;
-        owner_user.balance = owner_user.balance - tokens;
-        owner_user.insert_approved(spender, allowed_balance - tokens);
-        write_state!(&owner.to_hex::<String>() => owner_user, &to.to_hex::<String>() => to_user);
+        // update the balances and the allowance in the state
+        balances.insert(owner, owner_balance - tokens);
+        let to_balance = balances.get(&to).unwrap_or_default();
+        balances.insert(to, to_balance + tokens);
+        allowances.insert((owner, spender), allowed_balance - tokens);
     }
 }
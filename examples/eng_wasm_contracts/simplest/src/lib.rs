@@ -14,10 +14,17 @@ pub trait ContractInterface{
     fn choose_rand_color() -> Vec<u8>;
     fn get_scrambled_vec();
     fn addition(x: U256, y: U256) -> U256;
+    fn subtraction(x: U256, y: U256) -> Result<U256, String>;
     fn get_last_sum() -> U256;
     fn print_test(x: U256, y: U256);
     fn dynamic_types(bytes_arr: Vec<Vec<u8>>, string_arr: Vec<String>, fixed_arr: Vec<H256>);
     fn construct(param: U256);
+    fn get_block_number() -> U256;
+    fn get_self_address() -> H256;
+    fn get_sender_pubkey() -> Vec<u8>;
+    fn push_history(value: U256) -> U256;
+    fn get_history() -> Vec<U256>;
+    fn authorize_upgrade(new_code_hash: H256, sender: Vec<u8>) -> Result<(), String>;
 }
 
 pub struct Contract;
@@ -108,6 +115,14 @@ impl ContractInterface for Contract {
         sum.into()
     }
 
+    // fails the task instead of underflowing
+    fn subtraction(x: U256, y: U256) -> Result<U256, String> {
+        if y > x {
+            return Err("the result would be negative".to_string());
+        }
+        Ok(x - y)
+    }
+
     fn get_last_sum() -> U256 {
         let sum: u64 = read_state!("curr_sum").unwrap_or_default();
         sum.into()
@@ -119,5 +134,38 @@ impl ContractInterface for Contract {
 
     fn construct(param: U256){
         write_state!("1" => param.as_u64());
+        write_state!("owner" => sender_pubkey());
+    }
+
+    fn get_block_number() -> U256 {
+        block_number().into()
+    }
+
+    fn get_self_address() -> H256 {
+        self_address()
+    }
+
+    fn get_sender_pubkey() -> Vec<u8> {
+        sender_pubkey()
     }
-}
\ No newline at end of file
+
+    // tests the storage collections, returns the new length
+    fn push_history(value: U256) -> U256 {
+        let mut history = StorageVec::new("history");
+        history.push(value);
+        history.len().into()
+    }
+
+    fn get_history() -> Vec<U256> {
+        StorageVec::new("history").iter().collect()
+    }
+
+    // only the sender that deployed the contract can upgrade it
+    fn authorize_upgrade(_new_code_hash: H256, sender: Vec<u8>) -> Result<(), String> {
+        let owner: Vec<u8> = read_state!("owner").unwrap_or_default();
+        if sender != owner {
+            return Err("only the owner can upgrade the contract".to_string());
+        }
+        Ok(())
+    }
+}